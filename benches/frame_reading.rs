@@ -0,0 +1,86 @@
+//! Compares reading every frame of a recording with parse_raw_frame, which
+//! allocates each payload, and with read_frame_into, which reuses one buffer.
+//!
+//! cargo bench --bench frame_reading [-- recording.vraw]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use vraw_convert::{parse_raw_frame, read_frame_into, read_index, ParseOptions};
+
+/// The system allocator, counting the allocations and the bytes allocated.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Reads every frame of the recording, returning the number of payload bytes.
+fn measure(name: &str, path: &str, mut read: impl FnMut(&mut BufReader<File>) -> usize) {
+    let mut f = BufReader::new(File::open(path).unwrap());
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    let payload = read(&mut f);
+
+    println!(
+        "{name:>16}: {payload} payload bytes in {:?}, {} allocations of {} bytes",
+        start.elapsed(),
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED.load(Ordering::Relaxed) - allocated,
+    );
+}
+
+fn main() {
+    let path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .unwrap_or_else(|| "assets/h265.vraw".into());
+    let options = ParseOptions::default();
+    let entries = read_index(&mut BufReader::new(File::open(&path).unwrap()), &options)
+        .unwrap()
+        .entries;
+    println!("{path}: {} frames", entries.len());
+
+    measure("parse_raw_frame", &path, |f| {
+        entries
+            .iter()
+            .filter_map(|entry| parse_raw_frame(f, entry, &options).ok())
+            .map(|frame| frame.raw_data.len())
+            .sum()
+    });
+
+    measure("read_frame_into", &path, |f| {
+        let mut buf = Vec::new();
+        entries
+            .iter()
+            .filter_map(|entry| {
+                let frame = read_frame_into(f, entry, &options, &mut buf).ok()?;
+                Some(frame.raw_data.len())
+            })
+            .sum()
+    });
+}
@@ -0,0 +1,24 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The header of the C interface is written to OUT_DIR, include/ holds a
+    // copy of it for C and C++ projects building against the shared library
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+            .expect("cbindgen.toml is valid");
+        // Only the C interface is parsed, cbindgen cannot read the rest of
+        // the crate
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .generate()
+            .expect("the C interface has a header")
+            .write_to_file(format!("{out_dir}/vraw_convert.h"));
+    }
+}
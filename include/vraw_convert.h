@@ -0,0 +1,244 @@
+#ifndef VRAW_CONVERT_H
+#define VRAW_CONVERT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ The container is chosen after the recorded format
+ */
+#define VRAW_CONTAINER_AUTO 0
+
+#define VRAW_CONTAINER_MP4 1
+
+#define VRAW_CONTAINER_ELEMENTARY_STREAM 2
+
+#define VRAW_CONTAINER_IMAGE_SEQUENCE 3
+
+#define VRAW_CONTAINER_Y4M 4
+
+#define VRAW_CONTAINER_FFMPEG 5
+
+#define VRAW_CONTAINER_AVI 6
+
+#define VRAW_CONTAINER_MATROSKA 7
+
+#define VRAW_CONTAINER_DUMP 8
+
+/*
+ Outcome of a call. The codes of failed conversions are the exit codes of
+ the command line converter.
+ */
+typedef enum VrawStatus {
+  VRAW_STATUS_OK = 0,
+  /*
+   A pointer is null where it may not be, or a path is not UTF-8
+   */
+  VRAW_STATUS_INVALID_ARGUMENT = 1,
+  /*
+   The options cannot be applied to the recording
+   */
+  VRAW_STATUS_INVALID_OPTIONS = 2,
+  /*
+   The recording cannot be opened, is corrupt or holds no video that can
+   be converted
+   */
+  VRAW_STATUS_BAD_INPUT = 3,
+  /*
+   The output cannot be written
+   */
+  VRAW_STATUS_OUTPUT_FAILED = 4,
+  /*
+   The progress callback cancelled the conversion
+   */
+  VRAW_STATUS_CANCELLED = 5,
+  /*
+   The converter panicked, which is a bug
+   */
+  VRAW_STATUS_PANIC = 6,
+} VrawStatus;
+
+/*
+ Progress of a conversion, see [`Progress`].
+ */
+typedef struct VrawProgress {
+  /*
+   Number of frames read so far, converted or not
+   */
+  uint64_t frames_processed;
+  /*
+   Number of frames listed by the index
+   */
+  uint64_t total_frames;
+  /*
+   Byte offset in the recording the reading has reached
+   */
+  uint64_t bytes_read;
+  /*
+   Receive timestamp of the last frame read, in nanoseconds
+   */
+  int64_t receive_timestamp;
+} VrawProgress;
+
+/*
+ Called with the progress of a conversion and the `progress_user_data` of
+ its options, on the thread converting. Returning false cancels the
+ conversion.
+ */
+typedef bool (*VrawProgressCallback)(const struct VrawProgress *progress, void *user_data);
+
+/*
+ Options of [`vraw_convert_file`], see [`ConvertOptions`]. Zeroed options
+ are the defaults, see [`vraw_default_options`].
+ */
+typedef struct VrawConvertOptions {
+  /*
+   One of the `VRAW_CONTAINER_*` constants
+   */
+  uint32_t container;
+  /*
+   Also write the Stats frames to a .stats.json file next to the output
+   */
+  bool export_stats;
+  /*
+   Place the moov box of .mp4 files before the media data
+   */
+  bool faststart;
+  /*
+   Write fragmented .mp4 files with fragments of at least this many
+   milliseconds, 0 for unfragmented files
+   */
+  uint64_t fragment_duration_ms;
+  /*
+   Fail on any inconsistency of the recording instead of recovering from
+   it with a warning
+   */
+  bool strict;
+  /*
+   Stop at the first frame that cannot be read instead of skipping it
+   */
+  bool stop_at_corrupt_frame;
+  /*
+   Fail instead of replacing an output that exists
+   */
+  bool no_overwrite;
+  VrawProgressCallback progress;
+  /*
+   Number of frames between calls of the progress callback, 0 for the
+   default
+   */
+  uint64_t progress_stride;
+  /*
+   Passed to the progress callback as is
+   */
+  void *progress_user_data;
+} VrawConvertOptions;
+
+/*
+ What a recording holds, see [`VrawInfo`](crate::VrawInfo). Timestamps
+ and durations are in nanoseconds.
+ */
+typedef struct VrawProbeInfo {
+  /*
+   Number of frames listed by the index, Stats frames included
+   */
+  uint64_t frame_count;
+  /*
+   Whether the index was missing or damaged and rebuilt from the frames
+   */
+  bool index_rebuilt;
+  /*
+   Whether the recording has a header with its start time
+   */
+  bool has_recording_start;
+  /*
+   Wall clock time at which the recording started, as unix time in
+   seconds
+   */
+  int64_t recording_start;
+  /*
+   Receive timestamp of the first frame
+   */
+  int64_t first_timestamp;
+  /*
+   Receive timestamp of the last frame
+   */
+  int64_t last_timestamp;
+  /*
+   Time between the first and the last frame received
+   */
+  int64_t duration;
+  /*
+   Size of the recording in bytes
+   */
+  uint64_t size;
+  /*
+   Bits per second over the duration, 0 for recordings of less than two
+   frames
+   */
+  uint64_t bitrate;
+  /*
+   Number of source streams of the frames whose header was read
+   */
+  uint64_t stream_count;
+  /*
+   Number of frames whose header was read
+   */
+  uint64_t frames_probed;
+  /*
+   Number of frames whose header could not be read
+   */
+  uint64_t unreadable_frames;
+} VrawProbeInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ The default options of [`vraw_convert_file`].
+ */
+struct VrawConvertOptions vraw_default_options(void);
+
+/*
+ Converts the recording at `in_path` to `out_path`, see
+ [`convert_vraw_with_options`]. Without an output path the output is named
+ after the input and the time of the conversion, without options the
+ defaults are used.
+
+ # Safety
+
+ `in_path` must be a nul terminated string, `out_path` and `options` null
+ or valid for the duration of the call.
+ */
+enum VrawStatus vraw_convert_file(const char *in_path,
+                                  const char *out_path,
+                                  const struct VrawConvertOptions *options);
+
+/*
+ Tells what the recording at `in_path` holds without converting it, see
+ [`probe`](crate::probe).
+
+ # Safety
+
+ `in_path` must be a nul terminated string and `out_info` point to a
+ writable [`VrawProbeInfo`]. It is left untouched if the probe fails.
+ */
+enum VrawStatus vraw_probe(const char *in_path, struct VrawProbeInfo *out_info);
+
+/*
+ The message of the last failed call of the calling thread, null if the
+ last call succeeded. It stays valid until the next call of the thread.
+ */
+const char *vraw_last_error_message(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* VRAW_CONVERT_H */
@@ -0,0 +1,230 @@
+use crate::error::VrawError;
+use crate::parser::RecordingReader;
+use crate::processing::{convert_indexed_recording, ConversionSummary, ConvertOptions};
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tokio::sync::{mpsc, oneshot};
+
+/// Number of bytes read from the async reader at once. Frame headers, index
+/// entries and small frames are then taken from the block already read.
+const BLOCK_LEN: usize = 1 << 20;
+
+/// Same as [`convert_vraw_with_options`](crate::convert_vraw_with_options),
+/// for async services. The recording is read with [`tokio::fs::File`], see
+/// [`convert_vraw_from_async_reader`].
+///
+/// input: path to .vraw file, recordings cannot be read from stdin
+pub async fn convert_vraw_async(
+    input: impl AsRef<Path>,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let input = input.as_ref().to_path_buf();
+    let file = {
+        let input = input.clone();
+        async move {
+            tokio::fs::File::open(input)
+                .await
+                .map_err(VrawError::input("failed to open file"))
+        }
+    };
+
+    convert(file, input, output, options).await
+}
+
+/// Same as [`convert_vraw_from_reader`](crate::convert_vraw_from_reader), from
+/// an async reader. The index and the frames are read from the async reader
+/// by a task of the runtime, in blocks of 1 MiB, while the outputs, which are
+/// written synchronously, are written on a blocking thread from the blocks
+/// read.
+///
+/// Dropping the returned future, e.g. when a [`tokio::select!`] or a timeout
+/// gives up on it, cancels the conversion like
+/// [`ConvertOptions::cancel`] does: it stops at the next frame and removes
+/// the output written so far.
+pub async fn convert_vraw_from_async_reader<R>(
+    reader: R,
+    output: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send + 'static,
+{
+    let output = output.as_ref().to_path_buf();
+    convert(async { Ok(reader) }, output.clone(), Some(output), options).await
+}
+
+/// Converts the recording read from the reader `open` resolves to. A future
+/// dropped while the reader is still being opened is cancelled as well.
+async fn convert<R>(
+    open: impl Future<Output = Result<R, VrawError>>,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send + 'static,
+{
+    let mut options = options.clone();
+    let cancel = options.cancel.get_or_insert_with(Default::default).clone();
+    let mut guard = CancelOnDrop(Some(cancel));
+
+    let result = match open.await {
+        Ok(reader) => convert_opened(reader, input, output, options).await,
+        Err(e) => Err(e),
+    };
+
+    guard.0 = None;
+    result
+}
+
+async fn convert_opened<R>(
+    mut reader: R,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    options: ConvertOptions,
+) -> Result<ConversionSummary, VrawError>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send + 'static,
+{
+    let len = reader
+        .seek(SeekFrom::End(0))
+        .await
+        .map_err(VrawError::input("failed to read the file size"))?;
+
+    // The reads outlive a dropped conversion until it stops at the next frame
+    let (reads, received_reads) = mpsc::channel(1);
+    tokio::spawn(serve_reads(reader, received_reads));
+
+    let mut f = BlockReader {
+        reads,
+        len,
+        position: 0,
+        block_start: 0,
+        block: Vec::new(),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        convert_indexed_recording(&mut f, &input, output, &options)
+    })
+    .await;
+
+    result.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Cancels the conversion of a future dropped before it finished.
+struct CancelOnDrop(Option<Arc<AtomicBool>>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.0 {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A read of the bytes of a recording from an offset, fewer at its end.
+struct BlockRead {
+    offset: u64,
+    len: usize,
+    block: oneshot::Sender<io::Result<Vec<u8>>>,
+}
+
+/// Reads the blocks asked for by a [`BlockReader`] until it is dropped.
+async fn serve_reads<R>(mut reader: R, mut reads: mpsc::Receiver<BlockRead>)
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    while let Some(read) = reads.recv().await {
+        let block = read_block(&mut reader, read.offset, read.len).await;
+        // Nobody waits for the block if the conversion panicked meanwhile
+        let _ = read.block.send(block);
+    }
+}
+
+async fn read_block<R>(reader: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    reader.seek(SeekFrom::Start(offset)).await?;
+
+    let mut block = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut block).await?;
+    Ok(block)
+}
+
+/// A recording read on a blocking thread from the blocks read by
+/// [`serve_reads`], asking for a block whenever a read leaves the last one.
+/// Seeking only moves the position.
+struct BlockReader {
+    reads: mpsc::Sender<BlockRead>,
+    /// Length of the recording, as found before the conversion
+    len: u64,
+    position: u64,
+    /// Offset of the last block read
+    block_start: u64,
+    block: Vec<u8>,
+}
+
+impl BlockReader {
+    fn read_block(&mut self, len: usize) -> io::Result<()> {
+        let (block, received_block) = oneshot::channel();
+        let read = BlockRead {
+            offset: self.position,
+            len: len.max(BLOCK_LEN),
+            block,
+        };
+
+        let stopped = || io::Error::other("the reads of the recording stopped");
+        self.reads.blocking_send(read).map_err(|_| stopped())?;
+        self.block = received_block.blocking_recv().map_err(|_| stopped())??;
+        self.block_start = self.position;
+        Ok(())
+    }
+}
+
+impl Read for BlockReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.position < self.block_start || self.position >= block_end {
+            self.read_block(buf.len())?;
+        }
+
+        let block = &self.block[(self.position - self.block_start) as usize..];
+        let read = block.len().min(buf.len());
+        buf[..read].copy_from_slice(&block[..read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for BlockReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the recording",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl RecordingReader for BlockReader {
+    fn recording_len(&mut self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
@@ -0,0 +1,188 @@
+//! Minimal AVI (RIFF) writer for a single MJPEG video stream.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Size of everything preceding the first frame chunk: the RIFF header, the
+/// hdrl list with the avih, strh and strf chunks, and the movi list header.
+const HEADER_SIZE: u32 = 224;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+const FRAME_CHUNK_ID: &[u8; 4] = b"00dc";
+
+/// Writes frames to an AVI file. The header is written with placeholder sizes
+/// up front and rewritten by [`AviWriter::finish`] once all frames are known.
+pub struct AviWriter<W: Write + Seek> {
+    writer: W,
+    width: u32,
+    height: u32,
+    frame_rate: (u32, u32),
+    /// Offset and size of every frame chunk, offsets relative to the movi fourcc
+    index: Vec<(u32, u32)>,
+    /// Size of the movi list content, starting with the movi fourcc
+    movi_size: u32,
+    max_frame_size: u32,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    /// Starts an AVI file with a video stream of the given dimensions and frame
+    /// rate, the frame rate given as a fraction.
+    pub fn new(writer: W, width: u32, height: u32, frame_rate: (u32, u32)) -> io::Result<Self> {
+        let mut avi = Self {
+            writer,
+            width,
+            height,
+            frame_rate,
+            index: Vec::new(),
+            movi_size: 4,
+            max_frame_size: 0,
+        };
+
+        let header = avi.header(0);
+        avi.writer.write_all(&header)?;
+
+        Ok(avi)
+    }
+
+    /// Writes one JPEG image as a frame chunk.
+    pub fn write_frame(&mut self, jpeg: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(jpeg.len()).map_err(|_| too_large())?;
+        let padding = jpeg.len() % 2;
+        let chunk_size = 8 + size as u64 + padding as u64;
+
+        // Everything in a plain AVI file is addressed with 32 bits
+        if HEADER_SIZE as u64 + self.movi_size as u64 + chunk_size + self.index_size() + 16
+            > u32::MAX as u64
+        {
+            return Err(too_large());
+        }
+
+        self.writer.write_all(FRAME_CHUNK_ID)?;
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.write_all(jpeg)?;
+        // chunks are padded to an even size
+        self.writer.write_all(&[0; 1][..padding])?;
+
+        self.index.push((self.movi_size, size));
+        self.movi_size += chunk_size as u32;
+        self.max_frame_size = self.max_frame_size.max(size);
+
+        Ok(())
+    }
+
+    /// Writes the idx1 index and rewrites the header with the final sizes.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut idx1 = Vec::with_capacity(self.index_size() as usize);
+        idx1.extend_from_slice(b"idx1");
+        idx1.extend_from_slice(&(self.index.len() as u32 * 16).to_le_bytes());
+        for (offset, size) in &self.index {
+            idx1.extend_from_slice(FRAME_CHUNK_ID);
+            idx1.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+            idx1.extend_from_slice(&offset.to_le_bytes());
+            idx1.extend_from_slice(&size.to_le_bytes());
+        }
+        self.writer.write_all(&idx1)?;
+
+        let riff_size = HEADER_SIZE - 8 + self.movi_size - 4 + idx1.len() as u32;
+        let header = self.header(riff_size);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn index_size(&self) -> u64 {
+        8 + (self.index.len() as u64 + 1) * 16
+    }
+
+    /// Everything up to and including the movi fourcc.
+    fn header(&self, riff_size: u32) -> Vec<u8> {
+        let (rate, scale) = self.frame_rate;
+        let frame_count = self.index.len() as u32;
+        let micro_sec_per_frame = (1_000_000u64 * scale as u64 / rate.max(1) as u64) as u32;
+        let max_bytes_per_sec = (self.max_frame_size as u64 * rate as u64 / scale.max(1) as u64)
+            .min(u32::MAX as u64) as u32;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        let mut put = |bytes: &[u8]| header.extend_from_slice(bytes);
+
+        put(b"RIFF");
+        put(&riff_size.to_le_bytes());
+        put(b"AVI ");
+
+        put(b"LIST");
+        put(&192u32.to_le_bytes());
+        put(b"hdrl");
+
+        // MainAVIHeader
+        put(b"avih");
+        put(&56u32.to_le_bytes());
+        put(&micro_sec_per_frame.to_le_bytes());
+        put(&max_bytes_per_sec.to_le_bytes());
+        put(&0u32.to_le_bytes()); // padding granularity
+        put(&AVIF_HASINDEX.to_le_bytes());
+        put(&frame_count.to_le_bytes());
+        put(&0u32.to_le_bytes()); // initial frames
+        put(&1u32.to_le_bytes()); // streams
+        put(&self.max_frame_size.to_le_bytes()); // suggested buffer size
+        put(&self.width.to_le_bytes());
+        put(&self.height.to_le_bytes());
+        put(&[0; 16]); // reserved
+
+        put(b"LIST");
+        put(&116u32.to_le_bytes());
+        put(b"strl");
+
+        // AVIStreamHeader
+        put(b"strh");
+        put(&56u32.to_le_bytes());
+        put(b"vids");
+        put(b"MJPG");
+        put(&0u32.to_le_bytes()); // flags
+        put(&0u16.to_le_bytes()); // priority
+        put(&0u16.to_le_bytes()); // language
+        put(&0u32.to_le_bytes()); // initial frames
+        put(&scale.to_le_bytes());
+        put(&rate.to_le_bytes());
+        put(&0u32.to_le_bytes()); // start
+        put(&frame_count.to_le_bytes()); // length
+        put(&self.max_frame_size.to_le_bytes()); // suggested buffer size
+        put(&u32::MAX.to_le_bytes()); // quality, -1 for the default
+        put(&0u32.to_le_bytes()); // sample size, 0 as frames vary in size
+        put(&0u16.to_le_bytes()); // frame rectangle
+        put(&0u16.to_le_bytes());
+        put(&(self.width as u16).to_le_bytes());
+        put(&(self.height as u16).to_le_bytes());
+
+        // BITMAPINFOHEADER
+        put(b"strf");
+        put(&40u32.to_le_bytes());
+        put(&40u32.to_le_bytes());
+        put(&self.width.to_le_bytes());
+        put(&self.height.to_le_bytes());
+        put(&1u16.to_le_bytes()); // planes
+        put(&24u16.to_le_bytes()); // bit count
+        put(b"MJPG");
+        put(&self
+            .width
+            .saturating_mul(self.height)
+            .saturating_mul(3)
+            .to_le_bytes()); // image size
+        put(&[0; 16]); // resolution and palette
+
+        put(b"LIST");
+        put(&self.movi_size.to_le_bytes());
+        put(b"movi");
+
+        debug_assert_eq!(header.len(), HEADER_SIZE as usize);
+
+        header
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::other("AVI files larger than 4 GB are not supported")
+}
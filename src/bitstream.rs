@@ -0,0 +1,888 @@
+//! Parsing of Annex-B formatted H.264 and HEVC bitstreams, as far as needed to
+//! describe the streams in containers, and writing of the few NAL units added
+//! to them. JPEG images are only checked for their markers.
+
+pub const AVC_NAL_TYPE_IDR: u8 = 5;
+pub const AVC_NAL_TYPE_SPS: u8 = 7;
+pub const AVC_NAL_TYPE_PPS: u8 = 8;
+
+pub const HEVC_NAL_TYPE_VPS: u8 = 32;
+pub const HEVC_NAL_TYPE_SPS: u8 = 33;
+pub const HEVC_NAL_TYPE_PPS: u8 = 34;
+pub const HEVC_NAL_TYPE_PREFIX_SEI: u8 = 39;
+/// NAL unit types below this one are VCL NAL units, carrying slices
+const HEVC_NAL_TYPE_FIRST_NON_VCL: u8 = 32;
+
+/// payloadType of user_data_unregistered SEI messages
+const SEI_PAYLOAD_TYPE_USER_DATA_UNREGISTERED: u32 = 5;
+/// Start of image marker, the first bytes of a JPEG image
+pub const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+/// End of image marker, the last bytes of a JPEG image
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+
+/// BLA_W_LP to the reserved IRAP types
+pub const HEVC_NAL_TYPES_IRAP: std::ops::RangeInclusive<u8> = 16..=23;
+
+/// Splits an Annex-B formatted access unit into its NAL units, without start
+/// codes.
+pub fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = match find_start_code(data) {
+        Some((_, end)) => &data[end..],
+        None => &data[data.len()..],
+    };
+
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        let (nal, next) = match find_start_code(rest) {
+            Some((start, end)) => (&rest[..start], &rest[end..]),
+            None => (rest, &rest[rest.len()..]),
+        };
+        rest = next;
+
+        // zero bytes before a start code belong to the next start code
+        let len = nal.iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1);
+        Some(&nal[..len])
+    })
+    .filter(|nal| !nal.is_empty())
+}
+
+/// Start and end of the first three byte start code.
+fn find_start_code(data: &[u8]) -> Option<(usize, usize)> {
+    data.windows(3)
+        .position(|window| window == [0, 0, 1])
+        .map(|start| (start, start + 3))
+}
+
+/// The nal_unit_type of an H.264 NAL unit.
+pub fn avc_nal_type(nal: &[u8]) -> u8 {
+    nal[0] & 0x1F
+}
+
+/// The nal_unit_type of an HEVC NAL unit.
+pub fn hevc_nal_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3F
+}
+
+/// Whether an Annex-B formatted H.264 access unit is an IDR picture.
+pub fn is_avc_idr(data: &[u8]) -> bool {
+    nal_units(data).any(|nal| avc_nal_type(nal) == AVC_NAL_TYPE_IDR)
+}
+
+/// Whether an Annex-B formatted HEVC access unit is an IRAP picture (BLA, IDR
+/// or CRA), which decoding can start at.
+pub fn is_hevc_irap(data: &[u8]) -> bool {
+    nal_units(data).any(|nal| HEVC_NAL_TYPES_IRAP.contains(&hevc_nal_type(nal)))
+}
+
+/// Codec of a coded frame, as told by its bitstream.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Codec {
+    Avc,
+    Hevc,
+    Jpeg,
+}
+
+/// Tells the codec of a frame from its bitstream: a JPEG SOI marker, or an
+/// Annex-B access unit whose NAL unit headers are all valid for one of H.264
+/// and HEVC, but not all for the other. None if the frame is neither, or could
+/// be both.
+pub fn sniff_codec(data: &[u8]) -> Option<Codec> {
+    if data.starts_with(&JPEG_SOI) && data.get(2) == Some(&0xFF) {
+        return Some(Codec::Jpeg);
+    }
+
+    match find_start_code(data) {
+        Some((start, _)) if data[..start].iter().all(|&byte| byte == 0) => {}
+        _ => return None,
+    }
+
+    let (mut avc, mut hevc) = (true, true);
+    for nal in nal_units(data) {
+        avc &= is_avc_nal_header(nal);
+        hevc &= is_hevc_nal_header(nal);
+    }
+
+    match (avc, hevc) {
+        (true, false) => Some(Codec::Avc),
+        (false, true) => Some(Codec::Hevc),
+        _ => None,
+    }
+}
+
+/// Whether an H.264 NAL unit header is valid, for the NAL unit types of the
+/// profiles of cameras. Data partitions, only used by the Extended profile,
+/// are left out, as their headers are those of common HEVC NAL units.
+fn is_avc_nal_header(nal: &[u8]) -> bool {
+    let nal_ref_idc = (nal[0] >> 5) & 0x03;
+    let valid_ref_idc = match avc_nal_type(nal) {
+        // Non-IDR slices
+        1 => true,
+        // IDR slices and parameter sets are always referenced
+        AVC_NAL_TYPE_IDR | AVC_NAL_TYPE_SPS | AVC_NAL_TYPE_PPS => nal_ref_idc != 0,
+        // SEI, access unit delimiters, end of sequence or stream and filler
+        // data never are
+        6 | 9..=12 => nal_ref_idc == 0,
+        _ => false,
+    };
+
+    nal[0] & 0x80 == 0 && valid_ref_idc
+}
+
+/// Whether an HEVC NAL unit header is valid for the base layer, with a NAL
+/// unit type that is not reserved.
+fn is_hevc_nal_header(nal: &[u8]) -> bool {
+    let [first, second, ..] = *nal else {
+        return false;
+    };
+    let nuh_layer_id = ((first & 0x01) << 5) | (second >> 3);
+    let nuh_temporal_id_plus1 = second & 0x07;
+
+    first & 0x80 == 0
+        && nuh_layer_id == 0
+        && nuh_temporal_id_plus1 != 0
+        && matches!(hevc_nal_type(nal), 0..=9 | 16..=21 | 32..=40)
+}
+
+/// The length of a JPEG image up to and including its last end of image
+/// marker, as bytes after it are not part of the image. None unless it starts
+/// with a start of image marker and has an end of image marker.
+pub fn jpeg_image_len(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&JPEG_SOI) {
+        return None;
+    }
+
+    data.windows(2)
+        .rposition(|marker| marker == JPEG_EOI)
+        .filter(|&position| position >= JPEG_SOI.len())
+        .map(|position| position + JPEG_EOI.len())
+}
+
+/// Converts an Annex-B formatted access unit to NAL units prefixed with their
+/// size as 4 byte big endian integers, as expected by ISO BMFF and Matroska.
+pub fn annex_b_to_length_prefixed(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len() + 4);
+
+    for nal in nal_units(data) {
+        res.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        res.extend_from_slice(nal);
+    }
+
+    res
+}
+
+/// Drops the VPS, SPS and PPS NAL units of an Annex-B formatted HEVC access
+/// unit, for sample entries carrying the parameter sets out of band. The
+/// remaining NAL units are written with 4 byte start codes.
+pub fn strip_hevc_parameter_sets(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+
+    for nal in nal_units(data).filter(|nal| {
+        !matches!(
+            hevc_nal_type(nal),
+            HEVC_NAL_TYPE_VPS | HEVC_NAL_TYPE_SPS | HEVC_NAL_TYPE_PPS
+        )
+    }) {
+        res.extend_from_slice(&[0, 0, 0, 1]);
+        res.extend_from_slice(nal);
+    }
+
+    res
+}
+
+/// Removes the emulation prevention bytes (00 00 03) of a NAL unit.
+fn to_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        res.push(byte);
+    }
+
+    res
+}
+
+/// Inserts emulation prevention bytes into an RBSP, so that the NAL unit
+/// contains neither start codes nor 00 00 03 sequences of its own.
+pub fn escape_rbsp(rbsp: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(rbsp.len() + rbsp.len() / 64 + 1);
+    let mut zeros = 0;
+
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= 3 {
+            res.push(3);
+            zeros = 0;
+        }
+
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        res.push(byte);
+    }
+
+    // a NAL unit must not end with a zero byte
+    if res.last() == Some(&0) {
+        res.push(3);
+    }
+
+    res
+}
+
+/// Builds an HEVC prefix SEI NAL unit (ITU-T H.265 D.2.7) with a single
+/// user_data_unregistered message carrying the given data.
+pub fn hevc_user_data_sei(uuid: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len() + 24);
+
+    // payload type and size are coded as runs of 0xFF and a final byte
+    let put_sei_value = |rbsp: &mut Vec<u8>, mut value: u32| {
+        while value >= 255 {
+            rbsp.push(0xFF);
+            value -= 255;
+        }
+        rbsp.push(value as u8);
+    };
+    put_sei_value(&mut rbsp, SEI_PAYLOAD_TYPE_USER_DATA_UNREGISTERED);
+    put_sei_value(&mut rbsp, (uuid.len() + data.len()) as u32);
+    rbsp.extend_from_slice(uuid);
+    rbsp.extend_from_slice(data);
+    rbsp.push(0x80); // rbsp_trailing_bits
+
+    // forbidden_zero_bit, nal_unit_type, nuh_layer_id 0, nuh_temporal_id_plus1 1
+    let mut nal = vec![HEVC_NAL_TYPE_PREFIX_SEI << 1, 1];
+    nal.extend(escape_rbsp(&rbsp));
+    nal
+}
+
+/// Inserts a NAL unit into an Annex-B formatted HEVC access unit, in front of
+/// its first slice as required of prefix SEI NAL units. The NAL units are
+/// written with 4 byte start codes.
+pub fn insert_hevc_nal_before_slices(data: &[u8], inserted: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len() + inserted.len() + 8);
+    let mut pending = Some(inserted);
+
+    for nal in nal_units(data) {
+        if hevc_nal_type(nal) < HEVC_NAL_TYPE_FIRST_NON_VCL {
+            if let Some(inserted) = pending.take() {
+                res.extend_from_slice(&[0, 0, 0, 1]);
+                res.extend_from_slice(inserted);
+            }
+        }
+        res.extend_from_slice(&[0, 0, 0, 1]);
+        res.extend_from_slice(nal);
+    }
+
+    // access units without slices get it at the end
+    if let Some(inserted) = pending {
+        res.extend_from_slice(&[0, 0, 0, 1]);
+        res.extend_from_slice(inserted);
+    }
+
+    res
+}
+
+/// Reads bits most significant first, with the exp-Golomb codes of H.264 and
+/// HEVC.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<u32> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn read_bits(&mut self, count: u32) -> Option<u64> {
+        (0..count).try_fold(0, |value, _| Some(value << 1 | self.read_bit()? as u64))
+    }
+
+    fn skip_bits(&mut self, count: usize) -> Option<()> {
+        self.pos += count;
+        (self.pos <= self.data.len() * 8).then_some(())
+    }
+
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.read_bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+
+        let value = (1u64 << leading_zeros) - 1 + self.read_bits(leading_zeros)?;
+        u32::try_from(value).ok()
+    }
+
+    fn read_se(&mut self) -> Option<i32> {
+        let value = self.read_ue()? as i64;
+        let value = if value % 2 == 1 {
+            (value + 1) / 2
+        } else {
+            -value / 2
+        };
+        i32::try_from(value).ok()
+    }
+}
+
+/// The colour description of a stream, from the video signal type of the VUI
+/// of its SPS. The code points are those of ITU-T H.273.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColourDescription {
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub full_range: bool,
+}
+
+/// Code point of ITU-T H.273 for unspecified primaries, transfer
+/// characteristics and matrix coefficients.
+const COLOUR_UNSPECIFIED: u8 = 2;
+
+/// Reads the beginning of VUI parameters up to the video signal type, which
+/// H.264 (E.1.1) and HEVC (E.2.1) have in common. None if the VUI has no video
+/// signal type.
+fn parse_vui_colour_description(reader: &mut BitReader) -> Option<ColourDescription> {
+    if reader.read_bit()? == 1 {
+        // aspect_ratio_info_present_flag
+        if reader.read_bits(8)? == 255 {
+            reader.skip_bits(32)?; // sar_width, sar_height of Extended_SAR
+        }
+    }
+    if reader.read_bit()? == 1 {
+        reader.skip_bits(1)?; // overscan_appropriate_flag
+    }
+    if reader.read_bit()? == 0 {
+        // video_signal_type_present_flag
+        return None;
+    }
+
+    reader.skip_bits(3)?; // video_format
+    let full_range = reader.read_bit()? == 1;
+    let mut colour = ColourDescription {
+        colour_primaries: COLOUR_UNSPECIFIED,
+        transfer_characteristics: COLOUR_UNSPECIFIED,
+        matrix_coefficients: COLOUR_UNSPECIFIED,
+        full_range,
+    };
+    if reader.read_bit()? == 1 {
+        colour.colour_primaries = reader.read_bits(8)? as u8;
+        colour.transfer_characteristics = reader.read_bits(8)? as u8;
+        colour.matrix_coefficients = reader.read_bits(8)? as u8;
+    }
+
+    Some(colour)
+}
+
+/// The fields of an H.264 sequence parameter set needed by containers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcSps {
+    pub profile_idc: u8,
+    /// constraint_set0_flag to constraint_set5_flag and the reserved bits
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    /// Width after applying the frame cropping
+    pub width: u32,
+    /// Height after applying the frame cropping
+    pub height: u32,
+    /// The colour description of the VUI, if the SPS has one
+    pub colour: Option<ColourDescription>,
+}
+
+/// Profiles whose SPS carries the chroma format and bit depths, and whose avcC
+/// record ends with them.
+fn is_avc_high_profile(profile_idc: u8) -> bool {
+    matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    )
+}
+
+/// Parses an H.264 SPS NAL unit (ITU-T H.264 7.3.2.1.1), None if it is
+/// malformed.
+pub fn parse_avc_sps(nal: &[u8]) -> Option<AvcSps> {
+    let rbsp = to_rbsp(nal.get(1..)?);
+    let mut reader = BitReader::new(&rbsp);
+
+    let profile_idc = reader.read_bits(8)? as u8;
+    let constraint_flags = reader.read_bits(8)? as u8;
+    let level_idc = reader.read_bits(8)? as u8;
+    reader.read_ue()?; // seq_parameter_set_id
+
+    let (mut chroma_format_idc, mut bit_depth_luma, mut bit_depth_chroma) = (1, 8, 8);
+    if is_avc_high_profile(profile_idc) {
+        chroma_format_idc = reader.read_ue()?;
+        if chroma_format_idc == 3 {
+            reader.skip_bits(1)?; // separate_colour_plane_flag
+        }
+        bit_depth_luma = reader.read_ue()? + 8;
+        bit_depth_chroma = reader.read_ue()? + 8;
+        reader.skip_bits(1)?; // qpprime_y_zero_transform_bypass_flag
+
+        if reader.read_bit()? == 1 {
+            // seq_scaling_list_present_flag for every list
+            let list_count = if chroma_format_idc == 3 { 12 } else { 8 };
+            for list in 0..list_count {
+                if reader.read_bit()? == 1 {
+                    skip_avc_scaling_list(&mut reader, if list < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    reader.read_ue()?; // log2_max_frame_num_minus4
+    match reader.read_ue()? {
+        0 => {
+            reader.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.skip_bits(1)?; // delta_pic_order_always_zero_flag
+            reader.read_se()?; // offset_for_non_ref_pic
+            reader.read_se()?; // offset_for_top_to_bottom_field
+            for _ in 0..reader.read_ue()? {
+                reader.read_se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    reader.read_ue()?; // max_num_ref_frames
+    reader.skip_bits(1)?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = reader.read_ue()? + 1;
+    let height_in_map_units = reader.read_ue()? + 1;
+    let frame_mbs_only = reader.read_bit()?;
+    if frame_mbs_only == 0 {
+        reader.skip_bits(1)?; // mb_adaptive_frame_field_flag
+    }
+    reader.skip_bits(1)?; // direct_8x8_inference_flag
+
+    let mut width = width_in_mbs.checked_mul(16)?;
+    let mut height = height_in_map_units.checked_mul(16 * (2 - frame_mbs_only))?;
+
+    if reader.read_bit()? == 1 {
+        // frame cropping offsets are in chroma samples, and in field pairs for
+        // interlaced streams
+        let (crop_unit_x, crop_unit_y) = match chroma_format_idc {
+            1 => (2, 2 * (2 - frame_mbs_only)),
+            2 => (2, 2 - frame_mbs_only),
+            _ => (1, 2 - frame_mbs_only),
+        };
+        let left = reader.read_ue()?;
+        let right = reader.read_ue()?;
+        let top = reader.read_ue()?;
+        let bottom = reader.read_ue()?;
+
+        width = width.checked_sub(crop_unit_x * (left + right))?;
+        height = height.checked_sub(crop_unit_y * (top + bottom))?;
+    }
+
+    // vui_parameters_present_flag, a malformed VUI leaves the colour unknown
+    let colour = match reader.read_bit() {
+        Some(1) => parse_vui_colour_description(&mut reader),
+        _ => None,
+    };
+
+    Some(AvcSps {
+        profile_idc,
+        constraint_flags,
+        level_idc,
+        chroma_format_idc: u8::try_from(chroma_format_idc).ok()?,
+        bit_depth_luma: u8::try_from(bit_depth_luma).ok()?,
+        bit_depth_chroma: u8::try_from(bit_depth_chroma).ok()?,
+        width,
+        height,
+        colour,
+    })
+}
+
+/// Skips a scaling_list of the given size, which is delta coded.
+fn skip_avc_scaling_list(reader: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = reader.read_se()?;
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+
+    Some(())
+}
+
+/// The first SPS and PPS of an H.264 stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcParameterSets {
+    pub sps: Vec<u8>,
+    pub pps: Vec<u8>,
+}
+
+/// Returns the SPS and PPS NAL units (without start codes) of an Annex-B
+/// formatted access unit, None unless both are present.
+pub fn find_avc_parameter_sets(data: &[u8]) -> Option<AvcParameterSets> {
+    let (mut sps, mut pps) = (None, None);
+
+    for nal in nal_units(data) {
+        let slot = match avc_nal_type(nal) {
+            AVC_NAL_TYPE_SPS => &mut sps,
+            AVC_NAL_TYPE_PPS => &mut pps,
+            _ => continue,
+        };
+        slot.get_or_insert_with(|| nal.to_vec());
+    }
+
+    Some(AvcParameterSets {
+        sps: sps?,
+        pps: pps?,
+    })
+}
+
+/// Builds an AVCDecoderConfigurationRecord (ISO/IEC 14496-15 5.3.3.1), the
+/// content of an avcC box. NAL units are declared as prefixed with 4 byte
+/// sizes.
+pub fn avc_decoder_configuration_record(
+    parameter_sets: &AvcParameterSets,
+    sps: &AvcSps,
+) -> Vec<u8> {
+    let mut res = vec![
+        1, // configurationVersion
+        sps.profile_idc,
+        sps.constraint_flags,
+        sps.level_idc,
+        0xFC | 3, // lengthSizeMinusOne
+        0xE0 | 1, // numOfSequenceParameterSets
+    ];
+    res.extend_from_slice(&(parameter_sets.sps.len() as u16).to_be_bytes());
+    res.extend_from_slice(&parameter_sets.sps);
+
+    res.push(1); // numOfPictureParameterSets
+    res.extend_from_slice(&(parameter_sets.pps.len() as u16).to_be_bytes());
+    res.extend_from_slice(&parameter_sets.pps);
+
+    if is_avc_high_profile(sps.profile_idc) {
+        res.push(0xFC | sps.chroma_format_idc);
+        res.push(0xF8 | (sps.bit_depth_luma - 8));
+        res.push(0xF8 | (sps.bit_depth_chroma - 8));
+        res.push(0); // numOfSequenceParameterSetExt
+    }
+
+    res
+}
+
+/// The fields of an HEVC sequence parameter set needed by containers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcSps {
+    pub max_sub_layers: u8,
+    pub temporal_id_nesting: bool,
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// The 48 bits following the compatibility flags
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    /// Width after applying the conformance window
+    pub width: u32,
+    /// Height after applying the conformance window
+    pub height: u32,
+    /// The colour description of the VUI, if the SPS has one
+    pub colour: Option<ColourDescription>,
+}
+
+/// Parses an HEVC SPS NAL unit (ITU-T H.265 7.3.2.2), None if it is malformed.
+pub fn parse_hevc_sps(nal: &[u8]) -> Option<HevcSps> {
+    let rbsp = to_rbsp(nal.get(2..)?);
+    let mut reader = BitReader::new(&rbsp);
+
+    reader.skip_bits(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.read_bits(3)? as usize;
+    let temporal_id_nesting = reader.read_bit()? == 1;
+
+    // profile_tier_level
+    let general_profile_space = reader.read_bits(2)? as u8;
+    let general_tier_flag = reader.read_bit()? == 1;
+    let general_profile_idc = reader.read_bits(5)? as u8;
+    let general_profile_compatibility_flags = reader.read_bits(32)? as u32;
+    let general_constraint_indicator_flags = reader.read_bits(48)?;
+    let general_level_idc = reader.read_bits(8)? as u8;
+
+    let mut sub_layers_present = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = reader.read_bit()? == 1;
+        let level_present = reader.read_bit()? == 1;
+        sub_layers_present.push((profile_present, level_present));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip_bits(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layers_present {
+        if profile_present {
+            reader.skip_bits(88)?;
+        }
+        if level_present {
+            reader.skip_bits(8)?;
+        }
+    }
+
+    reader.read_ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = reader.read_ue()?;
+    if chroma_format_idc == 3 {
+        reader.skip_bits(1)?; // separate_colour_plane_flag
+    }
+
+    let mut width = reader.read_ue()?;
+    let mut height = reader.read_ue()?;
+
+    if reader.read_bit()? == 1 {
+        // conformance window offsets are in chroma samples
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let left = reader.read_ue()?;
+        let right = reader.read_ue()?;
+        let top = reader.read_ue()?;
+        let bottom = reader.read_ue()?;
+
+        width = width.checked_sub(sub_width * (left + right))?;
+        height = height.checked_sub(sub_height * (top + bottom))?;
+    }
+
+    let bit_depth_luma = reader.read_ue()? + 8;
+    let bit_depth_chroma = reader.read_ue()? + 8;
+
+    // a malformed VUI leaves the colour unknown
+    let colour = match skip_hevc_sps_to_vui(&mut reader, max_sub_layers_minus1) {
+        Some(true) => parse_vui_colour_description(&mut reader),
+        _ => None,
+    };
+
+    Some(HevcSps {
+        max_sub_layers: max_sub_layers_minus1 as u8 + 1,
+        temporal_id_nesting,
+        general_profile_space,
+        general_tier_flag,
+        general_profile_idc,
+        general_profile_compatibility_flags,
+        general_constraint_indicator_flags,
+        general_level_idc,
+        chroma_format_idc: u8::try_from(chroma_format_idc).ok()?,
+        bit_depth_luma: u8::try_from(bit_depth_luma).ok()?,
+        bit_depth_chroma: u8::try_from(bit_depth_chroma).ok()?,
+        width,
+        height,
+        colour,
+    })
+}
+
+/// Skips the fields of an HEVC SPS between the bit depths and the VUI, returns
+/// vui_parameters_present_flag.
+fn skip_hevc_sps_to_vui(reader: &mut BitReader, max_sub_layers_minus1: usize) -> Option<bool> {
+    let log2_max_pic_order_cnt_lsb = reader.read_ue()? + 4;
+
+    let sub_layer_ordering_info_present = reader.read_bit()? == 1;
+    let first_sub_layer = if sub_layer_ordering_info_present {
+        0
+    } else {
+        max_sub_layers_minus1
+    };
+    for _ in first_sub_layer..=max_sub_layers_minus1 {
+        reader.read_ue()?; // sps_max_dec_pic_buffering_minus1
+        reader.read_ue()?; // sps_max_num_reorder_pics
+        reader.read_ue()?; // sps_max_latency_increase_plus1
+    }
+
+    // log2_min_luma_coding_block_size_minus3 to max_transform_hierarchy_depth_intra
+    for _ in 0..6 {
+        reader.read_ue()?;
+    }
+
+    if reader.read_bit()? == 1 && reader.read_bit()? == 1 {
+        // scaling_list_enabled_flag and sps_scaling_list_data_present_flag
+        skip_hevc_scaling_list_data(reader)?;
+    }
+
+    reader.skip_bits(2)?; // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    if reader.read_bit()? == 1 {
+        // pcm_enabled_flag
+        reader.skip_bits(8)?; // pcm bit depths
+        reader.read_ue()?; // log2_min_pcm_luma_coding_block_size_minus3
+        reader.read_ue()?; // log2_diff_max_min_pcm_luma_coding_block_size
+        reader.skip_bits(1)?; // pcm_loop_filter_disabled_flag
+    }
+
+    let num_short_term_ref_pic_sets = reader.read_ue()?;
+    let mut num_delta_pocs = Vec::with_capacity(num_short_term_ref_pic_sets.min(64) as usize);
+    for index in 0..num_short_term_ref_pic_sets as usize {
+        let count = skip_hevc_short_term_ref_pic_set(reader, index, &num_delta_pocs)?;
+        num_delta_pocs.push(count);
+    }
+
+    if reader.read_bit()? == 1 {
+        // long_term_ref_pics_present_flag
+        for _ in 0..reader.read_ue()? {
+            // lt_ref_pic_poc_lsb_sps and used_by_curr_pic_lt_sps_flag
+            reader.skip_bits(log2_max_pic_order_cnt_lsb as usize + 1)?;
+        }
+    }
+
+    reader.skip_bits(2)?; // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+
+    Some(reader.read_bit()? == 1)
+}
+
+/// Skips a scaling_list_data (ITU-T H.265 7.3.4).
+fn skip_hevc_scaling_list_data(reader: &mut BitReader) -> Option<()> {
+    for size_id in 0..4 {
+        let matrix_step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(matrix_step) {
+            if reader.read_bit()? == 0 {
+                reader.read_ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+
+            let coefficients = 64.min(1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                reader.read_se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coefficients {
+                reader.read_se()?; // scaling_list_delta_coef
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Skips the st_ref_pic_set with the given index of an SPS (ITU-T H.265
+/// 7.3.7), given the number of delta POCs of the sets before it. Returns the
+/// number of delta POCs of this set.
+fn skip_hevc_short_term_ref_pic_set(
+    reader: &mut BitReader,
+    index: usize,
+    num_delta_pocs: &[u32],
+) -> Option<u32> {
+    if index != 0 && reader.read_bit()? == 1 {
+        // inter_ref_pic_set_prediction_flag, sets of an SPS are predicted
+        // from the set before them
+        reader.skip_bits(1)?; // delta_rps_sign
+        reader.read_ue()?; // abs_delta_rps_minus1
+
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[index - 1] {
+            let used_by_curr_pic = reader.read_bit()? == 1;
+            if used_by_curr_pic || reader.read_bit()? == 1 {
+                // use_delta_flag
+                count += 1;
+            }
+        }
+
+        return Some(count);
+    }
+
+    let num_negative_pics = reader.read_ue()?;
+    let num_positive_pics = reader.read_ue()?;
+    let count = num_negative_pics.checked_add(num_positive_pics)?;
+    for _ in 0..count {
+        reader.read_ue()?; // delta_poc_minus1
+        reader.skip_bits(1)?; // used_by_curr_pic_flag
+    }
+
+    Some(count)
+}
+
+/// The first VPS, SPS and PPS of an HEVC stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcParameterSets {
+    pub vps: Vec<u8>,
+    pub sps: Vec<u8>,
+    pub pps: Vec<u8>,
+}
+
+/// Returns the VPS, SPS and PPS NAL units (without start codes) of an Annex-B
+/// formatted access unit, None unless all three are present.
+pub fn find_hevc_parameter_sets(data: &[u8]) -> Option<HevcParameterSets> {
+    let (mut vps, mut sps, mut pps) = (None, None, None);
+
+    for nal in nal_units(data) {
+        let slot = match hevc_nal_type(nal) {
+            HEVC_NAL_TYPE_VPS => &mut vps,
+            HEVC_NAL_TYPE_SPS => &mut sps,
+            HEVC_NAL_TYPE_PPS => &mut pps,
+            _ => continue,
+        };
+        slot.get_or_insert_with(|| nal.to_vec());
+    }
+
+    Some(HevcParameterSets {
+        vps: vps?,
+        sps: sps?,
+        pps: pps?,
+    })
+}
+
+/// Builds an HEVCDecoderConfigurationRecord (ISO/IEC 14496-15 8.3.3.1), the
+/// content of an hvcC box and the Matroska CodecPrivate of HEVC tracks. NAL
+/// units are declared as prefixed with 4 byte sizes.
+pub fn hevc_decoder_configuration_record(
+    parameter_sets: &HevcParameterSets,
+    sps: &HevcSps,
+) -> Vec<u8> {
+    let mut res = Vec::new();
+
+    res.push(1); // configurationVersion
+    res.push(
+        sps.general_profile_space << 6
+            | (sps.general_tier_flag as u8) << 5
+            | sps.general_profile_idc,
+    );
+    res.extend_from_slice(&sps.general_profile_compatibility_flags.to_be_bytes());
+    res.extend_from_slice(&sps.general_constraint_indicator_flags.to_be_bytes()[2..]);
+    res.push(sps.general_level_idc);
+    res.extend_from_slice(&0xF000u16.to_be_bytes()); // min_spatial_segmentation_idc
+    res.push(0xFC); // parallelismType
+    res.push(0xFC | sps.chroma_format_idc);
+    res.push(0xF8 | (sps.bit_depth_luma - 8));
+    res.push(0xF8 | (sps.bit_depth_chroma - 8));
+    res.extend_from_slice(&0u16.to_be_bytes()); // avgFrameRate
+    res.push(sps.max_sub_layers << 3 | (sps.temporal_id_nesting as u8) << 2 | 3);
+
+    let arrays = [
+        (HEVC_NAL_TYPE_VPS, &parameter_sets.vps),
+        (HEVC_NAL_TYPE_SPS, &parameter_sets.sps),
+        (HEVC_NAL_TYPE_PPS, &parameter_sets.pps),
+    ];
+
+    res.push(arrays.len() as u8);
+    for (nal_type, nal) in arrays {
+        res.push(nal_type); // array_completeness 0, parameter sets may be repeated in-band
+        res.extend_from_slice(&1u16.to_be_bytes());
+        res.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        res.extend_from_slice(nal);
+    }
+
+    res
+}
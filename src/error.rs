@@ -0,0 +1,183 @@
+use crate::parser::VideoCaptureFormat;
+use crate::processing::SUPPORTED_FORMATS;
+use std::error::Error;
+use std::io;
+use std::process::ExitStatus;
+
+/// Error of a conversion. The message of every variant reads as a sentence on
+/// its own, while the error it was caused by stays available as its source.
+#[derive(Debug, thiserror::Error)]
+pub enum VrawError {
+    /// Opening or reading the recording failed
+    #[error("vraw_convert: {context}: {source}")]
+    Input {
+        /// What was being done, e.g. "failed to open file"
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Writing the output or a temporary file, or running ffmpeg, failed
+    #[error("vraw_convert: {context}: {source}")]
+    Io {
+        /// What was being done, e.g. "file creation failed"
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The header at the start of the recording cannot be read
+    #[error("vraw_convert: {context}: {source}")]
+    CorruptHeader {
+        /// What the header was read for
+        context: String,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+
+    /// A recording read front to back, as from a pipe, cannot be read
+    #[error("vraw_convert: failed to read the stream: {0}")]
+    CorruptStream(#[source] Box<dyn Error + Send + Sync>),
+
+    /// The index of the recording cannot be used to find its frames
+    #[error("vraw_convert: {reason}")]
+    CorruptIndex {
+        /// Offset of the frame of the index entry at fault, if it is a
+        /// single entry
+        offset: Option<i64>,
+        reason: String,
+        #[source]
+        source: Option<Box<dyn Error + Send + Sync>>,
+    },
+
+    /// A frame of the recording cannot be read or converted
+    #[error("vraw_convert: frame {index} at offset {offset}: {reason}")]
+    BadFrame {
+        /// Position of the frame in the index
+        index: usize,
+        offset: i64,
+        #[source]
+        reason: Box<dyn Error + Send + Sync>,
+    },
+
+    /// The recording holds no video in a format that can be converted, the
+    /// format is that of the first video frame
+    #[error(
+        "vraw_convert: VideoCaptureFormat {format:?} ({width}x{height}) not supported, supported \
+         are {}",
+        supported_formats()
+    )]
+    UnsupportedFormat {
+        format: VideoCaptureFormat,
+        width: i32,
+        height: i32,
+    },
+
+    /// The video of the recording cannot be converted, e.g. because its
+    /// parameter sets are missing
+    #[error("vraw_convert: {0}")]
+    InvalidVideo(String),
+
+    /// The options cannot be applied to the recording
+    #[error("vraw_convert: {0}")]
+    InvalidOptions(String),
+
+    /// Writing the container of the output failed
+    #[error("vraw_convert: {context}: {source}")]
+    Mux {
+        /// What was being written, e.g. "failed to write frame"
+        context: String,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+
+    /// ffmpeg exited with an error
+    #[error("vraw_convert: ffmpeg failed with {0}")]
+    Ffmpeg(ExitStatus),
+
+    /// The conversion was cancelled, see
+    /// [`ConvertOptions::cancel`](crate::ConvertOptions::cancel)
+    #[error("vraw_convert: the conversion was cancelled")]
+    Cancelled,
+}
+
+/// What a failed conversion is at fault of, telling whether the recording, the
+/// output or the options have to change.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VrawErrorKind {
+    /// The options cannot be applied to the recording
+    InvalidOptions,
+    /// The recording cannot be opened, is corrupt or holds no video that can be
+    /// converted
+    BadInput,
+    /// The output cannot be written
+    OutputFailed,
+    /// The conversion was cancelled
+    Cancelled,
+}
+
+/// The formats of [`SUPPORTED_FORMATS`], as listed by the error of a recording
+/// without any of them.
+fn supported_formats() -> String {
+    SUPPORTED_FORMATS
+        .iter()
+        .map(|format| format!("{format:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl VrawError {
+    /// What the error is at fault of, as told by the exit codes of the command
+    /// line converter and the statuses of the C interface.
+    pub fn kind(&self) -> VrawErrorKind {
+        match self {
+            VrawError::Input { .. }
+            | VrawError::CorruptHeader { .. }
+            | VrawError::CorruptStream(_)
+            | VrawError::CorruptIndex { .. }
+            | VrawError::BadFrame { .. }
+            | VrawError::UnsupportedFormat { .. }
+            | VrawError::InvalidVideo(_) => VrawErrorKind::BadInput,
+            VrawError::Io { .. } | VrawError::Mux { .. } | VrawError::Ffmpeg(_) => {
+                VrawErrorKind::OutputFailed
+            }
+            VrawError::Cancelled => VrawErrorKind::Cancelled,
+            VrawError::InvalidOptions(_) => VrawErrorKind::InvalidOptions,
+        }
+    }
+
+    pub(crate) fn input(context: impl Into<String>) -> impl FnOnce(io::Error) -> Self {
+        let context = context.into();
+        move |source| Self::Input { context, source }
+    }
+
+    pub(crate) fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> Self {
+        let context = context.into();
+        move |source| Self::Io { context, source }
+    }
+
+    pub(crate) fn corrupt_header(
+        context: impl Into<String>,
+    ) -> impl FnOnce(Box<dyn Error + Send + Sync>) -> Self {
+        let context = context.into();
+        move |source| Self::CorruptHeader { context, source }
+    }
+
+    pub(crate) fn mux<E: Into<Box<dyn Error + Send + Sync>>>(
+        context: impl Into<String>,
+    ) -> impl FnOnce(E) -> Self {
+        let context = context.into();
+        move |source| Self::Mux {
+            context,
+            source: source.into(),
+        }
+    }
+
+    pub(crate) fn corrupt_index(reason: impl Into<String>) -> Self {
+        Self::CorruptIndex {
+            offset: None,
+            reason: reason.into(),
+            source: None,
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! C interface of the conversion and the probe, built with the ffi feature.
+//! The build script generates its header with cbindgen into OUT_DIR, which is
+//! copied to include/vraw_convert.h when the interface changes. The library
+//! is built as a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Every function catches the panics of the conversion, which must not unwind
+//! into C, and reports them as [`VrawStatus::Panic`]. The message of the last
+//! error of the calling thread is available from
+//! [`vraw_last_error_message`].
+
+use crate::error::{VrawError, VrawErrorKind};
+use crate::parser::Strictness;
+use crate::probe::{probe_with_options, ProbeOptions};
+use crate::processing::{
+    convert_vraw_with_options, Container, ConvertOptions, Progress, ProgressCallback,
+};
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The container is chosen after the recorded format
+pub const VRAW_CONTAINER_AUTO: u32 = 0;
+pub const VRAW_CONTAINER_MP4: u32 = 1;
+pub const VRAW_CONTAINER_ELEMENTARY_STREAM: u32 = 2;
+pub const VRAW_CONTAINER_IMAGE_SEQUENCE: u32 = 3;
+pub const VRAW_CONTAINER_Y4M: u32 = 4;
+pub const VRAW_CONTAINER_FFMPEG: u32 = 5;
+pub const VRAW_CONTAINER_AVI: u32 = 6;
+pub const VRAW_CONTAINER_MATROSKA: u32 = 7;
+pub const VRAW_CONTAINER_DUMP: u32 = 8;
+
+/// Outcome of a call. The codes of failed conversions are the exit codes of
+/// the command line converter.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VrawStatus {
+    Ok = 0,
+    /// A pointer is null where it may not be, or a path is not UTF-8
+    InvalidArgument = 1,
+    /// The options cannot be applied to the recording
+    InvalidOptions = 2,
+    /// The recording cannot be opened, is corrupt or holds no video that can
+    /// be converted
+    BadInput = 3,
+    /// The output cannot be written
+    OutputFailed = 4,
+    /// The progress callback cancelled the conversion
+    Cancelled = 5,
+    /// The converter panicked, which is a bug
+    Panic = 6,
+}
+
+impl From<VrawErrorKind> for VrawStatus {
+    fn from(kind: VrawErrorKind) -> Self {
+        match kind {
+            VrawErrorKind::InvalidOptions => Self::InvalidOptions,
+            VrawErrorKind::BadInput => Self::BadInput,
+            VrawErrorKind::OutputFailed => Self::OutputFailed,
+            VrawErrorKind::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+/// Progress of a conversion, see [`Progress`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct VrawProgress {
+    /// Number of frames read so far, converted or not
+    pub frames_processed: u64,
+    /// Number of frames listed by the index
+    pub total_frames: u64,
+    /// Byte offset in the recording the reading has reached
+    pub bytes_read: u64,
+    /// Receive timestamp of the last frame read, in nanoseconds
+    pub receive_timestamp: i64,
+}
+
+/// Called with the progress of a conversion and the `progress_user_data` of
+/// its options, on the thread converting. Returning false cancels the
+/// conversion.
+pub type VrawProgressCallback =
+    Option<unsafe extern "C" fn(progress: *const VrawProgress, user_data: *mut c_void) -> bool>;
+
+/// Options of [`vraw_convert_file`], see [`ConvertOptions`]. Zeroed options
+/// are the defaults, see [`vraw_default_options`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct VrawConvertOptions {
+    /// One of the `VRAW_CONTAINER_*` constants
+    pub container: u32,
+    /// Also write the Stats frames to a .stats.json file next to the output
+    pub export_stats: bool,
+    /// Place the moov box of .mp4 files before the media data
+    pub faststart: bool,
+    /// Write fragmented .mp4 files with fragments of at least this many
+    /// milliseconds, 0 for unfragmented files
+    pub fragment_duration_ms: u64,
+    /// Fail on any inconsistency of the recording instead of recovering from
+    /// it with a warning
+    pub strict: bool,
+    /// Stop at the first frame that cannot be read instead of skipping it
+    pub stop_at_corrupt_frame: bool,
+    /// Fail instead of replacing an output that exists
+    pub no_overwrite: bool,
+    pub progress: VrawProgressCallback,
+    /// Number of frames between calls of the progress callback, 0 for the
+    /// default
+    pub progress_stride: u64,
+    /// Passed to the progress callback as is
+    pub progress_user_data: *mut c_void,
+}
+
+/// What a recording holds, see [`VrawInfo`](crate::VrawInfo). Timestamps
+/// and durations are in nanoseconds.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct VrawProbeInfo {
+    /// Number of frames listed by the index, Stats frames included
+    pub frame_count: u64,
+    /// Whether the index was missing or damaged and rebuilt from the frames
+    pub index_rebuilt: bool,
+    /// Whether the recording has a header with its start time
+    pub has_recording_start: bool,
+    /// Wall clock time at which the recording started, as unix time in
+    /// seconds
+    pub recording_start: i64,
+    /// Receive timestamp of the first frame
+    pub first_timestamp: i64,
+    /// Receive timestamp of the last frame
+    pub last_timestamp: i64,
+    /// Time between the first and the last frame received
+    pub duration: i64,
+    /// Size of the recording in bytes
+    pub size: u64,
+    /// Bits per second over the duration, 0 for recordings of less than two
+    /// frames
+    pub bitrate: u64,
+    /// Number of source streams of the frames whose header was read
+    pub stream_count: u64,
+    /// Number of frames whose header was read
+    pub frames_probed: u64,
+    /// Number of frames whose header could not be read
+    pub unreadable_frames: u64,
+}
+
+/// The progress callback and its user data, which C hands over to the
+/// converting thread.
+struct CallbackContext {
+    callback: unsafe extern "C" fn(*const VrawProgress, *mut c_void) -> bool,
+    user_data: *mut c_void,
+}
+
+// The caller of vraw_convert_file is responsible for the user data, the
+// callback is only called during the call
+unsafe impl Send for CallbackContext {}
+unsafe impl Sync for CallbackContext {}
+
+impl CallbackContext {
+    /// Calls the callback, which tells whether the conversion goes on.
+    fn call(&self, progress: &VrawProgress) -> bool {
+        // SAFETY: the caller passes a callback taking this user data
+        unsafe { (self.callback)(progress, self.user_data) }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages are paths and errors, which have no nul bytes but on purpose
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Runs `f` with the panics caught, keeping the message of the error for
+/// [`vraw_last_error_message`].
+fn guard(f: impl FnOnce() -> Result<(), (VrawStatus, String)>) -> VrawStatus {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => VrawStatus::Ok,
+        Ok(Err((status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(payload) => {
+            set_last_error(format!(
+                "vraw_convert: panicked: {}",
+                panic_message(payload.as_ref())
+            ));
+            VrawStatus::Panic
+        }
+    }
+}
+
+fn vraw_error(e: VrawError) -> (VrawStatus, String) {
+    (e.kind().into(), e.to_string())
+}
+
+/// Reads a path argument, which may be null if `optional`.
+///
+/// # Safety
+///
+/// `path` must be null or a nul terminated string.
+unsafe fn path_arg(
+    path: *const c_char,
+    name: &str,
+    optional: bool,
+) -> Result<Option<PathBuf>, (VrawStatus, String)> {
+    if path.is_null() {
+        return match optional {
+            true => Ok(None),
+            false => Err((
+                VrawStatus::InvalidArgument,
+                format!("vraw_convert: {name} is null"),
+            )),
+        };
+    }
+
+    CStr::from_ptr(path)
+        .to_str()
+        .map(|path| Some(PathBuf::from(path)))
+        .map_err(|_| {
+            (
+                VrawStatus::InvalidArgument,
+                format!("vraw_convert: {name} is not UTF-8"),
+            )
+        })
+}
+
+fn container(container: u32) -> Result<Option<Container>, (VrawStatus, String)> {
+    Ok(Some(match container {
+        VRAW_CONTAINER_AUTO => return Ok(None),
+        VRAW_CONTAINER_MP4 => Container::Mp4,
+        VRAW_CONTAINER_ELEMENTARY_STREAM => Container::ElementaryStream,
+        VRAW_CONTAINER_IMAGE_SEQUENCE => Container::ImageSequence,
+        VRAW_CONTAINER_Y4M => Container::Y4m,
+        VRAW_CONTAINER_FFMPEG => Container::Ffmpeg,
+        VRAW_CONTAINER_AVI => Container::Avi,
+        VRAW_CONTAINER_MATROSKA => Container::Matroska,
+        VRAW_CONTAINER_DUMP => Container::Dump,
+        _ => {
+            return Err((
+                VrawStatus::InvalidOptions,
+                format!("vraw_convert: unknown container {container}"),
+            ))
+        }
+    }))
+}
+
+impl VrawConvertOptions {
+    fn to_options(self) -> Result<ConvertOptions, (VrawStatus, String)> {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let progress = self.progress.map(|callback| {
+            let context = CallbackContext {
+                callback,
+                user_data: self.progress_user_data,
+            };
+            let cancel = cancel.clone();
+            ProgressCallback::new(move |progress: Progress| {
+                let progress = VrawProgress {
+                    frames_processed: progress.frames_processed as u64,
+                    total_frames: progress.total_frames as u64,
+                    bytes_read: progress.bytes_read,
+                    receive_timestamp: progress.receive_timestamp,
+                };
+                if !context.call(&progress) {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            })
+        });
+
+        Ok(ConvertOptions {
+            container: container(self.container)?,
+            export_stats: self.export_stats,
+            faststart: self.faststart,
+            fragment_duration: (self.fragment_duration_ms > 0)
+                .then(|| Duration::from_millis(self.fragment_duration_ms)),
+            strictness: match self.strict {
+                true => Strictness::Strict,
+                false => Strictness::Lenient,
+            },
+            stop_at_corrupt_frame: self.stop_at_corrupt_frame,
+            no_overwrite: self.no_overwrite,
+            progress,
+            progress_stride: (self.progress_stride > 0).then_some(self.progress_stride as usize),
+            cancel: Some(cancel),
+            ..Default::default()
+        })
+    }
+}
+
+/// The default options of [`vraw_convert_file`].
+#[no_mangle]
+pub extern "C" fn vraw_default_options() -> VrawConvertOptions {
+    VrawConvertOptions {
+        container: VRAW_CONTAINER_AUTO,
+        export_stats: false,
+        faststart: false,
+        fragment_duration_ms: 0,
+        strict: false,
+        stop_at_corrupt_frame: false,
+        no_overwrite: false,
+        progress: None,
+        progress_stride: 0,
+        progress_user_data: ptr::null_mut(),
+    }
+}
+
+/// Converts the recording at `in_path` to `out_path`, see
+/// [`convert_vraw_with_options`]. Without an output path the output is named
+/// after the input and the time of the conversion, without options the
+/// defaults are used.
+///
+/// # Safety
+///
+/// `in_path` must be a nul terminated string, `out_path` and `options` null
+/// or valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn vraw_convert_file(
+    in_path: *const c_char,
+    out_path: *const c_char,
+    options: *const VrawConvertOptions,
+) -> VrawStatus {
+    guard(|| {
+        let input = path_arg(in_path, "in_path", false)?.unwrap_or_default();
+        let output = path_arg(out_path, "out_path", true)?;
+        let options = options
+            .as_ref()
+            .copied()
+            .unwrap_or_else(|| vraw_default_options())
+            .to_options()?;
+
+        convert_vraw_with_options(input, output, &options)
+            .map(|_| ())
+            .map_err(vraw_error)
+    })
+}
+
+/// Tells what the recording at `in_path` holds without converting it, see
+/// [`probe`](crate::probe).
+///
+/// # Safety
+///
+/// `in_path` must be a nul terminated string and `out_info` point to a
+/// writable [`VrawProbeInfo`]. It is left untouched if the probe fails.
+#[no_mangle]
+pub unsafe extern "C" fn vraw_probe(
+    in_path: *const c_char,
+    out_info: *mut VrawProbeInfo,
+) -> VrawStatus {
+    guard(|| {
+        let input = path_arg(in_path, "in_path", false)?.unwrap_or_default();
+        if out_info.is_null() {
+            return Err((
+                VrawStatus::InvalidArgument,
+                "vraw_convert: out_info is null".into(),
+            ));
+        }
+
+        let info = probe_with_options(input, &ProbeOptions::default()).map_err(vraw_error)?;
+        *out_info = VrawProbeInfo {
+            frame_count: info.frame_count as u64,
+            index_rebuilt: info.index_rebuilt,
+            has_recording_start: info.recording_start.is_some(),
+            recording_start: info.recording_start.map_or(0, |start| start.timestamp()),
+            first_timestamp: info.first_timestamp.unwrap_or(0),
+            last_timestamp: info.last_timestamp.unwrap_or(0),
+            duration: info.duration,
+            size: info.size,
+            bitrate: info.bitrate.unwrap_or(0),
+            stream_count: info.stream_ids.len() as u64,
+            frames_probed: info.frames_probed as u64,
+            unreadable_frames: info.unreadable_frames as u64,
+        };
+
+        Ok(())
+    })
+}
+
+/// The message of the last failed call of the calling thread, null if the
+/// last call succeeded. It stays valid until the next call of the thread.
+#[no_mangle]
+pub extern "C" fn vraw_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
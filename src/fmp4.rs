@@ -0,0 +1,225 @@
+//! Writer for fragmented MP4 files: an init segment (ftyp and moov without
+//! samples) followed by moof/mdat fragments, as required by MSE based players.
+
+use crate::isobmff::Mp4Box;
+use mp4::{Mp4Config, Mp4Sample, Mp4Writer, TrackConfig};
+use std::io::{self, Cursor, Write};
+
+const TRACK_ID: u32 = 1;
+
+const TFHD_DEFAULT_BASE_IS_MOOF: u32 = 0x020000;
+const TRUN_DATA_OFFSET_PRESENT: u32 = 0x000001;
+const TRUN_SAMPLE_DURATION_PRESENT: u32 = 0x000100;
+const TRUN_SAMPLE_SIZE_PRESENT: u32 = 0x000200;
+const TRUN_SAMPLE_FLAGS_PRESENT: u32 = 0x000400;
+const TRUN_SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT: u32 = 0x000800;
+
+/// sample_depends_on = 2, the sample does not depend on others
+const SYNC_SAMPLE_FLAGS: u32 = 0x0200_0000;
+/// sample_depends_on = 1 and sample_is_non_sync_sample
+const NON_SYNC_SAMPLE_FLAGS: u32 = 0x0101_0000;
+
+/// Builds the init segment of a single track. The ftyp and the moov are taken
+/// from the mp4 crate writing a file without samples, and the moov gets an mvex
+/// box announcing the fragments. The moov is patched like the moov of regular
+/// files before it is written.
+pub fn init_segment<F>(config: &Mp4Config, track: &TrackConfig, patch: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&mut Mp4Box) -> Result<(), String>,
+{
+    let mut mp4_writer = Mp4Writer::write_start(Cursor::new(Vec::new()), config)
+        .map_err(|e| format!("failed to start init segment: {e}"))?;
+    mp4_writer
+        .add_track(track)
+        .map_err(|e| format!("failed to add track to init segment: {e}"))?;
+    mp4_writer
+        .write_end()
+        .map_err(|e| format!("failed to end init segment: {e}"))?;
+
+    let bytes = mp4_writer.into_writer().into_inner();
+
+    let mut boxes = Mp4Box::parse(&bytes)?;
+    let moov = boxes
+        .iter_mut()
+        .find(|mp4_box| &mp4_box.kind == b"moov")
+        .ok_or("init segment contains no moov box")?;
+
+    patch(moov)?;
+
+    let mut trex = full_box_header(0, 0);
+    trex.extend_from_slice(&TRACK_ID.to_be_bytes());
+    trex.extend_from_slice(&1u32.to_be_bytes()); // default_sample_description_index
+    trex.extend_from_slice(&[0; 12]); // default duration, size and flags
+
+    let mut mvex = Mp4Box::new(b"mvex", Vec::new());
+    mvex.children.push(Mp4Box::new(b"trex", trex));
+    moov.children.push(mvex);
+
+    // the mdat is empty
+    let mut res = Vec::new();
+    for mp4_box in boxes
+        .iter()
+        .filter(|mp4_box| matches!(&mp4_box.kind, b"ftyp" | b"moov"))
+    {
+        mp4_box.write(&mut res);
+    }
+
+    Ok(res)
+}
+
+/// Writes samples as fragments of at least the given duration, in the
+/// timescale of the track.
+pub struct FragmentedMp4Writer<W: Write> {
+    writer: W,
+    fragment_duration: u64,
+    sequence_number: u32,
+    base_media_decode_time: u64,
+    /// Duration, size, sync flag and composition offset of the samples of the
+    /// current fragment
+    samples: Vec<(u32, u32, bool, i32)>,
+    data: Vec<u8>,
+    pending_duration: u64,
+}
+
+impl<W: Write> FragmentedMp4Writer<W> {
+    pub fn new(mut writer: W, init_segment: &[u8], fragment_duration: u64) -> io::Result<Self> {
+        writer.write_all(init_segment)?;
+
+        Ok(Self {
+            writer,
+            fragment_duration,
+            sequence_number: 1,
+            base_media_decode_time: 0,
+            samples: Vec::new(),
+            data: Vec::new(),
+            pending_duration: 0,
+        })
+    }
+
+    /// Adds a sample to the current fragment, a new fragment is started once the
+    /// current one lasts the fragment duration.
+    pub fn write_sample(&mut self, sample: &Mp4Sample) -> io::Result<()> {
+        if !self.samples.is_empty() && self.pending_duration >= self.fragment_duration {
+            self.write_fragment()?;
+        }
+
+        let size = u32::try_from(sample.bytes.len())
+            .map_err(|_| io::Error::other("sample larger than 4 GB"))?;
+
+        self.samples.push((
+            sample.duration,
+            size,
+            sample.is_sync,
+            sample.rendering_offset,
+        ));
+        self.data.extend_from_slice(&sample.bytes);
+        self.pending_duration += sample.duration as u64;
+
+        Ok(())
+    }
+
+    /// Writes the last fragment.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.samples.is_empty() {
+            self.write_fragment()?;
+        }
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_fragment(&mut self) -> io::Result<()> {
+        let mut mfhd = full_box_header(0, 0);
+        mfhd.extend_from_slice(&self.sequence_number.to_be_bytes());
+
+        let mut tfhd = full_box_header(0, TFHD_DEFAULT_BASE_IS_MOOF);
+        tfhd.extend_from_slice(&TRACK_ID.to_be_bytes());
+
+        let mut tfdt = full_box_header(1, 0);
+        tfdt.extend_from_slice(&self.base_media_decode_time.to_be_bytes());
+
+        let has_offsets = self.samples.iter().any(|sample| sample.3 != 0);
+        let mut trun_flags = TRUN_DATA_OFFSET_PRESENT
+            | TRUN_SAMPLE_DURATION_PRESENT
+            | TRUN_SAMPLE_SIZE_PRESENT
+            | TRUN_SAMPLE_FLAGS_PRESENT;
+        if has_offsets {
+            trun_flags |= TRUN_SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT;
+        }
+
+        // version 1 has signed composition offsets
+        let mut trun = full_box_header(has_offsets as u8, trun_flags);
+        trun.extend_from_slice(&(self.samples.len() as u32).to_be_bytes());
+        let data_offset_position = trun.len();
+        trun.extend_from_slice(&0i32.to_be_bytes()); // data_offset, set below
+        for (duration, size, is_sync, composition_offset) in &self.samples {
+            let flags = if *is_sync {
+                SYNC_SAMPLE_FLAGS
+            } else {
+                NON_SYNC_SAMPLE_FLAGS
+            };
+            trun.extend_from_slice(&duration.to_be_bytes());
+            trun.extend_from_slice(&size.to_be_bytes());
+            trun.extend_from_slice(&flags.to_be_bytes());
+            if has_offsets {
+                trun.extend_from_slice(&composition_offset.to_be_bytes());
+            }
+        }
+
+        let mut traf = Mp4Box::new(b"traf", Vec::new());
+        traf.children = vec![
+            Mp4Box::new(b"tfhd", tfhd),
+            Mp4Box::new(b"tfdt", tfdt),
+            Mp4Box::new(b"trun", trun),
+        ];
+
+        let mut moof = Mp4Box::new(b"moof", Vec::new());
+        moof.children = vec![Mp4Box::new(b"mfhd", mfhd), traf];
+
+        let mdat_header = box_header(b"mdat", self.data.len() as u64);
+
+        // The samples start right after the mdat header
+        let data_offset = i32::try_from(moof.size() + mdat_header.len() as u64)
+            .map_err(|_| io::Error::other("moof too large"))?;
+        let trun = &mut moof.children[1].children[2].data;
+        trun[data_offset_position..data_offset_position + 4]
+            .copy_from_slice(&data_offset.to_be_bytes());
+
+        let mut bytes = Vec::with_capacity(moof.size() as usize + mdat_header.len());
+        moof.write(&mut bytes);
+        bytes.extend_from_slice(&mdat_header);
+
+        self.writer.write_all(&bytes)?;
+        self.writer.write_all(&self.data)?;
+
+        self.sequence_number += 1;
+        self.base_media_decode_time += self.pending_duration;
+        self.pending_duration = 0;
+        self.samples.clear();
+        self.data.clear();
+
+        Ok(())
+    }
+}
+
+fn full_box_header(version: u8, flags: u32) -> Vec<u8> {
+    let mut res = flags.to_be_bytes();
+    res[0] = version;
+    res.to_vec()
+}
+
+/// Header of a box with the given content size.
+fn box_header(kind: &[u8; 4], content_size: u64) -> Vec<u8> {
+    let mut res = Vec::with_capacity(16);
+
+    if content_size + 8 > u32::MAX as u64 {
+        res.extend_from_slice(&1u32.to_be_bytes());
+        res.extend_from_slice(kind);
+        res.extend_from_slice(&(content_size + 16).to_be_bytes());
+    } else {
+        res.extend_from_slice(&(content_size as u32 + 8).to_be_bytes());
+        res.extend_from_slice(kind);
+    }
+
+    res
+}
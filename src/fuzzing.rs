@@ -0,0 +1,42 @@
+//! Entry points of the fuzz targets in fuzz/, reading recordings from memory.
+//! Not part of the API.
+
+use crate::parser::{self, FrameStream, ParseOptions, Strictness};
+use std::io::Cursor;
+
+/// Reads the index of a recording, strictly and leniently, and checks its
+/// entries.
+pub fn read_index(data: &[u8]) {
+    let mut f = Cursor::new(data);
+    for strictness in [Strictness::Strict, Strictness::Lenient] {
+        let options = ParseOptions {
+            strictness,
+            ..Default::default()
+        };
+        if let Ok(index) = parser::read_index(&mut f, &options) {
+            let first_frame_offset = parser::first_frame_offset(&mut f);
+            parser::find_invalid_index_entries(&index.entries, first_frame_offset, data.len() as _);
+        }
+    }
+}
+
+/// Parses every frame of a recording, as listed by its index or found by
+/// scanning it, and as read front to back.
+pub fn parse_frames(data: &[u8]) {
+    let options = ParseOptions::default();
+    let mut f = Cursor::new(data);
+    let entries = match parser::read_index(&mut f, &options) {
+        Ok(index) => index.entries,
+        Err(_) => parser::scan_index(&mut f)
+            .map(|index| index.entries)
+            .unwrap_or_default(),
+    };
+    for entry in &entries {
+        let _ = parser::parse_frame_metadata(&mut f, entry);
+        let _ = parser::parse_raw_frame(&mut f, entry, &options);
+    }
+
+    if let Ok(stream) = FrameStream::new(data, options) {
+        stream.for_each(drop);
+    }
+}
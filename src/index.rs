@@ -0,0 +1,68 @@
+//! Reading the index of a recording and the frames it lists directly, for
+//! tools working on the file itself. The errors are those of the conversion.
+
+use crate::error::VrawError;
+use crate::parser::{
+    self, FrameInfo, FrameMetadata, FrameRef, ParseOptions, RecordingIndex, RecordingIndexEntry,
+    RecordingReader,
+};
+use std::error::Error;
+
+/// Reads the index at the end of a recording, in the order it was written.
+/// Recordings cut off before their index was written have none, see
+/// [`VrawReader`](crate::VrawReader), which rebuilds it from the frames.
+pub fn read_index(
+    f: &mut impl RecordingReader,
+    options: &ParseOptions,
+) -> Result<RecordingIndex, VrawError> {
+    parser::read_index(f, options).map_err(|e| VrawError::CorruptIndex {
+        offset: None,
+        reason: format!("failed to read index: {e}"),
+        source: Some(e),
+    })
+}
+
+/// Reads and validates the header of the frame of an index entry, without
+/// reading its payload. The file is left at the start of the payload.
+pub fn parse_frame_metadata(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+) -> Result<FrameMetadata, VrawError> {
+    parser::parse_frame_metadata(f, entry).map_err(unreadable_frame(entry))
+}
+
+/// Reads a frame in full. The video placement footer is searched for followed
+/// by up to [`ParseOptions::max_placement_padding`] bytes of alignment data.
+pub fn parse_raw_frame(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+) -> Result<FrameInfo, VrawError> {
+    parser::parse_raw_frame(f, entry, options).map_err(unreadable_frame(entry))
+}
+
+/// Reads a frame like [`parse_raw_frame`], into a buffer reused for every
+/// frame instead of allocating its payload. The frame borrows its payload from
+/// the buffer.
+pub fn read_frame_into<'a>(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+    buf: &'a mut Vec<u8>,
+) -> Result<FrameRef<'a>, VrawError> {
+    parser::read_frame_into(f, entry, options, buf).map_err(unreadable_frame(entry))
+}
+
+/// Error of a frame that cannot be read from the offset of its index entry.
+/// Its position in the index is not known here, unlike for the
+/// [`VrawError::BadFrame`] of the frames read by a reader.
+fn unreadable_frame(
+    entry: &RecordingIndexEntry,
+) -> impl FnOnce(Box<dyn Error + Send + Sync>) -> VrawError {
+    let offset = entry.offset();
+    move |e| VrawError::CorruptIndex {
+        offset: Some(offset),
+        reason: format!("failed to read frame: {e}"),
+        source: Some(e),
+    }
+}
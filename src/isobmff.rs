@@ -0,0 +1,603 @@
+//! Minimal ISO base media file format box handling, used to adjust the moov box
+//! written by the mp4 crate where the crate itself lacks support.
+
+use crate::bitstream::ColourDescription;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const HEADER_SIZE: u64 = 8;
+const LARGE_HEADER_SIZE: u64 = 16;
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mp4Box {
+    pub kind: [u8; 4],
+    /// The box content preceding any children (all of it for leaf boxes)
+    pub data: Vec<u8>,
+    pub children: Vec<Mp4Box>,
+}
+
+/// Size of the fields preceding the children for box types that contain other
+/// boxes, or None for leaf boxes.
+fn container_header_size(kind: &[u8; 4]) -> Option<usize> {
+    match kind {
+        b"moov" | b"trak" | b"mdia" | b"minf" | b"stbl" | b"dinf" | b"edts" | b"udta" | b"mvex"
+        | b"moof" | b"traf" => Some(0),
+        b"stsd" => Some(8),
+        b"avc1" | b"hev1" | b"hvc1" | b"jpeg" => Some(VISUAL_SAMPLE_ENTRY_SIZE),
+        _ => None,
+    }
+}
+
+impl Mp4Box {
+    pub fn new(kind: &[u8; 4], data: Vec<u8>) -> Self {
+        Self {
+            kind: *kind,
+            data,
+            children: Vec::new(),
+        }
+    }
+
+    /// Parses a sequence of consecutive boxes.
+    pub fn parse(mut bytes: &[u8]) -> Result<Vec<Mp4Box>, String> {
+        let mut boxes = Vec::new();
+
+        while !bytes.is_empty() {
+            if (bytes.len() as u64) < HEADER_SIZE {
+                return Err("truncated box header".into());
+            }
+
+            let size = u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as u64;
+            let kind: [u8; 4] = bytes[4..8].try_into().unwrap();
+
+            let (header_size, size) = match size {
+                0 => (HEADER_SIZE, bytes.len() as u64),
+                1 if bytes.len() as u64 >= LARGE_HEADER_SIZE => (
+                    LARGE_HEADER_SIZE,
+                    u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+                ),
+                1 => return Err("truncated box header".into()),
+                size => (HEADER_SIZE, size),
+            };
+
+            if size < header_size || size > bytes.len() as u64 {
+                return Err(format!(
+                    "invalid size {} of box {}",
+                    size,
+                    String::from_utf8_lossy(&kind)
+                ));
+            }
+
+            let content = &bytes[header_size as usize..size as usize];
+
+            let mp4_box = match container_header_size(&kind) {
+                Some(prefix) if prefix <= content.len() => Mp4Box {
+                    kind,
+                    data: content[..prefix].to_vec(),
+                    children: Mp4Box::parse(&content[prefix..])?,
+                },
+                _ => Mp4Box::new(&kind, content.to_vec()),
+            };
+
+            boxes.push(mp4_box);
+            bytes = &bytes[size as usize..];
+        }
+
+        Ok(boxes)
+    }
+
+    pub fn size(&self) -> u64 {
+        let content_size =
+            self.data.len() as u64 + self.children.iter().map(|child| child.size()).sum::<u64>();
+
+        if content_size + HEADER_SIZE > u32::MAX as u64 {
+            content_size + LARGE_HEADER_SIZE
+        } else {
+            content_size + HEADER_SIZE
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let size = self.size();
+
+        if size > u32::MAX as u64 {
+            out.extend_from_slice(&1u32.to_be_bytes());
+            out.extend_from_slice(&self.kind);
+            out.extend_from_slice(&size.to_be_bytes());
+        } else {
+            out.extend_from_slice(&(size as u32).to_be_bytes());
+            out.extend_from_slice(&self.kind);
+        }
+
+        out.extend_from_slice(&self.data);
+
+        for child in &self.children {
+            child.write(out);
+        }
+    }
+
+    pub fn child_mut(&mut self, kind: &[u8; 4]) -> Option<&mut Mp4Box> {
+        self.children.iter_mut().find(|child| &child.kind == kind)
+    }
+
+    /// Follows a path of box types below this box, taking the first match at
+    /// every level.
+    pub fn find_mut(&mut self, path: &[&[u8; 4]]) -> Option<&mut Mp4Box> {
+        path.iter()
+            .try_fold(self, |current, kind| current.child_mut(kind))
+    }
+}
+
+/// A VisualSampleEntry (ISO/IEC 14496-12 12.1.3) without any child boxes.
+pub fn visual_sample_entry(
+    kind: &[u8; 4],
+    width: u16,
+    height: u16,
+    compressor_name: &str,
+) -> Mp4Box {
+    let mut data = Vec::with_capacity(VISUAL_SAMPLE_ENTRY_SIZE);
+    data.extend_from_slice(&[0; 6]); // reserved
+    data.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+    data.extend_from_slice(&[0; 16]); // pre_defined, reserved
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&0x0048_0000u32.to_be_bytes()); // horizresolution, 72 dpi
+    data.extend_from_slice(&0x0048_0000u32.to_be_bytes()); // vertresolution, 72 dpi
+    data.extend_from_slice(&[0; 4]); // reserved
+    data.extend_from_slice(&1u16.to_be_bytes()); // frame_count
+
+    let mut name = [0u8; 32];
+    let name_len = compressor_name.len().min(31);
+    name[0] = name_len as u8;
+    name[1..=name_len].copy_from_slice(&compressor_name.as_bytes()[..name_len]);
+    data.extend_from_slice(&name);
+
+    data.extend_from_slice(&0x0018u16.to_be_bytes()); // depth
+    data.extend_from_slice(&(-1i16).to_be_bytes()); // pre_defined
+
+    Mp4Box::new(kind, data)
+}
+
+/// Builds a colr box with an nclx colour type (ISO/IEC 14496-12 12.1.5), which
+/// follows the decoder configuration in a visual sample entry.
+pub fn colour_information(colour: &ColourDescription) -> Mp4Box {
+    let mut data = Vec::with_capacity(11);
+    data.extend_from_slice(b"nclx");
+    data.extend_from_slice(&(colour.colour_primaries as u16).to_be_bytes());
+    data.extend_from_slice(&(colour.transfer_characteristics as u16).to_be_bytes());
+    data.extend_from_slice(&(colour.matrix_coefficients as u16).to_be_bytes());
+    data.push((colour.full_range as u8) << 7);
+
+    Mp4Box::new(b"colr", data)
+}
+
+/// Replaces the sample entries of the first track of a moov box.
+pub fn replace_sample_entry(moov: &mut Mp4Box, sample_entry: Mp4Box) -> Result<(), String> {
+    let stsd = moov
+        .find_mut(&[b"trak", b"mdia", b"minf", b"stbl", b"stsd"])
+        .ok_or("missing stsd box")?;
+
+    stsd.children = vec![sample_entry];
+
+    Ok(())
+}
+
+/// Seconds from 1904-01-01, the epoch of ISO base media file times, to
+/// 1970-01-01.
+const UNIX_EPOCH_IN_MP4_TIME: u64 = 2_082_844_800;
+
+/// Sets the creation and modification times of the movie and of every track
+/// and media header, given in seconds since the unix epoch.
+pub fn set_creation_time(moov: &mut Mp4Box, unix_time: u64) -> Result<(), String> {
+    let time = unix_time + UNIX_EPOCH_IN_MP4_TIME;
+
+    let set_time = |header: &mut Mp4Box| -> Result<(), String> {
+        // version and flags, then both times, 64 bit in version 1
+        match header.data.first() {
+            Some(1) if header.data.len() >= 20 => {
+                header.data[4..12].copy_from_slice(&time.to_be_bytes());
+                header.data[12..20].copy_from_slice(&time.to_be_bytes());
+            }
+            Some(0) if header.data.len() >= 12 => {
+                let time = u32::try_from(time).map_err(|_| "creation time exceeds 32 bits")?;
+                header.data[4..8].copy_from_slice(&time.to_be_bytes());
+                header.data[8..12].copy_from_slice(&time.to_be_bytes());
+            }
+            _ => {
+                return Err(format!(
+                    "invalid {} box",
+                    String::from_utf8_lossy(&header.kind)
+                ))
+            }
+        }
+        Ok(())
+    };
+
+    set_time(moov.child_mut(b"mvhd").ok_or("missing mvhd box")?)?;
+
+    for trak in moov
+        .children
+        .iter_mut()
+        .filter(|child| &child.kind == b"trak")
+    {
+        set_time(trak.child_mut(b"tkhd").ok_or("missing tkhd box")?)?;
+        set_time(
+            trak.find_mut(&[b"mdia", b"mdhd"])
+                .ok_or("missing mdhd box")?,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Sets the transformation matrix of the first track to rotate the video
+/// clockwise by 90, 180 or 270 degrees when displayed.
+pub fn set_rotation(moov: &mut Mp4Box, degrees: u16) -> Result<(), String> {
+    let tkhd = moov
+        .find_mut(&[b"trak", b"tkhd"])
+        .ok_or("missing tkhd box")?;
+
+    // the matrix follows the times, track id and duration, which are 64 bit
+    // in version 1, and is followed by the width and height
+    let matrix_start = match tkhd.data.first() {
+        Some(1) => 52,
+        Some(0) => 40,
+        _ => return Err("invalid tkhd box".into()),
+    };
+    if tkhd.data.len() < matrix_start + 36 + 8 {
+        return Err("invalid tkhd box".into());
+    }
+
+    // width and height are 16.16 fixed point, as are the matrix entries
+    // except u, v and w, which are 2.30
+    let size = &tkhd.data[matrix_start + 36..];
+    let width = u32::from_be_bytes(size[0..4].try_into().unwrap()) as i32;
+    let height = u32::from_be_bytes(size[4..8].try_into().unwrap()) as i32;
+    let one = 0x10000;
+
+    let (a, b, c, d, x, y) = match degrees {
+        90 => (0, one, -one, 0, height, 0),
+        180 => (-one, 0, 0, -one, width, height),
+        270 => (0, -one, one, 0, 0, width),
+        _ => (one, 0, 0, one, 0, 0),
+    };
+
+    let matrix = [a, b, 0, c, d, 0, x, y, 0x4000_0000];
+    for (i, value) in matrix.iter().enumerate() {
+        let start = matrix_start + 4 * i;
+        tkhd.data[start..start + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    Ok(())
+}
+
+/// Adds an edit list to the first track that skips the media before the given
+/// time in the track timescale, so presentation starts at zero. The edit lasts
+/// as long as the movie, or the whole media when the movie has no duration, as
+/// is the case for fragmented files.
+pub fn set_edit_list(moov: &mut Mp4Box, media_time: u64) -> Result<(), String> {
+    // the duration follows the times and the timescale, 64 bit in version 1
+    let mvhd = moov.child_mut(b"mvhd").ok_or("missing mvhd box")?;
+    let movie_duration = match mvhd.data.first() {
+        Some(1) if mvhd.data.len() >= 32 => {
+            u64::from_be_bytes(mvhd.data[24..32].try_into().unwrap())
+        }
+        Some(0) if mvhd.data.len() >= 20 => {
+            u32::from_be_bytes(mvhd.data[16..20].try_into().unwrap()) as u64
+        }
+        _ => return Err("invalid mvhd box".into()),
+    };
+    let segment_duration = movie_duration.saturating_sub(media_time);
+
+    let mut elst = Vec::with_capacity(28);
+    match (u32::try_from(segment_duration), i32::try_from(media_time)) {
+        (Ok(segment_duration), Ok(media_time)) => {
+            elst.extend_from_slice(&[0; 4]); // version 0
+            elst.extend_from_slice(&1u32.to_be_bytes());
+            elst.extend_from_slice(&segment_duration.to_be_bytes());
+            elst.extend_from_slice(&media_time.to_be_bytes());
+        }
+        _ => {
+            elst.extend_from_slice(&[1, 0, 0, 0]);
+            elst.extend_from_slice(&1u32.to_be_bytes());
+            elst.extend_from_slice(&segment_duration.to_be_bytes());
+            elst.extend_from_slice(&(media_time as i64).to_be_bytes());
+        }
+    }
+    elst.extend_from_slice(&1i16.to_be_bytes()); // media rate
+    elst.extend_from_slice(&0i16.to_be_bytes());
+
+    let mut edts = Mp4Box::new(b"edts", Vec::new());
+    edts.children.push(Mp4Box::new(b"elst", elst));
+
+    let trak = moov.child_mut(b"trak").ok_or("missing trak box")?;
+    trak.children.retain(|child| &child.kind != b"edts");
+    // the edts follows the tkhd
+    let position = trak
+        .children
+        .iter()
+        .position(|child| &child.kind == b"tkhd")
+        .map_or(0, |index| index + 1);
+    trak.children.insert(position, edts);
+
+    Ok(())
+}
+
+/// Adds Nero chapters (a chpl box in the udta of the moov), given as their
+/// start in nanoseconds and their title. Titles are cut to 255 bytes and
+/// chapters beyond the 255th are left out.
+pub fn set_chapters(moov: &mut Mp4Box, chapters: &[(i64, String)]) -> Result<(), String> {
+    let chapters = &chapters[..chapters.len().min(u8::MAX as usize)];
+
+    let mut chpl = vec![1, 0, 0, 0]; // version 1
+    chpl.extend_from_slice(&[0; 4]); // reserved
+    chpl.push(chapters.len() as u8);
+    for (start, title) in chapters {
+        // the start is in units of 100 nanoseconds
+        chpl.extend_from_slice(&((*start).max(0) / 100).to_be_bytes());
+        let title = &title.as_bytes()[..title.len().min(u8::MAX as usize)];
+        chpl.push(title.len() as u8);
+        chpl.extend_from_slice(title);
+    }
+
+    if moov.child_mut(b"udta").is_none() {
+        moov.children.push(Mp4Box::new(b"udta", Vec::new()));
+    }
+    let udta = moov.child_mut(b"udta").ok_or("missing udta box")?;
+    udta.children.retain(|child| &child.kind != b"chpl");
+    udta.children.push(Mp4Box::new(b"chpl", chpl));
+
+    Ok(())
+}
+
+/// A writer for the mp4 crate that holds back the moov box it writes last, to
+/// be patched before it is written. The mp4 crate writes the moov box after
+/// seeking back to the mdat header to write its size, so everything written
+/// from the end of the output at that point on is the moov box. Resizing it
+/// leaves all chunk offsets valid.
+pub struct MoovWriter<W> {
+    writer: W,
+    position: u64,
+    /// End of the output written to the writer
+    end: u64,
+    /// Where the moov box starts, once the writer seeked back from the end
+    moov_offset: Option<u64>,
+    moov: Vec<u8>,
+}
+
+impl<W: Write + Seek> MoovWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let position = writer.stream_position()?;
+        Ok(Self {
+            writer,
+            position,
+            end: position,
+            moov_offset: None,
+            moov: Vec::new(),
+        })
+    }
+
+    /// Patches the moov box held back and writes it, returning the writer.
+    pub fn finish<F>(mut self, patch: F) -> Result<W, String>
+    where
+        F: FnOnce(&mut Mp4Box) -> Result<(), String>,
+    {
+        let (moov_offset, mut moov) = match (self.moov_offset, Mp4Box::parse(&self.moov)?.pop()) {
+            (Some(moov_offset), Some(moov)) if &moov.kind == b"moov" => (moov_offset, moov),
+            _ => return Err("mp4 contains no moov box".into()),
+        };
+
+        patch(&mut moov)?;
+
+        let mut patched = Vec::with_capacity(self.moov.len());
+        moov.write(&mut patched);
+
+        self.writer
+            .seek(SeekFrom::Start(moov_offset))
+            .and_then(|_| self.writer.write_all(&patched))
+            .map_err(|e| format!("failed to write patched moov: {e}"))?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> Write for MoovWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.moov_offset {
+            Some(moov_offset) if self.position >= moov_offset => {
+                let start = (self.position - moov_offset) as usize;
+                if self.moov.len() < start + buf.len() {
+                    self.moov.resize(start + buf.len(), 0);
+                }
+                self.moov[start..start + buf.len()].copy_from_slice(buf);
+                self.position += buf.len() as u64;
+                Ok(buf.len())
+            }
+            moov_offset => {
+                // Writes before the moov box do not reach into it
+                let len = moov_offset.map_or(buf.len() as u64, |moov_offset| {
+                    (moov_offset - self.position).min(buf.len() as u64)
+                });
+                let written = self.writer.write(&buf[..len as usize])?;
+                self.position += written as u64;
+                self.end = self.end.max(self.position);
+                Ok(written)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Seek for MoovWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let moov_end = self
+            .moov_offset
+            .map_or(self.end, |moov_offset| moov_offset + self.moov.len() as u64);
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => moov_end.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+
+        if position < self.end && self.moov_offset.is_none() {
+            self.moov_offset = Some(self.end);
+        }
+        if self
+            .moov_offset
+            .is_none_or(|moov_offset| position < moov_offset)
+        {
+            self.writer.seek(SeekFrom::Start(position))?;
+        }
+
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// Offset and size of every top level box of a file.
+fn top_level_boxes(file: &mut File) -> Result<Vec<([u8; 4], u64, u64)>, String> {
+    let file_len = file
+        .metadata()
+        .map_err(|e| format!("failed to read mp4 metadata: {e}"))?
+        .len();
+
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+
+    while offset + HEADER_SIZE <= file_len {
+        let mut header = [0u8; LARGE_HEADER_SIZE as usize];
+        let header_len = (file_len - offset).min(LARGE_HEADER_SIZE) as usize;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut header[..header_len]))
+            .map_err(|e| format!("failed to read mp4 box header: {e}"))?;
+
+        let size = match u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64 {
+            0 => file_len - offset,
+            1 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            size => size,
+        };
+
+        if size < HEADER_SIZE || offset + size > file_len {
+            return Err("invalid mp4 box size".into());
+        }
+
+        boxes.push((header[4..8].try_into().unwrap(), offset, size));
+        offset += size;
+    }
+
+    Ok(boxes)
+}
+
+/// Adds a shift to the chunk offsets of every track.
+fn shift_chunk_offsets(moov: &mut Mp4Box, shift: u64) -> Result<(), String> {
+    for trak in moov
+        .children
+        .iter_mut()
+        .filter(|child| &child.kind == b"trak")
+    {
+        let stbl = trak
+            .find_mut(&[b"mdia", b"minf", b"stbl"])
+            .ok_or("missing stbl box")?;
+
+        for table in stbl.children.iter_mut() {
+            let entry_size = match &table.kind {
+                b"stco" => 4,
+                b"co64" => 8,
+                _ => continue,
+            };
+
+            // version and flags, entry count
+            for entry in table
+                .data
+                .get_mut(8..)
+                .unwrap_or_default()
+                .chunks_exact_mut(entry_size)
+            {
+                if entry_size == 4 {
+                    let offset = u32::from_be_bytes(entry.try_into().unwrap()) as u64 + shift;
+                    let offset = u32::try_from(offset)
+                        .map_err(|_| "chunk offset exceeds 32 bits after moving the moov")?;
+                    entry.copy_from_slice(&offset.to_be_bytes());
+                } else {
+                    let offset = u64::from_be_bytes(entry.try_into().unwrap()) + shift;
+                    entry.copy_from_slice(&offset.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the moov box in front of the mdat box so that players can start
+/// playback before the whole file is downloaded. The file is rewritten through
+/// a temporary file next to it.
+pub fn move_moov_to_front<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    let path = path.as_ref();
+    let mut file = File::open(path).map_err(|e| format!("failed to open mp4: {e}"))?;
+    let boxes = top_level_boxes(&mut file)?;
+
+    let moov_index = boxes
+        .iter()
+        .position(|(kind, _, _)| kind == b"moov")
+        .ok_or("mp4 contains no moov box")?;
+    let first_mdat_index = boxes
+        .iter()
+        .position(|(kind, _, _)| kind == b"mdat")
+        .ok_or("mp4 contains no mdat box")?;
+
+    if moov_index < first_mdat_index {
+        return Ok(());
+    }
+
+    let (_, moov_offset, moov_size) = boxes[moov_index];
+    let mut moov_bytes = vec![0; moov_size as usize];
+    file.seek(SeekFrom::Start(moov_offset))
+        .and_then(|_| file.read_exact(&mut moov_bytes))
+        .map_err(|e| format!("failed to read moov: {e}"))?;
+
+    let mut moov = Mp4Box::parse(&moov_bytes)?
+        .pop()
+        .ok_or("failed to parse moov")?;
+
+    // Everything from the first mdat on moves back by the size of the moov
+    let moov_size = moov.size();
+    shift_chunk_offsets(&mut moov, moov_size)?;
+
+    let mut patched = Vec::with_capacity(moov_bytes.len());
+    moov.write(&mut patched);
+
+    let temp_path = path.with_extension("faststart.tmp");
+    let copy = |file: &mut File| -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+
+        for (index, (_, offset, size)) in boxes.iter().enumerate() {
+            if index == first_mdat_index {
+                writer.write_all(&patched)?;
+            }
+            if index != moov_index {
+                file.seek(SeekFrom::Start(*offset))?;
+                io::copy(&mut (&mut *file).take(*size), &mut writer)?;
+            }
+        }
+
+        writer.flush()
+    };
+
+    let copied = copy(&mut file);
+    drop(file);
+
+    copied
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!("failed to move moov to the front: {e}")
+        })
+}
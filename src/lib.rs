@@ -1,21 +1,3588 @@
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod avi;
+mod bitstream;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fmp4;
+#[doc(hidden)]
+pub mod fuzzing;
+mod index;
+mod isobmff;
+mod matroska;
 mod parser;
+mod probe;
 mod processing;
+#[cfg(feature = "python")]
+mod python;
+mod rawvideo;
+mod reader;
+mod verify;
 
-pub use processing::convert_vraw_to_mp4;
+#[cfg(feature = "tokio")]
+pub use asynchronous::{convert_vraw_async, convert_vraw_from_async_reader};
+pub use error::{VrawError, VrawErrorKind};
+pub use index::{parse_frame_metadata, parse_raw_frame, read_frame_into, read_index};
+pub use parser::{
+    FrameInfo, FrameMetadata, FrameRef, ParseOptions, RecordingIndex, RecordingIndexEntry,
+    RecordingMetadata, RecordingReader, Strictness, VideoCaptureFormat,
+    DEFAULT_MAX_PLACEMENT_PADDING, FRAME_HEADER_LEN,
+};
+pub use probe::{probe, probe_with_options, ProbeOptions, VrawInfo, PROBE_FRAMES};
+pub use processing::{
+    convert_vraw, convert_vraw_bytes, convert_vraw_from_reader, convert_vraw_to_vec,
+    convert_vraw_to_writer, convert_vraw_with, convert_vraw_with_options, export_stats,
+    recording_start, ColourSpace, Container, ConversionSummary, ConvertOptions, Gap,
+    HevcSampleEntry, IndexOrder, Progress, ProgressCallback, ResolutionChange, Rotation,
+    TimestampRegressions, TimestampSource, DEFAULT_GAP_THRESHOLD, DEFAULT_MP4_TIMESCALE,
+    DEFAULT_PROGRESS_STRIDE, SUPPORTED_FORMATS, TIMESTAMP_SEI_UUID,
+};
+pub use rawvideo::BayerPattern;
+pub use reader::{SeekPolicy, VrawFrames, VrawReader};
+pub use verify::{verify_vraw, FrameProblem, VerifyReport};
 
 #[cfg(test)]
 mod tests {
     #[test]
     fn try_convert_h265() {
-        crate::processing::convert_vraw_to_mp4(&"assets/h265.vraw".to_string(), None).unwrap();
+        crate::processing::convert_vraw("assets/h265.vraw", None).unwrap();
+    }
+
+    #[test]
+    fn convert_h265_to_fragmented_mp4() {
+        let output = std::env::temp_dir().join("vraw_convert_fragmented.mp4");
+        let options = crate::ConvertOptions {
+            fragment_duration: Some(std::time::Duration::from_secs(1)),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h265.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        let mut boxes = crate::isobmff::Mp4Box::parse(&std::fs::read(&output).unwrap()).unwrap();
+        let kinds: Vec<&[u8; 4]> = boxes.iter().map(|mp4_box| &mp4_box.kind).collect();
+
+        // init segment followed by 11 fragments of the 10.5 s recording
+        assert_eq!(kinds[..2], [b"ftyp", b"moov"]);
+        assert_eq!(kinds.len(), 2 + 2 * 11);
+        assert!(kinds[2..]
+            .chunks(2)
+            .all(|fragment| fragment == [b"moof", b"mdat"]));
+        assert!(boxes[1].find_mut(&[b"mvex", b"trex"]).is_some());
+
+        let sample_count: u32 = boxes
+            .iter_mut()
+            .filter(|mp4_box| &mp4_box.kind == b"moof")
+            .map(|moof| {
+                let trun = moof.find_mut(&[b"traf", b"trun"]).unwrap();
+                u32::from_be_bytes(trun.data[4..8].try_into().unwrap())
+            })
+            .sum();
+
+        assert_eq!(summary.frames_written, 1265);
+        assert_eq!(sample_count, 1265);
+    }
+
+    #[test]
+    fn convert_h265_with_decoder_configuration() {
+        let output = std::env::temp_dir().join("vraw_convert_hvcc.mp4");
+
+        crate::processing::convert_vraw("assets/h265.vraw", Some(output.to_path_buf())).unwrap();
+
+        let mut boxes = crate::isobmff::Mp4Box::parse(&std::fs::read(&output).unwrap()).unwrap();
+        let moov = boxes.iter_mut().find(|mp4_box| &mp4_box.kind == b"moov");
+        let hvcc = moov
+            .unwrap()
+            .find_mut(&[
+                b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"hev1", b"hvcC",
+            ])
+            .unwrap();
+
+        // Main profile, level 4.1, 4:2:0, 8 bit, and a VPS, SPS and PPS array
+        assert_eq!(hvcc.data[1], 1);
+        assert_eq!(hvcc.data[12], 123);
+        assert_eq!(hvcc.data[16..19], [0xFD, 0xF8, 0xF8]);
+        assert_eq!(hvcc.data[22], 3);
+
+        // The VUI of the SPS describes BT.709 full range colours
+        let moov = boxes.iter_mut().find(|mp4_box| &mp4_box.kind == b"moov");
+        let colr = moov
+            .unwrap()
+            .find_mut(&[
+                b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"hev1", b"colr",
+            ])
+            .unwrap();
+        assert_eq!(colr.data, b"nclx\0\x01\0\x01\0\x01\x80");
+
+        let size = std::fs::metadata(&output).unwrap().len();
+        let file = std::fs::File::open(&output).unwrap();
+        let reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+        let track = reader.tracks().values().next().unwrap();
+        assert_eq!((track.width(), track.height()), (1920, 1088));
+    }
+
+    #[test]
+    fn convert_h265_to_hvc1_mp4() {
+        let output = std::env::temp_dir().join("vraw_convert_hvc1.mp4");
+        let options = crate::ConvertOptions {
+            hevc_sample_entry: crate::HevcSampleEntry::Hvc1,
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h265.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(summary.frames_written, 1265);
+
+        let mut boxes = crate::isobmff::Mp4Box::parse(&std::fs::read(&output).unwrap()).unwrap();
+        assert!(boxes[0].data.windows(4).any(|brand| brand == b"hvc1"));
+
+        let moov = boxes.iter_mut().find(|mp4_box| &mp4_box.kind == b"moov");
+        let stsd = moov
+            .unwrap()
+            .find_mut(&[b"trak", b"mdia", b"minf", b"stbl", b"stsd"])
+            .unwrap();
+        assert_eq!(&stsd.children[0].kind, b"hvc1");
+        let hvcc = stsd.children[0].child_mut(b"hvcC").unwrap();
+        assert_eq!(hvcc.data[22], 3); // numOfArrays
+
+        // the parameter sets are only in the hvcC box
+        let mdat = boxes.iter().find(|mp4_box| &mp4_box.kind == b"mdat");
+        let mut data = &mdat.unwrap().data[..];
+        while !data.is_empty() {
+            let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+            assert!(!(32..=34).contains(&crate::bitstream::hevc_nal_type(&data[4..])));
+            data = &data[4 + size..];
+        }
+    }
+
+    #[test]
+    fn convert_h265_to_elementary_stream() {
+        let output = std::env::temp_dir().join("vraw_convert_h265.h265");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ElementaryStream),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h265.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(summary.frames_written, 1265);
+        assert!(std::fs::read(&output).unwrap().starts_with(&[0, 0, 0, 1]));
+    }
+
+    #[test]
+    fn convert_h265_to_mkv() {
+        let output = std::env::temp_dir().join("vraw_convert_h265.mkv");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::Matroska),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h265.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        let mkv = std::fs::read(&output).unwrap();
+
+        assert_eq!(summary.frames_written, 1265);
+        assert!(mkv.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]));
+        assert!(mkv.windows(16).any(|window| window == b"V_MPEGH/ISO/HEVC"));
+    }
+
+    #[test]
+    fn parse_hevc_sps() {
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x40, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03,
+            0x00, 0x00, 0x03, 0x00, 0x7B, 0xA0, 0x03, 0xC0, 0x80, 0x11, 0x05, 0x96, 0xB4, 0xA4,
+            0x25, 0x92, 0xE3, 0x01, 0x6E, 0x02, 0x02, 0x02, 0x08, 0x00, 0x00, 0x03, 0x00, 0x08,
+            0x00, 0x00, 0x03, 0x01, 0xE0, 0x40,
+        ];
+
+        let sps = crate::bitstream::parse_hevc_sps(&sps).unwrap();
+
+        assert_eq!(sps.general_profile_idc, 1);
+        assert_eq!(sps.general_profile_compatibility_flags, 0x4000_0000);
+        assert_eq!(sps.general_level_idc, 123);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!(sps.bit_depth_luma, 8);
+        assert_eq!((sps.width, sps.height), (1920, 1088));
+    }
+
+    #[test]
+    fn avc_decoder_configuration_record() {
+        // Baseline profile SPS and PPS of assets/h264.vraw
+        let parameter_sets = crate::bitstream::AvcParameterSets {
+            sps: vec![0x67, 0x42, 0xC0, 0x0A, 0xDA, 0x2E, 0x40],
+            pps: vec![0x68, 0xCE, 0x3C, 0x80],
+        };
+        let sps = crate::bitstream::parse_avc_sps(&parameter_sets.sps).unwrap();
+        assert_eq!((sps.width, sps.height), (32, 16));
+
+        assert_eq!(
+            crate::bitstream::avc_decoder_configuration_record(&parameter_sets, &sps),
+            [
+                0x01, 0x42, 0xC0, 0x0A, 0xFF, 0xE1, 0x00, 0x07, 0x67, 0x42, 0xC0, 0x0A, 0xDA, 0x2E,
+                0x40, 0x01, 0x00, 0x04, 0x68, 0xCE, 0x3C, 0x80,
+            ]
+        );
+
+        // High profile 720p SPS with a VUI, the record ends with the chroma
+        // format and bit depths
+        let parameter_sets = crate::bitstream::AvcParameterSets {
+            sps: vec![
+                0x67, 0x64, 0x00, 0x1F, 0xAC, 0xD9, 0x40, 0x50, 0x05, 0xBB, 0x01, 0x10, 0x00, 0x00,
+                0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x03, 0xC0, 0xF1, 0x83, 0x19, 0x60,
+            ],
+            pps: vec![0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0],
+        };
+        let sps = crate::bitstream::parse_avc_sps(&parameter_sets.sps).unwrap();
+        assert_eq!((sps.width, sps.height), (1280, 720));
+
+        let record = crate::bitstream::avc_decoder_configuration_record(&parameter_sets, &sps);
+        assert_eq!(record[..4], [0x01, 0x64, 0x00, 0x1F]);
+        assert_eq!(record[record.len() - 4..], [0xFD, 0xF8, 0xF8, 0x00]);
+    }
+
+    #[test]
+    fn hevc_main10_decoder_configuration_record() {
+        // Main 10 profile 2160p SPS with BT.2020 PQ colours
+        let parameter_sets = crate::bitstream::HevcParameterSets {
+            vps: vec![0x40, 0x01, 0x0C],
+            sps: vec![
+                0x42, 0x01, 0x01, 0x02, 0x20, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+                0x00, 0x03, 0x00, 0x78, 0xA0, 0x01, 0xE0, 0x20, 0x02, 0x1C, 0x4D, 0x96, 0x57, 0x92,
+                0x46, 0xDA, 0xE6, 0xA1, 0x22, 0x01, 0x20, 0x10,
+            ],
+            pps: vec![0x44, 0x01, 0xC1],
+        };
+        let sps = crate::bitstream::parse_hevc_sps(&parameter_sets.sps).unwrap();
+        assert_eq!((sps.width, sps.height), (3840, 2160));
+        assert_eq!((sps.bit_depth_luma, sps.bit_depth_chroma), (10, 10));
+        assert_eq!(
+            sps.colour,
+            Some(crate::bitstream::ColourDescription {
+                colour_primaries: 9,
+                transfer_characteristics: 16,
+                matrix_coefficients: 9,
+                full_range: false,
+            })
+        );
+
+        let record = crate::bitstream::hevc_decoder_configuration_record(&parameter_sets, &sps);
+        assert_eq!(
+            record[..23],
+            [
+                0x01, // configurationVersion
+                0x02, // Main 10 profile
+                0x20, 0x00, 0x00, 0x00, // compatible with Main 10
+                0x90, 0x00, 0x00, 0x00, 0x00, 0x00, // progressive, frame only
+                120,  // level 4
+                0xF0, 0x00, 0xFC, 0xFD, // 4:2:0
+                0xFA, 0xFA, // 10 bit luma and chroma
+                0x00, 0x00, 0x0F, 0x03,
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_rbsp() {
+        assert_eq!(
+            crate::bitstream::escape_rbsp(&[0, 0, 0, 0, 0, 1, 0, 0, 2, 0, 0, 3, 0, 0, 4, 0, 0]),
+            [0, 0, 3, 0, 0, 3, 0, 1, 0, 0, 3, 2, 0, 0, 3, 3, 0, 0, 4, 0, 0, 3]
+        );
+        assert_eq!(crate::bitstream::escape_rbsp(&[1, 0, 2]), [1, 0, 2]);
+    }
+
+    #[test]
+    fn hevc_user_data_sei() {
+        let sei = crate::bitstream::hevc_user_data_sei(&[0xAA; 16], &[0, 0, 1, 5]);
+
+        let mut expected = vec![0x4E, 0x01, 5, 20];
+        expected.extend_from_slice(&[0xAA; 16]);
+        expected.extend_from_slice(&[0, 0, 3, 1, 5, 0x80]);
+        assert_eq!(sei, expected);
+
+        // payload sizes from 255 on are coded with 0xFF runs
+        let sei = crate::bitstream::hevc_user_data_sei(&[0xAA; 16], &[0x11; 300]);
+        assert_eq!(sei[2..6], [5, 0xFF, 61, 0xAA]);
+        assert_eq!(sei.len(), 2 + 3 + 316 + 1);
+    }
+
+    #[test]
+    fn convert_h265_with_timestamp_sei() {
+        let output = std::env::temp_dir().join("vraw_convert_timestamp_sei.mp4");
+        crate::processing::convert_vraw_with_options(
+            "assets/h265.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                timestamp_sei: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/h265.vraw").unwrap());
+        let entries = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+            .unwrap()
+            .entries;
+        let first_frame = entries
+            .iter()
+            .map(|entry| {
+                crate::parser::parse_raw_frame(&mut f, entry, &crate::ParseOptions::default())
+                    .unwrap()
+            })
+            .find(|frame| frame.format == crate::VideoCaptureFormat::H265)
+            .unwrap();
+
+        let mut user_data = Vec::new();
+        user_data.extend_from_slice(&1661237603u64.to_be_bytes());
+        user_data.extend_from_slice(&231180500u32.to_be_bytes());
+        user_data.extend_from_slice(&first_frame.capture_timestamp.to_be_bytes());
+        user_data.extend_from_slice(&first_frame.receive_timestamp.to_be_bytes());
+        let sei = crate::bitstream::hevc_user_data_sei(&crate::TIMESTAMP_SEI_UUID, &user_data);
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+        let sample = reader.read_sample(1, 1).unwrap().unwrap();
+
+        // The SEI precedes the first slice
+        let mut nal_units = Vec::new();
+        let mut rest = &sample.bytes[..];
+        while !rest.is_empty() {
+            let size = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            nal_units.push(&rest[4..4 + size]);
+            rest = &rest[4 + size..];
+        }
+        let sei_position = nal_units.iter().position(|nal| nal == &sei).unwrap();
+        let first_slice = nal_units
+            .iter()
+            .position(|nal| crate::bitstream::hevc_nal_type(nal) < 32)
+            .unwrap();
+        assert_eq!(sei_position + 1, first_slice);
+    }
+
+    #[test]
+    fn vui_colour_description() {
+        // Baseline profile 720p SPS with an extended sample aspect ratio and
+        // BT.601 limited range colours
+        let sps = crate::bitstream::parse_avc_sps(&[
+            0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x01, 0x40, 0x16, 0xEF, 0xFC, 0x00, 0x10, 0x00, 0x0D,
+            0xA8, 0x30, 0x30, 0x30, 0x20,
+        ])
+        .unwrap();
+        assert_eq!((sps.width, sps.height), (1280, 720));
+        assert_eq!(
+            sps.colour,
+            Some(crate::bitstream::ColourDescription {
+                colour_primaries: 6,
+                transfer_characteristics: 6,
+                matrix_coefficients: 6,
+                full_range: false,
+            })
+        );
+
+        // The SPS of assets/h264.vraw has no VUI
+        let sps = crate::bitstream::parse_avc_sps(&[0x67, 0x42, 0xC0, 0x0A, 0xDA, 0x2E, 0x40]);
+        assert_eq!(sps.unwrap().colour, None);
+    }
+
+    #[test]
+    fn convert_h264_with_colour_space() {
+        let read_colr = |colour_space| {
+            let output = std::env::temp_dir().join(format!("vraw_convert_{colour_space:?}.mp4"));
+            crate::processing::convert_vraw_with_options(
+                "assets/h264.vraw",
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    colour_space,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            let mut boxes =
+                crate::isobmff::Mp4Box::parse(&std::fs::read(&output).unwrap()).unwrap();
+            let moov = boxes.iter_mut().find(|mp4_box| &mp4_box.kind == b"moov");
+            moov.unwrap()
+                .find_mut(&[
+                    b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"avc1", b"colr",
+                ])
+                .map(|colr| colr.data.clone())
+        };
+
+        assert_eq!(read_colr(None), None);
+        assert_eq!(
+            read_colr(Some(crate::ColourSpace::Bt709)).unwrap(),
+            b"nclx\0\x01\0\x01\0\x01\0"
+        );
+    }
+
+    #[test]
+    fn annex_b_to_length_prefixed() {
+        // 4 and 3 byte start codes, the trailing zero of the SPS belongs to the
+        // start code of the PPS, emulation prevention bytes are kept
+        let access_unit = [
+            0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE, 0, 0, 1, 0x65, 0x88, 0, 0, 3, 1,
+        ];
+
+        assert_eq!(
+            crate::bitstream::annex_b_to_length_prefixed(&access_unit),
+            [0, 0, 0, 2, 0x67, 0x42, 0, 0, 0, 2, 0x68, 0xCE, 0, 0, 0, 6, 0x65, 0x88, 0, 0, 3, 1,]
+        );
+    }
+
+    #[test]
+    fn detect_keyframes() {
+        use crate::bitstream::{is_avc_idr, is_hevc_irap};
+
+        // VPS followed by an IDR_W_RADL slice, CRA slice, TRAIL_R slice
+        assert!(is_hevc_irap(&[
+            0, 0, 0, 1, 0x40, 0x01, 0x0C, 0, 0, 1, 0x26, 0x01, 0xAF
+        ]));
+        assert!(is_hevc_irap(&[0, 0, 1, 0x2A, 0x01, 0xAF]));
+        assert!(!is_hevc_irap(&[0, 0, 0, 1, 0x02, 0x01, 0xD0]));
+
+        // SPS followed by an IDR slice, non-IDR slice
+        assert!(is_avc_idr(&[
+            0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x65, 0x88
+        ]));
+        assert!(!is_avc_idr(&[0, 0, 0, 1, 0x41, 0x9A]));
+        assert!(!is_avc_idr(&[]));
+    }
+
+    #[test]
+    fn convert_h264_with_timescale() {
+        let read_timescale = |timescale| {
+            let output = std::env::temp_dir().join(format!("vraw_convert_{timescale:?}.mp4"));
+            crate::processing::convert_vraw_with_options(
+                "assets/h264.vraw",
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    timescale,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            let file = std::fs::File::open(&output).unwrap();
+            let size = file.metadata().unwrap().len();
+            let reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+            let track = reader.tracks().values().next().unwrap();
+            (reader.timescale(), track.timescale(), track.duration())
+        };
+
+        let (movie_timescale, track_timescale, duration) = read_timescale(None);
+        assert_eq!((movie_timescale, track_timescale), (90_000, 90_000));
+
+        let (movie_timescale, track_timescale, duration_in_ms) = read_timescale(Some(1000));
+        assert_eq!((movie_timescale, track_timescale), (1000, 1000));
+        assert!(duration.abs_diff(duration_in_ms) <= std::time::Duration::from_millis(30));
+    }
+
+    #[test]
+    fn convert_h264_starts_at_zero() {
+        let output = std::env::temp_dir().join("vraw_convert_start.mp4");
+        crate::processing::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+
+        // the receive timestamps span 29 frame intervals, the last frame lasts
+        // one more. The reader rounds to milliseconds.
+        let span = std::time::Duration::from_nanos(967_633_328);
+        let frame_interval = std::time::Duration::from_nanos(33_366_667);
+        let millisecond = std::time::Duration::from_millis(1);
+        assert!(reader.duration() + millisecond >= span);
+        assert!(reader.duration() <= span + frame_interval + millisecond);
+
+        let first_sample = reader.read_sample(1, 1).unwrap().unwrap();
+        assert_eq!(first_sample.start_time, 0);
+        assert_eq!(first_sample.duration, 3003);
+    }
+
+    #[test]
+    fn first_and_last_samples_have_durations() {
+        let output = std::env::temp_dir().join("vraw_convert_h265_durations.mp4");
+        crate::processing::convert_vraw("assets/h265.vraw", Some(output.to_path_buf())).unwrap();
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+
+        // the first frame lasts until the second one
+        let first_sample = reader.read_sample(1, 1).unwrap().unwrap();
+        let second_sample = reader.read_sample(1, 2).unwrap().unwrap();
+        assert!(first_sample.duration > 0);
+        assert_eq!(
+            first_sample.start_time + first_sample.duration as u64,
+            second_sample.start_time
+        );
+
+        // A recording of a single frame, whose index is rebuilt by scanning
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let input = std::env::temp_dir().join("vraw_convert_single_frame.vraw");
+        std::fs::write(&input, &recording[..898]).unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_single_frame.mp4");
+        let summary = crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(summary.frames_written, 1);
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+        assert_eq!(reader.sample_count(1).unwrap(), 1);
+        assert_eq!(reader.read_sample(1, 1).unwrap().unwrap().duration, 1);
+    }
+
+    #[test]
+    fn sample_durations_do_not_drift() {
+        // 59.94 fps, which is no whole number of ticks at either timescale
+        let timestamps: Vec<i64> = (0..10_000)
+            .map(|frame| (frame as f64 * 1e9 * 1001.0 / 60_000.0).round() as i64)
+            .collect();
+
+        for timescale in [1000, crate::DEFAULT_MP4_TIMESCALE] {
+            let total = timestamps[1..].iter().fold(0, |position, &next_timestamp| {
+                position
+                    + crate::processing::sample_duration(
+                        position,
+                        timestamps[0],
+                        next_timestamp,
+                        timescale,
+                    ) as i64
+            });
+            let exact = timestamps[9_999] as f64 * timescale as f64 / 1e9;
+
+            assert!((total as f64 - exact).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn convert_h264_with_duplicate_timestamps() {
+        let output = std::env::temp_dir().join("vraw_convert_duplicates.mp4");
+        let summary = crate::processing::convert_vraw(
+            "assets/h264_duplicates.vraw",
+            Some(output.to_path_buf()),
+        )
+        .unwrap();
+        assert_eq!(summary.duplicate_timestamps, 2);
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+
+        let durations: Vec<u32> = (1..=10)
+            .map(|sample| reader.read_sample(1, sample).unwrap().unwrap().duration)
+            .collect();
+        assert_eq!(
+            durations,
+            [3600, 3600, 3600, 1, 1, 3598, 3600, 3600, 3600, 3600]
+        );
+    }
+
+    #[test]
+    fn convert_h264_with_capture_timestamps() {
+        let read_durations = |timestamp_source| {
+            let output =
+                std::env::temp_dir().join(format!("vraw_convert_{timestamp_source:?}.mp4"));
+            crate::processing::convert_vraw_with_options(
+                "assets/h264_jitter.vraw",
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    timestamp_source,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            let file = std::fs::File::open(&output).unwrap();
+            let size = file.metadata().unwrap().len();
+            let mut reader =
+                mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+            (1..=10)
+                .map(|sample| reader.read_sample(1, sample).unwrap().unwrap().duration)
+                .collect::<Vec<u32>>()
+        };
+
+        // the frames are captured every 40 ms and received with jitter
+        assert_eq!(read_durations(crate::TimestampSource::Capture), [3600; 10]);
+        assert_eq!(
+            read_durations(crate::TimestampSource::Receive),
+            [4230, 2700, 4950, 2610, 2970, 4950, 2790, 3960, 3060, 3060]
+        );
+    }
+
+    #[test]
+    fn fragmented_mp4_beyond_4_gb() {
+        /// Keeps the boxes written and counts the bytes of the sample data
+        #[derive(Default)]
+        struct BoxRecorder {
+            position: u64,
+            boxes: Vec<(u64, Vec<u8>)>,
+        }
+
+        impl std::io::Write for BoxRecorder {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if buf.len() < 4096 {
+                    self.boxes.push((self.position, buf.to_vec()));
+                }
+                self.position += buf.len() as u64;
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sample = mp4::Mp4Sample {
+            start_time: 0,
+            duration: 1,
+            rendering_offset: 0,
+            is_sync: true,
+            bytes: mp4::Bytes::from(vec![0xAB; 64 << 20]),
+        };
+
+        let mut writer =
+            crate::fmp4::FragmentedMp4Writer::new(BoxRecorder::default(), &[], 1).unwrap();
+        for _ in 0..65 {
+            writer.write_sample(&sample).unwrap();
+        }
+        let recorder = writer.finish().unwrap();
+        // 100 bytes of moof and 8 bytes of mdat header per fragment
+        assert_eq!(recorder.position, 65 * ((64 << 20) + 108));
+
+        // Every fragment addresses its samples relative to its moof, so the
+        // fragments beyond 4 GB are no different from the first one
+        let (position, last_fragment) = recorder.boxes.last().unwrap();
+        assert!(*position > u32::MAX as u64);
+        let mdat_header = [&((64u32 << 20) + 8).to_be_bytes()[..], b"mdat"].concat();
+        assert_eq!(last_fragment[100..], mdat_header[..]);
+
+        let boxes = crate::isobmff::Mp4Box::parse(&last_fragment[..100]).unwrap();
+        assert_eq!(&boxes[0].kind, b"moof");
+        let trun = &boxes[0].children[1].children[2].data;
+        assert_eq!(trun[8..12], 108u32.to_be_bytes());
+    }
+
+    #[test]
+    fn edit_list_skips_to_presentation_start() {
+        use crate::isobmff::Mp4Box;
+
+        // version 0 mvhd with a duration of 1000
+        let mut mvhd = vec![0; 20];
+        mvhd[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        let mut trak = Mp4Box::new(b"trak", Vec::new());
+        trak.children = vec![
+            Mp4Box::new(b"tkhd", vec![0; 84]),
+            Mp4Box::new(b"mdia", vec![]),
+        ];
+        let mut moov = Mp4Box::new(b"moov", Vec::new());
+        moov.children = vec![Mp4Box::new(b"mvhd", mvhd), trak];
+
+        crate::isobmff::set_edit_list(&mut moov, 30).unwrap();
+
+        let trak = &moov.children[1];
+        assert_eq!(&trak.children[1].kind, b"edts");
+        assert_eq!(
+            trak.children[1].children[0],
+            Mp4Box::new(
+                b"elst",
+                vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 3, 202, 0, 0, 0, 30, 0, 1, 0, 0]
+            )
+        );
+    }
+
+    #[test]
+    fn convert_h264_with_creation_time() {
+        let output = std::env::temp_dir().join("vraw_convert_creation_time.mp4");
+        crate::processing::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+
+        let recording_start = crate::recording_start("assets/h264.vraw").unwrap();
+        assert_eq!(
+            recording_start.to_rfc3339(),
+            "2022-08-23T06:53:23.231180500+00:00"
+        );
+
+        // seconds since 1904
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+        assert_eq!(reader.moov.mvhd.creation_time, 1661237603 + 2082844800);
+        let track = reader.tracks().values().next().unwrap();
+        assert_eq!(track.trak.tkhd.creation_time, 1661237603 + 2082844800);
+        assert_eq!(
+            track.trak.mdia.mdhd.modification_time,
+            1661237603 + 2082844800
+        );
+    }
+
+    #[test]
+    fn convert_h264_with_rotation() {
+        let output = std::env::temp_dir().join("vraw_convert_rotation.mp4");
+        crate::processing::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                rotation: crate::Rotation::Clockwise90,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+        let track = reader.tracks().values().next().unwrap();
+        let matrix = &track.trak.tkhd.matrix;
+
+        // the 32x16 video is shifted right by its height after the rotation
+        assert_eq!(
+            (matrix.a, matrix.b, matrix.c, matrix.d),
+            (0, 0x10000, -0x10000, 0)
+        );
+        assert_eq!((matrix.x, matrix.y, matrix.w), (16 << 16, 0, 0x4000_0000));
+    }
+
+    #[test]
+    fn convert_h264_with_subtitles() {
+        let output = std::env::temp_dir().join("vraw_convert_subtitles.mp4");
+        crate::processing::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                subtitles: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(output.with_extension("srt")).unwrap(),
+            std::fs::read_to_string("assets/h264.srt").unwrap()
+        );
+    }
+
+    #[test]
+    fn export_generic_metadata() {
+        let output = std::env::temp_dir().join("vraw_convert_generic_metadata");
+        crate::processing::convert_vraw_with_options(
+            "assets/generic_metadata.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                export_generic_metadata: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let lines: Vec<serde_json::Value> =
+            std::fs::read_to_string(output.with_extension("metadata.jsonl"))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+
+        // the Stats frame is not part of the output
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["frame"], 0);
+        assert_eq!(lines[0]["timestamp"], 2_000_000);
+        assert_eq!(lines[0]["receive_timestamp"], 3_000_000);
+        assert_eq!(
+            base64::Engine::decode(
+                &base64::engine::general_purpose::STANDARD,
+                lines[0]["payload"].as_str().unwrap()
+            )
+            .unwrap(),
+            br#"{"gps":[57.7089,11.9746]}"#
+        );
+        assert_eq!(lines[1]["frame"], 1);
+        assert_eq!(lines[1]["payload"], "AP8B/g==");
+        assert_eq!(lines[2]["payload"], "");
+    }
+
+    #[test]
+    fn convert_h264_with_chapters() {
+        let output = std::env::temp_dir().join("vraw_convert_chapters.mp4");
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h264_gaps.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                chapters: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary.gaps,
+            [
+                crate::Gap {
+                    frame: 10,
+                    position: 1_400_000_000,
+                    duration: 1_040_000_000,
+                },
+                crate::Gap {
+                    frame: 15,
+                    position: 2_060_000_000,
+                    duration: 500_000_000,
+                },
+            ]
+        );
+
+        let bytes = std::fs::read(&output).unwrap();
+        let boxes = crate::isobmff::Mp4Box::parse(&bytes).unwrap();
+        let moov = boxes.iter().find(|b| &b.kind == b"moov").unwrap();
+        let udta = moov.children.iter().find(|b| &b.kind == b"udta").unwrap();
+        let chpl = udta.children.iter().find(|b| &b.kind == b"chpl").unwrap();
+
+        let mut expected = vec![1, 0, 0, 0, 0, 0, 0, 0, 3];
+        for (start, title) in [
+            (0u64, "Start"),
+            (14_000_000, "Gap of 1.040 s"),
+            (20_600_000, "Gap of 0.500 s"),
+        ] {
+            expected.extend_from_slice(&start.to_be_bytes());
+            expected.push(title.len() as u8);
+            expected.extend_from_slice(title.as_bytes());
+        }
+        assert_eq!(chpl.data, expected);
+    }
+
+    #[test]
+    fn find_gaps() {
+        assert_eq!(
+            crate::processing::find_gaps(&[0, 10, 20, 30, 100, 110], 3.0),
+            [crate::Gap {
+                frame: 4,
+                position: 100,
+                duration: 70,
+            }]
+        );
+        assert!(crate::processing::find_gaps(&[0, 10, 20, 50], 3.0).is_empty());
+        assert!(crate::processing::find_gaps(&[5], 3.0).is_empty());
+    }
+
+    #[test]
+    fn composition_offsets_of_b_frames() {
+        // I0 P3 B1 B2 in decode order
+        assert_eq!(
+            crate::processing::composition_offsets(&[0, 30, 10, 20]),
+            [10, 30, 0, 0]
+        );
+        assert_eq!(
+            crate::processing::composition_offsets(&[0, 10, 20]),
+            [0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn try_convert_h264() {
+        let summary = crate::processing::convert_vraw("assets/h264.vraw", None).unwrap();
+
+        // The 10 Stats frames are not video and not counted as skipped
+        assert_eq!(summary.frames_written, 30);
+        assert!(summary.frames_skipped.is_empty());
+        assert_eq!(summary.frames_corrupt, 0);
+        assert_eq!(summary.first_timestamp, Some(7_723_900));
+        assert_eq!(summary.last_timestamp, Some(975_357_228));
+        assert_eq!(
+            summary.duration(),
+            std::time::Duration::from_nanos(967_633_328)
+        );
+        assert_eq!(summary.format, Some(crate::VideoCaptureFormat::H264));
+        assert_eq!(
+            summary.output_size,
+            std::fs::metadata(&summary.output).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn convert_h264_to_elementary_stream() {
+        let output = std::env::temp_dir().join("vraw_convert_h264.h264");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ElementaryStream),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        let stream = std::fs::read(&output).unwrap();
+
+        assert_eq!(summary.frames_written, 30);
+        // The first access unit starts with the SPS
+        assert!(stream.starts_with(&[0, 0, 0, 1, 0x67]));
+    }
+
+    #[test]
+    fn convert_mixed_recording_skips_other_formats() {
+        let output = std::env::temp_dir().join("vraw_convert_mixed.mp4");
+
+        let summary =
+            crate::processing::convert_vraw("assets/mixed.vraw", Some(output.to_path_buf()))
+                .unwrap();
+
+        assert_eq!(summary.frames_written, 10);
+        assert_eq!(summary.frames_skipped.len(), 2);
+        assert_eq!(summary.frames_skipped[&crate::VideoCaptureFormat::Mjpeg], 5);
+        assert_eq!(summary.frames_skipped[&crate::VideoCaptureFormat::Raw], 2);
+    }
+
+    #[test]
+    fn dump_mixed_recording() {
+        let output = std::env::temp_dir().join("vraw_convert_dump.bin");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::Dump),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/mixed.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        let dump = std::fs::read(&output).unwrap();
+        let descriptor: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(output.with_extension("json")).unwrap()).unwrap();
+        let frames = descriptor["frames"].as_array().unwrap();
+
+        // 10 H264, 5 MJPEG and 2 Raw frames
+        assert_eq!(summary.frames_written, 17);
+        assert_eq!(frames.len(), 17);
+
+        let mut next_offset = 0;
+        for frame in frames {
+            let offset = frame["offset"].as_u64().unwrap() as usize;
+            let size = frame["size"].as_u64().unwrap() as usize;
+            let payload = &dump[offset..offset + size];
+
+            assert_eq!(offset, next_offset);
+            match frame["format"].as_str().unwrap() {
+                "H264" => {
+                    assert!(payload.starts_with(&[0, 0, 0, 1]));
+                    assert_eq!(frame["placement_metadata"], "AQEBAQEBAQEBAQEB");
+                }
+                "Mjpeg" => assert!(payload.starts_with(&[0xFF, 0xD8])),
+                "Raw" => {
+                    assert_eq!(payload, (0..64).collect::<Vec<u8>>());
+                    assert_eq!(frame["width"], 8);
+                }
+                format => panic!("unexpected format {format}"),
+            }
+
+            next_offset = offset + size;
+        }
+        assert_eq!(next_offset, dump.len());
+    }
+
+    #[test]
+    fn convert_h264_to_faststart_mp4() {
+        let regular = std::env::temp_dir().join("vraw_convert_regular.mp4");
+        let faststart = std::env::temp_dir().join("vraw_convert_faststart.mp4");
+
+        crate::processing::convert_vraw("assets/h264.vraw", Some(regular.to_path_buf())).unwrap();
+        crate::processing::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(faststart.to_path_buf()),
+            &crate::ConvertOptions {
+                faststart: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let boxes = crate::isobmff::Mp4Box::parse(&std::fs::read(&faststart).unwrap()).unwrap();
+        let kinds: Vec<&[u8; 4]> = boxes.iter().map(|mp4_box| &mp4_box.kind).collect();
+        assert_eq!(kinds, [b"ftyp", b"moov", b"mdat"]);
+
+        // The samples are still found after moving the mdat
+        let read_samples = |path: &std::path::Path| {
+            let file = std::fs::File::open(path).unwrap();
+            let size = file.metadata().unwrap().len();
+            let mut reader =
+                mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+            (1..=reader.sample_count(1).unwrap())
+                .map(|id| reader.read_sample(1, id).unwrap().unwrap().bytes)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(read_samples(&faststart), read_samples(&regular));
+        assert_eq!(read_samples(&faststart).len(), 30);
+    }
+
+    #[test]
+    fn try_convert_mjpeg() {
+        let summary = crate::processing::convert_vraw("assets/mjpeg.vraw", None).unwrap();
+
+        assert_eq!(summary.frames_written, 12);
+        assert_eq!(summary.first_timestamp, Some(5_000_000));
+        assert_eq!(summary.last_timestamp, Some(445_000_000));
+        assert_eq!(summary.duration(), std::time::Duration::from_millis(440));
+        assert_eq!(summary.format, Some(crate::VideoCaptureFormat::Mjpeg));
+        assert_eq!(
+            summary.output_size,
+            std::fs::metadata(&summary.output).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn convert_time_range() {
+        let output = std::env::temp_dir().join("vraw_convert_time_range.mp4");
+        let convert = |start: Option<u64>, end: Option<u64>| {
+            crate::processing::convert_vraw_with_options(
+                "assets/mjpeg.vraw",
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    start: start.map(std::time::Duration::from_millis),
+                    end: end.map(std::time::Duration::from_millis),
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The MJPEG frames are received 40 ms apart from 5 ms on
+        let summary = convert(Some(100), Some(300)).unwrap();
+        assert_eq!(summary.frames_written, 5);
+        assert_eq!(summary.first_timestamp, Some(125_000_000));
+        assert_eq!(summary.last_timestamp, Some(285_000_000));
+
+        let summary = convert(Some(400), None).unwrap();
+        assert_eq!(summary.frames_written, 2);
+        assert_eq!(summary.first_timestamp, Some(405_000_000));
+
+        let summary = convert(None, Some(45)).unwrap();
+        assert_eq!(summary.frames_written, 2);
+        assert_eq!(summary.last_timestamp, Some(45_000_000));
+
+        let e = convert(Some(300), Some(100)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: the start 300ms is after the end 100ms"
+        );
+        let e = convert(Some(1000), None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: no frame was received from 1s on after the recording started"
+        );
+    }
+
+    #[test]
+    fn report_progress() {
+        let output = std::env::temp_dir().join("vraw_convert_progress.mp4");
+        let convert = |input, progress_stride| {
+            let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let progress_reports = reports.clone();
+            crate::processing::convert_vraw_with_options(
+                input,
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    progress: Some(crate::ProgressCallback::new(move |progress| {
+                        progress_reports.lock().unwrap().push(progress)
+                    })),
+                    progress_stride,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let reports = reports.lock().unwrap().clone();
+            reports
+        };
+
+        // Every 10th of the 40 frames, Stats frames included
+        let reports = convert("assets/h264.vraw", Some(10));
+        let processed: Vec<_> = reports.iter().map(|p| p.frames_processed).collect();
+        assert_eq!(processed, [10, 20, 30, 40]);
+        assert!(reports.iter().all(|p| p.total_frames == 40));
+        assert!(reports
+            .windows(2)
+            .all(|p| p[0].bytes_read < p[1].bytes_read));
+        assert_eq!(reports[3].receive_timestamp, 975_357_228);
+
+        // The 15 frames are fewer than the default stride, only the last one
+        // is reported
+        let reports = convert("assets/mjpeg.vraw", None);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].frames_processed, 15);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn convert_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = |name: &[u8]| std::ffi::OsStr::from_bytes(name).to_owned();
+        let input = std::env::temp_dir().join(name(b"vraw_convert_\xff.vraw"));
+        let output = std::env::temp_dir().join(name(b"vraw_convert_\xff.mp4"));
+        std::fs::copy("assets/mjpeg.vraw", &input).unwrap();
+
+        let summary = crate::processing::convert_vraw(&input, Some(output.clone())).unwrap();
+        assert_eq!(summary.output, output);
+        assert!(output.exists());
+
+        // Outputs named after the input keep its name as it is
+        let derived =
+            crate::processing::derive_output_from_input(&input, "mp4", chrono::Local::now())
+                .unwrap();
+        assert!(derived
+            .file_name()
+            .unwrap()
+            .as_bytes()
+            .starts_with(b"vraw_convert_\xff_"));
+    }
+
+    #[test]
+    fn payload_ranges_from_index() {
+        let bytes = std::fs::read("assets/h264.vraw").unwrap();
+        let mut f = std::io::Cursor::new(&bytes[..]);
+        let options = crate::ParseOptions::default();
+        let index = crate::read_index(&mut f, &options).unwrap();
+        assert_eq!(index.entries.len(), 40);
+
+        for entry in &index.entries {
+            let metadata = crate::parse_frame_metadata(&mut f, entry).unwrap();
+            let range = entry.payload_range(&metadata);
+            assert_eq!(
+                range.start,
+                entry.offset() as u64 + crate::FRAME_HEADER_LEN as u64
+            );
+
+            // The payload read in full starts the range, the video placement
+            // metadata follows it
+            let frame = crate::parse_raw_frame(&mut f, entry, &options).unwrap();
+            let payload = &bytes[range.start as usize..range.end as usize];
+            assert!(payload.starts_with(&frame.raw_data));
+            assert_eq!(payload.len(), frame.size);
+        }
+
+        let first = &index.entries[0];
+        let metadata = crate::parse_frame_metadata(&mut f, first).unwrap();
+        assert_eq!(first.payload_range(&metadata), 64..882);
+
+        // An entry leading to no frame, and a recording cut off before its
+        // index
+        let entry = crate::RecordingIndexEntry::new(65, first.receive_timestamp());
+        assert!(matches!(
+            crate::parse_frame_metadata(&mut f, &entry),
+            Err(crate::VrawError::CorruptIndex {
+                offset: Some(65),
+                ..
+            })
+        ));
+        assert!(matches!(
+            crate::parse_raw_frame(&mut f, &entry, &options),
+            Err(crate::VrawError::CorruptIndex {
+                offset: Some(65),
+                ..
+            })
+        ));
+        let mut f = std::io::Cursor::new(&bytes[..1000]);
+        assert!(matches!(
+            crate::read_index(&mut f, &options),
+            Err(crate::VrawError::CorruptIndex { offset: None, .. })
+        ));
+    }
+
+    #[test]
+    fn probe_recordings() {
+        let info = crate::probe("assets/h264.vraw").unwrap();
+        assert_eq!(info.frame_count, 40);
+        assert!(!info.index_rebuilt);
+        assert_eq!(info.first_timestamp, Some(7_723_900));
+        assert_eq!(info.last_timestamp, Some(975_357_228));
+        assert_eq!(info.duration, 967_633_328);
+        assert_eq!(
+            info.bitrate,
+            Some(info.size * 8 * 1_000_000_000 / 967_633_328)
+        );
+        // Only the first and last frames are read
+        assert_eq!(info.frames_probed, 2 * crate::PROBE_FRAMES);
+        assert_eq!(info.unreadable_frames, 0);
+        assert_eq!(
+            info.formats.values().sum::<usize>(),
+            2 * crate::PROBE_FRAMES
+        );
+
+        let exact =
+            crate::probe_with_options("assets/h264.vraw", &crate::ProbeOptions { exact: true })
+                .unwrap();
+        assert_eq!(exact.frames_probed, 40);
+        assert_eq!(exact.formats[&crate::VideoCaptureFormat::H264], 30);
+        assert_eq!(exact.formats[&crate::VideoCaptureFormat::Stats], 10);
+        assert_eq!(exact.recording_start, info.recording_start);
+
+        let info = crate::probe("assets/mjpeg.vraw").unwrap();
+        assert_eq!(info.frames_probed, 15);
+        assert_eq!(info.formats[&crate::VideoCaptureFormat::Mjpeg], 12);
+        assert!(info.resolutions.contains(&(32, 16)));
+        assert!(info.stream_ids.contains(&1));
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["frame_count"], 15);
+        assert_eq!(json["formats"]["Mjpeg"], 12);
+        assert_eq!(
+            json["recording_start"].as_str(),
+            info.recording_start
+                .map(|start| start.to_rfc3339())
+                .as_deref()
+        );
+
+        // A damaged index, with the timestamps of its first and last entries
+        // as far apart as they can be
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let entries_end = recording.len() - 8;
+        let entries_start = entries_end - 40 * 16;
+        recording[entries_start + 8..entries_start + 16].copy_from_slice(&i64::MIN.to_le_bytes());
+        recording[entries_end - 8..entries_end].copy_from_slice(&i64::MAX.to_le_bytes());
+        let input = std::env::temp_dir().join("vraw_convert_probe_damaged_index.vraw");
+        std::fs::write(&input, &recording).unwrap();
+        let info = crate::probe(&input).unwrap();
+        assert_eq!(info.first_timestamp, Some(i64::MIN));
+        assert_eq!(info.last_timestamp, Some(i64::MAX));
+        assert_eq!(info.duration, i64::MAX);
+        assert_eq!(
+            info.bitrate,
+            Some((info.size as u128 * 8 * 1_000_000_000 / i64::MAX as u128) as u64)
+        );
+
+        // Every frame received at once, without a duration to take the
+        // bitrate over
+        for entry in recording[entries_start..entries_end].chunks_mut(16) {
+            entry[8..].copy_from_slice(&5i64.to_le_bytes());
+        }
+        std::fs::write(&input, &recording).unwrap();
+        let info = crate::probe(&input).unwrap();
+        assert_eq!(info.duration, 0);
+        assert_eq!(info.bitrate, None);
+    }
+
+    #[test]
+    fn serde_round_trips() {
+        fn round_trip<T>(value: &T) -> serde_json::Value
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let json = serde_json::to_value(value).unwrap();
+            assert_eq!(&serde_json::from_value::<T>(json.clone()).unwrap(), value);
+            json
+        }
+
+        let json = round_trip(&crate::VideoCaptureFormat::H265);
+        assert_eq!(json, "H265");
+        let json = round_trip(&crate::VideoCaptureFormat::Unknown(-4801));
+        assert_eq!(json, "Unknown(-4801)");
+        assert_eq!(crate::VideoCaptureFormat::Mjpeg.code(), -4603);
+        assert!(serde_json::from_str::<crate::VideoCaptureFormat>("\"H266\"").is_err());
+
+        let frame = crate::VrawReader::open("assets/h264.vraw")
+            .unwrap()
+            .frame(0)
+            .unwrap();
+        let json = round_trip(&frame);
+        assert_eq!(json["format"], "H264");
+        assert_eq!(json["receive_timestamp"], 7_723_900);
+        assert!(json["raw_data"].as_str().unwrap().starts_with("AAAAA"));
+        assert!(json["placement_metadata"].is_string());
+
+        let info = crate::probe("assets/h264.vraw").unwrap();
+        let json = round_trip(&info);
+        assert_eq!(json["first_timestamp"], 7_723_900);
+
+        let output = std::env::temp_dir().join("vraw_convert_serde.mp4");
+        let summary = crate::convert_vraw("assets/h264_gaps.vraw", Some(output)).unwrap();
+        assert!(!summary.gaps.is_empty());
+        let json = round_trip(&summary);
+        assert_eq!(json["format"], "H264");
+        assert_eq!(json["gaps"][0]["duration"], summary.gaps[0].duration);
+    }
+
+    #[test]
+    fn keep_existing_output() {
+        let output = std::env::temp_dir().join("vraw_convert_existing.mp4");
+        std::fs::write(&output, b"existing").unwrap();
+
+        let e = crate::processing::convert_vraw_with_options(
+            "assets/mjpeg.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                no_overwrite: true,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(e, crate::VrawError::InvalidOptions(_)));
+        assert_eq!(std::fs::read(&output).unwrap(), b"existing");
+
+        crate::processing::convert_vraw("assets/mjpeg.vraw", Some(output.to_path_buf())).unwrap();
+        assert_ne!(std::fs::read(&output).unwrap(), b"existing");
+    }
+
+    #[test]
+    fn mjpeg_samples_start_at_zero() {
+        let output = std::env::temp_dir().join("vraw_convert_mjpeg_start.mp4");
+        crate::processing::convert_vraw("assets/mjpeg.vraw", Some(output.to_path_buf())).unwrap();
+
+        let file = std::fs::File::open(&output).unwrap();
+        let size = file.metadata().unwrap().len();
+        let mut reader = mp4::Mp4Reader::read_header(std::io::BufReader::new(file), size).unwrap();
+
+        // 12 frames received 40 ms apart from 5 ms on, the last frame lasts as
+        // long as the one before it
+        assert_eq!(reader.sample_count(1).unwrap(), 12);
+        assert_eq!(reader.duration(), std::time::Duration::from_millis(480));
+
+        let timescale = crate::DEFAULT_MP4_TIMESCALE as u64;
+        for sample_id in 1..=12 {
+            let sample = reader.read_sample(1, sample_id).unwrap().unwrap();
+            assert_eq!(sample.start_time, (sample_id as u64 - 1) * timescale / 25);
+            assert_eq!(sample.duration as u64, timescale / 25);
+        }
+    }
+
+    #[test]
+    fn try_convert_mjpeg_elementary_stream() {
+        let output = std::env::temp_dir().join("vraw_convert_mjpeg.mjpeg");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ElementaryStream),
+            ..Default::default()
+        };
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/mjpeg.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        // The Stats frames between the MJPEG frames are left out of the stream
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/mjpeg.vraw").unwrap());
+        let parse_options = crate::ParseOptions::default();
+        let mut jpeg_data = Vec::new();
+        let mut stats_frames = 0;
+        for entry in crate::parser::read_index(&mut f, &parse_options)
+            .unwrap()
+            .entries
+        {
+            let frame = crate::parser::parse_raw_frame(&mut f, &entry, &parse_options).unwrap();
+            match frame.format {
+                crate::VideoCaptureFormat::Mjpeg => jpeg_data.extend(frame.raw_data),
+                _ => stats_frames += 1,
+            }
+        }
+
+        assert_eq!(stats_frames, 3);
+        assert_eq!(summary.frames_written, 12);
+        assert!(summary.frames_skipped.is_empty());
+        assert_eq!(std::fs::read(&output).unwrap(), jpeg_data);
+    }
+
+    #[test]
+    fn convert_mjpeg_to_avi() {
+        let output = std::env::temp_dir().join("vraw_convert_mjpeg.avi");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::Avi),
+            ..Default::default()
+        };
+
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/mjpeg.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(summary.frames_written, 12);
+        assert_eq!(
+            std::fs::read(&output).unwrap(),
+            std::fs::read("assets/mjpeg.avi").unwrap()
+        );
+    }
+
+    #[test]
+    fn convert_to_memory() {
+        let output = std::env::temp_dir().join("vraw_convert_to_memory.mp4");
+        crate::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+        assert!(
+            crate::convert_vraw_to_vec("assets/h264.vraw").unwrap()
+                == std::fs::read(&output).unwrap()
+        );
+
+        // Written after what the writer holds already
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::Avi),
+            ..Default::default()
+        };
+        let mut writer = std::io::Cursor::new(b"header".to_vec());
+        writer.set_position(6);
+        let summary =
+            crate::convert_vraw_to_writer("assets/mjpeg.vraw", &mut writer, &options).unwrap();
+        let golden = std::fs::read("assets/mjpeg.avi").unwrap();
+        assert_eq!(summary.frames_written, 12);
+        assert_eq!(summary.output_size, golden.len() as u64);
+        assert_eq!(summary.output, std::path::PathBuf::new());
+        assert!(writer.get_ref()[6..] == golden[..]);
+
+        let output = std::env::temp_dir().join("vraw_convert_to_memory_fragmented.mp4");
+        let options = crate::ConvertOptions {
+            fragment_duration: Some(std::time::Duration::from_secs(1)),
+            ..Default::default()
+        };
+        crate::convert_vraw_with_options("assets/h265.vraw", Some(output.to_path_buf()), &options)
+            .unwrap();
+        let mut writer = std::io::Cursor::new(Vec::new());
+        crate::convert_vraw_to_writer("assets/h265.vraw", &mut writer, &options).unwrap();
+        assert!(writer.into_inner() == std::fs::read(&output).unwrap());
+
+        for options in [
+            crate::ConvertOptions {
+                container: Some(crate::Container::ImageSequence),
+                ..Default::default()
+            },
+            crate::ConvertOptions {
+                faststart: true,
+                ..Default::default()
+            },
+            crate::ConvertOptions {
+                export_stats: true,
+                ..Default::default()
+            },
+        ] {
+            let mut writer = std::io::Cursor::new(Vec::new());
+            assert!(matches!(
+                crate::convert_vraw_to_writer("assets/mjpeg.vraw", &mut writer, &options),
+                Err(crate::VrawError::InvalidOptions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn convert_mjpeg_to_jpeg_sequence() {
+        let output = std::env::temp_dir().join("vraw_convert_jpeg_sequence");
+        let _ = std::fs::remove_dir_all(&output);
+
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ImageSequence),
+            ..Default::default()
+        };
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/mjpeg.vraw",
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+
+        assert_eq!(summary.frames_written, 12);
+        assert_eq!(files.len(), 12);
+        assert_eq!(
+            summary.output_size,
+            files
+                .iter()
+                .map(|file| std::fs::metadata(file).unwrap().len())
+                .sum::<u64>()
+        );
+
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/mjpeg.vraw").unwrap());
+        let entries = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+            .unwrap()
+            .entries;
+        let first_frame =
+            crate::parser::parse_raw_frame(&mut f, &entries[0], &crate::ParseOptions::default())
+                .unwrap();
+
+        assert_eq!(
+            files[0].file_name().unwrap().to_str().unwrap(),
+            format!("mjpeg_000000_{}.jpg", first_frame.receive_timestamp)
+        );
+        assert_eq!(std::fs::read(&files[0]).unwrap(), first_frame.raw_data);
+    }
+
+    #[test]
+    fn convert_nv12_to_y4m() {
+        let output = std::env::temp_dir().join("vraw_convert_nv12.y4m");
+
+        let summary =
+            crate::processing::convert_vraw("assets/nv12.vraw", Some(output.to_path_buf()))
+                .unwrap();
+
+        let y4m = std::fs::read(&output).unwrap();
+        let header = b"YUV4MPEG2 W8 H4 F25000:1000 Ip A1:1 C420jpeg\n";
+
+        assert_eq!(summary.frames_written, 5);
+        assert!(y4m.starts_with(header));
+        assert_eq!(
+            y4m.len(),
+            header.len() + 5 * (b"FRAME\n".len() + 8 * 4 * 3 / 2)
+        );
+
+        // The interleaved chroma of NV12 is split into U and V planes
+        let first_frame = &y4m[header.len() + b"FRAME\n".len()..][..8 * 4 * 3 / 2];
+        assert_eq!(&first_frame[32..40], &[0x40; 8]);
+        assert_eq!(&first_frame[40..48], &[0xC0; 8]);
+    }
+
+    #[test]
+    fn packed_422_to_i420() {
+        use crate::rawvideo::{packed422_to_i420, Packed422};
+
+        // 4x2 frame, luma 10..=17 in reading order, chroma differing per row
+        let luma = [10u8, 11, 12, 13, 14, 15, 16, 17];
+        let u = [[100u8, 102], [110, 112]];
+        let v = [[200u8, 202], [210, 212]];
+
+        let mut yuyv = Vec::new();
+        let mut uyvy = Vec::new();
+        for row in 0..2 {
+            for pair in 0..2 {
+                let (y0, y1) = (luma[row * 4 + pair * 2], luma[row * 4 + pair * 2 + 1]);
+                yuyv.extend_from_slice(&[y0, u[row][pair], y1, v[row][pair]]);
+                uyvy.extend_from_slice(&[u[row][pair], y0, v[row][pair], y1]);
+            }
+        }
+
+        let expected = [10, 11, 12, 13, 14, 15, 16, 17, 105, 107, 205, 207];
+
+        assert_eq!(packed422_to_i420(&yuyv, 4, 2, Packed422::Yuyv), expected);
+        assert_eq!(packed422_to_i420(&uyvy, 4, 2, Packed422::Uyvy), expected);
+    }
+
+    #[test]
+    fn convert_bgr_to_png_sequence() {
+        let output = std::env::temp_dir().join("vraw_convert_png_sequence");
+        let _ = std::fs::remove_dir_all(&output);
+
+        let summary =
+            crate::processing::convert_vraw("assets/bgr.vraw", Some(output.to_path_buf())).unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+
+        assert_eq!(summary.frames_written, 3);
+        assert_eq!(files.len(), 3);
+
+        let decoder = png::Decoder::new(std::fs::File::open(&files[0]).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        // The first pixel is stored as pure blue in BGR order
+        assert_eq!(&pixels[..3], &[0, 0, 255]);
+    }
+
+    fn read_mono16_png(path: &std::path::Path) -> Vec<u16> {
+        let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+
+        pixels
+            .chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn convert_mono16_to_png_sequence() {
+        for auto_scale in [false, true] {
+            let output = std::env::temp_dir().join(format!("vraw_convert_mono16_{}", auto_scale));
+            let _ = std::fs::remove_dir_all(&output);
+
+            let options = crate::ConvertOptions {
+                auto_scale,
+                ..Default::default()
+            };
+            let summary = crate::processing::convert_vraw_with_options(
+                "assets/mono16.vraw",
+                Some(output.to_path_buf()),
+                &options,
+            )
+            .unwrap();
+
+            let mut files: Vec<_> = std::fs::read_dir(&output)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            files.sort();
+
+            assert_eq!(summary.frames_written, 2);
+
+            let samples = read_mono16_png(&files[0]);
+            if auto_scale {
+                assert_eq!(&samples[..3], &[0, 21845, 43690]);
+                assert_eq!(samples[7], u16::MAX);
+            } else {
+                assert_eq!(&samples[..3], &[1000, 1100, 1200]);
+                assert_eq!(samples[7], 1300);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_raw16_to_png_sequence() {
+        for (pattern, expected) in [
+            (crate::BayerPattern::Rggb, [1000, 2000, 3000]),
+            (crate::BayerPattern::Bggr, [3000, 2000, 1000]),
+        ] {
+            let output = std::env::temp_dir().join(format!("vraw_convert_raw16_{:?}", pattern));
+            let _ = std::fs::remove_dir_all(&output);
+
+            let options = crate::ConvertOptions {
+                bayer_pattern: pattern,
+                ..Default::default()
+            };
+            let summary = crate::processing::convert_vraw_with_options(
+                "assets/raw16.vraw",
+                Some(output.to_path_buf()),
+                &options,
+            )
+            .unwrap();
+
+            let files: Vec<_> = std::fs::read_dir(&output)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+
+            assert_eq!(summary.frames_written, 2);
+
+            let decoder = png::Decoder::new(std::fs::File::open(&files[0]).unwrap());
+            let mut reader = decoder.read_info().unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut pixels).unwrap();
+
+            // A uniformly lit RGGB sensor demosaics to the same color everywhere
+            for pixel in pixels.chunks_exact(6) {
+                let rgb: Vec<u16> = pixel
+                    .chunks_exact(2)
+                    .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+                    .collect();
+                assert_eq!(rgb, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_mono8_skips_frames_of_wrong_size() {
+        let output = std::env::temp_dir().join("vraw_convert_mono8");
+        let _ = std::fs::remove_dir_all(&output);
+
+        let summary =
+            crate::processing::convert_vraw("assets/mono8.vraw", Some(output.to_path_buf()))
+                .unwrap();
+
+        // The second of the three frames is truncated
+        assert_eq!(summary.frames_written, 2);
+        assert_eq!(std::fs::read_dir(&output).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn export_stats_of_stats_only_recording() {
+        let output = std::env::temp_dir().join("vraw_convert_stats.json");
+
+        let stats_written = crate::processing::export_stats("assets/stats.vraw", &output).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+
+        assert_eq!(stats_written, 2);
+        assert_eq!(json[0]["timestamp"], 1_000_000);
+        assert_eq!(json[0]["receive_timestamp"], 2_000_000);
+        assert_eq!(json[0]["encoding"], "utf8");
+        assert_eq!(json[0]["payload"], "{\"bitrate\": 4000}");
+        assert_eq!(json[1]["encoding"], "base64");
+        assert_eq!(json[1]["payload"], "AP8BAv7/AP8BAv7/AP8BAv7/AP8BAv7/");
+
+        // Converting with stats export must not fail on the missing video
+        let summary = crate::processing::convert_vraw_with_options(
+            "assets/stats.vraw",
+            Some(output.to_path_buf()),
+            &crate::processing::ConvertOptions {
+                export_stats: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.frames_written, 0);
+        assert_eq!(summary.stats_written, 2);
+    }
+
+    #[test]
+    fn fail_on_recording_without_video() {
+        let e = crate::convert_vraw("assets/stats.vraw", None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: no video frames found, the recording holds 2 Stats frames"
+        );
+
+        // The first of the two Stats frames with a broken magic
+        let mut recording = std::fs::read("assets/stats.vraw").unwrap();
+        recording[16] ^= 0xFF;
+        let input = std::env::temp_dir().join("vraw_convert_stats_corrupt.vraw");
+        std::fs::write(&input, recording).unwrap();
+        let e = crate::convert_vraw(&input, None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: no video frames found, the recording holds 1 Stats frames and 1 \
+             frames that cannot be read"
+        );
     }
 
     #[test]
     fn try_convert_no_video_alignment_data() {
-        crate::processing::convert_vraw_to_mp4(
-            &"assets/no_output_alignment.vraw".to_string(),
-            None,
+        crate::processing::convert_vraw("assets/no_output_alignment.vraw", None).unwrap();
+    }
+
+    /// Writes a recording holding a single frame with the given payload and an
+    /// empty generic metadata block
+    fn write_single_frame_vraw(path: &std::path::Path, format: i32, payload: &[u8]) {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xFEEDFEEDu32.to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+
+        data.extend_from_slice(&0xAAAAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&0i64.to_le_bytes());
+        data.extend_from_slice(&1_000_000i64.to_le_bytes());
+        data.extend_from_slice(&(payload.len() as i64).to_le_bytes());
+        data.extend_from_slice(payload);
+        data.extend_from_slice(&0xBACCDEEFu32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0xBACCBEEFu32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        data.extend_from_slice(&0xABCDFEEDu32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&16i64.to_le_bytes());
+        data.extend_from_slice(&1_000_000i64.to_le_bytes());
+        data.extend_from_slice(&0xDCBAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+
+        std::fs::write(path, data).unwrap();
+    }
+
+    fn parse_single_frame(path: &std::path::Path) -> Result<crate::parser::FrameInfo, String> {
+        parse_single_frame_with(path, crate::Strictness::Lenient)
+    }
+
+    fn parse_single_frame_with(
+        path: &std::path::Path,
+        strictness: crate::Strictness,
+    ) -> Result<crate::parser::FrameInfo, String> {
+        let options = crate::ParseOptions {
+            strictness,
+            ..Default::default()
+        };
+        let mut f = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+        let index = crate::parser::read_index(&mut f, &options).unwrap().entries;
+        crate::parser::parse_raw_frame(&mut f, &index[0], &options).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn parse_frames_smaller_than_placement_footer() {
+        let path = std::env::temp_dir().join("vraw_convert_tiny_frame.vraw");
+
+        write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &[]);
+        assert_eq!(
+            parse_single_frame(&path).unwrap_err(),
+            "Frame size not parsed correctly."
+        );
+
+        for size in 1..=10 {
+            let payload: Vec<u8> = (1..=size).collect();
+            write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &payload);
+
+            let frame = parse_single_frame(&path).unwrap();
+            assert_eq!(frame.raw_data, payload);
+            assert_eq!(frame.placement_metadata, None);
+        }
+
+        // A bare footer, and a footer claiming more metadata than the frame holds
+        let footer = [0, 0, 0, 0, 0, 0x56, 0x4A];
+        write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &footer);
+        let frame = parse_single_frame(&path).unwrap();
+        assert!(frame.raw_data.is_empty());
+        assert_eq!(frame.placement_metadata, Some(Vec::new()));
+
+        let footer = [1, 2, 3, 0x20, 0, 0, 0, 0, 0x56, 0x4A];
+        write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &footer);
+        let frame = parse_single_frame(&path).unwrap();
+        assert_eq!(frame.raw_data, footer);
+        assert_eq!(frame.placement_metadata, None);
+    }
+
+    #[test]
+    fn rebuild_index_of_crashed_recording() {
+        let recording = std::fs::read("assets/h265.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_len = 8 + 16 * frame_count as usize + 8;
+        let frames_len = recording.len() - index_len;
+
+        let input = std::env::temp_dir().join("vraw_convert_crashed.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_crashed.mp4");
+        let convert = |data: &[u8]| {
+            std::fs::write(&input, data).unwrap();
+            crate::processing::convert_vraw(&input, Some(output.to_path_buf()))
+                .unwrap()
+                .frames_written
+        };
+
+        // Without the index, with a corrupt footer and with the last frame cut off
+        assert_eq!(convert(&recording[..frames_len]), 1265);
+        let mut corrupt = recording.clone();
+        corrupt[recording.len() - 8] ^= 0xFF;
+        assert_eq!(convert(&corrupt), 1265);
+        assert_eq!(convert(&recording[..frames_len - 100]), 1264);
+    }
+
+    #[test]
+    fn convert_files_too_small_to_be_recordings() {
+        let input = std::env::temp_dir().join("vraw_convert_too_small.vraw");
+        let convert = |data: &[u8]| {
+            std::fs::write(&input, data).unwrap();
+            crate::processing::convert_vraw(&input, None)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(convert(&[]).ends_with("file too small to be a vraw recording (0 bytes)"));
+        assert!(convert(&[0xED; 7]).ends_with("file too small to be a vraw recording (7 bytes)"));
+
+        // Only an index footer, of an empty index
+        let mut footer = 0xDCBAFEEDu32.to_le_bytes().to_vec();
+        footer.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(convert(&footer), "vraw_convert: index contains no frames");
+    }
+
+    #[test]
+    fn validate_index_offsets() {
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+        let entry_offset = |i: usize| index_offset + 16 * i;
+
+        // An entry beyond the end of the file and one pointing back at the first frame
+        let offset = entry_offset(3);
+        recording[offset..offset + 8].copy_from_slice(&(1i64 << 40).to_le_bytes());
+        let offset = entry_offset(7);
+        recording[offset..offset + 8].copy_from_slice(&16i64.to_le_bytes());
+
+        let input = std::env::temp_dir().join("vraw_convert_invalid_index.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_invalid_index.mp4");
+        std::fs::write(&input, &recording).unwrap();
+        let convert = |lenient_index| {
+            crate::processing::convert_vraw_with_options(
+                &input,
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    lenient_index,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let err = convert(false).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                crate::VrawError::CorruptIndex {
+                    offset: Some(1099511627776),
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+        assert!(err
+            .to_string()
+            .starts_with("vraw_convert: invalid index entry 3: offset 1099511627776 outside"));
+
+        let summary = crate::processing::convert_vraw("assets/h264.vraw", None).unwrap();
+        let lenient_summary = convert(true).unwrap();
+        assert_eq!(lenient_summary.frames_written, summary.frames_written - 2);
+    }
+
+    #[test]
+    fn convert_h264_with_corrupt_frames() {
+        let output = std::env::temp_dir().join("vraw_convert_corrupt.mp4");
+        let convert = |stop_at_corrupt_frame| {
+            crate::processing::convert_vraw_with_options(
+                "assets/h264_corrupt.vraw",
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    stop_at_corrupt_frame,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // Frame 10 of 30 is corrupt, and frame 20 has a format unknown to the
+        // parser
+        let summary = convert(false);
+        assert_eq!(summary.frames_written, 28);
+        assert_eq!(summary.frames_corrupt, 1);
+        assert_eq!(summary.output_warnings.len(), 1);
+        assert!(summary.output_warnings[0].starts_with("Skipping corrupt frame 10 at offset "));
+        assert_eq!(
+            summary.frames_skipped[&crate::VideoCaptureFormat::Unknown(12345)],
+            1
+        );
+
+        let mut boxes = crate::isobmff::Mp4Box::parse(&std::fs::read(&output).unwrap()).unwrap();
+        let moov = boxes.iter_mut().find(|mp4_box| &mp4_box.kind == b"moov");
+        let stsz = moov
+            .unwrap()
+            .find_mut(&[b"trak", b"mdia", b"minf", b"stbl", b"stsz"])
+            .unwrap();
+        assert_eq!(u32::from_be_bytes(stsz.data[8..12].try_into().unwrap()), 28);
+
+        let summary = convert(true);
+        assert_eq!(summary.frames_written, 10);
+        assert_eq!(summary.frames_corrupt, 1);
+    }
+
+    #[test]
+    fn convert_recording_with_truncated_last_frame() {
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let frames_len = recording.len() - 8 - 16 * frame_count as usize - 8;
+        let last_entry = frames_len + 8 + 16 * (frame_count as usize - 1);
+        let last_frame =
+            i64::from_le_bytes(recording[last_entry..last_entry + 8].try_into().unwrap()) as usize;
+
+        let input = std::env::temp_dir().join("vraw_convert_truncated.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_truncated.mp4");
+        let convert = |data: &[u8]| {
+            std::fs::write(&input, data).unwrap();
+            crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap()
+        };
+
+        let summary = convert(&recording[..frames_len]);
+        assert!(!summary.truncated);
+
+        // Cut right after the header, in the payload and in the generic
+        // metadata header and footer
+        for cut in [
+            last_frame + 48,
+            last_frame + 60,
+            frames_len - 12,
+            frames_len - 4,
+        ] {
+            let truncated_summary = convert(&recording[..cut]);
+            assert!(truncated_summary.truncated);
+            assert_eq!(truncated_summary.frames_written, summary.frames_written - 1);
+            assert_eq!(truncated_summary.frames_corrupt, 0);
+        }
+    }
+
+    #[test]
+    fn parse_frame_metadata_without_payload() {
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/mixed.vraw").unwrap());
+        let index = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+            .unwrap()
+            .entries;
+
+        for entry in &index {
+            let metadata = crate::parser::parse_frame_metadata(&mut f, entry).unwrap();
+            let frame =
+                crate::parser::parse_raw_frame(&mut f, entry, &crate::ParseOptions::default())
+                    .unwrap();
+
+            assert_eq!(metadata.format, frame.format);
+            assert_eq!(metadata.receive_timestamp, frame.receive_timestamp);
+            assert_eq!(metadata.capture_timestamp, frame.capture_timestamp);
+            assert!(metadata.size >= frame.raw_data.len());
+            if !frame.format.is_coded() {
+                assert_eq!(
+                    (metadata.width, metadata.height),
+                    (frame.width, frame.height)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse_frames_with_absurd_sizes() {
+        let path = std::env::temp_dir().join("vraw_convert_absurd_size.vraw");
+        let parse_with_size = |size: i64| {
+            write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &[0; 32]);
+            let mut data = std::fs::read(&path).unwrap();
+            data[56..64].copy_from_slice(&size.to_le_bytes());
+            std::fs::write(&path, data).unwrap();
+
+            parse_single_frame(&path)
+        };
+
+        // 32 bytes of payload, 16 of generic metadata and 32 of index follow the header
+        assert_eq!(
+            parse_with_size(1 << 62).unwrap_err(),
+            "frame at offset 16 claims 4611686018427387904 bytes, more than the 80 bytes left in \
+             the file"
+        );
+        assert_eq!(
+            parse_with_size(81).unwrap_err(),
+            "frame at offset 16 claims 81 bytes, more than the 80 bytes left in the file"
+        );
+        assert_eq!(
+            parse_with_size(i64::MIN).unwrap_err(),
+            "Frame size not parsed correctly."
+        );
+        assert_eq!(
+            parse_with_size(80).unwrap_err(),
+            "frame at offset 16 is cut off by the end of the file, 128 bytes after it"
+        );
+        assert_eq!(parse_with_size(32).unwrap().raw_data, [0; 32]);
+    }
+
+    #[test]
+    fn parse_frames_with_corrupt_generic_metadata() {
+        let path = std::env::temp_dir().join("vraw_convert_corrupt_generic_metadata.vraw");
+        // 25 bytes of frame and a video placement footer of no metadata
+        let mut payload = vec![0; 25];
+        payload.extend_from_slice(&[0, 0, 0, 0, 0, 0x56, 0x4A]);
+        let write_with = |position: usize, value: u32| {
+            write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &payload);
+            let mut data = std::fs::read(&path).unwrap();
+            data[position..position + 4].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&path, data).unwrap();
+        };
+        let parse_with = |position: usize, value: u32| {
+            write_with(position, value);
+            parse_single_frame_with(&path, crate::Strictness::Strict).unwrap_err()
+        };
+
+        // The generic metadata header follows the 32 bytes of payload at 96
+        assert_eq!(
+            parse_with(100, 1 << 30),
+            "Frame at offset 16 claims 1073741824 bytes of generic metadata, more than the \
+             16777216 accepted"
+        );
+        assert_eq!(
+            parse_with(100, 100),
+            "frame at offset 16 is cut off by the end of the file, 40 bytes after it"
+        );
+        assert_eq!(
+            parse_with(96, 0xDEADBEEF),
+            "Generic metadata header of the frame at offset 16: Magic does not match"
+        );
+        assert_eq!(
+            parse_with(104, 0xDEADBEEF),
+            "Generic metadata footer of the frame at offset 16: Magic does not match"
+        );
+        assert_eq!(
+            parse_with(108, 4),
+            "Generic metadata footer of the frame at offset 16 claims 4 bytes, the header 0"
+        );
+
+        // Lenient parsing keeps the metadata of a footer not matching its header
+        write_with(108, 4);
+        let frame = parse_single_frame(&path).unwrap();
+        assert_eq!(frame.raw_data, [0; 25]);
+        assert_eq!(
+            frame.warnings,
+            ["Generic metadata footer of the frame at offset 16 claims 4 bytes, the header 0"]
+        );
+    }
+
+    #[test]
+    fn read_truncated_index_and_header() {
+        let path = std::env::temp_dir().join("vraw_convert_truncated_index.vraw");
+        let read = |data: &[u8]| {
+            std::fs::write(&path, data).unwrap();
+            let mut f = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+            (
+                crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+                    .map(|index| index.entries.len()),
+                crate::parser::read_recording_start(&mut f).map(|_| ()),
+            )
+        };
+
+        // A recording header followed by the footer of an index of 5 frames
+        let mut data = 0xFEEDFEEDu32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&0xDCBAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&5u32.to_le_bytes());
+        let (index, recording_start) = read(&data);
+        assert_eq!(
+            index.unwrap_err().to_string(),
+            "Index footer claims 5 frames, which do not fit in the 24 bytes of the file"
+        );
+        assert!(recording_start.is_ok());
+
+        // Only the footer of an empty index
+        data[20..24].copy_from_slice(&0u32.to_le_bytes());
+        let (index, recording_start) = read(&data[16..]);
+        assert_eq!(index.unwrap(), 0);
+        assert_eq!(
+            recording_start.unwrap_err().to_string(),
+            "Failed to read the recording header at offset 0, expected 16 bytes: failed to fill \
+             whole buffer"
+        );
+    }
+
+    #[test]
+    fn sort_and_deduplicate_index_entries() {
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+        let mut entries: Vec<&[u8]> = recording[index_offset..recording.len() - 8]
+            .chunks(16)
+            .collect();
+
+        // Two entries swapped and one written twice
+        entries.swap(4, 5);
+        entries.push(entries[2]);
+
+        let mut data = recording[..index_offset].to_vec();
+        data.extend(entries.concat());
+        data.extend_from_slice(&0xDCBAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+        let input = std::env::temp_dir().join("vraw_convert_unsorted_index.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_unsorted_index.mp4");
+        std::fs::write(&input, data).unwrap();
+
+        let summary = crate::processing::convert_vraw("assets/h264.vraw", None).unwrap();
+        assert_eq!(summary.index_entries_reordered, 0);
+        assert_eq!(summary.index_entries_duplicated, 0);
+
+        let repaired_summary =
+            crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(repaired_summary.index_entries_reordered, 2);
+        assert_eq!(repaired_summary.index_entries_duplicated, 1);
+        assert_eq!(repaired_summary.frames_written, summary.frames_written);
+    }
+
+    #[test]
+    fn rebuild_index_listing_no_frames() {
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+
+        let mut data = recording[..index_offset].to_vec();
+        data.extend_from_slice(&0xDCBAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let input = std::env::temp_dir().join("vraw_convert_unindexed.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_unindexed.mp4");
+        std::fs::write(&input, data).unwrap();
+
+        let summary = crate::processing::convert_vraw("assets/h264.vraw", None).unwrap();
+        let rebuilt_summary =
+            crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(rebuilt_summary.frames_written, summary.frames_written);
+    }
+
+    #[test]
+    fn convert_recording_with_negative_timestamps() {
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+        let read_i64 = |data: &[u8], offset: usize| {
+            i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+        };
+
+        // Shift the receive timestamps in the index and the frame headers to
+        // start at -5 s, as recorded by a device whose clock was not set
+        let shift = -5_000_000_000 - read_i64(&recording, index_offset + 8);
+        for i in 0..frame_count as usize {
+            let entry = index_offset + 16 * i;
+            let frame = read_i64(&recording, entry) as usize;
+            for offset in [entry + 8, frame + 32] {
+                let timestamp = read_i64(&recording, offset) + shift;
+                recording[offset..offset + 8].copy_from_slice(&timestamp.to_le_bytes());
+            }
+        }
+
+        let input = std::env::temp_dir().join("vraw_convert_negative_timestamps.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_negative_timestamps.mp4");
+        let reference_output = std::env::temp_dir().join("vraw_convert_reference_timestamps.mp4");
+        std::fs::write(&input, recording).unwrap();
+
+        crate::processing::convert_vraw("assets/h264.vraw", Some(reference_output.to_path_buf()))
+            .unwrap();
+        crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+
+        // The timing is relative to the first frame, so it is unchanged
+        assert!(std::fs::read(output).unwrap() == std::fs::read(reference_output).unwrap());
+    }
+
+    #[test]
+    fn repair_timestamp_regressions() {
+        use crate::TimestampRegressions::{Clamp, Drop, Interpolate};
+
+        let timestamps = [0, 10, 20, 15, 17, 40, 30];
+        let repair = |policy| crate::processing::repair_timestamps(&timestamps, policy);
+        assert_eq!(
+            repair(Clamp),
+            timestamps.iter().map(|&t| Some(t)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            repair(Drop),
+            [Some(0), Some(10), Some(20), None, None, Some(40), None]
+        );
+        assert_eq!(
+            repair(Interpolate),
+            [
+                Some(0),
+                Some(10),
+                Some(20),
+                Some(26),
+                Some(33),
+                Some(40),
+                Some(30)
+            ]
+        );
+    }
+
+    #[test]
+    fn convert_recording_with_timestamp_regression() {
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+        let read_i64 = |data: &[u8], offset: usize| {
+            i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+        };
+
+        // Step the clock back after frame 9, so that the H.264 frames 11 and
+        // 12 have a timestamp before it
+        let step = read_i64(&recording, index_offset + 16 * 12 + 8)
+            - read_i64(&recording, index_offset + 16 * 9 + 8)
+            + 1;
+        for i in 10..frame_count as usize {
+            let entry = index_offset + 16 * i;
+            let frame = read_i64(&recording, entry) as usize;
+            let timestamp = read_i64(&recording, frame + 32) - step;
+            recording[frame + 32..frame + 40].copy_from_slice(&timestamp.to_le_bytes());
+        }
+
+        let input = std::env::temp_dir().join("vraw_convert_timestamp_regression.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_timestamp_regression.mp4");
+        std::fs::write(&input, recording).unwrap();
+        let convert = |timestamp_regressions| {
+            crate::processing::convert_vraw_with_options(
+                &input,
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    timestamp_regressions,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let durations = |summary: crate::ConversionSummary| {
+            let mp4 = std::fs::File::open(&output).unwrap();
+            let size = mp4.metadata().unwrap().len();
+            let mut reader =
+                mp4::Mp4Reader::read_header(std::io::BufReader::new(mp4), size).unwrap();
+            assert_eq!(summary.timestamp_regressions, 2);
+            assert_eq!(
+                reader.sample_count(1).unwrap() as usize,
+                summary.frames_written
+            );
+
+            (1..=reader.sample_count(1).unwrap())
+                .map(|sample| reader.read_sample(1, sample).unwrap().unwrap().duration)
+                .collect::<Vec<_>>()
+        };
+
+        let clamped = durations(convert(crate::TimestampRegressions::Clamp));
+        assert_eq!(clamped.iter().filter(|&&duration| duration == 1).count(), 2);
+        let dropped = durations(convert(crate::TimestampRegressions::Drop));
+        assert_eq!(dropped.len(), clamped.len() - 2);
+        let interpolated = durations(convert(crate::TimestampRegressions::Interpolate));
+        assert_eq!(interpolated.len(), clamped.len());
+        assert!(interpolated.iter().all(|&duration| duration > 500));
+    }
+
+    #[test]
+    fn derive_output_names() {
+        let derive = |input: &str| {
+            let output = crate::processing::derive_output_from_input(
+                std::path::Path::new(input),
+                "mp4",
+                chrono::Local::now(),
+            )
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+            let (name, time) = output.split_at(output.len() - "_2022-01-01T12_00_00.mp4".len());
+            assert!(time.starts_with('_') && time.ends_with(".mp4"), "{output}");
+
+            name.to_string()
+        };
+
+        assert_eq!(
+            derive("recordings/a/recording.vraw"),
+            "recordings/recording"
+        );
+        assert_eq!(
+            derive("recordings/a/RECORDING.VRAW"),
+            "recordings/RECORDING"
+        );
+        assert_eq!(derive("recordings/a/capture.raw"), "recordings/capture");
+        assert_eq!(derive("recordings/a/recording"), "recordings/recording");
+        assert_eq!(
+            derive("recordings/a/recording.vraw.vraw"),
+            "recordings/recording.vraw"
+        );
+        assert_eq!(
+            derive("recordings/a/cam1.2024-05-01"),
+            "recordings/cam1.2024-05-01"
+        );
+        assert_eq!(
+            derive("recordings/a/cam1.2024-05-01.vraw"),
+            "recordings/cam1.2024-05-01"
+        );
+    }
+
+    #[test]
+    fn derive_output_names_of_unusual_paths() {
+        let derive = |input: &std::path::Path| {
+            crate::processing::derive_output_from_input(input, "mp4", chrono::Local::now())
+        };
+
+        // Inputs in the root folder or without folders are named in the
+        // current folder
+        for input in ["/recording.vraw", "recording.vraw"] {
+            let output = derive(std::path::Path::new(input)).unwrap();
+            assert_eq!(output.parent(), Some(std::path::Path::new("")));
+        }
+
+        assert_eq!(
+            derive(std::path::Path::new("recordings/.."))
+                .unwrap_err()
+                .to_string(),
+            "vraw_convert: no output can be named after recordings/.., which has no file name"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let input = std::ffi::OsStr::from_bytes(b"recordings/a/recording\xFF.vraw");
+            let output = derive(std::path::Path::new(input)).unwrap();
+            assert!(output
+                .as_os_str()
+                .as_bytes()
+                .starts_with(b"recordings/recording\xFF_"));
+        }
+    }
+
+    #[test]
+    fn summarize_chosen_output() {
+        let output = std::env::temp_dir().join("vraw_convert_chosen_output.mp4");
+        let summary =
+            crate::processing::convert_vraw("assets/h264.vraw", Some(output.to_path_buf()))
+                .unwrap();
+        assert_eq!(summary.output, output);
+
+        // The output among the options, replaced by the one given apart
+        let options = crate::ConvertOptions {
+            output: Some(output.to_path_buf()),
+            ..Default::default()
+        };
+        let summary = crate::convert_vraw_with("assets/h264.vraw", &options).unwrap();
+        assert_eq!(summary.output, output);
+
+        let other_output = std::env::temp_dir().join("vraw_convert_other_output.mp4");
+        let summary = crate::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(other_output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(summary.output, other_output);
+    }
+
+    #[test]
+    fn name_outputs_after_recording_start() {
+        let recording_start = crate::recording_start("assets/h264.vraw").unwrap();
+        let output = crate::processing::derive_output_from_input(
+            std::path::Path::new("recordings/a/recording.vraw"),
+            "mp4",
+            recording_start.with_timezone(&chrono::Local),
+        )
+        .unwrap();
+
+        let expected = format!(
+            "recordings/recording_{}.mp4",
+            recording_start
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%dT%H_%M_%S")
+        );
+        assert_eq!(output, std::path::Path::new(&expected));
+    }
+
+    #[test]
+    fn convert_recording_without_header() {
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_offset = recording.len() - 8 - 16 * frame_count as usize;
+
+        // Old recorders start with the first frame
+        for i in 0..frame_count as usize {
+            let entry = index_offset + 16 * i;
+            let offset = i64::from_le_bytes(recording[entry..entry + 8].try_into().unwrap()) - 16;
+            recording[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+        }
+        let recording = recording.split_off(16);
+
+        let input = std::env::temp_dir().join("vraw_convert_without_header.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_without_header.mp4");
+        let convert = |data: &[u8]| {
+            std::fs::write(&input, data).unwrap();
+            crate::processing::convert_vraw(&input, Some(output.to_path_buf()))
+                .unwrap()
+                .frames_written
+        };
+
+        let summary = crate::processing::convert_vraw("assets/h264.vraw", None).unwrap();
+        assert_eq!(convert(&recording), summary.frames_written);
+
+        // Also when the index has to be rebuilt
+        let frames_len = index_offset - 8 - 16;
+        assert_eq!(convert(&recording[..frames_len]), summary.frames_written);
+
+        let mut f = std::io::BufReader::new(std::fs::File::open(&input).unwrap());
+        assert!(crate::parser::read_recording_metadata(&mut f)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            crate::recording_start(&input).unwrap_err().to_string(),
+            "vraw_convert: failed to read the recording start time: The recording has no header, \
+             as written by old recorders."
+        );
+    }
+
+    #[test]
+    fn strip_placement_footers_with_padding() {
+        let path = std::env::temp_dir().join("vraw_convert_padded_frame.vraw");
+        let frame = [0, 0, 0, 1, 0x41, 0x9A];
+        let parse = |padding: usize, max_placement_padding| {
+            let mut payload = frame.to_vec();
+            payload.extend_from_slice(&[1, 1, 1, 3, 0, 0, 0, 0, 0x56, 0x4A]);
+            payload.resize(payload.len() + padding, 0);
+            write_single_frame_vraw(&path, i32::from(crate::VideoCaptureFormat::H264), &payload);
+
+            let mut f = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+            let index = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+                .unwrap()
+                .entries;
+            let options = crate::ParseOptions {
+                max_placement_padding,
+                ..Default::default()
+            };
+            crate::parser::parse_raw_frame(&mut f, &index[0], &options).unwrap()
+        };
+
+        for padding in 0..=3 {
+            let parsed = parse(padding, crate::DEFAULT_MAX_PLACEMENT_PADDING);
+            assert_eq!(parsed.raw_data, frame);
+            assert_eq!(parsed.placement_metadata, Some(vec![1, 1, 1]));
+            assert_eq!(parsed.placement_padding, Some(padding));
+        }
+
+        // Padding beyond the search window is kept together with the footer
+        let parsed = parse(13, crate::DEFAULT_MAX_PLACEMENT_PADDING);
+        assert_eq!(parsed.raw_data.len(), frame.len() + 10 + 13);
+        assert_eq!(parsed.placement_padding, None);
+
+        let parsed = parse(13, 16);
+        assert_eq!(parsed.raw_data, frame);
+        assert_eq!(parsed.placement_padding, Some(13));
+
+        let summary = crate::processing::convert_vraw_with_options(
+            &path,
+            Some(path.with_extension("h264")),
+            &crate::ConvertOptions {
+                container: Some(crate::Container::ElementaryStream),
+                max_placement_padding: Some(12),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(summary.placement_footers_missing, 1);
+        // The frame is converted as stored, not left out
+        assert!(summary.output_warnings.is_empty());
+    }
+
+    #[test]
+    fn convert_recording_without_generic_metadata() {
+        let output = std::env::temp_dir().join("vraw_convert_no_generic_metadata.mp4");
+        let summary = crate::processing::convert_vraw(
+            "assets/no_generic_metadata.vraw",
+            Some(output.to_path_buf()),
+        )
+        .unwrap();
+        assert_eq!(summary.frames_written, 10);
+        assert_eq!(summary.frames_corrupt, 0);
+
+        // Also when the index has to be rebuilt, the last frame then cannot
+        // be told from a frame cut off by the end of the file
+        let recording = std::fs::read("assets/no_generic_metadata.vraw").unwrap();
+        let input = std::env::temp_dir().join("vraw_convert_no_generic_metadata.vraw");
+        std::fs::write(&input, &recording[..recording.len() - 8 - 16 * 10 - 8]).unwrap();
+        let summary = crate::processing::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(summary.frames_written, 9);
+        assert!(summary.truncated);
+    }
+
+    #[test]
+    fn convert_strictly_and_leniently() {
+        let convert = |input: &str, strictness| {
+            let output = std::env::temp_dir().join("vraw_convert_strictness.h264");
+            crate::processing::convert_vraw_with_options(
+                input,
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    container: Some(crate::Container::ElementaryStream),
+                    strictness,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let summary = convert("assets/h264.vraw", crate::Strictness::Strict).unwrap();
+        assert!(summary.warnings.is_empty());
+
+        assert_eq!(
+            convert("assets/no_generic_metadata.vraw", crate::Strictness::Strict)
+                .unwrap_err()
+                .to_string(),
+            "vraw_convert: frame 0 at offset 16: Frame at offset 16 has no generic metadata"
+        );
+        let summary = convert(
+            "assets/no_generic_metadata.vraw",
+            crate::Strictness::Lenient,
+        )
+        .unwrap();
+        assert_eq!(summary.frames_written, 10);
+        assert_eq!(summary.warnings.len(), 10);
+
+        assert!(matches!(
+            convert("assets/h264_corrupt.vraw", crate::Strictness::Strict).unwrap_err(),
+            crate::VrawError::BadFrame { index: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn convert_recording_mixing_sources() {
+        let input = std::env::temp_dir().join("vraw_convert_mixed_sources.vraw");
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/h264.vraw").unwrap());
+        let index = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+            .unwrap()
+            .entries;
+        // The frames of the fixture are of source 1, from entry 8 on they are of
+        // a second camera
+        for entry in &index[8..] {
+            let id = entry.offset() as usize + 4;
+            recording[id..id + 4].copy_from_slice(&2i32.to_le_bytes());
+        }
+        std::fs::write(&input, recording).unwrap();
+
+        let convert = |strictness| {
+            crate::processing::convert_vraw_with_options(
+                &input,
+                Some(input.with_extension("h264")),
+                &crate::ConvertOptions {
+                    container: Some(crate::Container::ElementaryStream),
+                    strictness,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let offset = index[8].offset();
+        let summary = convert(crate::Strictness::Lenient).unwrap();
+        assert_eq!(
+            summary.warnings,
+            [format!(
+                "Frame at offset {offset} of source 2 mixes into the H264 video of source 1"
+            )]
+        );
+        assert_eq!(
+            convert(crate::Strictness::Strict).unwrap_err().to_string(),
+            format!(
+                "vraw_convert: frame 8 at offset {offset}: Frame at offset {offset} of source 2 mixes into the H264 \
+                 video of source 1"
+            )
+        );
+
+        let mut f = std::io::BufReader::new(std::fs::File::open(&input).unwrap());
+        let ids: Vec<_> = index
+            .iter()
+            .map(|entry| {
+                crate::parser::parse_raw_frame(&mut f, entry, &crate::ParseOptions::default())
+                    .unwrap()
+                    .id
+            })
+            .collect();
+        assert_eq!(ids[..8], [1; 8]);
+        assert_eq!(ids[8..], vec![2; ids.len() - 8]);
+    }
+
+    #[test]
+    fn stream_frames_without_seeking() {
+        for input in [
+            "assets/h264.vraw",
+            "assets/h265.vraw",
+            "assets/mixed.vraw",
+            "assets/no_generic_metadata.vraw",
+        ] {
+            let options = crate::ParseOptions::default();
+            let mut f = std::io::BufReader::new(std::fs::File::open(input).unwrap());
+            let index = crate::parser::read_index(&mut f, &options).unwrap().entries;
+
+            let recording = std::fs::read(input).unwrap();
+            let streamed: Vec<_> = crate::parser::FrameStream::new(&recording[..], options)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+            assert_eq!(streamed.len(), index.len(), "{input}");
+            for ((entry, frame), indexed_entry) in streamed.iter().zip(&index) {
+                assert_eq!(entry.offset(), indexed_entry.offset());
+                assert_eq!(entry.receive_timestamp(), indexed_entry.receive_timestamp());
+
+                let indexed_frame =
+                    crate::parser::parse_raw_frame(&mut f, indexed_entry, &options).unwrap();
+                assert_eq!(frame.raw_data, indexed_frame.raw_data);
+                assert_eq!(frame.generic_metadata, indexed_frame.generic_metadata);
+                assert_eq!(frame.warnings, indexed_frame.warnings);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_stream_of_recording() {
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_stream.h264");
+        let convert = |recording: &[u8]| {
+            crate::processing::convert_stream(
+                recording,
+                std::path::Path::new("stdin"),
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    container: Some(crate::Container::ElementaryStream),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let summary = convert(&recording);
+        let streamed = std::fs::read(&output).unwrap();
+        let expected_summary = crate::processing::convert_vraw_with_options(
+            "assets/h264.vraw",
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                container: Some(crate::Container::ElementaryStream),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(summary, expected_summary);
+        assert_eq!(streamed, std::fs::read(&output).unwrap());
+
+        // A stream cut off in the last frame, before the index was written
+        let mut f = std::io::BufReader::new(std::fs::File::open("assets/h264.vraw").unwrap());
+        let index = crate::parser::read_index(&mut f, &crate::ParseOptions::default())
+            .unwrap()
+            .entries;
+        let last_frame = index.last().unwrap().offset() as usize;
+        let summary = convert(&recording[..last_frame + 60]);
+        assert!(summary.truncated);
+        assert_eq!(summary.frames_written, expected_summary.frames_written - 1);
+        assert_eq!(summary.frames_corrupt, 0);
+    }
+
+    #[test]
+    fn fuzz_entry_points_on_damaged_recordings() {
+        for input in [
+            "assets/h264.vraw",
+            "assets/mixed.vraw",
+            "assets/no_generic_metadata.vraw",
+            "assets/stats.vraw",
+        ] {
+            let recording = std::fs::read(input).unwrap();
+            for len in 0..recording.len() {
+                crate::fuzzing::read_index(&recording[..len]);
+                crate::fuzzing::parse_frames(&recording[..len]);
+            }
+
+            // The bytes of the header, the first frame and the index set to
+            // values that make sizes and offsets absurd
+            let len = recording.len();
+            for position in (0..len.min(160)).chain(len.saturating_sub(64)..len) {
+                for value in [0x00, 0x7F, 0x80, 0xFF] {
+                    let mut damaged = recording.clone();
+                    damaged[position] = value;
+                    crate::fuzzing::read_index(&damaged);
+                    crate::fuzzing::parse_frames(&damaged);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_regressions() {
+        // Minimized inputs that crashed the fuzz targets, see fuzz/regressions
+        for input in std::fs::read_dir("fuzz/regressions/parse_frames").unwrap() {
+            let recording = std::fs::read(input.unwrap().path()).unwrap();
+            crate::fuzzing::read_index(&recording);
+            crate::fuzzing::parse_frames(&recording);
+        }
+
+        // A frame claiming 12 GB, which was allocated before it was read
+        let recording = std::fs::read("fuzz/regressions/parse_frames/oversized_frame").unwrap();
+        let mut f = std::io::Cursor::new(&recording);
+        let index = crate::parser::scan_index(&mut f).unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(
+            crate::parser::parse_frame_metadata(&mut f, &index.entries[0])
+                .unwrap_err()
+                .to_string(),
+            "frame at offset 0 claims 12293177069 bytes, more than the 0 bytes left in the file"
+        );
+
+        // A payload of 1 byte, from whose end the video placement footer was
+        // searched for
+        let recording =
+            std::fs::read("fuzz/regressions/parse_frames/frame_smaller_than_placement_footer")
+                .unwrap();
+        let frames: Vec<_> = crate::parser::FrameStream::new(&recording[..], Default::default())
+            .unwrap()
+            .collect();
+        assert_eq!(frames.len(), 1);
+        let (_, frame) = frames[0].as_ref().unwrap();
+        assert_eq!(frame.raw_data.len(), 1);
+        assert_eq!(frame.placement_metadata, None);
+    }
+
+    #[test]
+    fn resynchronize_after_damaged_frame() {
+        // Frame 5 of the recording overwritten, with a frame header received
+        // two hours later in the damaged bytes
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let frame_count = u32::from_le_bytes(recording[recording.len() - 4..].try_into().unwrap());
+        let index_len = 8 + 16 * frame_count as usize + 8;
+        let mut damaged = recording[..recording.len() - index_len].to_vec();
+        damaged[1265..1356].fill(0x55);
+        let mut fake_header = recording[1265..1313].to_vec();
+        let receive_timestamp = i64::from_le_bytes(fake_header[32..40].try_into().unwrap());
+        fake_header[32..40]
+            .copy_from_slice(&(receive_timestamp + 2 * 3600 * 1_000_000_000).to_le_bytes());
+        fake_header[40..48].copy_from_slice(&39i64.to_le_bytes());
+        damaged[1269..1317].copy_from_slice(&fake_header);
+
+        let index = crate::parser::scan_index(&mut std::io::Cursor::new(&damaged)).unwrap();
+        assert_eq!(index.entries.len(), frame_count as usize - 1);
+        assert!(index.entries.iter().all(|entry| entry.offset() != 1269));
+        assert_eq!(
+            index.warnings,
+            ["Skipped 91 damaged bytes at offset 1265 to the frame at offset 1356"]
+        );
+
+        let streamed: Vec<_> =
+            crate::parser::FrameStream::new(&damaged[..], crate::ParseOptions::default())
+                .unwrap()
+                .collect();
+        assert_eq!(streamed.len(), frame_count as usize);
+        let e = streamed[5].as_ref().unwrap_err().to_string();
+        assert!(
+            e.ends_with("skipped 91 damaged bytes to the next frame at offset 1356"),
+            "{e}"
+        );
+        let offsets: Vec<_> = streamed
+            .iter()
+            .filter_map(|frame| Some(frame.as_ref().ok()?.0.offset()))
+            .collect();
+        let expected: Vec<_> = index.entries.iter().map(|entry| entry.offset()).collect();
+        assert_eq!(offsets, expected);
+
+        // Strict parsing stops at the damaged frame
+        let options = crate::ParseOptions {
+            strictness: crate::Strictness::Strict,
+            ..Default::default()
+        };
+        let streamed: Vec<_> = crate::parser::FrameStream::new(&damaged[..], options)
+            .unwrap()
+            .collect();
+        assert_eq!(streamed.len(), 6);
+        assert!(streamed[5].is_err());
+
+        // Converted from a file and from a stream
+        let input = std::env::temp_dir().join("vraw_convert_damaged.vraw");
+        let output = std::env::temp_dir().join("vraw_convert_damaged.h264");
+        std::fs::write(&input, &damaged).unwrap();
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ElementaryStream),
+            ..Default::default()
+        };
+        let summary = crate::processing::convert_vraw_with_options(
+            &input,
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+        assert!(summary
+            .warnings
+            .iter()
+            .any(|warning| warning.starts_with("Skipped 91 damaged bytes")));
+        let summary = crate::processing::convert_stream(
+            &damaged[..],
+            std::path::Path::new("stdin"),
+            Some(output.to_path_buf()),
+            &options,
+        )
+        .unwrap();
+        assert!(summary
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("skipped 91 damaged bytes")));
+        assert_eq!(summary.frames_written, 29);
+    }
+
+    #[test]
+    fn read_frames_of_recordings() {
+        let mut reader = crate::VrawReader::open("assets/h264.vraw").unwrap();
+        assert_eq!(reader.frame_count(), 40);
+        assert_eq!(
+            reader.recording_metadata().unwrap().start().unwrap(),
+            crate::recording_start("assets/h264.vraw").unwrap()
+        );
+
+        let frames: Vec<_> = reader.frames().map(Result::unwrap).collect();
+        let count = |format| frames.iter().filter(|frame| frame.format == format).count();
+        assert_eq!(count(crate::VideoCaptureFormat::H264), 30);
+        assert_eq!(count(crate::VideoCaptureFormat::Stats), 10);
+        assert_eq!(frames[0].receive_timestamp, 7_723_900);
+        assert!(frames[0].raw_data.starts_with(&[0, 0, 0, 1]));
+        // The payload is stored with its video placement metadata
+        assert_eq!(frames[0].size, 818);
+        assert!(frames[0].raw_data.len() < frames[0].size);
+
+        // The frames can be read again, and from memory
+        assert_eq!((&mut reader).into_iter().count(), 40);
+        let recording = std::fs::read("assets/h264_corrupt.vraw").unwrap();
+        let mut reader = crate::VrawReader::new(std::io::Cursor::new(recording)).unwrap();
+        let results: Vec<_> = (&mut reader).into_iter().collect();
+        assert_eq!(results.len(), reader.frame_count());
+        assert!(matches!(
+            results[10],
+            Err(crate::VrawError::BadFrame { index: 10, .. })
+        ));
+        assert!(results[11].is_ok());
+
+        let mut reader = crate::VrawReader::open("assets/mjpeg.vraw").unwrap();
+        let frame = reader.frame(0).unwrap();
+        assert_eq!((frame.id, frame.width, frame.height), (1, 32, 16));
+        assert_eq!(frame.resolution(), "32x16");
+        assert_eq!(frame.to_string(), "32x16");
+    }
+
+    #[test]
+    fn read_frames_into_buffer() {
+        let options = crate::ParseOptions::default();
+        for path in ["assets/h264.vraw", "assets/mjpeg.vraw"] {
+            let mut f = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+            let index = crate::read_index(&mut f, &options).unwrap();
+
+            let mut buf = Vec::new();
+            let mut capacity = 0;
+            for entry in &index.entries {
+                let frame = crate::read_frame_into(&mut f, entry, &options, &mut buf).unwrap();
+                let info = crate::parse_raw_frame(&mut f, entry, &options).unwrap();
+                assert_eq!(frame.raw_data, &info.raw_data[..]);
+                assert_eq!(frame.placement_metadata, info.placement_metadata.as_deref());
+                assert_eq!(frame.generic_metadata, info.generic_metadata);
+                assert_eq!(
+                    (frame.id, frame.format, frame.size, frame.receive_timestamp),
+                    (info.id, info.format, info.size, info.receive_timestamp)
+                );
+                assert_eq!(frame.to_frame_info().raw_data, info.raw_data);
+                let size = frame.size;
+
+                // The buffer only grows for frames larger than all before
+                assert!(buf.capacity() >= capacity);
+                if size <= capacity {
+                    assert_eq!(buf.capacity(), capacity);
+                }
+                capacity = buf.capacity();
+            }
+        }
+
+        let mut reader = crate::VrawReader::open("assets/mjpeg.vraw").unwrap();
+        let mut buf = Vec::new();
+        let frame = reader.read_frame_into(&mut buf, 0).unwrap();
+        assert_eq!((frame.id, frame.width, frame.height), (1, 32, 16));
+        assert!(frame.raw_data.starts_with(&[0xff, 0xd8]));
+        assert!(matches!(
+            reader.read_frame_into(&mut buf, 15),
+            Err(crate::VrawError::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn read_frames_from_threads() {
+        let mut reader = crate::VrawReader::open("assets/h265.vraw").unwrap();
+        let sequential: Vec<_> = reader
+            .frames()
+            .map(|frame| frame.unwrap().raw_data)
+            .collect();
+
+        // Every thread reads a quarter of the frames with its own file handle
+        let chunk = sequential.len().div_ceil(4);
+        let parallel: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|thread| {
+                    let mut reader = reader.try_clone().unwrap();
+                    scope.spawn(move || {
+                        let end = (chunk * (thread + 1)).min(reader.frame_count());
+                        (chunk * thread..end)
+                            .map(|index| reader.frame(index).unwrap().raw_data)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert_eq!(parallel.len(), 2981);
+        assert!(parallel == sequential);
+
+        let recording = std::fs::read("assets/mjpeg.vraw").unwrap();
+        let reader = crate::VrawReader::from_bytes(&recording).unwrap();
+        let mut clone = reader.try_clone().unwrap();
+        assert_eq!(clone.frame_count(), 15);
+        assert_eq!(clone.frame(0).unwrap().resolution(), "32x16");
+
+        let file = std::fs::File::open("assets/mjpeg.vraw").unwrap();
+        let reader = crate::VrawReader::new(std::io::BufReader::new(file)).unwrap();
+        assert!(matches!(
+            reader.try_clone(),
+            Err(crate::VrawError::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn convert_recording_in_memory() {
+        let output = std::env::temp_dir().join("vraw_convert_from_file.mp4");
+        let summary = crate::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+
+        let recording = std::io::Cursor::new(std::fs::read("assets/h264.vraw").unwrap());
+        let index = crate::parser::read_index(&mut recording.clone(), &Default::default()).unwrap();
+        assert_eq!(index.entries.len(), 40);
+
+        let memory_output = std::env::temp_dir().join("vraw_convert_from_memory.mp4");
+        let memory_summary =
+            crate::convert_vraw_from_reader(recording, &memory_output, &Default::default())
+                .unwrap();
+
+        assert_eq!(memory_summary.frames_written, summary.frames_written);
+        assert_eq!(memory_summary.output, memory_output);
+        assert_eq!(
+            std::fs::read(&memory_output).unwrap(),
+            std::fs::read(&output).unwrap()
+        );
+    }
+
+    #[test]
+    fn convert_recording_bytes() {
+        let output = std::env::temp_dir().join("vraw_convert_bytes_reference.mp4");
+        crate::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+
+        let recording = std::fs::read("assets/h264.vraw").unwrap();
+        let mut mp4 = Vec::new();
+        let summary = crate::convert_vraw_bytes(&recording, &mut mp4, &Default::default()).unwrap();
+        assert_eq!(summary.frames_written, 30);
+        assert_eq!(mp4, std::fs::read(&output).unwrap());
+
+        // Converted in memory, with the rules of convert_vraw_to_writer
+        let options = crate::ConvertOptions {
+            faststart: true,
+            ..Default::default()
+        };
+        let e = crate::convert_vraw_bytes(&recording, Vec::new(), &options).unwrap_err();
+        assert!(matches!(e, crate::VrawError::InvalidOptions(_)));
+
+        let mut reader = crate::VrawReader::from_bytes(&recording).unwrap();
+        assert_eq!(reader.frames().filter(Result::is_ok).count(), 40);
+
+        // Empty recordings and recordings shorter than the index footer. A
+        // header alone is a recording without frames.
+        for len in [0, 4, 16] {
+            assert!(
+                crate::convert_vraw_bytes(&recording[..len], Vec::new(), &Default::default())
+                    .is_err()
+            );
+        }
+        assert!(crate::VrawReader::from_bytes(&recording[..0]).is_err());
+        assert!(crate::VrawReader::from_bytes(&recording[..4]).is_err());
+        let reader = crate::VrawReader::from_bytes(&recording[..16]).unwrap();
+        assert_eq!(reader.frame_count(), 0);
+
+        // Image sequences are folders
+        let recording = std::fs::read("assets/mono8.vraw").unwrap();
+        let e = crate::convert_vraw_bytes(&recording, Vec::new(), &Default::default()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: image sequences, ffmpeg outputs and dumps cannot be written to a writer"
+        );
+    }
+
+    #[test]
+    fn read_frames_at_timestamps() {
+        use crate::SeekPolicy::{AtOrAfter, AtOrBefore, Nearest};
+
+        let mut reader = crate::VrawReader::open("assets/h264.vraw").unwrap();
+        let at = |timestamp, policy| reader.frame_index_at_timestamp(timestamp, policy);
+
+        // Before the first frame, received at 7723900 ns
+        assert_eq!(at(0, AtOrBefore), None);
+        assert_eq!(at(0, AtOrAfter), Some(0));
+        assert_eq!(at(0, Nearest), Some(0));
+        assert_eq!(at(i64::MIN, Nearest), Some(0));
+
+        // At a frame, and between frame 1 at 41090566 ns and frame 2 at
+        // 45090566 ns
+        for policy in [Nearest, AtOrBefore, AtOrAfter] {
+            assert_eq!(at(7_723_900, policy), Some(0));
+            assert_eq!(at(41_090_566, policy), Some(1));
+        }
+        assert_eq!(at(43_000_000, Nearest), Some(1));
+        assert_eq!(at(43_090_566, Nearest), Some(1));
+        assert_eq!(at(44_000_000, Nearest), Some(2));
+        assert_eq!(at(44_000_000, AtOrBefore), Some(1));
+        assert_eq!(at(43_000_000, AtOrAfter), Some(2));
+
+        // After the last frame, received at 975357228 ns
+        assert_eq!(at(i64::MAX, AtOrAfter), None);
+        assert_eq!(at(i64::MAX, AtOrBefore), Some(39));
+        assert_eq!(at(i64::MAX, Nearest), Some(39));
+
+        let frame = reader.frame_at_timestamp(74_000_000, Nearest).unwrap();
+        assert_eq!(frame.receive_timestamp, 74_457_233);
+        assert_eq!(frame.format, crate::VideoCaptureFormat::H264);
+        assert_eq!(reader.frame(39).unwrap().receive_timestamp, 975_357_228);
+
+        let e = reader.frame_at_timestamp(0, AtOrBefore).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: no frame was received at or before 0 ns"
+        );
+        let e = reader.frame(40).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: frame 40 out of range, the recording has 40 frames"
+        );
+    }
+
+    #[test]
+    fn verify_recordings() {
+        let report = crate::verify_vraw("assets/h264.vraw").unwrap();
+        assert!(report.is_intact(), "{report:?}");
+        assert_eq!(report.frames, 40);
+        assert_eq!(report.bytes, 6042 - 16);
+        assert_eq!(report.duration, 975357228 - 7723900);
+        assert_eq!(report.formats[&crate::VideoCaptureFormat::H264], 30);
+        assert_eq!(report.formats[&crate::VideoCaptureFormat::Stats], 10);
+
+        // Frames are checked as the conversion reads them
+        let options = crate::ParseOptions::default();
+        for input in [
+            "assets/h264.vraw",
+            "assets/h264_corrupt.vraw",
+            "assets/mixed.vraw",
+            "assets/no_generic_metadata.vraw",
+            "assets/stats.vraw",
+        ] {
+            let mut f = std::io::BufReader::new(std::fs::File::open(input).unwrap());
+            let index = crate::parser::read_index(&mut f, &options).unwrap();
+            for entry in &index.entries {
+                let frame = crate::parser::parse_raw_frame(&mut f, entry, &options);
+                let verified = crate::parser::verify_frame(&mut f, entry, &options);
+                match (frame, verified) {
+                    (Ok(frame), Ok(verified)) => assert_eq!(frame.warnings, verified.warnings),
+                    (Err(e), Err(verified_e)) => assert_eq!(e.to_string(), verified_e.to_string()),
+                    (frame, verified) => panic!("{input}: {frame:?} but {verified:?}"),
+                }
+            }
+        }
+
+        let report = crate::verify_vraw("assets/no_generic_metadata.vraw").unwrap();
+        assert_eq!(report.frames, 10);
+        assert_eq!(report.frame_problems.len(), 10);
+        assert_eq!(
+            report.frame_problems[0].problem,
+            "Frame at offset 16 has no generic metadata"
+        );
+
+        let report = crate::verify_vraw("assets/h264_corrupt.vraw").unwrap();
+        assert!(report.problems.is_empty());
+        let corrupt: Vec<_> = report.frame_problems.iter().map(|p| p.index).collect();
+        assert_eq!(corrupt, [10]);
+        assert_eq!(
+            report.formats[&crate::VideoCaptureFormat::Unknown(12345)],
+            1
+        );
+
+        // A frame received before the frame before it, and the index cut off
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        recording[1536 + 32..1536 + 40].copy_from_slice(&0i64.to_le_bytes());
+        let input = std::env::temp_dir().join("vraw_convert_verify.vraw");
+        std::fs::write(&input, &recording[..6042]).unwrap();
+        let report = crate::verify_vraw(&input).unwrap();
+        assert_eq!(report.frames, 40);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].starts_with("Failed to read index"));
+        assert_eq!(
+            report.frame_problems,
+            [crate::FrameProblem {
+                index: 8,
+                offset: 1536,
+                problem: "Frame received at 0 ns, before the frame before it at 174557232 ns"
+                    .into(),
+            }]
+        );
+
+        // The first and last frames received as far apart as they can be
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        let index = crate::read_index(&mut std::io::Cursor::new(&recording), &options).unwrap();
+        for (entry, timestamp) in [
+            (&index.entries[0], i64::MIN),
+            (&index.entries[39], i64::MAX),
+        ] {
+            let offset = entry.offset() as usize;
+            recording[offset + 32..offset + 40].copy_from_slice(&timestamp.to_le_bytes());
+        }
+        std::fs::write(&input, &recording).unwrap();
+        let report = crate::verify_vraw(&input).unwrap();
+        assert_eq!(report.frames, 40);
+        assert_eq!(report.duration, i64::MAX);
+    }
+
+    #[test]
+    fn split_output_at_resolution_changes() {
+        // An NV12 recording of 3 frames of 8x4, followed by 2 frames of 4x2
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xFEEDFEEDu32.to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        let mut index = Vec::new();
+        for frame in 0..5i64 {
+            let (width, height) = if frame < 3 { (8i32, 4i32) } else { (4, 2) };
+            let size = (width * height * 3 / 2) as i64;
+            let receive_timestamp = 1_000_000 + frame * 40_000_000;
+            index.extend_from_slice(&(data.len() as i64).to_le_bytes());
+            index.extend_from_slice(&receive_timestamp.to_le_bytes());
+
+            data.extend_from_slice(&0xAAAAFEEDu32.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&width.to_le_bytes());
+            data.extend_from_slice(&height.to_le_bytes());
+            data.extend_from_slice(&3i32.to_le_bytes());
+            data.extend_from_slice(&0i64.to_le_bytes());
+            data.extend_from_slice(&receive_timestamp.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.resize(data.len() + size as usize, 0x80);
+            data.extend_from_slice(&0xBACCDEEFu32.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&0xBACCBEEFu32.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+        data.extend_from_slice(&0xABCDFEEDu32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&index);
+        data.extend_from_slice(&0xDCBAFEEDu32.to_le_bytes());
+        data.extend_from_slice(&5u32.to_le_bytes());
+
+        let input = std::env::temp_dir().join("vraw_convert_resolution_change.vraw");
+        std::fs::write(&input, data).unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_resolution_change.y4m");
+        let part_output = std::env::temp_dir().join("vraw_convert_resolution_change_2.y4m");
+        let _ = std::fs::remove_file(&part_output);
+
+        let summary = crate::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+
+        assert_eq!(summary.frames_written, 5);
+        assert_eq!(summary.output, output);
+        assert_eq!(
+            summary.resolution_changes,
+            [crate::ResolutionChange {
+                frame: 3,
+                offset: 16 + 3 * (48 + 48 + 16),
+                from: (8, 4),
+                to: (4, 2),
+                output: part_output.clone(),
+            }]
+        );
+
+        let first = std::fs::read(&output).unwrap();
+        let header = b"YUV4MPEG2 W8 H4 F25000:1000 Ip A1:1 C420jpeg\n";
+        assert!(first.starts_with(header));
+        assert_eq!(first.len(), header.len() + 3 * (b"FRAME\n".len() + 48));
+
+        let second = std::fs::read(&part_output).unwrap();
+        let header = b"YUV4MPEG2 W4 H2 F25000:1000 Ip A1:1 C420jpeg\n";
+        assert!(second.starts_with(header));
+        assert_eq!(second.len(), header.len() + 2 * (b"FRAME\n".len() + 12));
+
+        // The SPS of every keyframe of a recording keeps its resolution
+        let output = std::env::temp_dir().join("vraw_convert_resolution_kept.mp4");
+        let summary = crate::convert_vraw("assets/h264.vraw", Some(output.to_path_buf())).unwrap();
+        assert!(summary.resolution_changes.is_empty());
+    }
+
+    #[test]
+    fn sniff_codecs() {
+        use crate::bitstream::{sniff_codec, Codec};
+
+        // SPS, PPS and IDR slice of H.264, VPS, SPS, PPS and IDR slice of HEVC
+        let avc_idr = [
+            0, 0, 0, 1, 0x67, 0x42, 0xC0, 0x1E, 0, 0, 0, 1, 0x68, 0xCE, 0x3C, 0x80, 0, 0, 1, 0x65,
+            0x88, 0x84,
+        ];
+        let hevc_irap = [
+            0, 0, 0, 1, 0x40, 0x01, 0x0C, 0, 0, 0, 1, 0x42, 0x01, 0x01, 0, 0, 0, 1, 0x44, 0x01,
+            0xC1, 0, 0, 1, 0x26, 0x01, 0xAF,
+        ];
+        assert_eq!(sniff_codec(&avc_idr), Some(Codec::Avc));
+        assert_eq!(sniff_codec(&hevc_irap), Some(Codec::Hevc));
+        assert_eq!(
+            sniff_codec(&[0, 0, 0, 1, 0x41, 0x9A, 0x02]),
+            Some(Codec::Avc)
+        );
+        assert_eq!(
+            sniff_codec(&[0, 0, 0, 1, 0x02, 0x01, 0xD0]),
+            Some(Codec::Hevc)
+        );
+        assert_eq!(sniff_codec(&[0xFF, 0xD8, 0xFF, 0xE0]), Some(Codec::Jpeg));
+
+        // An SEI NAL unit header is valid for both, and a payload without start
+        // code for neither
+        assert_eq!(sniff_codec(&[0, 0, 0, 1, 0x06, 0x05, 0x10]), None);
+        assert_eq!(sniff_codec(&[0x41, 0x9A, 0, 0, 1, 0x41, 0x9A]), None);
+
+        // An HEVC recording recorded as H.264
+        let mut recording = std::fs::read("assets/h265.vraw").unwrap();
+        let mut f = std::io::Cursor::new(&recording);
+        let entries = crate::parser::read_index(&mut f, &Default::default())
+            .unwrap()
+            .entries;
+        for entry in &entries {
+            let format = entry.offset() as usize + 20;
+            if recording[format..format + 4] == (-4602i32).to_le_bytes() {
+                recording[format..format + 4].copy_from_slice(&(-4601i32).to_le_bytes());
+            }
+        }
+        let input = std::env::temp_dir().join("vraw_convert_h265_as_h264.vraw");
+        std::fs::write(&input, recording).unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_h265_as_h264.mp4");
+
+        let strict = crate::ConvertOptions {
+            strictness: crate::Strictness::Strict,
+            ..Default::default()
+        };
+        let e = crate::convert_vraw_with_options(&input, Some(output.to_path_buf()), &strict)
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: the frames are recorded as H264, but their bitstream is H265"
+        );
+
+        let summary = crate::convert_vraw_with_options(
+            &input,
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                trust_bitstream: true,
+                ..Default::default()
+            },
         )
         .unwrap();
+        assert_eq!(summary.frames_written, 1265);
+        assert_eq!(
+            summary.warnings,
+            ["The frames are recorded as H264, but their bitstream is H265"]
+        );
+        assert!(summary.frames_skipped.is_empty());
+    }
+
+    #[test]
+    fn skip_mjpeg_frames_without_markers() {
+        let mut recording = std::fs::read("assets/mjpeg.vraw").unwrap();
+        let mut f = std::io::Cursor::new(&recording);
+        let entries = crate::parser::read_index(&mut f, &Default::default())
+            .unwrap()
+            .entries;
+        let options = crate::ParseOptions::default();
+        let end_markers: Vec<usize> = entries
+            .iter()
+            .map(|entry| {
+                let frame = crate::parser::parse_raw_frame(&mut f, entry, &options).unwrap();
+                entry.offset() as usize + 48 + frame.raw_data.len() - 2
+            })
+            .collect();
+
+        // Frame 1 loses its start marker, frame 5 its end marker, and frame 8
+        // has 2 bytes after it. Frames 2, 7 and 12 are Stats frames.
+        recording[entries[1].offset() as usize + 48] = 0;
+        recording[end_markers[5]..end_markers[5] + 2].fill(0);
+        recording[end_markers[8] - 2..end_markers[8] + 2].copy_from_slice(&[0xFF, 0xD9, 0, 0]);
+        let input = std::env::temp_dir().join("vraw_convert_mjpeg_markers.vraw");
+        std::fs::write(&input, recording).unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_mjpeg_markers.mjpeg");
+
+        let convert = |options: &crate::ConvertOptions| {
+            crate::convert_vraw_with_options(
+                &input,
+                Some(output.to_path_buf()),
+                &crate::ConvertOptions {
+                    container: options
+                        .container
+                        .or(Some(crate::Container::ElementaryStream)),
+                    ..options.clone()
+                },
+            )
+            .unwrap()
+        };
+
+        let summary = convert(&Default::default());
+        assert_eq!(summary.frames_written, 9);
+        assert_eq!(summary.jpeg_frames_invalid, 3);
+        let jpeg_warnings = |summary: &crate::ConversionSummary| -> Vec<String> {
+            summary
+                .warnings
+                .iter()
+                .filter(|warning| warning.contains("JPEG"))
+                .cloned()
+                .collect()
+        };
+        assert!(jpeg_warnings(&summary).is_empty());
+
+        let summary = convert(&crate::ConvertOptions {
+            trim_jpeg_trailing_bytes: true,
+            ..Default::default()
+        });
+        assert_eq!(summary.frames_written, 10);
+        assert_eq!(summary.jpeg_frames_invalid, 2);
+        assert_eq!(
+            jpeg_warnings(&summary),
+            [format!(
+                "Frame at offset {} has 2 bytes after its JPEG end of image marker, which were \
+                 cut off",
+                entries[8].offset()
+            )]
+        );
+        let stream = std::fs::read(&output).unwrap();
+        assert_eq!(
+            stream
+                .windows(2)
+                .filter(|marker| marker == &[0xFF, 0xD8])
+                .count(),
+            10
+        );
+        assert!(stream.ends_with(&[0xFF, 0xD9]));
+
+        // Dumps keep the payloads as stored
+        let summary = convert(&crate::ConvertOptions {
+            container: Some(crate::Container::Dump),
+            ..Default::default()
+        });
+        assert_eq!(summary.frames_written, 12);
+    }
+
+    #[test]
+    fn fail_on_unsupported_format() {
+        // An NV12 recording recorded as Raw, which cannot be converted
+        let mut recording = std::fs::read("assets/nv12.vraw").unwrap();
+        let mut f = std::io::Cursor::new(&recording);
+        let entries = crate::parser::read_index(&mut f, &Default::default())
+            .unwrap()
+            .entries;
+        for entry in &entries {
+            let format = entry.offset() as usize + 20;
+            if recording[format..format + 4] == 3i32.to_le_bytes() {
+                recording[format..format + 4].copy_from_slice(&6i32.to_le_bytes());
+            }
+        }
+        let input = std::env::temp_dir().join("vraw_convert_raw.vraw");
+        std::fs::write(&input, recording).unwrap();
+
+        let e = crate::convert_vraw(&input, None).unwrap_err();
+        assert!(matches!(
+            e,
+            crate::VrawError::UnsupportedFormat {
+                format: crate::VideoCaptureFormat::Raw,
+                ..
+            }
+        ));
+        assert!(e.to_string().ends_with(
+            "not supported, supported are H264, H265, Mjpeg, Nv12, Yuv, Yuyv, Uyvy, Rgb, Bgr, \
+             Mono8, Mono16, Raw16"
+        ));
+        assert!(!crate::SUPPORTED_FORMATS.contains(&crate::VideoCaptureFormat::Raw));
+    }
+
+    #[test]
+    fn skip_frames_of_unknown_formats() {
+        let path = std::env::temp_dir().join("vraw_convert_unknown_format.vraw");
+        write_single_frame_vraw(&path, 999, &[0; 16]);
+
+        let frame = parse_single_frame(&path).unwrap();
+        assert_eq!(frame.format, crate::VideoCaptureFormat::Unknown(999));
+        assert!(!frame.format.is_coded());
+        assert_eq!(i32::from(frame.format), 999);
+        assert_eq!(
+            parse_single_frame_with(&path, crate::Strictness::Strict).unwrap_err(),
+            "Unknown video capture format 999"
+        );
+
+        // A recording of only such frames has no video to convert
+        let e = crate::convert_vraw(&path, None).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "vraw_convert: VideoCaptureFormat Unknown(999) (0x0) not supported, supported are \
+             H264, H265, Mjpeg, Nv12, Yuv, Yuyv, Uyvy, Rgb, Bgr, Mono8, Mono16, Raw16"
+        );
+
+        // Between the frames of a recording they are skipped and counted, or
+        // fail the conversion when strict
+        let mut recording = std::fs::read("assets/h264.vraw").unwrap();
+        recording[1536 + 20..1536 + 24].copy_from_slice(&999i32.to_le_bytes());
+        let input = std::env::temp_dir().join("vraw_convert_h264_unknown_format.vraw");
+        std::fs::write(&input, &recording).unwrap();
+        let output = std::env::temp_dir().join("vraw_convert_h264_unknown_format.mp4");
+
+        let summary = crate::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(summary.frames_written, 29);
+        assert_eq!(summary.frames_corrupt, 0);
+        assert_eq!(
+            summary.frames_skipped[&crate::VideoCaptureFormat::Unknown(999)],
+            1
+        );
+
+        let e = crate::convert_vraw_with_options(
+            &input,
+            Some(output.to_path_buf()),
+            &crate::ConvertOptions {
+                strictness: crate::Strictness::Strict,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(
+            matches!(e, crate::VrawError::BadFrame { index: 8, .. }),
+            "{e}"
+        );
+
+        // Rebuilding the index keeps them
+        std::fs::write(&input, &recording[..6042]).unwrap();
+        let summary = crate::convert_vraw(&input, Some(output.to_path_buf())).unwrap();
+        assert_eq!(summary.frames_written, 29);
+        assert!(summary.warnings.iter().all(|w| !w.contains("damaged")));
+    }
+
+    /// Writes at most a few bytes per call, as writes to network filesystems
+    /// can, and fails after a limit
+    struct ShortWriter {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl std::io::Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.written.len() >= self.limit {
+                return Err(std::io::Error::other("disk full"));
+            }
+            let len = buf.len().min(7).min(self.limit - self.written.len());
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_elementary_stream_with_short_writes() {
+        let output = std::env::temp_dir().join("vraw_convert_short_writes.mjpeg");
+        let options = crate::ConvertOptions {
+            container: Some(crate::Container::ElementaryStream),
+            ..Default::default()
+        };
+        crate::convert_vraw_with_options("assets/mjpeg.vraw", Some(output.to_path_buf()), &options)
+            .unwrap();
+        let expected = std::fs::read(&output).unwrap();
+
+        let mut f = crate::VrawReader::open("assets/mjpeg.vraw").unwrap();
+        let index = f.index();
+        let entries = &index.entries;
+        let write = |f: &mut _, limit| {
+            let mut writer = ShortWriter {
+                written: Vec::new(),
+                limit,
+            };
+            let summary = crate::processing::write_elementary_stream(
+                f,
+                entries,
+                &mut writer,
+                crate::VideoCaptureFormat::Mjpeg,
+                &options,
+            );
+            (summary, writer.written)
+        };
+
+        let (summary, written) = write(&mut f, usize::MAX);
+        assert_eq!(summary.unwrap().frames_written, 12);
+        assert_eq!(written, expected);
+
+        // A failing write names the frame it failed at, the fourth MJPEG frame
+        // as frame 2 is a Stats frame
+        let (summary, _) = write(&mut f, 600);
+        assert_eq!(
+            summary.unwrap_err().to_string(),
+            "vraw_convert: failed to write frame 4: disk full"
+        );
     }
 }
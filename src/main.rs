@@ -1,10 +1,22 @@
 use clap::Parser;
-use msgbox::IconType;
-use std::error::Error;
-use vraw_convert::convert_vraw_to_mp4;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use vraw_convert::{
+    convert_vraw_with, BayerPattern, ColourSpace, Container, ConvertOptions, HevcSampleEntry,
+    IndexOrder, Progress, ProgressCallback, Rotation, Strictness, TimestampRegressions,
+    TimestampSource, VrawError, VrawErrorKind,
+};
 
-mod parser;
-mod processing;
+/// Exit code of options that cannot be applied to the recording, the same as
+/// for invalid arguments
+const EXIT_INVALID_OPTIONS: i32 = 2;
+/// Exit code of recordings that cannot be opened, are corrupt or hold no video
+/// that can be converted
+const EXIT_BAD_INPUT: i32 = 3;
+/// Exit code of outputs that cannot be written
+const EXIT_OUTPUT_FAILED: i32 = 4;
 
 #[derive(Parser)]
 #[clap(
@@ -14,23 +26,334 @@ mod processing;
     about = "Converts Voysys .vraw recordings to other formats, using ffmpeg"
 )]
 pub struct Config {
-    /// Specifies the raw input file
+    /// Specifies the raw input file, - reads it from stdin
     #[clap(default_value = "in.vraw")]
-    input: String,
+    input: PathBuf,
 
     /// Specifies the output file name ex. video.mp4 (Folder path must exist)
-    output: Option<String>,
+    output: Option<PathBuf>,
+
+    /// Specifies the container of the output, defaults to the most suitable one for the recorded format
+    #[clap(long, value_enum)]
+    container: Option<Container>,
+
+    /// Stretch Mono16 frames to the full 16 bit range so they are viewable without external tooling
+    #[clap(long)]
+    auto_scale: bool,
+
+    /// Specifies the color filter layout used to demosaic Raw16 frames
+    #[clap(long, value_enum, default_value = "rggb")]
+    bayer_pattern: BayerPattern,
+
+    /// Also write the Stats frames of the recording to a .stats.json file next to the output
+    #[clap(long)]
+    export_stats: bool,
+
+    /// Write H.264 and HEVC recordings as fragmented mp4 with fragments of at least this many milliseconds, for MSE based web players
+    #[clap(long)]
+    fragment_duration: Option<u64>,
+
+    /// Place the moov box of .mp4 files before the media data, for progressive playback over HTTP
+    #[clap(long)]
+    faststart: bool,
+
+    /// Specifies the sample entry type of HEVC .mp4 files, hvc1 is required by Safari and other Apple players
+    #[clap(long, value_enum, default_value = "hev1")]
+    hevc_sample_entry: HevcSampleEntry,
+
+    /// Present the frames of .mp4 files in the order of their capture timestamps, for recordings with B-frames
+    #[clap(long)]
+    composition_offsets: bool,
+
+    /// Specifies the timescale of .mp4 files in ticks per second [default: 90000]
+    #[clap(long)]
+    timescale: Option<u32>,
+
+    /// Rotates the video of .mp4 files clockwise by the given degrees when played, for cameras mounted rotated
+    #[clap(long, value_enum, default_value = "0")]
+    rotation: Rotation,
+
+    /// Also write an .srt file next to the output showing the wall clock time of every frame
+    #[clap(long)]
+    subtitles: bool,
+
+    /// Also write the generic metadata of every frame to a .metadata.jsonl file next to the output
+    #[clap(long)]
+    export_generic_metadata: bool,
+
+    /// Mark gaps in the recording with chapters in .mp4 files
+    #[clap(long)]
+    chapters: bool,
+
+    /// Frame intervals longer than this multiple of the median interval are gaps [default: 3]
+    #[clap(long)]
+    gap_threshold: Option<f64>,
+
+    /// The timestamps timing the frames of .mp4 and .mkv files
+    #[clap(long, value_enum, default_value = "receive")]
+    timestamp_source: TimestampSource,
+
+    /// Colour space written to .mp4 files whose stream does not describe its colours
+    #[clap(long, value_enum)]
+    colour_space: Option<ColourSpace>,
+
+    /// Start every HEVC frame with an SEI NAL unit carrying its capture and receive timestamps
+    #[clap(long)]
+    timestamp_sei: bool,
+
+    /// Leave out index entries pointing outside of the frames instead of failing
+    #[clap(long)]
+    lenient_index: bool,
+
+    /// Stop at the first frame that cannot be read instead of skipping it
+    #[clap(long)]
+    stop_at_corrupt_frame: bool,
+
+    /// The order the frames are converted in
+    #[clap(long, value_enum, default_value = "offset")]
+    index_order: IndexOrder,
+
+    /// How frames of .mp4 files with a timestamp before that of a frame before them are timed
+    #[clap(long, value_enum, default_value = "clamp")]
+    timestamp_regressions: TimestampRegressions,
+
+    /// Name the output after the time the recording started instead of the time of the conversion
+    #[clap(long)]
+    name_after_recording_start: bool,
+
+    /// Most alignment bytes searched for after the video placement footer of a frame [default: 11]
+    #[clap(long)]
+    max_placement_padding: Option<usize>,
+
+    /// Whether inconsistencies in the structure of the recording fail the conversion or are recovered from with a warning
+    #[clap(long, value_enum, default_value = "lenient")]
+    strictness: Strictness,
+
+    /// Convert coded frames as the codec of their bitstream when it differs from their recorded format
+    #[clap(long)]
+    trust_bitstream: bool,
+
+    /// Cut MJPEG frames off after their last JPEG end of image marker instead of leaving out frames with bytes after it
+    #[clap(long)]
+    trim_jpeg_trailing_bytes: bool,
+
+    /// Leave out the frames received before this many milliseconds after the recording started
+    #[clap(long)]
+    start: Option<u64>,
+
+    /// Leave out the frames received after this many milliseconds after the recording started
+    #[clap(long)]
+    end: Option<u64>,
+
+    /// Fail instead of replacing an output that exists
+    #[clap(long)]
+    no_overwrite: bool,
+
+    /// Show the number of frames written so far on stderr
+    #[clap(long)]
+    progress: bool,
+
+    /// Report a failed conversion only on stderr and in the exit code, never in a message box
+    #[clap(long)]
+    no_gui: bool,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
+    // Warnings are shown unless configured otherwise, RUST_LOG=vraw_convert=debug
+    // also shows why every frame is skipped
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("vraw_convert=warn"),
+    )
+    .init();
+
     let config = Config::parse();
 
-    if let Err(e) = convert_vraw_to_mp4(&config.input, config.output) {
-        println!("Application error: {}", e);
+    let options = ConvertOptions {
+        output: config.output,
+        container: config.container,
+        auto_scale: config.auto_scale,
+        bayer_pattern: config.bayer_pattern,
+        export_stats: config.export_stats,
+        fragment_duration: config.fragment_duration.map(Duration::from_millis),
+        faststart: config.faststart,
+        hevc_sample_entry: config.hevc_sample_entry,
+        composition_offsets: config.composition_offsets,
+        timescale: config.timescale,
+        rotation: config.rotation,
+        subtitles: config.subtitles,
+        export_generic_metadata: config.export_generic_metadata,
+        chapters: config.chapters,
+        gap_threshold: config.gap_threshold,
+        timestamp_source: config.timestamp_source,
+        colour_space: config.colour_space,
+        timestamp_sei: config.timestamp_sei,
+        lenient_index: config.lenient_index,
+        stop_at_corrupt_frame: config.stop_at_corrupt_frame,
+        index_order: config.index_order,
+        timestamp_regressions: config.timestamp_regressions,
+        name_after_recording_start: config.name_after_recording_start,
+        max_placement_padding: config.max_placement_padding,
+        strictness: config.strictness,
+        trust_bitstream: config.trust_bitstream,
+        trim_jpeg_trailing_bytes: config.trim_jpeg_trailing_bytes,
+        start: config.start.map(Duration::from_millis),
+        end: config.end.map(Duration::from_millis),
+        no_overwrite: config.no_overwrite,
+        progress: config.progress.then(|| {
+            ProgressCallback::new(|progress: Progress| {
+                eprint!(
+                    "\rRead {} of {} frames",
+                    progress.frames_processed, progress.total_frames
+                );
+                if progress.frames_processed == progress.total_frames {
+                    eprintln!();
+                }
+            })
+        }),
+        progress_stride: None,
+        cancel: None,
+    };
 
-        let err_msg: String = e.to_string();
-        msgbox::create("vraw_convert", &err_msg, IconType::Info)?;
+    match convert_vraw_with(&config.input, &options) {
+        Ok(summary) => {
+            println!(
+                "Wrote {} frames to {}",
+                summary.frames_written,
+                summary.output.display()
+            );
+            if let Some(format) = summary.format {
+                println!(
+                    "Converted {:?} video, {} bytes written",
+                    format, summary.output_size
+                );
+            }
+            if summary.first_timestamp.is_some() {
+                println!(
+                    "The frames were received over {:.3} s",
+                    summary.duration().as_secs_f64()
+                );
+            }
+            for (format, count) in &summary.frames_skipped {
+                println!("Skipped {} {:?} frames of another format", count, format);
+            }
+            if summary.truncated {
+                println!("The last frame was cut off by the end of the file");
+            }
+            if summary.index_entries_reordered > 0 {
+                println!(
+                    "{} index entries were out of order",
+                    summary.index_entries_reordered
+                );
+            }
+            if summary.index_entries_duplicated > 0 {
+                println!(
+                    "{} duplicate index entries were left out",
+                    summary.index_entries_duplicated
+                );
+            }
+            if summary.placement_footers_missing > 0 {
+                println!(
+                    "{} frames have no video placement footer, their alignment bytes are kept",
+                    summary.placement_footers_missing
+                );
+            }
+            if !summary.output_warnings.is_empty() {
+                println!(
+                    "{} frames were left out or cut off, RUST_LOG=vraw_convert=debug lists them",
+                    summary.output_warnings.len()
+                );
+            }
+            if !summary.warnings.is_empty() {
+                println!(
+                    "Recovered from {} inconsistencies of the recording",
+                    summary.warnings.len()
+                );
+            }
+            if summary.jpeg_frames_invalid > 0 {
+                println!(
+                    "{} MJPEG frames without JPEG start or end marker were left out",
+                    summary.jpeg_frames_invalid
+                );
+            }
+            if summary.frames_corrupt > 0 {
+                println!("{} frames could not be read", summary.frames_corrupt);
+            }
+            if summary.duplicate_timestamps > 0 {
+                println!(
+                    "{} frames were received at the same time as the frame before them",
+                    summary.duplicate_timestamps
+                );
+            }
+            if summary.timestamp_regressions > 0 {
+                println!(
+                    "{} frames have a timestamp before that of a frame before them",
+                    summary.timestamp_regressions
+                );
+            }
+            for gap in &summary.gaps {
+                println!(
+                    "Gap of {:.3} s before frame {}",
+                    gap.duration as f64 / 1e9,
+                    gap.frame
+                );
+            }
+            for change in &summary.resolution_changes {
+                println!(
+                    "The resolution changes from {}x{} to {}x{} at frame {}, wrote the frames \
+                     from there on to {}",
+                    change.from.0,
+                    change.from.1,
+                    change.to.0,
+                    change.to.1,
+                    change.frame,
+                    change.output.display()
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+
+            // Users starting the converter by dropping a recording on it have
+            // no terminal to read the error in
+            if !config.no_gui && !io::stderr().is_terminal() && has_display() {
+                show_error(&e);
+            }
+
+            process::exit(exit_code(&e));
+        }
+    }
+}
+
+/// Shows a failed conversion in a message box. A message box that cannot be
+/// shown must not hide the exit code.
+#[cfg(feature = "gui")]
+fn show_error(e: &VrawError) {
+    let _ = msgbox::create("vraw_convert", &e.to_string(), msgbox::IconType::Info);
+}
+
+#[cfg(not(feature = "gui"))]
+fn show_error(_: &VrawError) {}
+
+/// Whether a message box can be shown. Without a display, as in containers and
+/// cron jobs, it would never be answered and the converter would hang.
+fn has_display() -> bool {
+    if cfg!(all(unix, not(target_os = "macos"))) {
+        ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+    } else {
+        true
     }
+}
 
-    Ok(())
+/// The exit code of a failed conversion, telling scripts whether the
+/// recording, the output or the options are at fault.
+fn exit_code(e: &VrawError) -> i32 {
+    match e.kind() {
+        VrawErrorKind::BadInput => EXIT_BAD_INPUT,
+        // Conversions of the command line converter are only cancelled by
+        // ending the process
+        VrawErrorKind::OutputFailed | VrawErrorKind::Cancelled => EXIT_OUTPUT_FAILED,
+        VrawErrorKind::InvalidOptions => EXIT_INVALID_OPTIONS,
+    }
 }
@@ -0,0 +1,209 @@
+//! Minimal Matroska (.mkv) writer for a single video track.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const EBML: u32 = 0x1A45DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const DURATION: u32 = 0x4489;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+
+const CLUSTER: u32 = 0x1F43B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+
+/// Size of the segment size field, written as a placeholder and patched by
+/// [`MatroskaWriter::finish`].
+const SEGMENT_SIZE_LENGTH: usize = 8;
+
+/// The video track of a Matroska file.
+pub struct VideoTrack {
+    /// Matroska codec id, e.g. "V_MPEGH/ISO/HEVC"
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Writes frames to a Matroska file with nanosecond timestamps. As blocks can
+/// only be 32767 ticks apart from their cluster, every frame gets a cluster of
+/// its own.
+pub struct MatroskaWriter<W: Write + Seek> {
+    writer: W,
+    segment_start: u64,
+    duration_position: u64,
+    last_timestamp: u64,
+}
+
+impl<W: Write + Seek> MatroskaWriter<W> {
+    pub fn new(mut writer: W, track: &VideoTrack) -> io::Result<Self> {
+        let mut header = Vec::new();
+        header.extend(element(
+            EBML,
+            &[
+                uint_element(EBML_VERSION, 1),
+                uint_element(EBML_READ_VERSION, 1),
+                uint_element(EBML_MAX_ID_LENGTH, 4),
+                uint_element(EBML_MAX_SIZE_LENGTH, 8),
+                element(DOC_TYPE, b"matroska"),
+                uint_element(DOC_TYPE_VERSION, 4),
+                uint_element(DOC_TYPE_READ_VERSION, 2),
+            ]
+            .concat(),
+        ));
+
+        header.extend(id_bytes(SEGMENT));
+        let segment_size_position = header.len();
+        header.extend([0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]); // unknown size
+        let segment_start = header.len() as u64;
+
+        // Nanosecond timestamps
+        let timestamp_scale = uint_element(TIMESTAMP_SCALE, 1);
+        let info_payload = [
+            timestamp_scale.clone(),
+            element(DURATION, &0f64.to_be_bytes()),
+            element(MUXING_APP, b"vraw_convert"),
+            element(WRITING_APP, b"vraw_convert"),
+        ]
+        .concat();
+        let info = element(INFO, &info_payload);
+        // the duration value follows its 2 byte id and 1 byte size
+        let duration_position =
+            (header.len() + info.len() - info_payload.len() + timestamp_scale.len() + 3) as u64;
+        header.extend(info);
+
+        let mut entry = vec![
+            uint_element(TRACK_NUMBER, 1),
+            uint_element(TRACK_UID, 1),
+            uint_element(TRACK_TYPE, TRACK_TYPE_VIDEO),
+            uint_element(FLAG_LACING, 0),
+            element(CODEC_ID, track.codec_id.as_bytes()),
+        ];
+        if let Some(codec_private) = &track.codec_private {
+            entry.push(element(CODEC_PRIVATE, codec_private));
+        }
+        entry.push(element(
+            VIDEO,
+            &[
+                uint_element(PIXEL_WIDTH, track.width as u64),
+                uint_element(PIXEL_HEIGHT, track.height as u64),
+            ]
+            .concat(),
+        ));
+        header.extend(element(TRACKS, &element(TRACK_ENTRY, &entry.concat())));
+
+        writer.write_all(&header)?;
+        debug_assert_eq!(
+            segment_size_position + SEGMENT_SIZE_LENGTH,
+            segment_start as usize
+        );
+
+        Ok(Self {
+            writer,
+            segment_start,
+            duration_position,
+            last_timestamp: 0,
+        })
+    }
+
+    /// Writes a frame in a cluster of its own. The timestamp is in nanoseconds
+    /// relative to the start of the file.
+    pub fn write_frame(&mut self, timestamp: u64, data: &[u8], keyframe: bool) -> io::Result<()> {
+        let mut block = Vec::with_capacity(data.len() + 4);
+        block.push(0x81); // track number 1 as a variable size integer
+        block.extend_from_slice(&0i16.to_be_bytes()); // timestamp relative to the cluster
+        block.push(if keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(data);
+
+        let cluster = element(
+            CLUSTER,
+            &[
+                uint_element(CLUSTER_TIMESTAMP, timestamp),
+                element(SIMPLE_BLOCK, &block),
+            ]
+            .concat(),
+        );
+        self.writer.write_all(&cluster)?;
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+
+        Ok(())
+    }
+
+    /// Patches the segment size and the duration, which is taken as the last
+    /// timestamp plus the duration of the last frame, in nanoseconds.
+    pub fn finish(mut self, last_frame_duration: u64) -> io::Result<W> {
+        let end = self.writer.seek(SeekFrom::End(0))?;
+        let segment_size = end - self.segment_start;
+        let duration = (self.last_timestamp + last_frame_duration) as f64;
+
+        let mut segment_size_bytes = segment_size.to_be_bytes();
+        segment_size_bytes[0] = 0x01; // 8 byte variable size integer marker
+        self.writer.seek(SeekFrom::Start(
+            self.segment_start - SEGMENT_SIZE_LENGTH as u64,
+        ))?;
+        self.writer.write_all(&segment_size_bytes)?;
+
+        self.writer.seek(SeekFrom::Start(self.duration_position))?;
+        self.writer.write_all(&duration.to_be_bytes())?;
+
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Element ids are stored with their length marker, so leading zero bytes are
+/// dropped.
+fn id_bytes(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let first = bytes.iter().position(|&byte| byte != 0).unwrap_or(3);
+    bytes[first..].to_vec()
+}
+
+/// Encodes a size as the shortest variable size integer.
+fn size_bytes(size: u64) -> Vec<u8> {
+    // all ones is reserved for unknown sizes
+    let length = (1..=8)
+        .find(|&length| size < (1 << (7 * length)) - 1)
+        .expect("element size fits in 8 bytes");
+
+    (size | 1 << (7 * length)).to_be_bytes()[8 - length..].to_vec()
+}
+
+fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut res = id_bytes(id);
+    res.extend(size_bytes(payload.len() as u64));
+    res.extend_from_slice(payload);
+    res
+}
+
+fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let first = bytes.iter().position(|&byte| byte != 0).unwrap_or(7);
+    element(id, &bytes[first..])
+}
@@ -1,16 +1,24 @@
+use crate::bitstream;
 use byteorder::LittleEndian;
+use chrono::{DateTime, TimeZone, Utc};
 use static_assertions::const_assert_eq;
 use std::{
     convert::TryFrom,
     error::Error,
+    fmt,
     fs::File,
-    io::{BufReader, Read, Seek, SeekFrom},
+    io::{self, BufReader, Read, Seek, SeekFrom},
     mem::{self, size_of},
+    ops::Range,
+    str::FromStr,
 };
 use zerocopy::{AsBytes, FromBytes, LayoutVerified, Unaligned};
 
+const RECORDING_MAGIC: u32 = 0xFEEDFEED;
 const GENERIC_METADATA_HEADER_MAGIC: u32 = 0xBACCDEEF;
+const GENERIC_METADATA_FOOTER_MAGIC: u32 = 0xBACCBEEF;
 const RECORDING_FRAME_MAGIC: u32 = 0xAAAAFEED;
+const RECORDING_INDEX_HEADER_MAGIC: u32 = 0xABCDFEED;
 const RECORDING_INDEX_FOOTER_MAGIC: u32 = 0xDCBAFEED;
 
 const VIDEO_PLACEMENT_METADATA_MAGIC_1: u8 = 0x00;
@@ -19,20 +27,126 @@ const VIDEO_PLACEMENT_METADATA_MAGIC_3: u8 = 0x00;
 const VIDEO_PLACEMENT_METADATA_MAGIC_4: u8 = 0x56;
 const VIDEO_PLACEMENT_METADATA_MAGIC_5: u8 = 0x4A;
 
+/// Most alignment bytes searched for after the video placement footer of a
+/// frame unless configured otherwise.
+pub const DEFAULT_MAX_PLACEMENT_PADDING: usize = 11;
+
+/// How inconsistencies in the structure of a recording are handled.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum Strictness {
+    /// Fail on any inconsistency
+    Strict,
+    /// Recover from inconsistencies where possible, with a warning
+    #[default]
+    Lenient,
+}
+
+/// Most time a frame found after damaged bytes may have been received before or
+/// after the frame before them, in nanoseconds. Frame magics in the damaged
+/// bytes rarely make it through the other checks, and are left out by this one.
+const MAX_RESYNC_TIMESTAMP_JUMP: u64 = 3600 * 1_000_000_000;
+
+/// Whether a frame found after damaged bytes was received plausibly close to
+/// the frame before them
+fn plausible_after(previous_timestamp: Option<i64>, timestamp: i64) -> bool {
+    previous_timestamp
+        .is_none_or(|previous| previous.abs_diff(timestamp) <= MAX_RESYNC_TIMESTAMP_JUMP)
+}
+
+/// Options of the parser.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ParseOptions {
+    pub strictness: Strictness,
+    /// Most alignment bytes searched for after the video placement footer of
+    /// a frame
+    pub max_placement_padding: usize,
+    /// Take coded frames whose bitstream is of another codec than their
+    /// format as that codec, for recorders misconfigured to record the wrong
+    /// format. See [`sniff_format`].
+    pub trust_bitstream: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            strictness: Strictness::default(),
+            max_placement_padding: DEFAULT_MAX_PLACEMENT_PADDING,
+            trust_bitstream: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Fails on an inconsistency when strict, or else notes it as a warning
+    fn recover(
+        &self,
+        warnings: &mut Vec<String>,
+        inconsistency: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.strictness {
+            Strictness::Strict => Err(inconsistency.into()),
+            Strictness::Lenient => {
+                warnings.push(inconsistency);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A recording that can be read in any order, as a file or a recording in
+/// memory can.
+pub trait RecordingReader: Read + Seek {
+    /// Length of the recording in bytes
+    fn recording_len(&mut self) -> io::Result<u64>;
+}
+
+impl RecordingReader for BufReader<File> {
+    fn recording_len(&mut self) -> io::Result<u64> {
+        Ok(self.get_ref().metadata()?.len())
+    }
+}
+
+impl<T: AsRef<[u8]>> RecordingReader for io::Cursor<T> {
+    fn recording_len(&mut self) -> io::Result<u64> {
+        Ok(self.get_ref().as_ref().len() as u64)
+    }
+}
+
+impl<R: RecordingReader + ?Sized> RecordingReader for &mut R {
+    fn recording_len(&mut self) -> io::Result<u64> {
+        (**self).recording_len()
+    }
+}
+
 type I32 = zerocopy::I32<LittleEndian>;
 type I64 = zerocopy::I64<LittleEndian>;
 type U16 = zerocopy::U16<LittleEndian>;
 type U32 = zerocopy::U32<LittleEndian>;
 type U64 = zerocopy::U64<LittleEndian>;
 
+/// The header of a recording, as stored at its start. Only its methods are
+/// part of the API, its fields follow the file format.
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 #[repr(C)]
-struct RecordingMetadata {
+pub struct RecordingMetadata {
     magic: U32,
     unix_epoch_time_relative_nsec: U32,
     unix_epoch_time_sec: U64,
 }
 
+impl RecordingMetadata {
+    /// Wall clock time at which the recording started. The frame timestamps
+    /// are relative to it.
+    pub fn start(&self) -> Result<DateTime<Utc>, Box<dyn Error + Send + Sync>> {
+        Utc.timestamp_opt(
+            i64::try_from(self.unix_epoch_time_sec.get())?,
+            self.unix_epoch_time_relative_nsec.get(),
+        )
+        .single()
+        .ok_or_else(|| "Recording start time out of range".into())
+    }
+}
+
 const_assert_eq!(mem::size_of::<RecordingMetadata>(), 16);
 
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
@@ -51,6 +165,10 @@ struct RecordedFrameMetadata {
 
 const_assert_eq!(mem::size_of::<RecordedFrameMetadata>(), 48);
 
+/// Length of the header in front of the payload of every frame. The payload of
+/// the frame of an index entry starts this many bytes after its offset.
+pub const FRAME_HEADER_LEN: usize = mem::size_of::<RecordedFrameMetadata>();
+
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 #[repr(C)]
 struct GenericMetadataHeader {
@@ -60,6 +178,18 @@ struct GenericMetadataHeader {
 
 const_assert_eq!(mem::size_of::<GenericMetadataHeader>(), 8);
 
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
+#[repr(C)]
+struct GenericMetadataFooter {
+    magic: U32,
+    generic_metadata_size: U32,
+}
+
+const_assert_eq!(mem::size_of::<GenericMetadataFooter>(), 8);
+
+/// Largest generic metadata block accepted, larger sizes are taken as corrupt
+const MAX_GENERIC_METADATA_SIZE: u32 = 16 * 1024 * 1024;
+
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 #[repr(C)]
 struct RecordingIndexHeader {
@@ -69,6 +199,8 @@ struct RecordingIndexHeader {
 
 const_assert_eq!(mem::size_of::<RecordingIndexHeader>(), 8);
 
+/// An entry of the index of a recording, as stored in the file. Only its
+/// methods are part of the API, its fields follow the file format.
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 #[repr(C)]
 pub struct RecordingIndexEntry {
@@ -78,6 +210,34 @@ pub struct RecordingIndexEntry {
 
 const_assert_eq!(mem::size_of::<RecordingIndexEntry>(), 16);
 
+impl RecordingIndexEntry {
+    pub fn new(offset: i64, receive_timestamp: i64) -> Self {
+        Self {
+            offset: I64::new(offset),
+            receive_timestamp: I64::new(receive_timestamp),
+        }
+    }
+
+    /// Byte offset of the frame in the recording
+    pub fn offset(&self) -> i64 {
+        self.offset.get()
+    }
+
+    /// Time the frame was received, in nanoseconds relative to the recording
+    /// start
+    pub fn receive_timestamp(&self) -> i64 {
+        self.receive_timestamp.get()
+    }
+
+    /// Byte range of the payload of the frame in the recording, video
+    /// placement metadata included, given its header read with
+    /// [`parse_frame_metadata`](crate::parse_frame_metadata).
+    pub fn payload_range(&self, metadata: &FrameMetadata) -> Range<u64> {
+        let start = self.offset() as u64 + FRAME_HEADER_LEN as u64;
+        start..start + metadata.size as u64
+    }
+}
+
 #[derive(Debug, Clone, FromBytes, AsBytes, Unaligned)]
 #[repr(C)]
 struct RecordingIndexFooter {
@@ -100,31 +260,218 @@ struct VideoPlacementMetadataFooter {
 
 const_assert_eq!(mem::size_of::<VideoPlacementMetadataFooter>(), 7);
 
-#[derive(Debug, Clone)]
+/// A frame read in full. It is serialized with its payloads base64 encoded.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct FrameInfo {
-    pub resolution: String,
+    /// Id of the source stream, the camera, the frame belongs to
+    pub id: i32,
+    /// Width in pixels, that of the SPS for HEVC frames carrying one. Other
+    /// coded formats except MJPEG are stored without dimensions.
+    pub width: i32,
+    pub height: i32,
     pub format: VideoCaptureFormat,
+    /// The payload, without the video placement metadata after it
+    #[serde(with = "base64_bytes")]
     pub raw_data: Vec<u8>,
-    pub timestamp: i64,
+    /// Size of the payload as stored, including the video placement metadata
+    pub size: usize,
+    /// Time the frame was received at, in nanoseconds relative to the start
+    /// of the recording
+    pub receive_timestamp: i64,
+    /// Time the frame was captured at, as told by the capture system
+    pub capture_timestamp: i64,
+    /// The video placement metadata stored after the payload, if any. Its
+    /// layout is not known to the converter.
+    #[serde(with = "base64_bytes_option")]
+    pub placement_metadata: Option<Vec<u8>>,
+    /// Number of alignment bytes stripped after the video placement footer,
+    /// None if no footer was found and the payload is kept as stored
+    pub placement_padding: Option<usize>,
+    /// Inconsistencies of the frame recovered from by lenient parsing
+    pub warnings: Vec<String>,
+    /// The generic metadata block of the frame, empty if the frame has none
+    #[serde(with = "base64_bytes")]
+    pub generic_metadata: Vec<u8>,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[repr(i32)]
+/// Serializes bytes as a base64 string.
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes optional bytes as a base64 string or null.
+mod base64_bytes_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => super::base64_bytes::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        #[derive(Deserialize)]
+        struct Bytes(#[serde(with = "super::base64_bytes")] Vec<u8>);
+
+        Ok(Option::<Bytes>::deserialize(deserializer)?.map(|Bytes(bytes)| bytes))
+    }
+}
+
+impl FrameInfo {
+    /// The dimensions of the frame as "{width}x{height}"
+    pub fn resolution(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+/// Shows the dimensions of the frame, as [`resolution`](FrameInfo::resolution).
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// A frame read with [`read_frame_into`](crate::read_frame_into), borrowing
+/// its payload from the buffer it was read into. The fields are those of
+/// [`FrameInfo`].
+#[derive(Debug, Clone)]
+pub struct FrameRef<'a> {
+    pub id: i32,
+    pub width: i32,
+    pub height: i32,
+    pub format: VideoCaptureFormat,
+    /// The payload, without the video placement metadata after it
+    pub raw_data: &'a [u8],
+    pub size: usize,
+    pub receive_timestamp: i64,
+    pub capture_timestamp: i64,
+    pub placement_metadata: Option<&'a [u8]>,
+    pub placement_padding: Option<usize>,
+    pub warnings: Vec<String>,
+    /// The generic metadata block of the frame, which is small and read
+    /// separately from the payload
+    pub generic_metadata: Vec<u8>,
+}
+
+impl FrameRef<'_> {
+    /// The dimensions of the frame as "{width}x{height}"
+    pub fn resolution(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+
+    /// Copies the frame out of the buffer it was read into.
+    pub fn to_frame_info(&self) -> FrameInfo {
+        FrameInfo {
+            id: self.id,
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            raw_data: self.raw_data.to_vec(),
+            size: self.size,
+            receive_timestamp: self.receive_timestamp,
+            capture_timestamp: self.capture_timestamp,
+            placement_metadata: self.placement_metadata.map(<[u8]>::to_vec),
+            placement_padding: self.placement_padding,
+            warnings: self.warnings.clone(),
+            generic_metadata: self.generic_metadata.clone(),
+        }
+    }
+}
+
+/// Shows the dimensions of the frame, as [`resolution`](FrameRef::resolution).
+impl fmt::Display for FrameRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// The header of a frame, as read without its payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameMetadata {
+    /// Id of the source stream the frame belongs to
+    pub id: i32,
+    pub format: VideoCaptureFormat,
+    /// Width as stored, coded formats except MJPEG are stored without
+    /// dimensions
+    pub width: i32,
+    pub height: i32,
+    /// Size of the payload in bytes, including the video placement metadata
+    pub size: usize,
+    pub receive_timestamp: i64,
+    pub capture_timestamp: i64,
+}
+
+/// Error of a frame cut off by the end of the file, as the last frame of a
+/// recording that ended abruptly is.
+#[derive(Debug)]
+pub struct TruncatedFrame {
+    /// Byte offset of the frame in the recording
+    pub offset: i64,
+    /// Number of bytes in the file after the offset, or after the header if
+    /// the payload is cut off
+    pub remaining_len: u64,
+    /// Payload size stored in the header, if the payload is cut off. A corrupt
+    /// size looks the same.
+    pub claimed_size: Option<i64>,
+}
+
+impl fmt::Display for TruncatedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.claimed_size {
+            Some(size) => write!(
+                f,
+                "frame at offset {} claims {} bytes, more than the {} bytes left in the file",
+                self.offset, size, self.remaining_len
+            ),
+            None => write!(
+                f,
+                "frame at offset {} is cut off by the end of the file, {} bytes after it",
+                self.offset, self.remaining_len
+            ),
+        }
+    }
+}
+
+impl Error for TruncatedFrame {}
+
+/// The format of a frame. It is serialized as its name, e.g. "H264", or as
+/// "Unknown(-4801)" for unknown format codes, see [`code`](Self::code) for the
+/// code of the file format.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum VideoCaptureFormat {
-    Rgb = 0,
-    Bgr = 1,
-    Yuv = 2,
-    Nv12 = 3,
-    Yuyv = 4,
-    Uyvy = 5,
-    Raw = 6,
-    Mono16 = 7,
-    Raw16 = 8,
-    Mono8 = 9,
-    H264 = -4601,
-    H265 = -4602,
-    Mjpeg = -4603,
-    Stats = -4701,
+    Rgb,
+    Bgr,
+    Yuv,
+    Nv12,
+    Yuyv,
+    Uyvy,
+    Raw,
+    Mono16,
+    Raw16,
+    Mono8,
+    H264,
+    H265,
+    Mjpeg,
+    Stats,
+    /// A format code unknown to the parser, as written by newer recorders.
+    /// Its frames are read with [`Strictness::Lenient`] parsing, to be skipped.
+    Unknown(i32),
 }
 
 impl VideoCaptureFormat {
@@ -134,33 +481,121 @@ impl VideoCaptureFormat {
             VideoCaptureFormat::H264 | VideoCaptureFormat::H265 | VideoCaptureFormat::Mjpeg
         )
     }
+
+    /// The code of the format as stored in the frame headers
+    pub fn code(&self) -> i32 {
+        i32::from(*self)
+    }
 }
 
-impl TryFrom<i32> for VideoCaptureFormat {
-    type Error = Box<dyn Error>;
+impl FromStr for VideoCaptureFormat {
+    type Err = String;
 
-    fn try_from(format: i32) -> Result<Self, Self::Error> {
+    /// Parses the name of a format, as it is serialized.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let format = match name {
+            "Rgb" => VideoCaptureFormat::Rgb,
+            "Bgr" => VideoCaptureFormat::Bgr,
+            "Yuv" => VideoCaptureFormat::Yuv,
+            "Nv12" => VideoCaptureFormat::Nv12,
+            "Yuyv" => VideoCaptureFormat::Yuyv,
+            "Uyvy" => VideoCaptureFormat::Uyvy,
+            "Raw" => VideoCaptureFormat::Raw,
+            "Mono16" => VideoCaptureFormat::Mono16,
+            "Raw16" => VideoCaptureFormat::Raw16,
+            "Mono8" => VideoCaptureFormat::Mono8,
+            "H264" => VideoCaptureFormat::H264,
+            "H265" => VideoCaptureFormat::H265,
+            "Mjpeg" => VideoCaptureFormat::Mjpeg,
+            "Stats" => VideoCaptureFormat::Stats,
+            name => name
+                .strip_prefix("Unknown(")
+                .and_then(|code| code.strip_suffix(')'))
+                .and_then(|code| code.parse::<i32>().ok())
+                .map(VideoCaptureFormat::from)
+                .ok_or_else(|| format!("Unknown VideoCaptureFormat {name}"))?,
+        };
+
+        Ok(format)
+    }
+}
+
+// Serialized as a string, which maps of formats need as keys in JSON
+impl serde::Serialize for VideoCaptureFormat {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{self:?}"))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for VideoCaptureFormat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<i32> for VideoCaptureFormat {
+    fn from(format: i32) -> Self {
         match format {
-            0 => Ok(VideoCaptureFormat::Rgb),
-            1 => Ok(VideoCaptureFormat::Bgr),
-            2 => Ok(VideoCaptureFormat::Yuv),
-            3 => Ok(VideoCaptureFormat::Nv12),
-            4 => Ok(VideoCaptureFormat::Yuyv),
-            5 => Ok(VideoCaptureFormat::Uyvy),
-            6 => Ok(VideoCaptureFormat::Raw),
-            7 => Ok(VideoCaptureFormat::Mono16),
-            8 => Ok(VideoCaptureFormat::Raw16),
-            9 => Ok(VideoCaptureFormat::Mono8),
-            -4601 => Ok(VideoCaptureFormat::H264),
-            -4602 => Ok(VideoCaptureFormat::H265),
-            -4603 => Ok(VideoCaptureFormat::Mjpeg),
-            -4701 => Ok(VideoCaptureFormat::Stats),
-            _ => Err(format!("Unknown video capture format {}", format).into()),
-        }
-    }
-}
-
-fn parse_recording_index_footer(bytes: &[u8]) -> Result<&RecordingIndexFooter, Box<dyn Error>> {
+            0 => VideoCaptureFormat::Rgb,
+            1 => VideoCaptureFormat::Bgr,
+            2 => VideoCaptureFormat::Yuv,
+            3 => VideoCaptureFormat::Nv12,
+            4 => VideoCaptureFormat::Yuyv,
+            5 => VideoCaptureFormat::Uyvy,
+            6 => VideoCaptureFormat::Raw,
+            7 => VideoCaptureFormat::Mono16,
+            8 => VideoCaptureFormat::Raw16,
+            9 => VideoCaptureFormat::Mono8,
+            -4601 => VideoCaptureFormat::H264,
+            -4602 => VideoCaptureFormat::H265,
+            -4603 => VideoCaptureFormat::Mjpeg,
+            -4701 => VideoCaptureFormat::Stats,
+            format => VideoCaptureFormat::Unknown(format),
+        }
+    }
+}
+
+impl From<VideoCaptureFormat> for i32 {
+    fn from(format: VideoCaptureFormat) -> Self {
+        match format {
+            VideoCaptureFormat::Rgb => 0,
+            VideoCaptureFormat::Bgr => 1,
+            VideoCaptureFormat::Yuv => 2,
+            VideoCaptureFormat::Nv12 => 3,
+            VideoCaptureFormat::Yuyv => 4,
+            VideoCaptureFormat::Uyvy => 5,
+            VideoCaptureFormat::Raw => 6,
+            VideoCaptureFormat::Mono16 => 7,
+            VideoCaptureFormat::Raw16 => 8,
+            VideoCaptureFormat::Mono8 => 9,
+            VideoCaptureFormat::H264 => -4601,
+            VideoCaptureFormat::H265 => -4602,
+            VideoCaptureFormat::Mjpeg => -4603,
+            VideoCaptureFormat::Stats => -4701,
+            VideoCaptureFormat::Unknown(format) => format,
+        }
+    }
+}
+
+fn parse_recording_metadata(
+    bytes: &[u8],
+) -> Result<&RecordingMetadata, Box<dyn Error + Send + Sync>> {
+    LayoutVerified::<&[u8], RecordingMetadata>::new_unaligned(bytes)
+        .ok_or_else(|| "Failed to parse RecordingMetadata".into())
+        .map(|lv| lv.into_ref())
+        .and_then(|res| {
+            if res.magic.get() == RECORDING_MAGIC {
+                Ok(res)
+            } else {
+                Err("Magic does not match".into())
+            }
+        })
+}
+
+fn parse_recording_index_footer(
+    bytes: &[u8],
+) -> Result<&RecordingIndexFooter, Box<dyn Error + Send + Sync>> {
     LayoutVerified::<&[u8], RecordingIndexFooter>::new_unaligned(bytes)
         .ok_or_else(|| "Failed to parse RecordingIndexFooter".into())
         .map(|lv| lv.into_ref())
@@ -173,13 +608,17 @@ fn parse_recording_index_footer(bytes: &[u8]) -> Result<&RecordingIndexFooter, B
         })
 }
 
-fn parse_recording_index_entry(bytes: &[u8]) -> Result<&RecordingIndexEntry, Box<dyn Error>> {
+fn parse_recording_index_entry(
+    bytes: &[u8],
+) -> Result<&RecordingIndexEntry, Box<dyn Error + Send + Sync>> {
     LayoutVerified::<&[u8], RecordingIndexEntry>::new_unaligned(bytes)
         .ok_or_else(|| "Failed to parse RecordingIndexEntry".into())
         .map(|lv| lv.into_ref())
 }
 
-fn parse_recorded_frame_metadata(bytes: &[u8]) -> Result<&RecordedFrameMetadata, Box<dyn Error>> {
+fn parse_recorded_frame_metadata(
+    bytes: &[u8],
+) -> Result<&RecordedFrameMetadata, Box<dyn Error + Send + Sync>> {
     LayoutVerified::<&[u8], RecordedFrameMetadata>::new_unaligned(bytes)
         .ok_or_else(|| "Failed to parse RecordedFrameMetadata".into())
         .map(|lv| lv.into_ref())
@@ -192,7 +631,9 @@ fn parse_recorded_frame_metadata(bytes: &[u8]) -> Result<&RecordedFrameMetadata,
         })
 }
 
-fn parse_generic_metadata_header(bytes: &[u8]) -> Result<&GenericMetadataHeader, Box<dyn Error>> {
+fn parse_generic_metadata_header(
+    bytes: &[u8],
+) -> Result<&GenericMetadataHeader, Box<dyn Error + Send + Sync>> {
     LayoutVerified::<&[u8], GenericMetadataHeader>::new_unaligned(bytes)
         .ok_or_else(|| "Failed to parse GenericMetadataHeader".into())
         .map(|lv| lv.into_ref())
@@ -205,9 +646,24 @@ fn parse_generic_metadata_header(bytes: &[u8]) -> Result<&GenericMetadataHeader,
         })
 }
 
+fn parse_generic_metadata_footer(
+    bytes: &[u8],
+) -> Result<&GenericMetadataFooter, Box<dyn Error + Send + Sync>> {
+    LayoutVerified::<&[u8], GenericMetadataFooter>::new_unaligned(bytes)
+        .ok_or_else(|| "Failed to parse GenericMetadataFooter".into())
+        .map(|lv| lv.into_ref())
+        .and_then(|res| {
+            if res.magic.get() == GENERIC_METADATA_FOOTER_MAGIC {
+                Ok(res)
+            } else {
+                Err("Magic does not match".into())
+            }
+        })
+}
+
 fn parse_video_placement_footer(
     bytes: &[u8],
-) -> Result<&VideoPlacementMetadataFooter, Box<dyn Error>> {
+) -> Result<&VideoPlacementMetadataFooter, Box<dyn Error + Send + Sync>> {
     LayoutVerified::<&[u8], VideoPlacementMetadataFooter>::new_unaligned(bytes)
         .ok_or_else(|| "Failed to parse VideoPlacementMetadataFooter".into())
         .map(|lv| lv.into_ref())
@@ -225,22 +681,147 @@ fn parse_video_placement_footer(
         })
 }
 
-pub fn read_index(f: &mut BufReader<File>) -> Result<Vec<RecordingIndexEntry>, Box<dyn Error>> {
+/// Searches the last bytes of a frame for a video placement footer followed by
+/// at most `max_padding` bytes of alignment data, and returns it together with
+/// the position it starts at. Frames too small to hold a footer have no
+/// alignment data.
+fn find_video_placement_footer(
+    raw_frame_data: &[u8],
+    max_padding: usize,
+) -> Option<(usize, &VideoPlacementMetadataFooter)> {
+    // Loop from the end to try and match the video placement magic(s). If the
+    // end has to be looped further then it probably do not have alignment data
+    (0..=max_padding).find_map(|offset| {
+        let footer_end = raw_frame_data.len().checked_sub(offset)?;
+        let footer_start = footer_end.checked_sub(size_of::<VideoPlacementMetadataFooter>())?;
+
+        parse_video_placement_footer(&raw_frame_data[footer_start..footer_end])
+            .ok()
+            .map(|video_placement_footer| (footer_start, video_placement_footer))
+    })
+}
+
+/// Reads the header of the file. Returns None for recordings of old recorders,
+/// which start with the first frame instead.
+pub fn read_recording_metadata(
+    f: &mut impl RecordingReader,
+) -> Result<Option<RecordingMetadata>, Box<dyn Error + Send + Sync>> {
+    f.seek(SeekFrom::Start(0))?;
+
+    let mut recording_metadata_bytes = [0; mem::size_of::<RecordingMetadata>()];
+    read_with_context(f, &mut recording_metadata_bytes, "the recording header")?;
+
+    if recording_metadata_bytes.starts_with(&RECORDING_FRAME_MAGIC.to_le_bytes()) {
+        return Ok(None);
+    }
+
+    parse_recording_metadata(&recording_metadata_bytes).map(|metadata| Some(metadata.clone()))
+}
+
+/// Reads the wall clock time at which the recording started from the header of
+/// the file. The frame timestamps are relative to it.
+pub fn read_recording_start(
+    f: &mut impl RecordingReader,
+) -> Result<DateTime<Utc>, Box<dyn Error + Send + Sync>> {
+    read_recording_metadata(f)?
+        .ok_or("The recording has no header, as written by old recorders.")?
+        .start()
+}
+
+/// Offset of the first frame, which follows the header of the file unless the
+/// recording has none.
+pub fn first_frame_offset(f: &mut impl RecordingReader) -> u64 {
+    match read_recording_metadata(f) {
+        Ok(None) => 0,
+        _ => mem::size_of::<RecordingMetadata>() as u64,
+    }
+}
+
+/// Reads exactly `buf.len()` bytes, with an error naming what was read and
+/// where
+fn read_with_context(
+    f: &mut impl RecordingReader,
+    buf: &mut [u8],
+    what: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let offset = f.stream_position()?;
+
+    f.read_exact(buf).map_err(|e| {
+        format!(
+            "Failed to read {what} at offset {offset}, expected {} bytes: {e}",
+            buf.len()
+        )
+        .into()
+    })
+}
+
+/// Returns the length of the file, or an error if it is too small to hold
+/// `min_len` bytes of a recording
+fn check_file_len(
+    f: &mut impl RecordingReader,
+    min_len: usize,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let file_len = f.seek(SeekFrom::End(0))?;
+    if file_len < min_len as u64 {
+        return Err(format!("file too small to be a vraw recording ({file_len} bytes)").into());
+    }
+
+    Ok(file_len)
+}
+
+/// The index of a recording.
+#[derive(Debug, Clone)]
+pub struct RecordingIndex {
+    pub entries: Vec<RecordingIndexEntry>,
+    /// Inconsistencies of the index recovered from by lenient parsing
+    pub warnings: Vec<String>,
+}
+
+/// Reads the index at the end of a recording, in the order it was written.
+/// Recordings cut off before their index was written have none, see
+/// [`VrawReader`](crate::VrawReader), which rebuilds it from the frames.
+pub fn read_index(
+    f: &mut impl RecordingReader,
+    options: &ParseOptions,
+) -> Result<RecordingIndex, Box<dyn Error + Send + Sync>> {
+    let file_len = check_file_len(f, mem::size_of::<RecordingIndexFooter>())?;
+
     f.seek(SeekFrom::End(
         -(mem::size_of::<RecordingIndexFooter>() as i64),
     ))?;
 
     let mut index_footer_bytes: [u8; mem::size_of::<RecordingIndexFooter>()] =
         [0; mem::size_of::<RecordingIndexFooter>()];
-    f.read_exact(&mut index_footer_bytes).unwrap();
+    read_with_context(f, &mut index_footer_bytes, "the index footer")?;
 
     let footer = parse_recording_index_footer(&index_footer_bytes)?;
 
-    f.seek(SeekFrom::End(
-        -((mem::size_of::<RecordingIndexFooter>()
-            + footer.frame_count.get() as usize * mem::size_of::<RecordingIndexEntry>())
-            as i64),
-    ))?;
+    // A corrupt frame count would otherwise seek before the start of the file
+    let index_len = mem::size_of::<RecordingIndexFooter>() as u64
+        + footer.frame_count.get() as u64 * mem::size_of::<RecordingIndexEntry>() as u64;
+    if index_len > file_len {
+        return Err(format!(
+            "Index footer claims {} frames, which do not fit in the {} bytes of the file",
+            footer.frame_count.get(),
+            file_len
+        )
+        .into());
+    }
+
+    let mut warnings = Vec::new();
+    let index_header_len = mem::size_of::<RecordingIndexHeader>() as u64;
+    if index_len + index_header_len > file_len {
+        options.recover(&mut warnings, "Index header missing".into())?;
+    } else {
+        f.seek(SeekFrom::End(-((index_len + index_header_len) as i64)))?;
+        let mut index_header_bytes = [0; mem::size_of::<RecordingIndexHeader>()];
+        read_with_context(f, &mut index_header_bytes, "the index header")?;
+        if !index_header_bytes.starts_with(&RECORDING_INDEX_HEADER_MAGIC.to_le_bytes()) {
+            options.recover(&mut warnings, "Index header: Magic does not match".into())?;
+        }
+    }
+
+    f.seek(SeekFrom::End(-(index_len as i64)))?;
 
     // At the first frame now
     let mut res = Vec::with_capacity(footer.frame_count.get() as _);
@@ -248,38 +829,322 @@ pub fn read_index(f: &mut BufReader<File>) -> Result<Vec<RecordingIndexEntry>, B
     for _ in 0..footer.frame_count.get() {
         let mut index_entry_bytes: [u8; mem::size_of::<RecordingIndexEntry>()] =
             [0; mem::size_of::<RecordingIndexEntry>()];
-        f.read_exact(&mut index_entry_bytes)?;
+        read_with_context(f, &mut index_entry_bytes, "an index entry")?;
 
         let entry = parse_recording_index_entry(&index_entry_bytes)?;
 
         res.push(entry.to_owned());
     }
 
-    Ok(res)
+    Ok(RecordingIndex {
+        entries: res,
+        warnings,
+    })
 }
 
-pub fn parse_raw_frame(
-    f: &mut BufReader<File>,
+/// Checks the offsets of the index entries against the size of the file.
+/// Returns the position of every invalid entry in the index together with what
+/// is wrong with it. An entry has to point at or after the first frame, leave
+/// room for a frame header and come after the last valid entry.
+pub fn find_invalid_index_entries(
+    entries: &[RecordingIndexEntry],
+    first_frame_offset: u64,
+    file_len: u64,
+) -> Vec<(usize, String)> {
+    let min_offset = first_frame_offset as i64;
+    let max_offset = file_len as i64 - mem::size_of::<RecordedFrameMetadata>() as i64;
+
+    let mut res = Vec::new();
+    let mut previous_offset = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let offset = entry.offset.get();
+
+        match previous_offset {
+            _ if offset < min_offset || offset > max_offset => res.push((
+                i,
+                format!("offset {offset} outside of the frames ({min_offset}..={max_offset})"),
+            )),
+            Some(previous_offset) if offset <= previous_offset => res.push((
+                i,
+                format!(
+                    "offset {offset} not after the offset {previous_offset} of the entry before"
+                ),
+            )),
+            _ => previous_offset = Some(offset),
+        }
+    }
+
+    res
+}
+
+/// Rebuilds the index of a recording whose index footer is missing or corrupt,
+/// e.g. because the recorder crashed. The file is scanned from the start for
+/// frame headers, and a candidate is only taken as a frame when its header
+/// parses and its payload and generic metadata fit in the file. After damaged
+/// bytes, the candidate also has to have a known format and be received around
+/// the frame before them.
+/// The damaged bytes skipped are listed in the warnings.
+pub fn scan_index(
+    f: &mut impl RecordingReader,
+) -> Result<RecordingIndex, Box<dyn Error + Send + Sync>> {
+    let file_len = check_file_len(f, mem::size_of::<RecordingMetadata>())?;
+    let mut res: Vec<RecordingIndexEntry> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut truncated = None;
+    // Offset after the last frame found, where the next frame starts unless
+    // the bytes there are damaged
+    let mut expected = first_frame_offset(f);
+    let mut offset = expected;
+
+    while let Some(candidate) = find_frame_magic(f, offset, file_len)? {
+        let previous_timestamp = res.last().map(RecordingIndexEntry::receive_timestamp);
+        match scan_frame(f, candidate, file_len)? {
+            Some(ScannedFrame {
+                entry,
+                known_format,
+                ..
+            }) if candidate != expected
+                && (!known_format
+                    || !plausible_after(previous_timestamp, entry.receive_timestamp())) =>
+            {
+                offset = candidate + 1
+            }
+            Some(ScannedFrame {
+                entry,
+                next: Some(next),
+                ..
+            }) => {
+                if candidate != expected {
+                    warnings.push(format!(
+                        "Skipped {} damaged bytes at offset {expected} to the frame at offset \
+                         {candidate}",
+                        candidate - expected
+                    ));
+                }
+                res.push(entry);
+                truncated = None;
+                expected = next;
+                offset = next;
+            }
+            // A frame cut off by the end of the file is only taken as the last
+            // frame if no complete frame follows it
+            Some(ScannedFrame {
+                entry, next: None, ..
+            }) => {
+                truncated = Some(entry);
+                offset = candidate + 1;
+            }
+            None => offset = candidate + 1,
+        }
+    }
+
+    if let Some(entry) = truncated {
+        let candidate = entry.offset() as u64;
+        if candidate != expected {
+            warnings.push(format!(
+                "Skipped {} damaged bytes at offset {expected} to the frame at offset {candidate}",
+                candidate - expected
+            ));
+        }
+        res.push(entry);
+    }
+
+    Ok(RecordingIndex {
+        entries: res,
+        warnings,
+    })
+}
+
+/// Returns the offset of the first frame magic at or after `offset`
+fn find_frame_magic(
+    f: &mut impl RecordingReader,
+    offset: u64,
+    file_len: u64,
+) -> Result<Option<u64>, Box<dyn Error + Send + Sync>> {
+    let magic = RECORDING_FRAME_MAGIC.to_le_bytes();
+    let mut chunk = vec![0; 64 * 1024];
+    let mut chunk_start = offset;
+
+    while chunk_start + magic.len() as u64 <= file_len {
+        let chunk_len = chunk.len().min((file_len - chunk_start) as usize);
+        f.seek(SeekFrom::Start(chunk_start))?;
+        f.read_exact(&mut chunk[..chunk_len])?;
+
+        if let Some(position) = chunk[..chunk_len]
+            .windows(magic.len())
+            .position(|window| window == magic)
+        {
+            return Ok(Some(chunk_start + position as u64));
+        }
+
+        // Overlap the chunks so that a magic on the boundary is found
+        chunk_start += (chunk_len - (magic.len() - 1)) as u64;
+    }
+
+    Ok(None)
+}
+
+/// A frame found by [`scan_index`].
+struct ScannedFrame {
+    entry: RecordingIndexEntry,
+    /// Whether the format of the frame is known to the parser
+    known_format: bool,
+    /// Offset after the frame, None for a frame cut off by the end of the file
+    next: Option<u64>,
+}
+
+/// Checks whether a frame starts at `offset`
+fn scan_frame(
+    f: &mut impl RecordingReader,
+    offset: u64,
+    file_len: u64,
+) -> Result<Option<ScannedFrame>, Box<dyn Error + Send + Sync>> {
+    let header_len = mem::size_of::<RecordedFrameMetadata>() as u64;
+    let generic_metadata_header_len = mem::size_of::<GenericMetadataHeader>() as u64;
+    if offset + header_len > file_len {
+        return Ok(None);
+    }
+
+    f.seek(SeekFrom::Start(offset))?;
+    let mut recorded_frame_metadata_bytes = [0; mem::size_of::<RecordedFrameMetadata>()];
+    f.read_exact(&mut recorded_frame_metadata_bytes)?;
+    let recorded_frame_metadata =
+        match parse_recorded_frame_metadata(&recorded_frame_metadata_bytes) {
+            Ok(recorded_frame_metadata) => recorded_frame_metadata,
+            Err(_) => return Ok(None),
+        };
+
+    let size = recorded_frame_metadata.size.get();
+    if size <= 0 {
+        return Ok(None);
+    }
+    let known_format = !matches!(
+        VideoCaptureFormat::from(recorded_frame_metadata.format.get()),
+        VideoCaptureFormat::Unknown(_)
+    );
+
+    let entry = RecordingIndexEntry {
+        offset: I64::new(offset as i64),
+        receive_timestamp: recorded_frame_metadata.receive_timestamp,
+    };
+    if size as u64 + generic_metadata_header_len > file_len - offset - header_len {
+        return Ok(Some(ScannedFrame {
+            entry,
+            known_format,
+            next: None,
+        }));
+    }
+
+    // The generic metadata header, its data and the footer of the same size
+    let generic_metadata_offset = offset + header_len + size as u64;
+    f.seek(SeekFrom::Start(generic_metadata_offset))?;
+    let mut generic_metadata_header_bytes = [0; mem::size_of::<GenericMetadataHeader>()];
+    f.read_exact(&mut generic_metadata_header_bytes)?;
+    if starts_with_frame_or_index(&generic_metadata_header_bytes) {
+        return Ok(Some(ScannedFrame {
+            entry,
+            known_format,
+            next: Some(generic_metadata_offset),
+        }));
+    }
+    let generic_metadata_header =
+        match parse_generic_metadata_header(&generic_metadata_header_bytes) {
+            Ok(generic_metadata_header) => generic_metadata_header,
+            Err(_) => return Ok(None),
+        };
+
+    let next = generic_metadata_offset
+        + 2 * generic_metadata_header_len
+        + generic_metadata_header.generic_metadata_size.get() as u64;
+    Ok(Some(ScannedFrame {
+        entry,
+        known_format,
+        next: Some(next).filter(|&next| next <= file_len),
+    }))
+}
+
+/// Number of bytes in the file after the first `skip` bytes of the frame of an
+/// index entry
+fn remaining_len(
+    f: &mut impl RecordingReader,
     entry: &RecordingIndexEntry,
-) -> Result<FrameInfo, Box<dyn Error>> {
+    skip: usize,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    Ok(f.recording_len()?
+        .saturating_sub(entry.offset.get() as u64 + skip as u64))
+}
+
+/// Reads the next bytes of the frame of an index entry, a read beyond the end of
+/// the file is a [`TruncatedFrame`] error.
+fn read_frame_bytes(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    buf: &mut [u8],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match f.read_exact(buf) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(TruncatedFrame {
+            offset: entry.offset.get(),
+            remaining_len: remaining_len(f, entry, 0)?,
+            claimed_size: None,
+        }
+        .into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads and validates the header of the frame of an index entry, without
+/// reading its payload. The file is left at the start of the payload.
+pub fn parse_frame_metadata(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+) -> Result<FrameMetadata, Box<dyn Error + Send + Sync>> {
     f.seek(SeekFrom::Start(entry.offset.get() as _))?;
 
-    // ------------------------------------------------------------------------
-    // Parse header
     let mut recorded_frame_metadata_bytes: [u8; mem::size_of::<RecordedFrameMetadata>()] =
         [0; mem::size_of::<RecordedFrameMetadata>()];
-    f.read_exact(&mut recorded_frame_metadata_bytes)?;
+    read_frame_bytes(f, entry, &mut recorded_frame_metadata_bytes)?;
 
     let recorded_frame_metadata =
         parse_recorded_frame_metadata(&recorded_frame_metadata_bytes[..])?;
 
-    if recorded_frame_metadata.size.get() <= 0 {
+    // Check the size against the file before allocating the payload, a
+    // corrupt size would otherwise abort the conversion out of memory
+    let size = recorded_frame_metadata.size.get();
+    let remaining_len = remaining_len(f, entry, mem::size_of::<RecordedFrameMetadata>())?;
+    if size > 0 && size as u64 > remaining_len {
+        return Err(TruncatedFrame {
+            offset: entry.offset.get(),
+            remaining_len,
+            claimed_size: Some(size),
+        }
+        .into());
+    }
+
+    frame_metadata(recorded_frame_metadata, entry.offset.get())
+}
+
+/// Validates the header of the frame at `offset`.
+fn frame_metadata(
+    recorded_frame_metadata: &RecordedFrameMetadata,
+    offset: i64,
+) -> Result<FrameMetadata, Box<dyn Error + Send + Sync>> {
+    let size = recorded_frame_metadata.size.get();
+    if size <= 0 {
         return Err("Frame size not parsed correctly.".into());
     }
+    let size = usize::try_from(size).map_err(|_| {
+        format!(
+            "Frame at offset {} of {} bytes is too large for this platform",
+            offset, size
+        )
+    })?;
 
-    let format = VideoCaptureFormat::try_from(recorded_frame_metadata.format.get())?;
+    let format = VideoCaptureFormat::from(recorded_frame_metadata.format.get());
 
-    if format.is_coded() {
+    if let VideoCaptureFormat::Unknown(_) = format {
+        // The dimensions of unknown formats may mean anything
+    } else if format.is_coded() && format != VideoCaptureFormat::Mjpeg {
         if recorded_frame_metadata.width.get() != 0 && recorded_frame_metadata.height.get() != 0 {
             return Err("Frame width and height not parsed correctly.".into());
         }
@@ -289,69 +1154,961 @@ pub fn parse_raw_frame(
         return Err("Frame width and height not parsed correctly.".into());
     }
 
+    Ok(FrameMetadata {
+        id: recorded_frame_metadata.id.get(),
+        format,
+        width: recorded_frame_metadata.width.get(),
+        height: recorded_frame_metadata.height.get(),
+        size,
+        receive_timestamp: recorded_frame_metadata.receive_timestamp.get(),
+        capture_timestamp: recorded_frame_metadata.timestamp.get(),
+    })
+}
+
+/// Fails on a frame of a format unknown to the parser when strict. Lenient
+/// parsing reads it as [`VideoCaptureFormat::Unknown`], which the conversion
+/// skips like any other format it does not convert.
+fn check_known_format(
+    format: VideoCaptureFormat,
+    options: &ParseOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match format {
+        VideoCaptureFormat::Unknown(format) if options.strictness == Strictness::Strict => {
+            Err(format!("Unknown video capture format {}", format).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether the bytes after the payload of a frame are the start of the next
+/// frame or of the index, instead of a generic metadata header.
+fn starts_with_frame_or_index(bytes: &[u8]) -> bool {
+    bytes.starts_with(&RECORDING_FRAME_MAGIC.to_le_bytes())
+        || bytes.starts_with(&RECORDING_INDEX_HEADER_MAGIC.to_le_bytes())
+}
+
+/// Reads the generic metadata of a frame, following its header and the
+/// footer of the same size. A footer not matching the header is an
+/// inconsistency the metadata is kept despite.
+fn read_generic_metadata(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    payload_size: usize,
+    generic_metadata_header_data: &[u8],
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let generic_metadata_size =
+        generic_metadata_size_in_file(f, entry, payload_size, generic_metadata_header_data)?;
+
     // ------------------------------------------------------------------------
-    // Read frame data
-    let mut raw_frame_data: Vec<u8> = vec![0; recorded_frame_metadata.size.get() as usize];
-    f.read_exact(&mut raw_frame_data)?;
+    // Parse generic metadata
+    let mut generic_metadata_data: Vec<u8> = vec![0; generic_metadata_size as usize];
+    read_frame_bytes(f, entry, &mut generic_metadata_data)?;
 
     // ------------------------------------------------------------------------
-    // Parse VideoPlacementMetadataFooter
-    let frame_data: Vec<u8>;
-    if format != VideoCaptureFormat::Stats {
-        let mut offset = 0;
+    // Parse generic metadata footer
+    read_generic_metadata_footer(f, entry, generic_metadata_size, options, warnings)?;
 
-        loop {
-            // Loop from the end to try and match the video placement magic(s)
-            if let Ok(video_placement_footer) = parse_video_placement_footer(
-                &raw_frame_data[(raw_frame_data.len()
-                    - size_of::<VideoPlacementMetadataFooter>()
-                    - offset)..(raw_frame_data.len() - offset)],
-            ) {
-                frame_data = raw_frame_data[..(raw_frame_data.len()
-                    - video_placement_footer.clone().metadata_size.get() as usize
-                    - size_of::<VideoPlacementMetadataFooter>())]
-                    .to_vec();
-
-                break;
-            } else {
-                if offset > 10 {
-                    // If the end has to be looped more than 10 times then it probably do not have alignment data
-                    frame_data = raw_frame_data.clone();
-                    break;
-                }
+    Ok(generic_metadata_data)
+}
 
-                offset += 1;
-            }
+/// The size of the generic metadata of the frame of an index entry given by
+/// its header, checked to fit in the file together with the footer.
+fn generic_metadata_size_in_file(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    payload_size: usize,
+    generic_metadata_header_data: &[u8],
+) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    let generic_metadata_size =
+        generic_metadata_size(entry.offset.get(), generic_metadata_header_data)?;
+
+    let remaining_len = remaining_len(
+        f,
+        entry,
+        mem::size_of::<RecordedFrameMetadata>()
+            + payload_size
+            + mem::size_of::<GenericMetadataHeader>(),
+    )?;
+    if generic_metadata_size as u64 + mem::size_of::<GenericMetadataFooter>() as u64 > remaining_len
+    {
+        return Err(TruncatedFrame {
+            offset: entry.offset.get(),
+            remaining_len,
+            claimed_size: None,
         }
-    } else {
-        frame_data = raw_frame_data.clone();
+        .into());
     }
 
+    Ok(generic_metadata_size)
+}
+
+/// Reads the generic metadata footer of the frame of an index entry, which
+/// follows the generic metadata
+fn read_generic_metadata_footer(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    generic_metadata_size: u32,
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut generic_metadata_footer_data: [u8; 8] = [0; 8];
+    read_frame_bytes(f, entry, &mut generic_metadata_footer_data)?;
+
+    check_generic_metadata_footer(
+        entry.offset.get(),
+        &generic_metadata_footer_data,
+        generic_metadata_size,
+        options,
+        warnings,
+    )
+}
+
+/// The size of the generic metadata of the frame at `offset` given by its
+/// header.
+fn generic_metadata_size(
+    offset: i64,
+    generic_metadata_header_data: &[u8],
+) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    let generic_metadata_size = parse_generic_metadata_header(generic_metadata_header_data)
+        .map_err(|e| format!("Generic metadata header of the frame at offset {offset}: {e}"))?
+        .generic_metadata_size
+        .get();
+
+    if generic_metadata_size > MAX_GENERIC_METADATA_SIZE {
+        return Err(format!(
+            "Frame at offset {} claims {} bytes of generic metadata, more than the {} accepted",
+            offset, generic_metadata_size, MAX_GENERIC_METADATA_SIZE
+        )
+        .into());
+    }
+
+    Ok(generic_metadata_size)
+}
+
+/// Checks the generic metadata footer of the frame at `offset` against the
+/// size given by the header.
+fn check_generic_metadata_footer(
+    offset: i64,
+    generic_metadata_footer_data: &[u8],
+    generic_metadata_size: u32,
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match parse_generic_metadata_footer(generic_metadata_footer_data) {
+        Ok(generic_metadata_footer)
+            if generic_metadata_footer.generic_metadata_size.get() != generic_metadata_size =>
+        {
+            options.recover(
+                warnings,
+                format!(
+                    "Generic metadata footer of the frame at offset {} claims {} bytes, the \
+                     header {}",
+                    offset,
+                    generic_metadata_footer.generic_metadata_size.get(),
+                    generic_metadata_size
+                ),
+            )?;
+        }
+        Ok(_) => {}
+        Err(e) => options.recover(
+            warnings,
+            format!("Generic metadata footer of the frame at offset {offset}: {e}"),
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Reads a frame in full. The video placement footer is searched for followed
+/// by up to [`ParseOptions::max_placement_padding`] bytes of alignment data.
+pub fn parse_raw_frame(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+) -> Result<FrameInfo, Box<dyn Error + Send + Sync>> {
+    let mut raw_frame_data = Vec::new();
+    let frame = read_full_frame(f, entry, options, &mut raw_frame_data)?;
+    let payload = split_payload(raw_frame_data, frame.placement);
+
+    Ok(FrameInfo {
+        id: frame.metadata.id,
+        width: frame.width,
+        height: frame.height,
+        format: frame.format,
+        receive_timestamp: frame.metadata.receive_timestamp,
+        capture_timestamp: frame.metadata.capture_timestamp,
+        raw_data: payload.data,
+        size: frame.metadata.size,
+        placement_metadata: payload.placement_metadata,
+        placement_padding: payload.placement_padding,
+        warnings: frame.warnings,
+        generic_metadata: frame.generic_metadata,
+    })
+}
+
+/// Reads a frame like [`parse_raw_frame`], into a buffer reused for every
+/// frame instead of allocating its payload. The frame borrows its payload from
+/// the buffer.
+pub fn read_frame_into<'a>(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+    buf: &'a mut Vec<u8>,
+) -> Result<FrameRef<'a>, Box<dyn Error + Send + Sync>> {
+    Ok(read_full_frame(f, entry, options, buf)?.into_frame_ref(buf))
+}
+
+/// A frame whose payload was read into a buffer by [`read_full_frame`].
+pub(crate) struct ReadFrame {
+    pub(crate) metadata: FrameMetadata,
+    /// The format of the frame, that of its bitstream if it is trusted
+    pub(crate) format: VideoCaptureFormat,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    placement: Option<Placement>,
+    generic_metadata: Vec<u8>,
+    pub(crate) warnings: Vec<String>,
+}
+
+impl ReadFrame {
+    /// Length of the payload without the video placement metadata
+    pub(crate) fn payload_len(&self) -> usize {
+        self.placement
+            .map_or(self.metadata.size, |placement| placement.metadata_start)
+    }
+
+    /// Number of alignment bytes after the video placement footer, None if
+    /// the frame has no footer
+    pub(crate) fn placement_padding(&self) -> Option<usize> {
+        self.placement.map(|placement| placement.padding)
+    }
+
+    /// The frame, borrowing its payload from the buffer it was read into.
+    pub(crate) fn into_frame_ref(self, buf: &[u8]) -> FrameRef<'_> {
+        FrameRef {
+            id: self.metadata.id,
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            raw_data: &buf[..self.payload_len()],
+            size: self.metadata.size,
+            receive_timestamp: self.metadata.receive_timestamp,
+            capture_timestamp: self.metadata.capture_timestamp,
+            placement_metadata: self
+                .placement
+                .map(|placement| &buf[placement.metadata_start..placement.metadata_end]),
+            placement_padding: self.placement_padding(),
+            warnings: self.warnings,
+            generic_metadata: self.generic_metadata,
+        }
+    }
+}
+
+/// Reads the header, the payload and the generic metadata of a frame, the
+/// payload into `buf`.
+pub(crate) fn read_full_frame(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+    buf: &mut Vec<u8>,
+) -> Result<ReadFrame, Box<dyn Error + Send + Sync>> {
+    let mut warnings = Vec::new();
+
+    // ------------------------------------------------------------------------
+    // Parse header
+    let metadata = parse_frame_metadata(f, entry)?;
+    check_known_format(metadata.format, options)?;
+    let format = metadata.format;
+
+    // ------------------------------------------------------------------------
+    // Read frame data
+    buf.clear();
+    buf.resize(metadata.size, 0);
+    read_frame_bytes(f, entry, buf)?;
+
+    let placement = locate_placement(entry.offset.get(), format, buf, options, &mut warnings)?;
+
     // ------------------------------------------------------------------------
     // Parse generic metadata header
-    let mut generic_metadata_header_or_footer_data: [u8; 8] = [0; 8];
-    f.read_exact(&mut generic_metadata_header_or_footer_data)?;
-    let generic_metadata_header =
-        parse_generic_metadata_header(&generic_metadata_header_or_footer_data[..])?;
+    let mut generic_metadata_header_data: [u8; 8] = [0; 8];
+    read_frame_bytes(f, entry, &mut generic_metadata_header_data)?;
+    let generic_metadata = if starts_with_frame_or_index(&generic_metadata_header_data) {
+        // Recorders older than the generic metadata write the next frame or
+        // the index right after the payload
+        options.recover(
+            &mut warnings,
+            format!(
+                "Frame at offset {} has no generic metadata",
+                entry.offset.get()
+            ),
+        )?;
+        Vec::new()
+    } else {
+        read_generic_metadata(
+            f,
+            entry,
+            metadata.size,
+            &generic_metadata_header_data,
+            options,
+            &mut warnings,
+        )?
+    };
+
+    let payload_len = placement.map_or(buf.len(), |placement| placement.metadata_start);
+    let (format, width, height) = format_and_dimensions(&metadata, &buf[..payload_len], options);
+
+    Ok(ReadFrame {
+        metadata,
+        format,
+        width,
+        height,
+        placement,
+        generic_metadata,
+        warnings,
+    })
+}
+
+/// A frame read by [`IndexedFrames`], with its position in the index and its
+/// index entry.
+pub type IndexedFrame<'a> = (
+    usize,
+    &'a RecordingIndexEntry,
+    Result<FrameInfo, Box<dyn Error + Send + Sync>>,
+);
+
+/// A frame read by [`IndexedFrames::next_into`], its payload read into a
+/// buffer.
+pub(crate) type IndexedReadFrame<'a> = (
+    usize,
+    &'a RecordingIndexEntry,
+    Result<ReadFrame, Box<dyn Error + Send + Sync>>,
+);
+
+/// Reads the frames of index entries one after the other with
+/// [`parse_raw_frame`]. A frame that cannot be read does not end the
+/// iteration, the frame of the next entry is read after it.
+pub struct IndexedFrames<'a, R> {
+    f: &'a mut R,
+    entries: std::iter::Enumerate<std::slice::Iter<'a, RecordingIndexEntry>>,
+    options: ParseOptions,
+}
+
+impl<'a, R: RecordingReader> IndexedFrames<'a, R> {
+    pub fn new(f: &'a mut R, entries: &'a [RecordingIndexEntry], options: ParseOptions) -> Self {
+        Self {
+            f,
+            entries: entries.iter().enumerate(),
+            options,
+        }
+    }
+
+    /// Number of entries whose frames are not read yet
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Ends the iteration without reading the frames of the remaining entries
+    pub fn stop(&mut self) {
+        self.entries.by_ref().for_each(drop);
+    }
+
+    /// Byte offset in the recording the reading has reached
+    pub fn position(&mut self) -> io::Result<u64> {
+        self.f.stream_position()
+    }
+
+    /// Reads the frame of the next entry with its payload into a buffer, see
+    /// [`read_frame_into`].
+    pub(crate) fn next_into(&mut self, buf: &mut Vec<u8>) -> Option<IndexedReadFrame<'a>> {
+        let (index, entry) = self.entries.next()?;
+        Some((
+            index,
+            entry,
+            read_full_frame(self.f, entry, &self.options, buf),
+        ))
+    }
+}
+
+impl<'a, R: RecordingReader> Iterator for IndexedFrames<'a, R> {
+    type Item = IndexedFrame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, entry) = self.entries.next()?;
+        Some((index, entry, parse_raw_frame(self.f, entry, &self.options)))
+    }
+}
+
+/// A frame checked by [`verify_frame`].
+#[derive(Debug, Clone)]
+pub struct VerifiedFrame {
+    pub metadata: FrameMetadata,
+    /// Number of bytes of the frame in the file, from its header to the end of
+    /// its generic metadata
+    pub len: u64,
+    /// Inconsistencies of the frame recovered from by lenient parsing
+    pub warnings: Vec<String>,
+}
+
+/// Checks the frame of an index entry as [`parse_raw_frame`] reads it, but
+/// seeks over its payload and generic metadata instead of reading them. Only
+/// the end of the payload is read, for the video placement footer.
+pub fn verify_frame(
+    f: &mut impl RecordingReader,
+    entry: &RecordingIndexEntry,
+    options: &ParseOptions,
+) -> Result<VerifiedFrame, Box<dyn Error + Send + Sync>> {
+    let mut warnings = Vec::new();
 
     // ------------------------------------------------------------------------
-    // Parse generic metadata
-    let mut generic_metadata_data: Vec<u8> =
-        vec![0; generic_metadata_header.generic_metadata_size.get() as usize];
-    f.read_exact(&mut generic_metadata_data)?;
+    // Parse header
+    let metadata = parse_frame_metadata(f, entry)?;
+    check_known_format(metadata.format, options)?;
 
     // ------------------------------------------------------------------------
-    // Parse generic metadata footer
-    f.read_exact(&mut generic_metadata_header_or_footer_data)?;
+    // Seek to the end of the frame data, which holds the placement footer
+    let payload_end_len = metadata
+        .size
+        .min(size_of::<VideoPlacementMetadataFooter>() + options.max_placement_padding);
+    f.seek(SeekFrom::Current((metadata.size - payload_end_len) as i64))?;
+    let mut payload_end = vec![0; payload_end_len];
+    read_frame_bytes(f, entry, &mut payload_end)?;
+    locate_placement_metadata(
+        entry.offset.get(),
+        metadata.format,
+        metadata.size,
+        &payload_end,
+        options,
+        &mut warnings,
+    )?;
+
+    // ------------------------------------------------------------------------
+    // Parse generic metadata header, and seek over the generic metadata
+    let mut len = (mem::size_of::<RecordedFrameMetadata>() + metadata.size) as u64;
+    let mut generic_metadata_header_data: [u8; 8] = [0; 8];
+    read_frame_bytes(f, entry, &mut generic_metadata_header_data)?;
+    if starts_with_frame_or_index(&generic_metadata_header_data) {
+        options.recover(
+            &mut warnings,
+            format!(
+                "Frame at offset {} has no generic metadata",
+                entry.offset.get()
+            ),
+        )?;
+    } else {
+        let generic_metadata_size =
+            generic_metadata_size_in_file(f, entry, metadata.size, &generic_metadata_header_data)?;
+        f.seek(SeekFrom::Current(generic_metadata_size.into()))?;
+        read_generic_metadata_footer(f, entry, generic_metadata_size, options, &mut warnings)?;
 
-    let resolution = recorded_frame_metadata.width.to_string()
-        + "x"
-        + &recorded_frame_metadata.height.to_string();
+        len += (mem::size_of::<GenericMetadataHeader>()
+            + generic_metadata_size as usize
+            + mem::size_of::<GenericMetadataFooter>()) as u64;
+    }
 
-    Ok(FrameInfo {
-        resolution,
+    Ok(VerifiedFrame {
+        metadata,
+        len,
+        warnings,
+    })
+}
+
+/// Reads the frames of a recording front to back without seeking, as from a
+/// pipe. The frames end at the index, which is not read, or at the end of the
+/// stream. After a frame that cannot be read, lenient parsing resumes at the
+/// next frame found, as [`scan_index`] does.
+pub struct FrameStream<R> {
+    reader: R,
+    options: ParseOptions,
+    /// Number of bytes read from the reader so far
+    offset: u64,
+    /// Bytes read from the reader, but not consumed yet
+    pending: Vec<u8>,
+    /// The bytes consumed by the frame being read, which are searched for the
+    /// next frame if it cannot be read
+    frame_bytes: Vec<u8>,
+    /// Receive timestamp of the last frame read
+    last_timestamp: Option<i64>,
+    /// The frame found after a frame that could not be read, returned after
+    /// the error of that frame
+    resynced: Option<Result<(RecordingIndexEntry, FrameInfo), Box<dyn Error + Send + Sync>>>,
+    ended: bool,
+}
+
+impl<R: Read> FrameStream<R> {
+    /// Reads and validates the header of the recording, if it has one.
+    pub fn new(reader: R, options: ParseOptions) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut stream = Self {
+            reader,
+            options,
+            offset: 0,
+            pending: Vec::new(),
+            frame_bytes: Vec::new(),
+            last_timestamp: None,
+            resynced: None,
+            ended: false,
+        };
+
+        let magic = stream.peek(mem::size_of::<u32>())?;
+        if magic.is_empty() || magic.starts_with(&RECORDING_FRAME_MAGIC.to_le_bytes()) {
+            return Ok(stream);
+        }
+
+        let recording_metadata_bytes = stream.peek(mem::size_of::<RecordingMetadata>())?;
+        if recording_metadata_bytes.len() < mem::size_of::<RecordingMetadata>() {
+            return Err("The stream ends in the recording header".into());
+        }
+        parse_recording_metadata(recording_metadata_bytes)
+            .map_err(|e| format!("Recording header: {e}"))?;
+        stream.pending.drain(..mem::size_of::<RecordingMetadata>());
+
+        Ok(stream)
+    }
+
+    /// Offset of the next byte in the recording
+    fn next_offset(&self) -> u64 {
+        self.offset - self.pending.len() as u64
+    }
+
+    /// Returns the next `len` bytes without consuming them, fewer if the
+    /// stream ends before
+    fn peek(&mut self, len: usize) -> io::Result<&[u8]> {
+        let missing = len.saturating_sub(self.pending.len()) as u64;
+        let read = (&mut self.reader)
+            .take(missing)
+            .read_to_end(&mut self.pending)?;
+        self.offset += read as u64;
+
+        Ok(&self.pending[..len.min(self.pending.len())])
+    }
+
+    /// Consumes bytes until `buf` holds `len` bytes, returns false if the
+    /// stream ends before
+    fn fill(&mut self, buf: &mut Vec<u8>, len: usize) -> io::Result<bool> {
+        let start = buf.len();
+        let from_pending = len.saturating_sub(start).min(self.pending.len());
+        buf.extend(self.pending.drain(..from_pending));
+
+        let missing = len.saturating_sub(buf.len()) as u64;
+        let read = (&mut self.reader).take(missing).read_to_end(buf)?;
+        self.offset += read as u64;
+
+        self.frame_bytes.extend_from_slice(&buf[start..]);
+
+        Ok(buf.len() >= len)
+    }
+
+    fn read_frame(
+        &mut self,
+    ) -> Result<(RecordingIndexEntry, FrameInfo), Box<dyn Error + Send + Sync>> {
+        let offset = self.next_offset();
+        self.frame_bytes.clear();
+        let truncated = |stream: &Self, skip: usize, claimed_size| TruncatedFrame {
+            offset: offset as i64,
+            remaining_len: stream.offset - offset - skip as u64,
+            claimed_size,
+        };
+
+        // ------------------------------------------------------------------------
+        // Parse header
+        let mut recorded_frame_metadata_bytes = Vec::new();
+        if !self.fill(
+            &mut recorded_frame_metadata_bytes,
+            mem::size_of::<RecordedFrameMetadata>(),
+        )? {
+            return Err(truncated(self, 0, None).into());
+        }
+        let recorded_frame_metadata =
+            parse_recorded_frame_metadata(&recorded_frame_metadata_bytes)?;
+        let metadata = frame_metadata(recorded_frame_metadata, offset as i64)?;
+        check_known_format(metadata.format, &self.options)?;
+        let entry = RecordingIndexEntry::new(offset as i64, metadata.receive_timestamp);
+
+        // ------------------------------------------------------------------------
+        // Read frame data, growing the buffer as the data arrives, so that a
+        // corrupt size cannot run the conversion out of memory
+        let mut raw_frame_data = Vec::new();
+        if !self.fill(&mut raw_frame_data, metadata.size)? {
+            return Err(truncated(
+                self,
+                mem::size_of::<RecordedFrameMetadata>(),
+                Some(metadata.size as i64),
+            )
+            .into());
+        }
+
+        let mut warnings = Vec::new();
+        let placement = locate_placement(
+            offset as i64,
+            metadata.format,
+            &raw_frame_data,
+            &self.options,
+            &mut warnings,
+        )?;
+        let payload = split_payload(raw_frame_data, placement);
+
+        // ------------------------------------------------------------------------
+        // Parse generic metadata
+        let next = self.peek(mem::size_of::<u32>())?;
+        let generic_metadata =
+            if next.len() < mem::size_of::<u32>() || starts_with_frame_or_index(next) {
+                // Recorders older than the generic metadata write the next frame
+                // or the index right after the payload
+                self.options.recover(
+                    &mut warnings,
+                    format!("Frame at offset {offset} has no generic metadata"),
+                )?;
+                Vec::new()
+            } else {
+                let mut generic_metadata_header_data = Vec::new();
+                if !self.fill(
+                    &mut generic_metadata_header_data,
+                    mem::size_of::<GenericMetadataHeader>(),
+                )? {
+                    return Err(truncated(self, 0, None).into());
+                }
+                let generic_metadata_size =
+                    generic_metadata_size(offset as i64, &generic_metadata_header_data)?;
+
+                let mut generic_metadata_data = Vec::new();
+                let mut generic_metadata_footer_data = Vec::new();
+                if !self.fill(&mut generic_metadata_data, generic_metadata_size as usize)?
+                    || !self.fill(
+                        &mut generic_metadata_footer_data,
+                        mem::size_of::<GenericMetadataFooter>(),
+                    )?
+                {
+                    return Err(truncated(self, 0, None).into());
+                }
+                check_generic_metadata_footer(
+                    offset as i64,
+                    &generic_metadata_footer_data,
+                    generic_metadata_size,
+                    &self.options,
+                    &mut warnings,
+                )?;
+
+                generic_metadata_data
+            };
+
+        Ok((
+            entry,
+            frame_info(metadata, payload, generic_metadata, warnings, &self.options),
+        ))
+    }
+
+    /// Goes back to the byte after the start of the frame that could not be
+    /// read, to search the bytes it consumed for the next frame
+    fn rewind(&mut self) {
+        let mut frame_bytes = mem::take(&mut self.frame_bytes);
+        frame_bytes.drain(..frame_bytes.len().min(1));
+        frame_bytes.append(&mut self.pending);
+        self.pending = frame_bytes;
+    }
+
+    /// Consumes the bytes up to the next frame magic, returns false if the
+    /// stream ends or the index starts before
+    fn skip_to_frame_magic(&mut self) -> io::Result<bool> {
+        let frame_magic = RECORDING_FRAME_MAGIC.to_le_bytes();
+        let index_magic = RECORDING_INDEX_HEADER_MAGIC.to_le_bytes();
+
+        loop {
+            let len = self.pending.len().max(64 * 1024);
+            let bytes = self.peek(len)?;
+            let at_end = bytes.len() < len;
+            let magic = bytes
+                .windows(frame_magic.len())
+                .position(|window| window == frame_magic || window == index_magic)
+                .map(|position| (position, bytes[position..].starts_with(&frame_magic)));
+            match magic {
+                Some((position, is_frame)) => {
+                    self.pending.drain(..position);
+                    return Ok(is_frame);
+                }
+                None if at_end => {
+                    self.pending.clear();
+                    return Ok(false);
+                }
+                // Keep the end, which may be the start of a magic
+                None => {
+                    self.pending.drain(..len - (frame_magic.len() - 1));
+                }
+            }
+        }
+    }
+
+    /// Searches for the next frame after the frame at `damaged_offset`, which
+    /// could not be read. The frame found is returned next, the error of the
+    /// damaged frame names the bytes skipped to it.
+    fn resync(
+        &mut self,
+        damaged_offset: u64,
+        e: Box<dyn Error + Send + Sync>,
+    ) -> Box<dyn Error + Send + Sync> {
+        // A frame cut off by the end of the stream is only taken if no
+        // complete frame follows it
+        let mut truncated = None;
+        loop {
+            self.rewind();
+            match self.skip_to_frame_magic() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(io_error) => {
+                    self.ended = true;
+                    return io_error.into();
+                }
+            }
+
+            let candidate = self.next_offset();
+            match self.read_frame() {
+                Ok(frame)
+                    if !matches!(frame.1.format, VideoCaptureFormat::Unknown(_))
+                        && plausible_after(self.last_timestamp, frame.0.receive_timestamp()) =>
+                {
+                    self.last_timestamp = Some(frame.0.receive_timestamp());
+                    self.resynced = Some(Ok(frame));
+                    return format!(
+                        "{e}, skipped {} damaged bytes to the next frame at offset {candidate}",
+                        candidate - damaged_offset
+                    )
+                    .into();
+                }
+                Err(candidate_error) if candidate_error.is::<io::Error>() => {
+                    self.ended = true;
+                    return candidate_error;
+                }
+                Err(candidate_error) if candidate_error.is::<TruncatedFrame>() => {
+                    truncated.get_or_insert((candidate, candidate_error));
+                }
+                _ => {}
+            }
+        }
+
+        self.ended = true;
+        match truncated {
+            _ if e.is::<TruncatedFrame>() => e,
+            Some((candidate, truncated)) => {
+                self.resynced = Some(Err(truncated));
+                format!(
+                    "{e}, skipped {} damaged bytes to the frame at offset {candidate} cut off by \
+                     the end of the stream",
+                    candidate - damaged_offset
+                )
+                .into()
+            }
+            None => format!("{e}, no frame follows it").into(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameStream<R> {
+    /// A frame together with the index entry it would have
+    type Item = Result<(RecordingIndexEntry, FrameInfo), Box<dyn Error + Send + Sync>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(frame) = self.resynced.take() {
+            return Some(frame);
+        }
+        if self.ended {
+            return None;
+        }
+
+        let next = match self.peek(mem::size_of::<u32>()) {
+            Ok(next) => next,
+            Err(e) => {
+                self.ended = true;
+                return Some(Err(e.into()));
+            }
+        };
+        if next.is_empty() || next.starts_with(&RECORDING_INDEX_HEADER_MAGIC.to_le_bytes()) {
+            self.ended = true;
+            return None;
+        }
+
+        let offset = self.next_offset();
+        match self.read_frame() {
+            Ok(frame) => {
+                self.last_timestamp = Some(frame.0.receive_timestamp());
+                Some(Ok(frame))
+            }
+            Err(e) if self.options.strictness == Strictness::Strict || e.is::<io::Error>() => {
+                self.ended = true;
+                Some(Err(e))
+            }
+            Err(e) => Some(Err(self.resync(offset, e))),
+        }
+    }
+}
+
+/// The payload of a frame, split at its video placement footer.
+struct Payload {
+    data: Vec<u8>,
+    placement_metadata: Option<Vec<u8>>,
+    placement_padding: Option<usize>,
+}
+
+/// Where the video placement metadata of a payload is.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    metadata_start: usize,
+    metadata_end: usize,
+    /// Number of alignment bytes after the video placement footer
+    padding: usize,
+}
+
+/// Finds the video placement footer of the payload of the frame at `offset`,
+/// followed by up to [`ParseOptions::max_placement_padding`] bytes of
+/// alignment data.
+fn locate_placement(
+    offset: i64,
+    format: VideoCaptureFormat,
+    raw_frame_data: &[u8],
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Placement>, Box<dyn Error + Send + Sync>> {
+    let placement = locate_placement_metadata(
+        offset,
         format,
-        timestamp: recorded_frame_metadata.receive_timestamp.get(),
-        raw_data: frame_data,
+        raw_frame_data.len(),
+        raw_frame_data,
+        options,
+        warnings,
+    )?;
+
+    Ok(placement.map(|(metadata_start, metadata_end)| Placement {
+        metadata_start,
+        metadata_end,
+        padding: raw_frame_data.len() - metadata_end - size_of::<VideoPlacementMetadataFooter>(),
+    }))
+}
+
+/// Splits a payload at its video placement footer, cutting the footer off in
+/// place.
+fn split_payload(mut raw_frame_data: Vec<u8>, placement: Option<Placement>) -> Payload {
+    match placement {
+        Some(placement) => {
+            let placement_metadata =
+                raw_frame_data[placement.metadata_start..placement.metadata_end].to_vec();
+            raw_frame_data.truncate(placement.metadata_start);
+            Payload {
+                data: raw_frame_data,
+                placement_metadata: Some(placement_metadata),
+                placement_padding: Some(placement.padding),
+            }
+        }
+        None => Payload {
+            data: raw_frame_data,
+            placement_metadata: None,
+            placement_padding: None,
+        },
+    }
+}
+
+/// Locates the video placement metadata of the frame at `offset` in its
+/// payload of `payload_len` bytes, given the last bytes of the payload.
+/// Returns where the metadata starts and ends in the payload, None if the
+/// frame has none.
+fn locate_placement_metadata(
+    offset: i64,
+    format: VideoCaptureFormat,
+    payload_len: usize,
+    payload_end: &[u8],
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Option<(usize, usize)>, Box<dyn Error + Send + Sync>> {
+    if format == VideoCaptureFormat::Stats {
+        return Ok(None);
+    }
+
+    let payload_end_start = payload_len - payload_end.len();
+    match find_video_placement_footer(payload_end, options.max_placement_padding) {
+        Some((footer_start, video_placement_footer)) => {
+            let metadata_end = payload_end_start + footer_start;
+            let metadata_size = video_placement_footer.metadata_size.get() as usize;
+            match metadata_end.checked_sub(metadata_size) {
+                Some(metadata_start) => return Ok(Some((metadata_start, metadata_end))),
+                None => options.recover(
+                    warnings,
+                    format!(
+                        "Video placement footer of the frame at offset {} claims {} bytes of \
+                         metadata, more than the {} bytes before it",
+                        offset, metadata_size, metadata_end
+                    ),
+                )?,
+            }
+        }
+        None if format.is_coded() => options.recover(
+            warnings,
+            format!("Frame at offset {} has no video placement footer", offset),
+        )?,
+        None => {}
+    }
+
+    Ok(None)
+}
+
+/// The coded format of a payload as told by its bitstream, None if it cannot
+/// be told, see [`bitstream::sniff_codec`].
+pub fn sniff_format(payload: &[u8]) -> Option<VideoCaptureFormat> {
+    bitstream::sniff_codec(payload).map(|codec| match codec {
+        bitstream::Codec::Avc => VideoCaptureFormat::H264,
+        bitstream::Codec::Hevc => VideoCaptureFormat::H265,
+        bitstream::Codec::Jpeg => VideoCaptureFormat::Mjpeg,
     })
 }
+
+/// The format and dimensions of a frame, those of its bitstream where they
+/// are known from it.
+fn format_and_dimensions(
+    metadata: &FrameMetadata,
+    frame_data: &[u8],
+    options: &ParseOptions,
+) -> (VideoCaptureFormat, i32, i32) {
+    let format = match metadata.format {
+        format if options.trust_bitstream && format.is_coded() => {
+            sniff_format(frame_data).unwrap_or(format)
+        }
+        format => format,
+    };
+
+    // Coded frames are stored without dimensions, HEVC frames carrying an SPS
+    // get the dimensions of the SPS
+    let sps = match format {
+        VideoCaptureFormat::H265 => bitstream::nal_units(frame_data)
+            .find(|nal| bitstream::hevc_nal_type(nal) == bitstream::HEVC_NAL_TYPE_SPS)
+            .and_then(bitstream::parse_hevc_sps),
+        _ => None,
+    };
+    match sps {
+        Some(sps) => (format, sps.width as i32, sps.height as i32),
+        None => (format, metadata.width, metadata.height),
+    }
+}
+
+/// Assembles a frame read in full.
+fn frame_info(
+    metadata: FrameMetadata,
+    payload: Payload,
+    generic_metadata: Vec<u8>,
+    warnings: Vec<String>,
+    options: &ParseOptions,
+) -> FrameInfo {
+    let frame_data = payload.data;
+    let (format, width, height) = format_and_dimensions(&metadata, &frame_data, options);
+
+    FrameInfo {
+        id: metadata.id,
+        width,
+        height,
+        format,
+        receive_timestamp: metadata.receive_timestamp,
+        capture_timestamp: metadata.capture_timestamp,
+        raw_data: frame_data,
+        size: metadata.size,
+        placement_metadata: payload.placement_metadata,
+        placement_padding: payload.placement_padding,
+        warnings,
+        generic_metadata,
+    }
+}
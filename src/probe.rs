@@ -0,0 +1,170 @@
+use crate::error::VrawError;
+use crate::parser::{
+    parse_frame_metadata, read_recording_metadata, ParseOptions, RecordingIndexEntry,
+    RecordingReader, VideoCaptureFormat,
+};
+use crate::processing::{read_or_rebuild_index, IndexOrder};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Number of frames at the start and at the end of a recording whose headers
+/// [`probe`] reads, unless the probe is exact.
+pub const PROBE_FRAMES: usize = 8;
+
+/// Options of [`probe_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct ProbeOptions {
+    /// Read the header of every frame instead of only those at the start and
+    /// the end, for precise counts per format
+    pub exact: bool,
+}
+
+/// What a recording holds, as found by [`probe`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VrawInfo {
+    /// Number of frames listed by the index, Stats frames included
+    pub frame_count: usize,
+    /// Whether the index was missing or damaged and rebuilt from the frames
+    pub index_rebuilt: bool,
+    /// Wall clock time at which the recording started, None for recordings of
+    /// old recorders, which have no header
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
+    pub recording_start: Option<DateTime<Utc>>,
+    /// Receive timestamp of the first frame, in nanoseconds
+    pub first_timestamp: Option<i64>,
+    /// Receive timestamp of the last frame, in nanoseconds
+    pub last_timestamp: Option<i64>,
+    /// Time between the first and the last frame received, in nanoseconds,
+    /// at most `i64::MAX` for timestamps damaged that far apart
+    pub duration: i64,
+    /// Size of the recording in bytes
+    pub size: u64,
+    /// Bits per second of the whole recording over its duration, None for
+    /// recordings of less than two frames or frames all received at once
+    pub bitrate: Option<u64>,
+    /// Number of frames per format, of the frames whose header was read
+    pub formats: HashMap<VideoCaptureFormat, usize>,
+    /// Ids of the source streams of the frames whose header was read
+    pub stream_ids: BTreeSet<i32>,
+    /// Width and height of the frames whose header was read, as stored. Coded
+    /// formats except MJPEG are stored without dimensions
+    pub resolutions: BTreeSet<(i32, i32)>,
+    /// Number of frames whose header was read, all of them for exact probes
+    pub frames_probed: usize,
+    /// Number of frames whose header could not be read
+    pub unreadable_frames: usize,
+}
+
+fn serialize_time<S: serde::Serializer>(
+    time: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.serialize_some(&time.to_rfc3339()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_time<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let time: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    time.map(|time| {
+        DateTime::parse_from_rfc3339(&time)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(serde::de::Error::custom)
+    })
+    .transpose()
+}
+
+/// Tells what a .vraw recording holds without converting it, from its index
+/// and the headers of the first and last [`PROBE_FRAMES`] frames. The formats,
+/// stream ids and resolutions are those of these frames, see
+/// [`probe_with_options`] to read the headers of all frames.
+///
+/// input: path to .vraw file
+pub fn probe(input: impl AsRef<Path>) -> Result<VrawInfo, VrawError> {
+    probe_with_options(input, &ProbeOptions::default())
+}
+
+/// Same as [`probe`], but with explicit [`ProbeOptions`].
+pub fn probe_with_options(
+    input: impl AsRef<Path>,
+    options: &ProbeOptions,
+) -> Result<VrawInfo, VrawError> {
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+    let mut f = BufReader::new(input_file);
+
+    let metadata = read_recording_metadata(&mut f).map_err(VrawError::corrupt_header(
+        "failed to read the recording header",
+    ))?;
+    let recording_start = metadata
+        .map(|metadata| metadata.start())
+        .transpose()
+        .map_err(VrawError::corrupt_header("failed to read the start time"))?;
+
+    let (entries, repairs) =
+        read_or_rebuild_index(&mut f, &ParseOptions::default(), IndexOrder::Offset, false)?;
+    let size = f
+        .recording_len()
+        .map_err(VrawError::input("failed to read the file size"))?;
+
+    let first_timestamp = entries.iter().map(|entry| entry.receive_timestamp()).min();
+    let last_timestamp = entries.iter().map(|entry| entry.receive_timestamp()).max();
+    let duration = last_timestamp
+        .zip(first_timestamp)
+        .map_or(0, |(last, first)| last.saturating_sub(first));
+
+    let mut info = VrawInfo {
+        frame_count: entries.len(),
+        index_rebuilt: repairs.rebuilt,
+        recording_start,
+        first_timestamp,
+        last_timestamp,
+        duration,
+        size,
+        bitrate: (duration > 0)
+            .then(|| (size as u128 * 8 * 1_000_000_000 / duration as u128) as u64),
+        ..Default::default()
+    };
+
+    for entry in probed_entries(&entries, options.exact) {
+        info.frames_probed += 1;
+        match parse_frame_metadata(&mut f, entry) {
+            Ok(metadata) => {
+                *info.formats.entry(metadata.format).or_default() += 1;
+                info.stream_ids.insert(metadata.id);
+                if metadata.format != VideoCaptureFormat::Stats {
+                    info.resolutions.insert((metadata.width, metadata.height));
+                }
+            }
+            Err(_) => info.unreadable_frames += 1,
+        }
+    }
+
+    Ok(info)
+}
+
+/// The entries whose frame headers are read, the first and last
+/// [`PROBE_FRAMES`] unless all are.
+fn probed_entries(
+    entries: &[RecordingIndexEntry],
+    exact: bool,
+) -> impl Iterator<Item = &RecordingIndexEntry> {
+    let (start, end) = if exact || entries.len() <= 2 * PROBE_FRAMES {
+        (entries, &entries[..0])
+    } else {
+        (
+            &entries[..PROBE_FRAMES],
+            &entries[entries.len() - PROBE_FRAMES..],
+        )
+    };
+
+    start.iter().chain(end)
+}
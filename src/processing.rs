@@ -1,121 +1,3537 @@
-use crate::parser::{parse_raw_frame, read_index, VideoCaptureFormat};
-use chrono::Local;
-use mp4::{MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig};
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
-use zerocopy::AsBytes;
-
-/// Function that converts a .vraw file to an .mp4 file.
-/// NOTE: Currently only HEVC is supported!!!
+use crate::avi::AviWriter;
+use crate::bitstream;
+use crate::error::VrawError;
+use crate::fmp4;
+use crate::isobmff;
+use crate::matroska;
+use crate::parser::{
+    self, find_invalid_index_entries, first_frame_offset, read_index, read_recording_metadata,
+    read_recording_start, scan_index, FrameMetadata, FrameRef, FrameStream, ParseOptions,
+    RecordingIndexEntry, RecordingReader, Strictness, TruncatedFrame, VideoCaptureFormat,
+    DEFAULT_MAX_PLACEMENT_PADDING,
+};
+use crate::rawvideo::{self, BayerPattern, Packed422};
+use crate::reader::{VrawFrames, VrawReader};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Local, Utc};
+use mp4::{AvcConfig, MediaConfig, Mp4Config, Mp4Sample, Mp4Writer, TrackConfig};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const FFMPEG: &str = "ffmpeg";
+
+/// Timescale of .mp4 files unless configured otherwise, in ticks per second.
+/// 90 kHz represents the frame durations of all common frame rates closely.
+pub const DEFAULT_MP4_TIMESCALE: u32 = 90_000;
+
+/// Formats of video frames that can be converted, into at least one
+/// [`Container`].
+pub const SUPPORTED_FORMATS: &[VideoCaptureFormat] = &[
+    VideoCaptureFormat::H264,
+    VideoCaptureFormat::H265,
+    VideoCaptureFormat::Mjpeg,
+    VideoCaptureFormat::Nv12,
+    VideoCaptureFormat::Yuv,
+    VideoCaptureFormat::Yuyv,
+    VideoCaptureFormat::Uyvy,
+    VideoCaptureFormat::Rgb,
+    VideoCaptureFormat::Bgr,
+    VideoCaptureFormat::Mono8,
+    VideoCaptureFormat::Mono16,
+    VideoCaptureFormat::Raw16,
+];
+
+/// Container to write the converted frames into.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
+pub enum Container {
+    /// Mux the frames into an .mp4 file (H.264, HEVC and MJPEG)
+    Mp4,
+    /// Write the frames back to back as a raw elementary stream (H.264, HEVC and MJPEG)
+    ElementaryStream,
+    /// Write every frame as a separate image in an output folder (MJPEG, RGB, BGR, Mono8,
+    /// Mono16 and Raw16)
+    ImageSequence,
+    /// Write the frames to a YUV4MPEG2 file (NV12, YUV, YUYV and UYVY)
+    Y4m,
+    /// Encode the frames to an .mp4 file by piping them through an ffmpeg
+    /// subprocess (RGB, BGR, YUV, NV12, YUYV, UYVY, Mono8, Mono16 and Raw16)
+    Ffmpeg,
+    /// Mux the frames into an .avi file (MJPEG)
+    Avi,
+    /// Mux the frames into an .mkv file with nanosecond timestamps (HEVC)
+    Matroska,
+    /// Write the payloads of all video frames back to back to a .bin file,
+    /// described by a .json file next to it (all formats)
+    Dump,
+}
+
+/// The timestamps timing the frames of .mp4 and .mkv files.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum TimestampSource {
+    /// The time the frame was received by the recorder
+    #[default]
+    Receive,
+    /// The time the frame was captured, free of network jitter
+    Capture,
+}
+
+impl TimestampSource {
+    fn timestamp(self, frame: &FrameRef) -> i64 {
+        match self {
+            TimestampSource::Receive => frame.receive_timestamp,
+            TimestampSource::Capture => frame.capture_timestamp,
+        }
+    }
+}
+
+/// How frames of .mp4 files with a timestamp before that of a frame before them
+/// are timed, as recorded when the clock of the recorder is stepped back.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum TimestampRegressions {
+    /// Let the frames last one tick, taken from the following frames
+    #[default]
+    Clamp,
+    /// Leave the frames out
+    Drop,
+    /// Space the frames evenly up to the next frame after the regression
+    Interpolate,
+}
+
+/// Order the frames of a recording are converted in.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum IndexOrder {
+    /// The order the frames are stored in the file
+    #[default]
+    Offset,
+    /// The order the frames were received in
+    ReceiveTimestamp,
+}
+
+impl IndexOrder {
+    /// Number of index entries coming before an entry they should follow
+    fn count_out_of_order(self, entries: &[RecordingIndexEntry]) -> usize {
+        entries
+            .windows(2)
+            .filter(|pair| match self {
+                IndexOrder::Offset => pair[1].offset() < pair[0].offset(),
+                IndexOrder::ReceiveTimestamp => {
+                    pair[1].receive_timestamp() < pair[0].receive_timestamp()
+                }
+            })
+            .count()
+    }
+}
+
+/// Repairs of the index of a recording.
+pub(crate) struct IndexRepairs {
+    /// Number of entries out of order
+    reordered: usize,
+    /// Number of entries duplicating another one
+    duplicated: usize,
+    /// Inconsistencies of the index recovered from
+    warnings: Vec<String>,
+    /// Whether the index was rebuilt from the frames
+    pub(crate) rebuilt: bool,
+}
+
+/// Colour space of the video of .mp4 files, for streams that do not describe
+/// it themselves. All of them are limited range.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
+pub enum ColourSpace {
+    /// Standard definition video, as used by the PAL and NTSC successors
+    Bt601,
+    /// High definition video
+    Bt709,
+    /// Ultra high definition and HDR video
+    Bt2020,
+}
+
+impl ColourSpace {
+    /// The colour description with the code points of ITU-T H.273.
+    fn description(self) -> bitstream::ColourDescription {
+        let (colour_primaries, transfer_characteristics, matrix_coefficients) = match self {
+            ColourSpace::Bt601 => (6, 6, 6),
+            ColourSpace::Bt709 => (1, 1, 1),
+            ColourSpace::Bt2020 => (9, 14, 9),
+        };
+
+        bitstream::ColourDescription {
+            colour_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            full_range: false,
+        }
+    }
+}
+
+/// Sample entry type of HEVC tracks in .mp4 files.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum HevcSampleEntry {
+    /// Parameter sets stay in the samples, as recorded
+    #[default]
+    Hev1,
+    /// Parameter sets are moved from the samples to the decoder configuration,
+    /// as required by Safari and other Apple players
+    Hvc1,
+}
+
+/// Clockwise rotation applied by players when displaying the video.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Clockwise90,
+    #[value(name = "180")]
+    Clockwise180,
+    #[value(name = "270")]
+    Clockwise270,
+}
+
+impl Rotation {
+    fn degrees(self) -> u16 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+}
+
+/// Options controlling the conversion of a .vraw file.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Path of the output written by [`convert_vraw_with`]. None names it
+    /// after the input and the time of the conversion. The other conversions
+    /// are given their output instead
+    pub output: Option<PathBuf>,
+    /// The container of the output, None picks the default for the recorded format
+    pub container: Option<Container>,
+    /// Stretch the values of every Mono16 frame to the full 16 bit range
+    pub auto_scale: bool,
+    /// Color filter layout used to demosaic Raw16 frames
+    pub bayer_pattern: BayerPattern,
+    /// Also write the Stats frames to a .stats.json file next to the output
+    pub export_stats: bool,
+    /// Write H.264 and HEVC .mp4 files as fragmented mp4, with fragments of at
+    /// least this duration. None writes a regular mp4
+    pub fragment_duration: Option<Duration>,
+    /// Move the moov box of .mp4 files in front of the media data, so playback
+    /// can start before the whole file is downloaded
+    pub faststart: bool,
+    /// Sample entry type of HEVC .mp4 files
+    pub hevc_sample_entry: HevcSampleEntry,
+    /// Present the frames of .mp4 files in the order of their capture
+    /// timestamps, for recordings with B-frames
+    pub composition_offsets: bool,
+    /// Timescale of .mp4 files in ticks per second, None uses
+    /// [`DEFAULT_MP4_TIMESCALE`]
+    pub timescale: Option<u32>,
+    /// Rotation of the video track of .mp4 files, for cameras mounted rotated
+    pub rotation: Rotation,
+    /// Also write an .srt file next to the output, showing the wall clock time
+    /// at which every frame was received
+    pub subtitles: bool,
+    /// Also write the generic metadata of every converted frame to a
+    /// .metadata.jsonl file next to the output
+    pub export_generic_metadata: bool,
+    /// Mark the gaps of .mp4 files with chapters
+    pub chapters: bool,
+    /// Receive timestamp deltas longer than this multiple of the median frame
+    /// interval are gaps, None uses [`DEFAULT_GAP_THRESHOLD`]
+    pub gap_threshold: Option<f64>,
+    /// The timestamps timing the frames of .mp4 and .mkv files
+    pub timestamp_source: TimestampSource,
+    /// Colour space written to .mp4 files whose stream does not describe its
+    /// colours, None writes no colour information
+    pub colour_space: Option<ColourSpace>,
+    /// Start every HEVC sample of .mp4 and .mkv files with an SEI NAL unit
+    /// carrying the timestamps of its frame, see [`TIMESTAMP_SEI_UUID`]
+    pub timestamp_sei: bool,
+    /// Leave out index entries whose offsets cannot be frames instead of
+    /// failing the conversion
+    pub lenient_index: bool,
+    /// Stop at the first frame that cannot be read, as earlier versions did,
+    /// instead of skipping it
+    pub stop_at_corrupt_frame: bool,
+    /// Order the frames are converted in. Older recorders at times wrote the
+    /// index slightly out of order
+    pub index_order: IndexOrder,
+    /// How frames of .mp4 files with a timestamp before that of a frame
+    /// before them are timed
+    pub timestamp_regressions: TimestampRegressions,
+    /// Name outputs not given after the time the recording started instead of
+    /// the time of the conversion
+    pub name_after_recording_start: bool,
+    /// Most alignment bytes searched for after the video placement footer of
+    /// a frame, None uses [`DEFAULT_MAX_PLACEMENT_PADDING`]
+    pub max_placement_padding: Option<usize>,
+    /// Whether inconsistencies in the structure of the recording fail the
+    /// conversion or are recovered from with a warning
+    pub strictness: Strictness,
+    /// Convert coded frames as the codec of their bitstream when it differs
+    /// from their recorded format, as recorded by misconfigured recorders
+    pub trust_bitstream: bool,
+    /// Cut MJPEG frames off after their last JPEG end of image marker instead
+    /// of leaving out frames with bytes after it
+    pub trim_jpeg_trailing_bytes: bool,
+    /// Leave out the frames received before this time after the recording
+    /// started
+    pub start: Option<Duration>,
+    /// Leave out the frames received after this time after the recording
+    /// started
+    pub end: Option<Duration>,
+    /// Fail the conversion instead of replacing an output that exists
+    pub no_overwrite: bool,
+    /// Called with the progress of writing the output, every
+    /// `progress_stride` frames and after the last frame
+    pub progress: Option<ProgressCallback>,
+    /// Number of frames between calls of the progress callback, None uses
+    /// [`DEFAULT_PROGRESS_STRIDE`]
+    pub progress_stride: Option<usize>,
+    /// Stops the conversion at the next frame once set, from another thread,
+    /// failing it with [`VrawError::Cancelled`]. The output files written so
+    /// far are removed.
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl ConvertOptions {
+    pub(crate) fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            strictness: self.strictness,
+            max_placement_padding: self
+                .max_placement_padding
+                .unwrap_or(DEFAULT_MAX_PLACEMENT_PADDING),
+            trust_bitstream: self.trust_bitstream,
+        }
+    }
+}
+
+/// Default number of frames between calls of the progress callback.
+pub const DEFAULT_PROGRESS_STRIDE: usize = 100;
+
+/// Progress of writing the output, passed to the
+/// [`progress`](ConvertOptions::progress) callback.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of frames read so far, converted or not
+    pub frames_processed: usize,
+    /// Number of frames listed by the index. Outputs split at resolution
+    /// changes count the frames of the file being written
+    pub total_frames: usize,
+    /// Byte offset in the recording the reading has reached
+    pub bytes_read: u64,
+    /// Receive timestamp of the last frame read, in nanoseconds
+    pub receive_timestamp: i64,
+}
+
+/// Callback of the progress of a conversion, e.g. to drive a progress bar.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// UUID of the user_data_unregistered SEI messages carrying the timestamps of
+/// a frame. Their user data is the recording start as unix time in seconds
+/// (u64) and nanoseconds (u32), followed by the capture and receive timestamps
+/// of the frame in nanoseconds relative to it (i64), all big endian.
+pub const TIMESTAMP_SEI_UUID: [u8; 16] = [
+    0xCE, 0x45, 0x2D, 0xBC, 0x34, 0x38, 0x41, 0xB5, 0xB6, 0x8A, 0x74, 0x23, 0x89, 0xE7, 0xA1, 0xA0,
+];
+
+/// Default threshold of gaps, as a multiple of the median frame interval.
+pub const DEFAULT_GAP_THRESHOLD: f64 = 3.0;
+
+/// A gap in a recording, where the recorder dropped frames or paused.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Gap {
+    /// Index of the first frame after the gap in the output
+    pub frame: usize,
+    /// Receive timestamp of the first frame after the gap, in nanoseconds
+    /// relative to the first frame
+    pub position: i64,
+    /// Time between the frames around the gap, in nanoseconds
+    pub duration: i64,
+}
+
+/// A change of the resolution of the converted video in the middle of a
+/// recording, as when the camera renegotiated it. Outputs of a single
+/// resolution are split at it.
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ResolutionChange {
+    /// Position in the index of the first frame of the new resolution
+    pub frame: usize,
+    /// Byte offset of that frame in the recording
+    pub offset: i64,
+    /// Width and height before the change
+    pub from: (i32, i32),
+    /// Width and height from the change on
+    pub to: (i32, i32),
+    /// The file the frames from the change on are written to, up to the next
+    /// change
+    pub output: PathBuf,
+}
+
+/// Summary of a finished conversion.
+#[derive(Debug, Clone, Default, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConversionSummary {
+    /// Number of frames written to the output
+    pub frames_written: usize,
+    /// Number of Stats frames written to the .stats.json file
+    pub stats_written: usize,
+    /// Number of video frames left out because their format differs from the
+    /// converted one, per format
+    pub frames_skipped: HashMap<VideoCaptureFormat, usize>,
+    /// Gaps between the frames of .mp4 files
+    pub gaps: Vec<Gap>,
+    /// Number of frames of .mp4 files received at the same time as the frame
+    /// before them, which last one tick taken from the following frames
+    pub duplicate_timestamps: usize,
+    /// Number of frames that could not be read, skipped or ending the
+    /// conversion
+    pub frames_corrupt: usize,
+    /// Whether the last frame was cut off by the end of the file, as it is when
+    /// the recording ended abruptly. The frame is left out.
+    pub truncated: bool,
+    /// Number of index entries out of order, which were sorted
+    pub index_entries_reordered: usize,
+    /// Number of index entries duplicating another one, which were left out
+    pub index_entries_duplicated: usize,
+    /// Number of frames of .mp4 files with a timestamp before that of a frame
+    /// before them, timed as set by [`ConvertOptions::timestamp_regressions`]
+    pub timestamp_regressions: usize,
+    /// The file written, or the folder of image sequences
+    pub output: PathBuf,
+    /// Number of coded frames without a video placement footer, which are
+    /// converted with any alignment bytes after it
+    pub placement_footers_missing: usize,
+    /// Inconsistencies of the recording recovered from by
+    /// [`Strictness::Lenient`] parsing, in the order they were found
+    pub warnings: Vec<String>,
+    /// Number of MJPEG frames left out as they do not start with a JPEG start
+    /// of image marker or do not end with an end of image marker, which
+    /// players stop at
+    pub jpeg_frames_invalid: usize,
+    /// Changes of the resolution of the video, at which the output was split
+    /// into several files. The counts of the summary are those of all files,
+    /// the frames of gaps count from the start of their file.
+    pub resolution_changes: Vec<ResolutionChange>,
+    /// Receive timestamp of the first frame written, in nanoseconds
+    pub first_timestamp: Option<i64>,
+    /// Receive timestamp of the last frame written, in nanoseconds
+    pub last_timestamp: Option<i64>,
+    /// Format of the converted video, None for dumps and recordings of only
+    /// Stats frames
+    pub format: Option<VideoCaptureFormat>,
+    /// Size of the output in bytes, that of all its files for image sequences
+    /// and outputs split at resolution changes
+    pub output_size: u64,
+    /// Frames left out of the output or cut off while writing it, like
+    /// corrupt frames, raw frames of an unexpected size or MJPEG frames
+    /// without JPEG markers
+    pub output_warnings: Vec<String>,
+}
+
+impl ConversionSummary {
+    /// Counts a frame of another format than the converted one. Stats frames
+    /// are not video and not counted.
+    fn skip_frame(&mut self, index: usize, format: VideoCaptureFormat) {
+        log::debug!("skipping {format:?} frame {index} of another format");
+        if format != VideoCaptureFormat::Stats {
+            *self.frames_skipped.entry(format).or_default() += 1;
+        }
+    }
+
+    /// Time between the first and the last frame written were received.
+    pub fn duration(&self) -> Duration {
+        self.first_timestamp
+            .zip(self.last_timestamp)
+            .map_or(Duration::ZERO, |(first, last)| {
+                Duration::from_nanos(last.saturating_sub(first).max(0) as u64)
+            })
+    }
+
+    /// Notes a frame left out of the output, as its payload cannot be written.
+    fn leave_out_frame(&mut self, warning: String) {
+        log::debug!("{warning}");
+        self.output_warnings.push(warning);
+    }
+
+    /// Counts a frame written to the output, given its receive timestamp.
+    fn frame_written(&mut self, timestamp: i64) {
+        self.frames_written += 1;
+        self.first_timestamp.get_or_insert(timestamp);
+        self.last_timestamp = Some(timestamp);
+    }
+
+    /// Adds the summary of the next part of an output split at a resolution
+    /// change.
+    fn append_part(&mut self, part: ConversionSummary) {
+        self.frames_written += part.frames_written;
+        for (format, count) in part.frames_skipped {
+            *self.frames_skipped.entry(format).or_default() += count;
+        }
+        self.gaps.extend(part.gaps);
+        self.duplicate_timestamps += part.duplicate_timestamps;
+        self.frames_corrupt += part.frames_corrupt;
+        self.truncated = part.truncated;
+        self.timestamp_regressions += part.timestamp_regressions;
+        self.placement_footers_missing += part.placement_footers_missing;
+        self.jpeg_frames_invalid += part.jpeg_frames_invalid;
+        self.warnings.extend(part.warnings);
+        self.output_warnings.extend(part.output_warnings);
+        self.first_timestamp = self.first_timestamp.or(part.first_timestamp);
+        self.last_timestamp = part.last_timestamp.or(self.last_timestamp);
+    }
+}
+
+/// A Stats frame as written to the .stats.json file. Payloads that are not
+/// valid UTF-8 are base64 encoded.
+#[derive(Debug, serde::Serialize)]
+struct StatsRecord {
+    timestamp: i64,
+    receive_timestamp: i64,
+    encoding: &'static str,
+    payload: String,
+}
+
+/// The generic metadata of a frame, one JSON object per line of the
+/// .metadata.jsonl file. The payload is base64 encoded.
+#[derive(Debug, serde::Serialize)]
+struct GenericMetadataRecord {
+    /// Index of the frame in the output
+    frame: usize,
+    timestamp: i64,
+    receive_timestamp: i64,
+    payload: String,
+}
+
+/// Function that converts a .vraw file to an .mp4 file using the default options.
+/// NOTE: Currently only HEVC, H.264, MJPEG, NV12, YUV, YUYV, UYVY, RGB, BGR, Mono8, Mono16
+/// and Raw16 are supported!!!
+///
+/// input: path to .vraw file, or - to read it from stdin
+///
+/// output: name of the gengerated file. If None is specified the file will
+/// be named after the input and the time of generation. The summary holds the
+/// path of the file written.
+pub fn convert_vraw(
+    input: impl AsRef<Path>,
+    output: Option<PathBuf>,
+) -> Result<ConversionSummary, VrawError> {
+    convert_vraw_with(
+        input,
+        &ConvertOptions {
+            output,
+            ..Default::default()
+        },
+    )
+}
+
+/// Same as [`convert_vraw`], but with explicit [`ConvertOptions`], which give
+/// the output path as well.
+pub fn convert_vraw_with(
+    input: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let input = input.as_ref();
+    let output = options.output.clone();
+
+    // Recordings from pipes cannot be seeked, and are read front to back
+    if input == Path::new("-") {
+        return convert_stream(io::stdin().lock(), Path::new("stdin"), output, options);
+    }
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+    if matches!(input_file.metadata(), Ok(metadata) if !metadata.is_file()) {
+        return convert_stream(input_file, input, output, options);
+    }
+
+    convert_indexed_recording(&mut BufReader::new(input_file), input, output, options)
+}
+
+/// Same as [`convert_vraw_with`], with the output given apart from the
+/// options, in place of theirs.
+pub fn convert_vraw_with_options(
+    input: impl AsRef<Path>,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    convert_vraw_with(
+        input,
+        &ConvertOptions {
+            output,
+            ..options.clone()
+        },
+    )
+}
+
+/// Converts a recording read from a reader instead of a file, e.g. an upload
+/// held in memory in a [`std::io::Cursor`]. Image sequences are named after
+/// the output.
+pub fn convert_vraw_from_reader(
+    mut f: impl RecordingReader,
+    output: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let output = output.as_ref();
+    convert_indexed_recording(&mut f, output, Some(output.to_path_buf()), options)
+}
+
+/// Converts a recording held in memory, writing the output to a writer, e.g.
+/// the response to an upload. The output is converted in memory, as by
+/// [`convert_vraw_to_writer`], and copied to the writer, so nothing is written
+/// to disk. The same outputs can be written, with the summary of no output
+/// path.
+pub fn convert_vraw_bytes(
+    recording: &[u8],
+    mut writer: impl Write,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    check_writer_options(options)?;
+
+    let mut output = io::Cursor::new(Vec::new());
+    let summary = convert_indexed(io::Cursor::new(recording), options, |f, entries| {
+        convert_recording_to_writer(f, entries, &mut output, options)
+    })?;
+
+    writer
+        .write_all(output.get_ref())
+        .and_then(|_| writer.flush())
+        .map_err(VrawError::io("failed to write output"))?;
+    Ok(summary)
+}
+
+/// Converts a .vraw file, writing the output to a writer that can seek, e.g. a
+/// [`std::io::Cursor`] over a buffer to upload. Only outputs of a single file
+/// can be written to a writer, not image sequences, outputs split at
+/// resolution changes or the files next to the output. The moov box of mp4
+/// files is written at their end. The summary has no output path.
 ///
 /// input: path to .vraw file
+pub fn convert_vraw_to_writer(
+    input: impl AsRef<Path>,
+    mut writer: impl Write + Seek,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    check_writer_options(options)?;
+
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+    convert_indexed(BufReader::new(input_file), options, |f, entries| {
+        convert_recording_to_writer(f, entries, &mut writer, options)
+    })
+}
+
+/// Fails for the options whose outputs cannot be written to a writer.
+fn check_writer_options(options: &ConvertOptions) -> Result<(), VrawError> {
+    if options.export_stats || options.subtitles || options.export_generic_metadata {
+        return Err(VrawError::InvalidOptions(
+            "files next to the output cannot be written with the output to a writer".into(),
+        ));
+    }
+    if options.faststart {
+        return Err(VrawError::InvalidOptions(
+            "faststart mp4 files cannot be written to a writer, their moov box is moved to the \
+             front after they are written"
+                .into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Converts a .vraw file using the default options, returning the output,
+/// e.g. the bytes of an .mp4 file, see [`convert_vraw_to_writer`].
 ///
-/// output: name of the gengerated .mp4 file. If None is specified the file will
-/// be named after the input and the time of generation.
-pub fn convert_vraw_to_mp4(input: &String, output: Option<String>) -> Result<(), String> {
-    let input_file = File::open(input).map_err(|_| "vraw_convert: failed to open file")?;
+/// input: path to .vraw file
+pub fn convert_vraw_to_vec(input: impl AsRef<Path>) -> Result<Vec<u8>, VrawError> {
+    let mut output = io::Cursor::new(Vec::new());
+    convert_vraw_to_writer(input, &mut output, &ConvertOptions::default())?;
+
+    Ok(output.into_inner())
+}
+
+/// Removes an output, with the files of its other parts if it was split.
+fn remove_output(output: &Path) {
+    if output.is_dir() {
+        let _ = fs::remove_dir_all(output);
+    } else {
+        let _ = fs::remove_file(output);
+    }
+    for part in 2.. {
+        if fs::remove_file(part_output(output, part)).is_err() {
+            break;
+        }
+    }
+}
 
-    let output = output.unwrap_or_else(|| {
-        let input_path = Path::new(&input);
+/// Whether the conversion was cancelled with [`ConvertOptions::cancel`].
+fn is_cancelled(cancel: Option<&Arc<AtomicBool>>) -> bool {
+    cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+}
 
-        let output_file_name = input_path.file_name().unwrap().to_str().unwrap();
+/// The result of a conversion, which fails if it was cancelled, removing the
+/// output written until then.
+fn unless_cancelled(
+    result: Result<ConversionSummary, VrawError>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    if !is_cancelled(options.cancel.as_ref()) {
+        return result;
+    }
+    // Outputs written to a writer have no path
+    match result {
+        Ok(summary) if !summary.output.as_os_str().is_empty() => remove_output(&summary.output),
+        _ => {}
+    }
 
-        let output_file_name = format!(
-            "{}_{}.mp4",
-            output_file_name.trim_end_matches(".vraw"),
-            Local::now().format("%Y-%m-%dT%H_%M_%S")
-        );
+    Err(VrawError::Cancelled)
+}
 
-        input_path
-            .ancestors()
-            .nth(2)
-            .unwrap()
-            .join(output_file_name)
-            .to_string_lossy()
-            .to_string()
-    });
+/// Converts a recording that can be read in any order, finding its frames by
+/// its index.
+pub(crate) fn convert_indexed_recording(
+    f: &mut impl RecordingReader,
+    input: &Path,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    convert_indexed(f, options, |f, entries| {
+        convert_recording(f, entries, input, output, options)
+    })
+}
 
-    let mut f = BufReader::new(input_file);
+/// Reads or rebuilds the index of a recording and converts the frames it
+/// lists with `convert`.
+fn convert_indexed<R: RecordingReader>(
+    f: R,
+    options: &ConvertOptions,
+    convert: impl FnOnce(
+        &mut VrawReader<R>,
+        &[RecordingIndexEntry],
+    ) -> Result<ConversionSummary, VrawError>,
+) -> Result<ConversionSummary, VrawError> {
+    let (mut f, index_repairs) = VrawReader::for_conversion(f, options)?;
+    let index = f.index();
 
-    let entries =
-        read_index(&mut f).map_err(|e| format!("vraw_convert: failed to read index: {e}"))?;
+    if index.entries.is_empty() {
+        return Err(VrawError::corrupt_index("index contains no frames"));
+    }
 
-    if entries.is_empty() {
-        return Err("vraw_convert: index contains no frames".into());
+    if options.strictness == Strictness::Strict {
+        validate_frames(&mut f, &index.entries)?;
     }
 
-    let config = Mp4Config {
-        major_brand: str::parse("isom").unwrap(),
-        minor_version: 512,
-        compatible_brands: vec![str::parse("hev1").unwrap()],
-        timescale: 1000, // This specifies milliseconds
-    };
+    let result = convert(&mut f, &index.entries);
+    let mut summary = unless_cancelled(result, options)?;
+    summary.index_entries_reordered = index_repairs.reordered;
+    summary.index_entries_duplicated = index_repairs.duplicated;
+    summary.warnings.splice(0..0, index_repairs.warnings);
 
-    let dst_file = File::create(output).map_err(|_| "vraw_convert: file creation failed")?;
-    let writer = BufWriter::new(dst_file);
+    Ok(summary)
+}
 
-    let mut mp4_writer = Mp4Writer::write_start(writer, &config)
-        .map_err(|_| "vraw_convert: failed to start writing mp4")?;
+/// Converts a recording that can only be read front to back, as from a pipe.
+/// The frames are read as they arrive and spooled to a temporary file, which
+/// the conversion reads in several passes. Outputs not given are named after
+/// `input`.
+pub(crate) fn convert_stream(
+    reader: impl Read,
+    input: &Path,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let spool_path = temp_file_path("vraw");
 
-    // find first h265 frame
-    let mut last_timestamp = 0;
-    for entry in &entries {
-        let frame =
-            parse_raw_frame(&mut f, entry).map_err(|_| "vraw_convert: unable to read frame")?; // we discard the first frame for information about the video media
-        match frame.format {
-            VideoCaptureFormat::H265 => {
-                mp4_writer
-                    .add_track(&TrackConfig::from(MediaConfig::HevcConfig(
-                        mp4::HevcConfig::default(),
-                    )))
-                    .map_err(|_| "vraw_convert: failed to add mp4 track")?;
+    let result = spool_stream(reader, &spool_path, options).and_then(|mut spool| {
+        if spool.entries.is_empty() {
+            return Err(VrawError::CorruptStream(
+                "The stream contains no frames".into(),
+            ));
+        }
 
-                last_timestamp = frame.timestamp;
+        let metadata = read_recording_metadata(&mut spool.f).ok().flatten();
+        let mut f =
+            VrawReader::with_entries(spool.f, metadata, spool.entries, options.parse_options());
+        let index = f.index();
+        if options.strictness == Strictness::Strict {
+            validate_frames(&mut f, &index.entries)?;
+        }
 
-                break;
-            }
-            VideoCaptureFormat::Stats => {
+        let result = convert_recording(&mut f, &index.entries, input, output, options);
+        let mut summary = unless_cancelled(result, options)?;
+        summary.warnings.splice(0..0, spool.warnings);
+
+        Ok(summary)
+    });
+    let _ = fs::remove_file(&spool_path);
+
+    result
+}
+
+/// A path for a temporary file with the given extension, unique within the
+/// process.
+fn temp_file_path(extension: &str) -> PathBuf {
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "vraw_convert_{}_{}.{}",
+        process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
+
+/// Reads the frames of a stream into a spool file, until the index of the
+/// recording or the end of the stream. Damaged bytes are skipped with a warning.
+/// A frame cut off by the end of the stream is listed last, for the conversion
+/// to leave it out.
+fn spool_stream(
+    reader: impl Read,
+    spool_path: &Path,
+    options: &ConvertOptions,
+) -> Result<Spool, VrawError> {
+    let spool_file =
+        File::create(spool_path).map_err(VrawError::io("failed to create the spool file"))?;
+    let mut tee = Tee {
+        reader,
+        writer: BufWriter::new(spool_file),
+    };
+
+    let mut entries = Vec::new();
+    let mut truncated = None;
+    let mut warnings = Vec::new();
+    let stream =
+        FrameStream::new(&mut tee, options.parse_options()).map_err(VrawError::CorruptStream)?;
+    for frame in stream {
+        let e = match frame {
+            Ok((entry, _)) => {
+                entries.push(entry);
                 continue;
             }
-            _ => return Err("VideoCaptureFormat not supported".into()),
+            Err(e) => e,
         };
+
+        if let Some(truncated_frame) = e.downcast_ref::<TruncatedFrame>() {
+            truncated = Some(RecordingIndexEntry::new(truncated_frame.offset, 0));
+        } else if options.strictness == Strictness::Strict {
+            return Err(VrawError::CorruptStream(e));
+        } else {
+            // The stream resumes at the next frame found after the damaged one
+            log::warn!("{e}");
+            warnings.push(e.to_string());
+        }
     }
 
-    for entry in &entries {
-        let raw_frame = parse_raw_frame(&mut f, entry);
+    tee.writer
+        .flush()
+        .map_err(VrawError::io("failed to write the spool file"))?;
+    drop(tee);
 
-        match raw_frame {
-            Ok(frame) => {
-                if frame.format == VideoCaptureFormat::Stats {
-                    continue;
-                }
+    if options.index_order == IndexOrder::ReceiveTimestamp {
+        entries.sort_by_key(|entry| entry.receive_timestamp());
+    }
+    entries.extend(truncated);
 
-                let delta_t = (frame.timestamp - last_timestamp) as f64 * 1e-6; // duration in milliseconds of the frame
-                let video_sample = Mp4Sample {
-                    start_time: frame.timestamp as u64,
-                    duration: delta_t.round() as u32, // round to nearest millisecond
-                    rendering_offset: 0,
-                    is_sync: false,
-                    bytes: mp4::Bytes::copy_from_slice(frame.raw_data.as_bytes()),
-                };
+    let spool_file =
+        File::open(spool_path).map_err(VrawError::io("failed to read the spool file"))?;
 
-                mp4_writer
-                    .write_sample(1, &video_sample)
-                    .map_err(|_| "vraw_convert: failed to write sample")?;
+    Ok(Spool {
+        f: BufReader::new(spool_file),
+        entries,
+        warnings,
+    })
+}
+
+/// A stream read into a spool file.
+struct Spool {
+    f: BufReader<File>,
+    entries: Vec<RecordingIndexEntry>,
+    /// Warnings about the stream
+    warnings: Vec<String>,
+}
+
+/// Reads from a reader, copying everything read to a writer.
+struct Tee<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..read])?;
+
+        Ok(read)
+    }
+}
+
+/// Converts the frames of the index entries of a recording.
+fn convert_recording(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    input: &Path,
+    output: Option<PathBuf>,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let entries = &frames_in_time_range(entries, options)?;
+
+    let output_time = if options.name_after_recording_start {
+        match f.recording_start() {
+            Ok(recording_start) => recording_start.with_timezone(&Local),
+            Err(e) => {
+                log::warn!(
+                    "failed to read the recording start time: {e}, naming the output after the \
+                     time of the conversion"
+                );
+                Local::now()
+            }
+        }
+    } else {
+        Local::now()
+    };
+
+    // A dump takes every format as is, so there is no format to detect
+    if options.container == Some(Container::Dump) {
+        let output = output_or_derive(output, input, "bin", output_time, options)?;
+        let mut summary = extract_dump_from_vraw(f, entries, &output, options)?;
+
+        if options.export_stats {
+            let stats_output = output.with_extension("stats.json");
+            summary.stats_written = extract_stats_from_vraw(f, entries, &stats_output, options)?;
+        }
+
+        summary.output_size = output_size(&output);
+        summary.output = output;
+        return Ok(summary);
+    }
+
+    let (first_frame, recorded_format) = match find_video(f, entries, options) {
+        Ok(video) => video,
+        // A recording with only Stats frames still has stats worth exporting
+        Err(VrawError::UnsupportedFormat { .. } | VrawError::InvalidVideo(_))
+            if options.export_stats =>
+        {
+            let output = output_or_derive(output, input, "stats.json", output_time, options)?;
+            let stats_written = extract_stats_from_vraw(f, entries, &output, options)?;
+
+            return Ok(ConversionSummary {
+                stats_written,
+                output_size: output_size(&output),
+                output,
+                ..Default::default()
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    let codec_warning = check_recorded_codec(f, entries, recorded_format, options)?;
+
+    let container = options
+        .container
+        .unwrap_or_else(|| default_container(first_frame.format));
+    let extension = output_extension(first_frame.format, container)?;
+
+    let output = output_or_derive(output, input, extension, output_time, options)?;
+
+    // Outputs of a single resolution are split where it changes, as a camera
+    // renegotiating it would leave the frames after the change undecodable
+    let changes = match container {
+        Container::ElementaryStream | Container::ImageSequence => Vec::new(),
+        _ => find_resolution_changes(f, entries, &output, first_frame.format, options),
+    };
+
+    let end = changes.first().map_or(entries.len(), |change| change.frame);
+    let mut summary = convert_part(
+        f,
+        &entries[..end],
+        &output,
+        &first_frame,
+        container,
+        input,
+        options,
+    )?;
+
+    for (part, change) in changes.iter().enumerate() {
+        let end = changes
+            .get(part + 1)
+            .map_or(entries.len(), |next| next.frame);
+        log::info!(
+            "the resolution changes from {}x{} to {}x{} at frame {}, writing the frames from \
+             there on to {}",
+            change.from.0,
+            change.from.1,
+            change.to.0,
+            change.to.1,
+            change.frame,
+            change.output.display()
+        );
+
+        // The frame is of the format of the conversion, even if it is recorded
+        // as another codec than its bitstream
+        let metadata =
+            f.frame_metadata(&entries[change.frame])
+                .map_err(|reason| VrawError::BadFrame {
+                    index: change.frame,
+                    offset: change.offset,
+                    reason,
+                })?;
+        let part_first_frame = FrameMetadata {
+            format: first_frame.format,
+            ..metadata
+        };
+        summary.append_part(convert_part(
+            f,
+            &entries[change.frame..end],
+            &change.output,
+            &part_first_frame,
+            container,
+            input,
+            options,
+        )?);
+    }
+    summary.resolution_changes = changes;
+    summary.warnings.splice(0..0, codec_warning);
+
+    if options.export_stats {
+        let stats_output = output.with_extension("stats.json");
+        summary.stats_written = extract_stats_from_vraw(f, entries, &stats_output, options)?;
+    }
+
+    if options.subtitles {
+        let subtitles_output = output.with_extension("srt");
+        extract_subtitles_from_vraw(f, entries, &subtitles_output, first_frame.format, options)?;
+    }
+
+    if options.export_generic_metadata {
+        let metadata_output = output.with_extension("metadata.jsonl");
+        extract_generic_metadata_from_vraw(
+            f,
+            entries,
+            &metadata_output,
+            first_frame.format,
+            options,
+        )?;
+    }
+
+    summary.format = Some(first_frame.format);
+    summary.output_size = output_size(&output)
+        + summary
+            .resolution_changes
+            .iter()
+            .map(|change| output_size(&change.output))
+            .sum::<u64>();
+    summary.output = output;
+    Ok(summary)
+}
+
+/// Converts the frames of the index entries of a recording into an output of a
+/// single file written to a writer, see [`convert_vraw_to_writer`].
+fn convert_recording_to_writer(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: &mut (impl Write + Seek),
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let entries = &frames_in_time_range(entries, options)?;
+
+    let (first_frame, recorded_format) = find_video(f, entries, options)?;
+    let codec_warning = check_recorded_codec(f, entries, recorded_format, options)?;
+
+    let container = options
+        .container
+        .unwrap_or_else(|| default_container(first_frame.format));
+    output_extension(first_frame.format, container)?;
 
-                last_timestamp = frame.timestamp;
+    if container != Container::ElementaryStream {
+        let changes =
+            find_resolution_changes(f, entries, Path::new(""), first_frame.format, options);
+        if let Some(change) = changes.first() {
+            return Err(VrawError::InvalidOptions(format!(
+                "the resolution changes at frame {}, which splits the output into several \
+                 files that cannot be written to a writer",
+                change.frame
+            )));
+        }
+    }
+
+    let mut writer = OffsetWriter::new(writer).map_err(VrawError::io("failed to write output"))?;
+    let mut summary = write_part(f, entries, &mut writer, &first_frame, container, options)?;
+    summary.output_size = writer
+        .seek(io::SeekFrom::End(0))
+        .and_then(|end| writer.flush().map(|_| end))
+        .map_err(VrawError::io("failed to write output"))?;
+
+    summary.warnings.splice(0..0, codec_warning);
+    summary.format = Some(first_frame.format);
+    Ok(summary)
+}
+
+/// A writer whose positions start where the writer it writes to was, as the
+/// containers seek to the positions of their headers from the start.
+struct OffsetWriter<W> {
+    writer: W,
+    start: u64,
+}
+
+impl<W: Write + Seek> OffsetWriter<W> {
+    fn new(mut writer: W) -> io::Result<Self> {
+        let start = writer.stream_position()?;
+        Ok(Self { writer, start })
+    }
+}
+
+impl<W: Write> Write for OffsetWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Seek> Seek for OffsetWriter<W> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(position) => io::SeekFrom::Start(self.start + position),
+            pos => pos,
+        };
+        let position = self.writer.seek(pos)?;
+
+        position.checked_sub(self.start).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the output",
+            )
+        })
+    }
+}
+
+/// Finds the first video frame, which decides the format of the conversion.
+/// Frames of other formats, supported or not, are skipped by the conversion.
+/// Returns its header, with the format of its bitstream if it is trusted, and
+/// the format it is recorded as.
+fn find_video(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    options: &ConvertOptions,
+) -> Result<(FrameMetadata, VideoCaptureFormat), VrawError> {
+    let mut first_frame = None;
+    let mut unsupported_format = None;
+    // Counted to tell what a recording without video holds instead
+    let mut stats_frames = 0;
+    let mut unreadable_frames = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let mut metadata = match f.frame_metadata(entry) {
+            Ok(metadata) => metadata,
+            // Failing to read the file, unlike a broken frame, would fail
+            // the conversion after it as well
+            Err(e) if e.is::<io::Error>() => {
+                let e = *e
+                    .downcast::<io::Error>()
+                    .expect("checked to be an io::Error");
+                return Err(VrawError::input(format!("failed to read frame {index}"))(e));
             }
             Err(_) => {
-                // Here, we don't have a valid frame (we most likely reached the end of the recording)
+                unreadable_frames += 1;
+                if options.stop_at_corrupt_frame {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        match metadata.format {
+            VideoCaptureFormat::Stats => stats_frames += 1,
+            format if SUPPORTED_FORMATS.contains(&format) => {
+                // The header decides the format, the payload is read by the
+                // conversion. Only a trusted bitstream needs it read up front.
+                let recorded_format = metadata.format;
+                if options.trust_bitstream && metadata.format.is_coded() {
+                    match f.entry_frame(entry, &options.parse_options()) {
+                        Ok(frame) => metadata.format = frame.format,
+                        Err(_) => {
+                            unreadable_frames += 1;
+                            if options.stop_at_corrupt_frame {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+                }
+                first_frame = Some((metadata, recorded_format));
                 break;
             }
+            format => {
+                unsupported_format.get_or_insert(VrawError::UnsupportedFormat {
+                    format,
+                    width: metadata.width,
+                    height: metadata.height,
+                });
+            }
         }
     }
 
-    mp4_writer
-        .write_end()
-        .map_err(|_| "vraw_convert: failed to end mp4 writing")?;
+    first_frame.ok_or_else(|| {
+        unsupported_format.unwrap_or_else(|| {
+            VrawError::InvalidVideo(no_video_found(
+                entries.len(),
+                stats_frames,
+                unreadable_frames,
+            ))
+        })
+    })
+}
 
-    Ok(())
+/// Size of an output in bytes, of the files in it for a folder. Outputs that
+/// cannot be read count as empty.
+fn output_size(output: &Path) -> u64 {
+    match fs::metadata(output) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(output)
+            .map(|files| {
+                files
+                    .filter_map(|file| file.ok()?.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Tells what a recording without video frames holds instead.
+fn no_video_found(frames: usize, stats_frames: usize, unreadable_frames: usize) -> String {
+    if frames == 0 {
+        return "no video frames found, the recording holds no frames".into();
+    }
+
+    let mut found =
+        format!("no video frames found, the recording holds {stats_frames} Stats frames");
+    if unreadable_frames > 0 {
+        found += &format!(" and {unreadable_frames} frames that cannot be read");
+    }
+    found
+}
+
+/// Number of the first frames of a coded format whose bitstream is compared
+/// with it.
+const SNIFFED_FRAMES: usize = 5;
+
+/// Compares the recorded format of the first frames of a coded format with the
+/// codec of their bitstream. When most of them are of another codec, as
+/// recorded by misconfigured recorders, it is warned about and the warning
+/// returned, or it fails the conversion with [`Strictness::Strict`] unless the
+/// bitstream is trusted.
+fn check_recorded_codec(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Result<Option<String>, VrawError> {
+    if !format.is_coded() {
+        return Ok(None);
+    }
+
+    let parse_options = ParseOptions {
+        trust_bitstream: false,
+        ..options.parse_options()
+    };
+
+    let mut frames = 0;
+    let mut codecs = HashMap::new();
+    for entry in entries {
+        if frames == SNIFFED_FRAMES {
+            break;
+        }
+        let frame = match f.entry_frame(entry, &parse_options) {
+            Ok(frame) if frame.format == format => frame,
+            _ => continue,
+        };
+
+        frames += 1;
+        if let Some(codec) = parser::sniff_format(&frame.raw_data) {
+            *codecs.entry(codec).or_insert(0) += 1;
+        }
+    }
+
+    let Some(codec) = codecs
+        .into_iter()
+        .find(|&(codec, count)| codec != format && count * 2 > frames)
+        .map(|(codec, _)| codec)
+    else {
+        return Ok(None);
+    };
+
+    let warning =
+        format!("The frames are recorded as {format:?}, but their bitstream is {codec:?}");
+    if options.trust_bitstream {
+        log::warn!("{warning}, converting them as {codec:?}");
+    } else if options.strictness == Strictness::Strict {
+        return Err(VrawError::InvalidVideo(format!(
+            "the frames are recorded as {format:?}, but their bitstream is {codec:?}"
+        )));
+    } else {
+        log::warn!("{warning}, trusting the bitstream converts them as {codec:?}");
+    }
+
+    Ok(Some(warning))
+}
+
+/// Converts the frames of the index entries of a recording, all of them of the
+/// resolution of `first_frame`, the first video frame, into one output. An
+/// output file left incomplete by a failed conversion is removed.
+fn convert_part(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    first_frame: &FrameMetadata,
+    container: Container,
+    input: &Path,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let summary = match container {
+        Container::ImageSequence => {
+            extract_image_sequence_from_vraw(f, entries, output, input, first_frame, options)?
+        }
+        Container::Ffmpeg => extract_ffmpeg_from_vraw(f, entries, output, first_frame, options)?,
+        _ => {
+            let dst_file = File::create(output).map_err(VrawError::io("file creation failed"))?;
+            let mut writer = BufWriter::new(dst_file);
+            let result = write_part(f, entries, &mut writer, first_frame, container, options)
+                .and_then(|summary| {
+                    writer
+                        .flush()
+                        .map_err(VrawError::io("failed to write output"))?;
+                    Ok(summary)
+                });
+            if result.is_err() {
+                drop(writer);
+                let _ = fs::remove_file(output);
+            }
+            result?
+        }
+    };
+
+    if container == Container::Mp4 && options.faststart && options.fragment_duration.is_none() {
+        isobmff::move_moov_to_front(output)
+            .map_err(VrawError::mux("failed to move the moov box"))?;
+    }
+
+    Ok(summary)
+}
+
+/// Converts the frames of the index entries of a recording into an output of a
+/// single file, see [`convert_part`].
+fn write_part(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: &mut (impl Write + Seek),
+    first_frame: &FrameMetadata,
+    container: Container,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    match (first_frame.format, container) {
+        (VideoCaptureFormat::H264, Container::Mp4) => {
+            extract_avc_from_vraw(f, entries, writer, options)
+        }
+        (VideoCaptureFormat::H265, Container::Mp4) => {
+            extract_hevc_from_vraw(f, entries, writer, options)
+        }
+        (VideoCaptureFormat::Mjpeg, Container::Mp4) if options.fragment_duration.is_some() => Err(
+            VrawError::InvalidOptions("fragmented mp4 output is not supported for MJPEG".into()),
+        ),
+        (VideoCaptureFormat::Mjpeg, Container::Mp4) => extract_mjpeg_to_mp4(
+            f,
+            entries,
+            writer,
+            first_frame.width,
+            first_frame.height,
+            options,
+        ),
+        (VideoCaptureFormat::Mjpeg, Container::Avi) => {
+            extract_mjpeg_to_avi(f, entries, writer, first_frame, options)
+        }
+        (VideoCaptureFormat::H265, Container::Matroska) => {
+            extract_hevc_to_mkv(f, entries, writer, options)
+        }
+        (format, Container::ElementaryStream) => {
+            write_elementary_stream(f, entries, writer, format, options)
+        }
+        (_, Container::Y4m) => extract_y4m_from_vraw(f, entries, writer, first_frame, options),
+        (_, Container::ImageSequence | Container::Ffmpeg | Container::Dump) => {
+            Err(VrawError::InvalidOptions(
+                "image sequences, ffmpeg outputs and dumps cannot be written to a writer".into(),
+            ))
+        }
+        (format, container) => Err(unsupported_output(format, container)),
+    }
+}
+
+/// The output of a part of a conversion split at resolution changes. Parts are
+/// numbered from 2, as the first part keeps the name of the output, e.g.
+/// video_2.mp4 follows video.mp4.
+fn part_output(output: &Path, part: usize) -> PathBuf {
+    let mut file_name = output.file_stem().unwrap_or_default().to_os_string();
+    file_name.push(format!("_{part}"));
+    if let Some(extension) = output.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    output.with_file_name(file_name)
+}
+
+/// Finds the frames of `format` whose resolution differs from that of the
+/// frame of the same camera before them. The frames from every change on are
+/// written to the next part of `output`. Uncompressed and MJPEG frames are compared by the
+/// dimensions of their header, H.264 and HEVC frames by those of their SPS, and
+/// frames without one keep the resolution before them. Frames of different
+/// cameras are not compared, mixing them is warned about on its own.
+fn find_resolution_changes(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Vec<ResolutionChange> {
+    let mut resolutions = HashMap::new();
+    let mut changes = Vec::new();
+    let mut compare = |index: usize, id, resolution| {
+        if let Some(from) = resolutions.insert(id, resolution) {
+            if from != resolution {
+                changes.push(ResolutionChange {
+                    frame: index,
+                    offset: entries[index].offset(),
+                    from,
+                    to: resolution,
+                    output: part_output(output, changes.len() + 2),
+                });
+            }
+        }
+    };
+
+    match format {
+        VideoCaptureFormat::H264 | VideoCaptureFormat::H265 => {
+            let mut frames = Frames::new(f, entries, options);
+            while let Some((index, frame)) = frames.next_ref() {
+                if frame.format != format {
+                    continue;
+                }
+                if let Some(resolution) = sps_resolution(format, frame.raw_data) {
+                    compare(index, frame.id, resolution);
+                }
+            }
+        }
+        _ => {
+            for (index, entry) in entries.iter().enumerate() {
+                let metadata = match f.frame_metadata(entry) {
+                    Ok(metadata) => metadata,
+                    Err(_) if options.stop_at_corrupt_frame => break,
+                    Err(_) => continue,
+                };
+                if metadata.format == format {
+                    compare(index, metadata.id, (metadata.width, metadata.height));
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+/// The width and height of the SPS of an H.264 or HEVC access unit, None if it
+/// carries none.
+fn sps_resolution(format: VideoCaptureFormat, data: &[u8]) -> Option<(i32, i32)> {
+    let mut nal_units = bitstream::nal_units(data);
+    let (width, height) = match format {
+        VideoCaptureFormat::H264 => nal_units
+            .find(|nal| bitstream::avc_nal_type(nal) == bitstream::AVC_NAL_TYPE_SPS)
+            .and_then(bitstream::parse_avc_sps)
+            .map(|sps| (sps.width, sps.height))?,
+        VideoCaptureFormat::H265 => nal_units
+            .find(|nal| bitstream::hevc_nal_type(nal) == bitstream::HEVC_NAL_TYPE_SPS)
+            .and_then(bitstream::parse_hevc_sps)
+            .map(|sps| (sps.width, sps.height))?,
+        _ => return None,
+    };
+
+    Some((width as i32, height as i32))
+}
+
+/// A frame as described in the .json file of a dump.
+#[derive(Debug, serde::Serialize)]
+struct DumpedFrame {
+    /// Id of the source stream the frame belongs to
+    id: i32,
+    format: VideoCaptureFormat,
+    width: i32,
+    height: i32,
+    /// Byte offset of the payload in the .bin file
+    offset: u64,
+    size: usize,
+    timestamp: i64,
+    receive_timestamp: i64,
+    /// Base64 encoded video placement metadata, if the frame has any
+    placement_metadata: Option<String>,
+}
+
+/// Description of a dump, written to the .json file next to the .bin file.
+#[derive(Debug, serde::Serialize)]
+struct DumpDescriptor {
+    frames: Vec<DumpedFrame>,
+}
+
+/// Writes the Stats frames of a .vraw file to a JSON file, without converting
+/// any video. Returns the number of Stats frames written.
+///
+/// input: path to .vraw file
+///
+/// output: path of the generated .json file
+pub fn export_stats(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<usize, VrawError> {
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+
+    let (mut f, _) =
+        VrawReader::for_conversion(BufReader::new(input_file), &ConvertOptions::default())?;
+    let index = f.index();
+
+    extract_stats_from_vraw(
+        &mut f,
+        &index.entries,
+        output.as_ref(),
+        &ConvertOptions::default(),
+    )
+}
+
+/// Reads the index of a recording, falling back to rebuilding it from the frames
+/// when the index footer is missing or corrupt, or lists no frames while the
+/// file has some. The entries are sorted, and
+/// exact duplicates left out. Entries with offsets that cannot be frames are an
+/// error, or left out with a warning if `lenient_index` is set.
+///
+/// With [`Strictness::Strict`] any of these inconsistencies is an error.
+pub(crate) fn read_or_rebuild_index(
+    f: &mut impl RecordingReader,
+    options: &ParseOptions,
+    index_order: IndexOrder,
+    lenient_index: bool,
+) -> Result<(Vec<RecordingIndexEntry>, IndexRepairs), VrawError> {
+    let strict = options.strictness == Strictness::Strict;
+    let (entries, warnings, rebuilt) = match read_index(f, options) {
+        Ok(index) => {
+            for warning in &index.warnings {
+                log::warn!("{warning}");
+            }
+
+            (index.entries, index.warnings, false)
+        }
+        Err(e) if strict => {
+            return Err(VrawError::CorruptIndex {
+                offset: None,
+                reason: format!("failed to read index: {e}"),
+                source: Some(e),
+            })
+        }
+        Err(e) => {
+            log::warn!("failed to read index: {e}, rebuilding it from the frames");
+
+            let index = scan_index(f).map_err(|e| VrawError::CorruptIndex {
+                offset: None,
+                reason: format!("failed to rebuild index: {e}"),
+                source: Some(e),
+            })?;
+            for warning in &index.warnings {
+                log::warn!("{warning}");
+            }
+
+            let mut warnings = vec![format!("Failed to read index: {e}")];
+            warnings.extend(index.warnings);
+            (index.entries, warnings, true)
+        }
+    };
+
+    let (entries, warnings, rebuilt) = match entries.is_empty() && !strict {
+        // An index of no frames is also written when the recorder fails to
+        // index the frames, which are then still recoverable
+        true => match scan_index(f) {
+            Ok(index) if !index.entries.is_empty() => {
+                log::warn!(
+                    "index contains no frames, but {} frames were found in the file, rebuilding \
+                     the index from them",
+                    index.entries.len()
+                );
+                for warning in &index.warnings {
+                    log::warn!("{warning}");
+                }
+
+                (index.entries, [warnings, index.warnings].concat(), true)
+            }
+            _ => (entries, warnings, rebuilt),
+        },
+        false => (entries, warnings, rebuilt),
+    };
+
+    let reordered = index_order.count_out_of_order(&entries);
+
+    // The offsets are validated in the order of the file, while errors name
+    // the entries by their position in the index
+    let mut positioned_entries: Vec<_> = entries.into_iter().enumerate().collect();
+    positioned_entries.sort_by_key(|(_, entry)| (entry.offset(), entry.receive_timestamp()));
+    let len = positioned_entries.len();
+    positioned_entries.dedup_by_key(|(_, entry)| (entry.offset(), entry.receive_timestamp()));
+    let duplicated = len - positioned_entries.len();
+    let (positions, entries): (Vec<_>, Vec<_>) = positioned_entries.into_iter().unzip();
+
+    if strict && reordered > 0 {
+        return Err(VrawError::corrupt_index(format!(
+            "{reordered} index entries are out of order"
+        )));
+    }
+    if strict && duplicated > 0 {
+        return Err(VrawError::corrupt_index(format!(
+            "{duplicated} index entries duplicate another one"
+        )));
+    }
+
+    let file_len = f
+        .recording_len()
+        .map_err(VrawError::input("failed to read the file size"))?;
+    let invalid_entries = find_invalid_index_entries(&entries, first_frame_offset(f), file_len);
+    let mut invalid_positions: Vec<_> = invalid_entries
+        .iter()
+        .map(|(i, e)| (positions[*i], entries[*i].offset(), e))
+        .collect();
+    invalid_positions.sort();
+
+    let invalid_entry = |i, offset, reason| VrawError::CorruptIndex {
+        offset: Some(offset),
+        reason: format!("invalid index entry {i}: {reason}"),
+        source: None,
+    };
+    let mut entries = match invalid_positions.first() {
+        None => entries,
+        Some(&(i, offset, e)) if strict => return Err(invalid_entry(i, offset, e.to_string())),
+        Some(&(i, offset, e)) if !lenient_index => {
+            return Err(invalid_entry(
+                i,
+                offset,
+                format!("{e}, --lenient-index leaves out invalid entries"),
+            ))
+        }
+        Some(_) => {
+            for (i, _, e) in &invalid_positions {
+                log::warn!("leaving out invalid index entry {i}: {e}");
+            }
+
+            let mut invalid_entries = invalid_entries.iter().map(|(i, _)| *i).peekable();
+            entries
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| invalid_entries.next_if_eq(i).is_none())
+                .map(|(_, entry)| entry)
+                .collect()
+        }
+    };
+
+    if index_order == IndexOrder::ReceiveTimestamp {
+        entries.sort_by_key(|entry| entry.receive_timestamp());
+    }
+
+    Ok((
+        entries,
+        IndexRepairs {
+            reordered,
+            duplicated,
+            warnings,
+            rebuilt,
+        },
+    ))
+}
+
+/// Reads every frame of a recording ahead of a [`Strictness::Strict`]
+/// conversion, which fails at the first frame that cannot be read, including a
+/// last frame cut off by the end of the file.
+fn validate_frames(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+) -> Result<(), VrawError> {
+    let mut sources = Sources::default();
+    let mut buf = Vec::new();
+    let mut frames = f.frames_of(entries);
+    while let Some((index, entry, frame)) = frames.next_into(&mut buf) {
+        let bad_frame = |reason| VrawError::BadFrame {
+            index,
+            offset: entry.offset(),
+            reason,
+        };
+        let frame = frame.map_err(bad_frame)?;
+        if let Some(e) = sources.check(frame.format, frame.metadata.id, entry) {
+            return Err(bad_frame(e.into()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The ids of the sources of the coded frames of a recording, per format.
+/// Frames of several sources are converted into one video, which then
+/// alternates between the cameras.
+#[derive(Debug, Default)]
+struct Sources(HashMap<VideoCaptureFormat, BTreeSet<i32>>);
+
+impl Sources {
+    /// Notes the source of a frame, describing the mix if it is the first
+    /// frame of another source of the same format
+    fn check(
+        &mut self,
+        format: VideoCaptureFormat,
+        id: i32,
+        entry: &RecordingIndexEntry,
+    ) -> Option<String> {
+        if !format.is_coded() {
+            return None;
+        }
+
+        let ids = self.0.entry(format).or_default();
+        let first_id = ids.first().copied();
+        if !ids.insert(id) {
+            return None;
+        }
+
+        first_id.map(|first_id| {
+            format!(
+                "Frame at offset {} of source {} mixes into the {:?} video of source {}",
+                entry.offset(),
+                id,
+                format,
+                first_id
+            )
+        })
+    }
+}
+
+/// Reads the wall clock time at which a .vraw recording started. The timestamps
+/// of the frames are relative to it.
+///
+/// input: path to .vraw file
+pub fn recording_start(input: impl AsRef<Path>) -> Result<DateTime<Utc>, VrawError> {
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+
+    read_recording_start(&mut BufReader::new(input_file)).map_err(VrawError::corrupt_header(
+        "failed to read the recording start time",
+    ))
+}
+
+/// Iterates over the frames of a recording, together with their index in it,
+/// with [`next_ref`](Self::next_ref) reading every frame into the same buffer.
+/// Frames that cannot be read are skipped, or end the iteration if
+/// `stop_at_corrupt_frame` is set. A last frame cut off by the end of the file
+/// ends the recording and is not counted as corrupt.
+struct Frames<'a, R> {
+    frames: VrawFrames<'a, R>,
+    stop_at_corrupt_frame: bool,
+    /// Whether the decisions about every frame are logged
+    report: bool,
+    /// Number of corrupt frames so far
+    corrupt: usize,
+    /// Whether the last frame is cut off
+    truncated: bool,
+    /// Number of coded frames without a video placement footer so far
+    placement_footers_missing: usize,
+    /// Warnings of the frames so far
+    warnings: Vec<String>,
+    /// Frames left out or cut off so far
+    output_warnings: Vec<String>,
+    /// Sources of the coded frames so far
+    sources: Sources,
+    /// Whether the markers of MJPEG frames are checked
+    check_jpeg: bool,
+    trim_jpeg_trailing_bytes: bool,
+    /// Number of MJPEG frames left out for their markers so far
+    jpeg_invalid: usize,
+    /// Called in the pass writing the output
+    progress: Option<ProgressCallback>,
+    progress_stride: usize,
+    total_frames: usize,
+    cancel: Option<Arc<AtomicBool>>,
+    /// The payload of the last frame read, reused for every frame
+    buf: Vec<u8>,
+}
+
+impl<'a, R: RecordingReader> Frames<'a, R> {
+    fn new(
+        f: &'a mut VrawReader<R>,
+        entries: &'a [RecordingIndexEntry],
+        options: &ConvertOptions,
+    ) -> Self {
+        Self {
+            frames: f.frames_of(entries),
+            stop_at_corrupt_frame: options.stop_at_corrupt_frame,
+            report: false,
+            corrupt: 0,
+            truncated: false,
+            placement_footers_missing: 0,
+            warnings: Vec::new(),
+            output_warnings: Vec::new(),
+            sources: Sources::default(),
+            check_jpeg: true,
+            trim_jpeg_trailing_bytes: options.trim_jpeg_trailing_bytes,
+            jpeg_invalid: 0,
+            progress: options.progress.clone(),
+            progress_stride: options
+                .progress_stride
+                .unwrap_or(DEFAULT_PROGRESS_STRIDE)
+                .max(1),
+            total_frames: entries.len(),
+            cancel: options.cancel.clone(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Reports the corrupt frames, for the pass writing the output
+    fn reporting(mut self) -> Self {
+        self.report = true;
+        self
+    }
+
+    /// Keeps the payloads of MJPEG frames as stored, for dumps
+    fn unchecked(mut self) -> Self {
+        self.check_jpeg = false;
+        self
+    }
+
+    /// Checks that an MJPEG frame starts with a JPEG start of image marker and
+    /// ends with an end of image marker, cutting off the bytes after the last
+    /// end marker if asked to. Returns the length the payload is kept at, None
+    /// if the frame is left out.
+    fn check_jpeg(&mut self, index: usize, offset: i64, payload_len: usize) -> Option<usize> {
+        let payload = &self.buf[..payload_len];
+        let problem = match bitstream::jpeg_image_len(payload) {
+            Some(image_len) if image_len == payload_len => return Some(payload_len),
+            Some(image_len) if self.trim_jpeg_trailing_bytes => {
+                let warning = format!(
+                    "Frame at offset {offset} has {} bytes after its JPEG end of image marker, \
+                     which were cut off",
+                    payload_len - image_len
+                );
+                if self.report {
+                    log::debug!("MJPEG frame {index}: {warning}");
+                }
+                self.warnings.push(warning);
+                return Some(image_len);
+            }
+            Some(image_len) => format!(
+                "{} bytes follow its JPEG end of image marker",
+                payload_len - image_len
+            ),
+            None if !payload.starts_with(&bitstream::JPEG_SOI) => {
+                "it does not start with a JPEG start of image marker".to_string()
+            }
+            None => "it has no JPEG end of image marker".to_string(),
+        };
+
+        self.jpeg_invalid += 1;
+        let warning = format!("Skipping MJPEG frame {index} at offset {offset}, {problem}");
+        if self.report {
+            log::debug!("{warning}");
+        }
+        self.output_warnings.push(warning);
+
+        None
+    }
+
+    /// Calls the progress callback in the pass writing the output, every
+    /// stride frames and after the last one
+    fn report_progress(&mut self, index: usize, entry: &RecordingIndexEntry) {
+        let Some(progress) = self.progress.as_ref().filter(|_| self.report) else {
+            return;
+        };
+        let frames_processed = index + 1;
+        if !frames_processed.is_multiple_of(self.progress_stride) && self.frames.remaining() > 0 {
+            return;
+        }
+
+        (progress.0)(Progress {
+            frames_processed,
+            total_frames: self.total_frames,
+            bytes_read: self.frames.position().unwrap_or(entry.offset() as u64),
+            receive_timestamp: entry.receive_timestamp(),
+        });
+    }
+
+    /// Notes the corrupt frames, a cut off last frame, the frames without a
+    /// video placement footer and the warnings of the frames in the summary
+    fn summarize(&self, summary: &mut ConversionSummary) {
+        summary.frames_corrupt = self.corrupt;
+        summary.truncated = self.truncated;
+        summary.placement_footers_missing = self.placement_footers_missing;
+        summary.jpeg_frames_invalid = self.jpeg_invalid;
+        summary.warnings = self.warnings.clone();
+        summary
+            .output_warnings
+            .extend(self.output_warnings.iter().cloned());
+    }
+}
+
+impl<R: RecordingReader> Frames<'_, R> {
+    /// Reads the next frame into the buffer of the iteration, without
+    /// allocating its payload, for passes that do not keep the frames.
+    fn next_ref(&mut self) -> Option<(usize, FrameRef<'_>)> {
+        let (index, frame, payload_len) = loop {
+            if is_cancelled(self.cancel.as_ref()) {
+                self.frames.stop();
+            }
+            let (index, entry, frame) = self.frames.next_into(&mut self.buf)?;
+            self.report_progress(index, entry);
+
+            let e = match frame {
+                Ok(frame) => {
+                    if frame.format.is_coded() && frame.placement_padding().is_none() {
+                        self.placement_footers_missing += 1;
+                    }
+                    let mixed_source = self.sources.check(frame.format, frame.metadata.id, entry);
+                    for warning in frame.warnings.iter().chain(&mixed_source) {
+                        if self.report {
+                            log::debug!("{:?} frame {index}: {warning}", frame.format);
+                        }
+                        self.warnings.push(warning.clone());
+                    }
+
+                    let payload_len =
+                        if self.check_jpeg && frame.format == VideoCaptureFormat::Mjpeg {
+                            match self.check_jpeg(index, entry.offset(), frame.payload_len()) {
+                                Some(payload_len) => payload_len,
+                                None => continue,
+                            }
+                        } else {
+                            frame.payload_len()
+                        };
+
+                    break (index, frame, payload_len);
+                }
+                Err(e) => e,
+            };
+
+            if e.is::<TruncatedFrame>() && self.frames.remaining() == 0 {
+                if self.report {
+                    log::warn!("{e}, the recording probably ended abruptly");
+                }
+                self.truncated = true;
+                return None;
+            }
+
+            self.corrupt += 1;
+            let warning = format!(
+                "{} frame {} at offset {}: {e}",
+                if self.stop_at_corrupt_frame {
+                    "Stopping at corrupt"
+                } else {
+                    "Skipping corrupt"
+                },
+                index,
+                entry.offset()
+            );
+            if self.report {
+                log::debug!("{warning}");
+            }
+            self.output_warnings.push(warning);
+
+            if self.stop_at_corrupt_frame {
+                self.frames.stop();
+            }
+        };
+
+        let mut frame = frame.into_frame_ref(&self.buf);
+        frame.raw_data = &frame.raw_data[..payload_len];
+        Some((index, frame))
+    }
+}
+
+/// The receive timestamps of the frames of a format, read without keeping
+/// their payloads.
+fn frame_timestamps(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Vec<i64> {
+    let mut frames = Frames::new(f, entries, options);
+    let mut timestamps = Vec::new();
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format == format {
+            timestamps.push(frame.receive_timestamp);
+        }
+    }
+
+    timestamps
+}
+
+fn default_container(format: VideoCaptureFormat) -> Container {
+    match format {
+        VideoCaptureFormat::Nv12
+        | VideoCaptureFormat::Yuv
+        | VideoCaptureFormat::Yuyv
+        | VideoCaptureFormat::Uyvy => Container::Y4m,
+        VideoCaptureFormat::Rgb
+        | VideoCaptureFormat::Bgr
+        | VideoCaptureFormat::Mono8
+        | VideoCaptureFormat::Mono16
+        | VideoCaptureFormat::Raw16 => Container::ImageSequence,
+        _ => Container::Mp4,
+    }
+}
+
+/// The extension of the output for a format written to a container. An empty
+/// extension means the output is a folder.
+fn output_extension(
+    format: VideoCaptureFormat,
+    container: Container,
+) -> Result<&'static str, VrawError> {
+    match (format, container) {
+        (VideoCaptureFormat::H264 | VideoCaptureFormat::H265, Container::Mp4) => Ok("mp4"),
+        (VideoCaptureFormat::Mjpeg, Container::Mp4) => Ok("mp4"),
+        (VideoCaptureFormat::H264, Container::ElementaryStream) => Ok("h264"),
+        (VideoCaptureFormat::H265, Container::ElementaryStream) => Ok("h265"),
+        (VideoCaptureFormat::Mjpeg, Container::ElementaryStream) => Ok("mjpeg"),
+        (VideoCaptureFormat::Mjpeg, Container::Avi) => Ok("avi"),
+        (VideoCaptureFormat::H265, Container::Matroska) => Ok("mkv"),
+        (
+            VideoCaptureFormat::Mjpeg
+            | VideoCaptureFormat::Rgb
+            | VideoCaptureFormat::Bgr
+            | VideoCaptureFormat::Mono8
+            | VideoCaptureFormat::Mono16
+            | VideoCaptureFormat::Raw16,
+            Container::ImageSequence,
+        ) => Ok(""),
+        (
+            VideoCaptureFormat::Nv12
+            | VideoCaptureFormat::Yuv
+            | VideoCaptureFormat::Yuyv
+            | VideoCaptureFormat::Uyvy,
+            Container::Y4m,
+        ) => Ok("y4m"),
+        (format, Container::Ffmpeg)
+            if ffmpeg_pixel_format(format, BayerPattern::default()).is_some() =>
+        {
+            Ok("mp4")
+        }
+        (format, container) => Err(unsupported_output(format, container)),
+    }
+}
+
+fn unsupported_output(format: VideoCaptureFormat, container: Container) -> VrawError {
+    VrawError::InvalidOptions(format!(
+        "{:?} output is not supported for {:?}",
+        container, format
+    ))
+}
+
+/// The output asked for, or else one named by [`derive_output_from_input`].
+fn output_or_derive(
+    output: Option<PathBuf>,
+    input: &Path,
+    extension: &str,
+    time: DateTime<Local>,
+    options: &ConvertOptions,
+) -> Result<PathBuf, VrawError> {
+    let output = match output {
+        Some(output) => output,
+        None => derive_output_from_input(input, extension, time)?,
+    };
+
+    if options.no_overwrite && output.exists() {
+        return Err(VrawError::InvalidOptions(format!(
+            "{} exists and is not overwritten",
+            output.display()
+        )));
+    }
+
+    Ok(output)
+}
+
+/// The index entries of the frames received between the start and the end of
+/// the options. Receive timestamps are relative to the start of the recording.
+fn frames_in_time_range(
+    entries: &[RecordingIndexEntry],
+    options: &ConvertOptions,
+) -> Result<Vec<RecordingIndexEntry>, VrawError> {
+    let nanos = |time: Duration| i64::try_from(time.as_nanos()).unwrap_or(i64::MAX);
+    let start = options.start.map_or(i64::MIN, nanos);
+    let end = options.end.map_or(i64::MAX, nanos);
+    if start > end {
+        return Err(VrawError::InvalidOptions(format!(
+            "the start {:?} is after the end {:?}",
+            options.start.unwrap_or_default(),
+            options.end.unwrap_or_default()
+        )));
+    }
+
+    let in_range: Vec<_> = entries
+        .iter()
+        .filter(|entry| (start..=end).contains(&entry.receive_timestamp()))
+        .cloned()
+        .collect();
+    if in_range.is_empty() && !entries.is_empty() {
+        let range = match (options.start, options.end) {
+            (Some(start), Some(end)) => format!("between {start:?} and {end:?}"),
+            (Some(start), None) => format!("from {start:?} on"),
+            // Without bounds every frame is in range
+            (None, end) => format!("up to {:?}", end.unwrap_or_default()),
+        };
+        return Err(VrawError::InvalidOptions(format!(
+            "no frame was received {range} after the recording started"
+        )));
+    }
+
+    Ok(in_range)
+}
+
+/// Name the output after the input without its .vraw or .raw extension and
+/// the given time, placed next to the folder containing the input, or in the
+/// current folder for inputs without one. Other extensions are kept, as they
+/// are part of names like cam1.2024-05-01. An empty extension names a folder.
+pub fn derive_output_from_input(
+    input: &Path,
+    extension: &str,
+    time: DateTime<Local>,
+) -> Result<PathBuf, VrawError> {
+    let file_name = input.file_name().ok_or_else(|| {
+        VrawError::InvalidOptions(format!(
+            "no output can be named after {}, which has no file name",
+            input.display()
+        ))
+    })?;
+    let mut output_file_name = match input.extension() {
+        Some(extension)
+            if extension.eq_ignore_ascii_case("vraw") || extension.eq_ignore_ascii_case("raw") =>
+        {
+            input.file_stem().unwrap_or(file_name)
+        }
+        _ => file_name,
+    }
+    .to_os_string();
+
+    output_file_name.push(format!("_{}", time.format("%Y-%m-%dT%H_%M_%S")));
+
+    if !extension.is_empty() {
+        output_file_name.push(format!(".{extension}"));
+    }
+
+    let output_dir = input.ancestors().nth(2).unwrap_or(Path::new(""));
+
+    Ok(output_dir.join(output_file_name))
+}
+
+fn extract_hevc_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let (kind, compatible_brand) = match options.hevc_sample_entry {
+        HevcSampleEntry::Hev1 => (b"hev1", "hev1"),
+        HevcSampleEntry::Hvc1 => (b"hvc1", "hvc1"),
+    };
+
+    let (media_config, sample_entry, colour) = match find_hevc_parameter_sets(f, entries, options) {
+        Ok((parameter_sets, _)) => {
+            let sps = bitstream::parse_hevc_sps(&parameter_sets.sps)
+                .ok_or_else(|| VrawError::InvalidVideo("failed to parse the HEVC SPS".into()))?;
+            let width = u16::try_from(sps.width)
+                .map_err(|_| VrawError::InvalidVideo("HEVC width out of range".into()))?;
+            let height = u16::try_from(sps.height)
+                .map_err(|_| VrawError::InvalidVideo("HEVC height out of range".into()))?;
+
+            let mut sample_entry = isobmff::visual_sample_entry(kind, width, height, "");
+            sample_entry.children.push(isobmff::Mp4Box::new(
+                b"hvcC",
+                bitstream::hevc_decoder_configuration_record(&parameter_sets, &sps),
+            ));
+
+            (
+                mp4::HevcConfig { width, height },
+                Some(sample_entry),
+                sps.colour,
+            )
+        }
+        // With hev1 the parameter sets are read from the samples, so the
+        // stream stays playable by most players
+        Err(e) if options.hevc_sample_entry == HevcSampleEntry::Hev1 => {
+            log::warn!("{e}, the decoder configuration is left empty");
+            (mp4::HevcConfig::default(), None, None)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let track = Mp4Track {
+        format: VideoCaptureFormat::H265,
+        compatible_brand,
+        media_config: MediaConfig::HevcConfig(media_config),
+        sample_entry,
+        colour,
+        sample_bytes: match options.hevc_sample_entry {
+            HevcSampleEntry::Hev1 => bitstream::annex_b_to_length_prefixed,
+            HevcSampleEntry::Hvc1 => |data| {
+                bitstream::annex_b_to_length_prefixed(&bitstream::strip_hevc_parameter_sets(data))
+            },
+        },
+        is_sync: bitstream::is_hevc_irap,
+    };
+
+    write_mp4(f, entries, writer, track, options)
+}
+
+/// Looks for the first HEVC frame carrying a VPS, SPS and PPS, which is not
+/// necessarily the first frame of streams using intra refresh. Returns the
+/// parameter sets and the timestamp of the first HEVC frame.
+fn find_hevc_parameter_sets(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    options: &ConvertOptions,
+) -> Result<(bitstream::HevcParameterSets, i64), VrawError> {
+    let mut first_timestamp = None;
+    let mut frames = Frames::new(f, entries, options);
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format != VideoCaptureFormat::H265 {
+            continue;
+        }
+
+        let first_timestamp =
+            *first_timestamp.get_or_insert(options.timestamp_source.timestamp(&frame));
+
+        if let Some(parameter_sets) = bitstream::find_hevc_parameter_sets(frame.raw_data) {
+            return Ok((parameter_sets, first_timestamp));
+        }
+    }
+
+    Err(VrawError::InvalidVideo(
+        "no HEVC parameter sets found in the recording".into(),
+    ))
+}
+
+/// The recording start the timestamp SEI NAL units are written with, None
+/// unless they are asked for.
+fn timestamp_sei_recording_start(
+    f: &mut VrawReader<impl RecordingReader>,
+    options: &ConvertOptions,
+) -> Result<Option<DateTime<Utc>>, VrawError> {
+    if !options.timestamp_sei {
+        return Ok(None);
+    }
+
+    f.recording_start()
+        .map(Some)
+        .map_err(VrawError::corrupt_header(
+            "the timestamp SEI needs the recording start time",
+        ))
+}
+
+/// Inserts an SEI NAL unit with the timestamps of a frame, as described by
+/// [`TIMESTAMP_SEI_UUID`], into its HEVC access unit.
+fn with_timestamp_sei(frame: &FrameRef, recording_start: DateTime<Utc>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(28);
+    data.extend_from_slice(&(recording_start.timestamp() as u64).to_be_bytes());
+    data.extend_from_slice(&recording_start.timestamp_subsec_nanos().to_be_bytes());
+    data.extend_from_slice(&frame.capture_timestamp.to_be_bytes());
+    data.extend_from_slice(&frame.receive_timestamp.to_be_bytes());
+
+    let sei = bitstream::hevc_user_data_sei(&TIMESTAMP_SEI_UUID, &data);
+    bitstream::insert_hevc_nal_before_slices(frame.raw_data, &sei)
+}
+
+/// Muxes the HEVC frames into an .mkv file, timed by their receive or capture
+/// timestamps in nanoseconds. The decoder configuration is taken from the first frame
+/// carrying a VPS, SPS and PPS.
+fn extract_hevc_to_mkv(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let timestamp_source = options.timestamp_source;
+    let (parameter_sets, first_timestamp) = find_hevc_parameter_sets(f, entries, options)?;
+    let sps = bitstream::parse_hevc_sps(&parameter_sets.sps)
+        .ok_or_else(|| VrawError::InvalidVideo("failed to parse the HEVC SPS".into()))?;
+
+    let track = matroska::VideoTrack {
+        codec_id: "V_MPEGH/ISO/HEVC",
+        codec_private: Some(bitstream::hevc_decoder_configuration_record(
+            &parameter_sets,
+            &sps,
+        )),
+        width: sps.width,
+        height: sps.height,
+    };
+
+    let sei_recording_start = timestamp_sei_recording_start(f, options)?;
+
+    let mut writer = matroska::MatroskaWriter::new(writer, &track)
+        .map_err(VrawError::mux("failed to write mkv header"))?;
+
+    let mut summary = ConversionSummary::default();
+    let mut last_timestamp = first_timestamp;
+    let mut last_duration = 0;
+    let mut frames_before_start = 0;
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != VideoCaptureFormat::H265 {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        let keyframe = bitstream::is_hevc_irap(frame.raw_data);
+        let timestamp = timestamp_source.timestamp(&frame);
+        if timestamp < first_timestamp {
+            frames_before_start += 1;
+        }
+        let data = match sei_recording_start {
+            Some(recording_start) => Cow::Owned(with_timestamp_sei(&frame, recording_start)),
+            None => Cow::Borrowed(frame.raw_data),
+        };
+
+        writer
+            .write_frame(
+                timestamp.saturating_sub(first_timestamp).max(0) as u64,
+                &bitstream::annex_b_to_length_prefixed(&data),
+                keyframe,
+            )
+            .map_err(VrawError::mux("failed to write frame"))?;
+
+        summary.frame_written(frame.receive_timestamp);
+        last_duration = timestamp.saturating_sub(last_timestamp).max(0) as u64;
+        last_timestamp = timestamp;
+    }
+    frames.summarize(&mut summary);
+
+    warn_frames_before_start(frames_before_start);
+
+    writer
+        .finish(last_duration)
+        .map_err(VrawError::mux("failed to finish mkv"))?;
+
+    Ok(summary)
+}
+
+fn extract_avc_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    // The avcC box needs the parameter sets, so look for the first frame carrying them
+    let mut parameter_sets = None;
+    let mut frames = Frames::new(f, entries, options);
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format != VideoCaptureFormat::H264 {
+            continue;
+        }
+
+        if let Some(sets) = bitstream::find_avc_parameter_sets(frame.raw_data) {
+            parameter_sets = Some(sets);
+            break;
+        }
+    }
+
+    let parameter_sets = parameter_sets
+        .ok_or_else(|| VrawError::InvalidVideo("unable to find H.264 SPS/PPS".into()))?;
+    let sps = bitstream::parse_avc_sps(&parameter_sets.sps)
+        .ok_or_else(|| VrawError::InvalidVideo("failed to parse the H.264 SPS".into()))?;
+    let width = u16::try_from(sps.width)
+        .map_err(|_| VrawError::InvalidVideo("H.264 width out of range".into()))?;
+    let height = u16::try_from(sps.height)
+        .map_err(|_| VrawError::InvalidVideo("H.264 height out of range".into()))?;
+
+    // The avcC box of the mp4 crate lacks the fields of the high profiles
+    let mut sample_entry = isobmff::visual_sample_entry(b"avc1", width, height, "");
+    sample_entry.children.push(isobmff::Mp4Box::new(
+        b"avcC",
+        bitstream::avc_decoder_configuration_record(&parameter_sets, &sps),
+    ));
+
+    let track = Mp4Track {
+        format: VideoCaptureFormat::H264,
+        compatible_brand: "avc1",
+        media_config: MediaConfig::AvcConfig(AvcConfig {
+            width,
+            height,
+            seq_param_set: parameter_sets.sps,
+            pic_param_set: parameter_sets.pps,
+        }),
+        sample_entry: Some(sample_entry),
+        colour: sps.colour,
+        sample_bytes: bitstream::annex_b_to_length_prefixed,
+        is_sync: bitstream::is_avc_idr,
+    };
+
+    write_mp4(f, entries, writer, track, options)
+}
+
+/// Muxes the MJPEG frames into an .avi file, with a frame rate estimated from
+/// the frame timestamps.
+fn extract_mjpeg_to_avi(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    first_frame: &FrameMetadata,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    // The frame rate is part of the header, which is written up front
+    let timestamps = frame_timestamps(f, entries, VideoCaptureFormat::Mjpeg, options);
+
+    let mut writer = AviWriter::new(
+        writer,
+        first_frame.width as u32,
+        first_frame.height as u32,
+        rawvideo::estimate_frame_rate(&timestamps),
+    )
+    .map_err(VrawError::mux("failed to write avi header"))?;
+
+    let mut summary = ConversionSummary::default();
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != VideoCaptureFormat::Mjpeg {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        writer
+            .write_frame(frame.raw_data)
+            .map_err(VrawError::mux("failed to write frame"))?;
+
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    writer
+        .finish()
+        .map_err(VrawError::mux("failed to finish avi"))?;
+
+    Ok(summary)
+}
+
+fn extract_mjpeg_to_mp4(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    width: i32,
+    height: i32,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let width = u16::try_from(width)
+        .map_err(|_| VrawError::InvalidVideo("MJPEG width out of range".into()))?;
+    let height = u16::try_from(height)
+        .map_err(|_| VrawError::InvalidVideo("MJPEG height out of range".into()))?;
+
+    // The mp4 crate has no motion JPEG sample entry, so the track is written
+    // as a video track and its sample description replaced
+    let track = Mp4Track {
+        format: VideoCaptureFormat::Mjpeg,
+        compatible_brand: "mp41",
+        media_config: MediaConfig::HevcConfig(mp4::HevcConfig { width, height }),
+        sample_entry: Some(isobmff::visual_sample_entry(
+            b"jpeg",
+            width,
+            height,
+            "Photo - JPEG",
+        )),
+        colour: None,
+        sample_bytes: <[u8]>::to_vec,
+        // every JPEG image stands on its own
+        is_sync: |_| true,
+    };
+
+    write_mp4(f, entries, writer, track, options)
+}
+
+/// Writes the frames of a format back to back as stored, which ffmpeg and
+/// friends can read as an elementary stream. Coded video frames are expected to
+/// be in Annex-B format, frames that do not start with a start code are written
+/// anyway but warned about. Writes cut short, as on network filesystems, are
+/// continued until the whole frame is written.
+pub(crate) fn write_elementary_stream(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: &mut impl Write,
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let mut summary = ConversionSummary::default();
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != format {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        if format != VideoCaptureFormat::Mjpeg && !has_annex_b_start_code(frame.raw_data) {
+            log::debug!(
+                "{:?} frame {} does not start with an Annex-B start code",
+                format,
+                index
+            );
+        }
+
+        writer
+            .write_all(frame.raw_data)
+            .map_err(VrawError::io(format!("failed to write frame {index}")))?;
+
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    Ok(summary)
+}
+
+/// Writes every frame as a separate image in the output folder, named after the
+/// input, the index of the frame in the recording and its timestamp. MJPEG
+/// frames are written as .jpg files as is, uncompressed frames as .png files.
+/// Uncompressed frames whose size does not match their dimensions are skipped.
+fn extract_image_sequence_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    input: &Path,
+    first_frame: &FrameMetadata,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let format = first_frame.format;
+
+    let output_dir = output;
+    fs::create_dir_all(output_dir).map_err(VrawError::io(format!(
+        "failed to create output folder {}",
+        output_dir.display()
+    )))?;
+
+    let prefix = input
+        .file_stem()
+        .map_or("frame".into(), |stem| stem.to_string_lossy());
+
+    let mut summary = ConversionSummary::default();
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != format {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        let extension = if format == VideoCaptureFormat::Mjpeg {
+            "jpg"
+        } else {
+            "png"
+        };
+        let file_name = format!(
+            "{}_{:06}_{}.{}",
+            prefix, index, frame.receive_timestamp, extension
+        );
+        let path = output_dir.join(&file_name);
+
+        match format {
+            VideoCaptureFormat::Mjpeg => fs::write(&path, frame.raw_data)
+                .map_err(VrawError::io(format!("failed to write {file_name}")))?,
+            _ => {
+                let width = frame.width as usize;
+                let height = frame.height as usize;
+                let bytes_per_pixel = match format {
+                    VideoCaptureFormat::Mono8 => 1,
+                    VideoCaptureFormat::Mono16 | VideoCaptureFormat::Raw16 => 2,
+                    _ => 3,
+                };
+
+                if frame.raw_data.len() != width * height * bytes_per_pixel {
+                    summary.leave_out_frame(format!(
+                        "Skipping frame {}, it has {} bytes but {} are expected for {:?} {}",
+                        index,
+                        frame.raw_data.len(),
+                        width * height * bytes_per_pixel,
+                        format,
+                        frame.resolution()
+                    ));
+                    continue;
+                }
+
+                let (color_type, bit_depth, pixels) = match format {
+                    VideoCaptureFormat::Rgb => (
+                        png::ColorType::Rgb,
+                        png::BitDepth::Eight,
+                        Cow::Borrowed(frame.raw_data),
+                    ),
+                    VideoCaptureFormat::Bgr => (
+                        png::ColorType::Rgb,
+                        png::BitDepth::Eight,
+                        Cow::Owned(rawvideo::bgr_to_rgb(frame.raw_data)),
+                    ),
+                    VideoCaptureFormat::Mono8 => (
+                        png::ColorType::Grayscale,
+                        png::BitDepth::Eight,
+                        Cow::Borrowed(frame.raw_data),
+                    ),
+                    VideoCaptureFormat::Mono16 => (
+                        png::ColorType::Grayscale,
+                        png::BitDepth::Sixteen,
+                        Cow::Owned(rawvideo::mono16_to_png_samples(
+                            frame.raw_data,
+                            options.auto_scale,
+                        )),
+                    ),
+                    VideoCaptureFormat::Raw16 => (
+                        png::ColorType::Rgb,
+                        png::BitDepth::Sixteen,
+                        Cow::Owned(
+                            rawvideo::demosaic_bilinear(
+                                frame.raw_data,
+                                width,
+                                height,
+                                options.bayer_pattern,
+                            )
+                            .iter()
+                            .flat_map(|sample| sample.to_be_bytes())
+                            .collect(),
+                        ),
+                    ),
+                    format => return Err(unsupported_output(format, Container::ImageSequence)),
+                };
+
+                rawvideo::write_png(&path, width, height, color_type, bit_depth, &pixels)
+                    .map_err(VrawError::mux(format!("failed to write {file_name}")))?;
+            }
+        }
+
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    Ok(summary)
+}
+
+/// Writes NV12, planar YUV 4:2:0 or packed YUYV/UYVY frames to a .y4m file, with
+/// a frame rate estimated from the frame timestamps. Packed 4:2:2 frames are
+/// converted to 4:2:0.
+fn extract_y4m_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    first_frame: &FrameMetadata,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let format = first_frame.format;
+    let width = first_frame.width as usize;
+    let height = first_frame.height as usize;
+    let frame_size = match format {
+        VideoCaptureFormat::Yuyv | VideoCaptureFormat::Uyvy => {
+            if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+                return Err(VrawError::InvalidVideo(format!(
+                    "{:?} frames must have an even width and height, got {}x{}",
+                    format, width, height
+                )));
+            }
+            width * height * 2
+        }
+        _ => rawvideo::yuv420_frame_size(width, height),
+    };
+
+    // The y4m header needs the frame rate up front
+    let timestamps = frame_timestamps(f, entries, format, options);
+
+    let mut writer = rawvideo::Y4mWriter::new(
+        writer,
+        width,
+        height,
+        rawvideo::estimate_frame_rate(&timestamps),
+        "420jpeg",
+    )
+    .map_err(VrawError::mux("failed to write y4m header"))?;
+
+    let mut summary = ConversionSummary::default();
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != format {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        if frame.raw_data.len() != frame_size
+            || frame.width != first_frame.width
+            || frame.height != first_frame.height
+        {
+            return Err(VrawError::BadFrame {
+                index,
+                offset: entries[index].offset(),
+                reason: format!(
+                    "Frame has {} bytes, expected {} for {:?} {}x{}",
+                    frame.raw_data.len(),
+                    frame_size,
+                    format,
+                    width,
+                    height
+                )
+                .into(),
+            });
+        }
+
+        let planes = match format {
+            VideoCaptureFormat::Nv12 => {
+                Cow::Owned(rawvideo::nv12_to_i420(frame.raw_data, width, height))
+            }
+            VideoCaptureFormat::Yuyv => Cow::Owned(rawvideo::packed422_to_i420(
+                frame.raw_data,
+                width,
+                height,
+                Packed422::Yuyv,
+            )),
+            VideoCaptureFormat::Uyvy => Cow::Owned(rawvideo::packed422_to_i420(
+                frame.raw_data,
+                width,
+                height,
+                Packed422::Uyvy,
+            )),
+            _ => Cow::Borrowed(frame.raw_data),
+        };
+
+        writer
+            .write_frame(&planes)
+            .map_err(VrawError::io("failed to write frame"))?;
+
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    writer
+        .into_inner()
+        .flush()
+        .map_err(VrawError::io("failed to write frame"))?;
+
+    Ok(summary)
+}
+
+/// The ffmpeg rawvideo pixel format matching an uncompressed format, None for
+/// formats ffmpeg can not read.
+fn ffmpeg_pixel_format(format: VideoCaptureFormat, pattern: BayerPattern) -> Option<&'static str> {
+    match format {
+        VideoCaptureFormat::Rgb => Some("rgb24"),
+        VideoCaptureFormat::Bgr => Some("bgr24"),
+        VideoCaptureFormat::Yuv => Some("yuv420p"),
+        VideoCaptureFormat::Nv12 => Some("nv12"),
+        VideoCaptureFormat::Yuyv => Some("yuyv422"),
+        VideoCaptureFormat::Uyvy => Some("uyvy422"),
+        VideoCaptureFormat::Mono8 => Some("gray"),
+        VideoCaptureFormat::Mono16 => Some("gray16le"),
+        VideoCaptureFormat::Raw16 => Some(match pattern {
+            BayerPattern::Rggb => "bayer_rggb16le",
+            BayerPattern::Bggr => "bayer_bggr16le",
+            BayerPattern::Grbg => "bayer_grbg16le",
+            BayerPattern::Gbrg => "bayer_gbrg16le",
+        }),
+        _ => None,
+    }
+}
+
+/// Size in bytes of an uncompressed frame.
+fn raw_frame_size(format: VideoCaptureFormat, width: usize, height: usize) -> usize {
+    match format {
+        VideoCaptureFormat::Yuv | VideoCaptureFormat::Nv12 => {
+            rawvideo::yuv420_frame_size(width, height)
+        }
+        VideoCaptureFormat::Mono8 => width * height,
+        VideoCaptureFormat::Yuyv
+        | VideoCaptureFormat::Uyvy
+        | VideoCaptureFormat::Mono16
+        | VideoCaptureFormat::Raw16 => width * height * 2,
+        _ => width * height * 3,
+    }
+}
+
+/// Encodes uncompressed frames to H.264 in an .mp4 file by streaming them to
+/// an ffmpeg subprocess, with a frame rate estimated from the frame timestamps.
+/// Frames whose size does not match the first frame are skipped.
+fn extract_ffmpeg_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    first_frame: &FrameMetadata,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let format = first_frame.format;
+    let pixel_format = ffmpeg_pixel_format(format, options.bayer_pattern).ok_or_else(|| {
+        VrawError::InvalidOptions(format!("{:?} can not be encoded with ffmpeg", format))
+    })?;
+    let frame_size = raw_frame_size(
+        format,
+        first_frame.width as usize,
+        first_frame.height as usize,
+    );
+    let resolution = format!("{}x{}", first_frame.width, first_frame.height);
+
+    // Fail early with a helpful message instead of a broken pipe
+    match Command::new(FFMPEG).arg("-version").output() {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(VrawError::io(
+                "ffmpeg not found, install it and make sure it is in the PATH",
+            )(e))
+        }
+        Err(e) => return Err(VrawError::io("failed to run ffmpeg")(e)),
+    }
+
+    let timestamps = frame_timestamps(f, entries, format, options);
+    let (rate_num, rate_den) = rawvideo::estimate_frame_rate(&timestamps);
+
+    let mut child = Command::new(FFMPEG)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", pixel_format])
+        .args(["-s", &resolution])
+        .args(["-r", &format!("{}/{}", rate_num, rate_den)])
+        .args(["-i", "-"])
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(VrawError::io("failed to start ffmpeg"))?;
+
+    let mut stdin = child.stdin.take().expect("ffmpeg stdin is piped");
+    let mut summary = ConversionSummary::default();
+    let mut write_error = None;
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != format {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        if frame.raw_data.len() != frame_size {
+            summary.leave_out_frame(format!(
+                "Skipping frame {}, it has {} bytes but {} are expected for {:?} {}",
+                index,
+                frame.raw_data.len(),
+                frame_size,
+                format,
+                resolution
+            ));
+            continue;
+        }
+
+        // A failed write means ffmpeg quit, its exit status tells why
+        if let Err(e) = stdin.write_all(frame.raw_data) {
+            write_error = Some(e);
+            break;
+        }
+
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    // Closing stdin signals the end of the stream
+    drop(stdin);
+
+    let status = child
+        .wait()
+        .map_err(VrawError::io("failed to wait for ffmpeg"))?;
+
+    if !status.success() {
+        return Err(VrawError::Ffmpeg(status));
+    }
+
+    if let Some(e) = write_error {
+        return Err(VrawError::io("failed to write frame to ffmpeg")(e));
+    }
+
+    Ok(summary)
+}
+
+/// Writes the payloads of all frames except Stats frames back to back, and a
+/// [`DumpDescriptor`] locating them to a .json file next to the output.
+fn extract_dump_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let dst_file = File::create(output).map_err(VrawError::io("file creation failed"))?;
+    let mut writer = BufWriter::new(dst_file);
+    let mut descriptor = DumpDescriptor { frames: Vec::new() };
+    let mut offset = 0;
+    let mut summary = ConversionSummary::default();
+
+    let mut frames = Frames::new(f, entries, options).reporting().unchecked();
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format == VideoCaptureFormat::Stats {
+            continue;
+        }
+
+        writer
+            .write_all(frame.raw_data)
+            .map_err(VrawError::io("failed to write frame"))?;
+
+        descriptor.frames.push(DumpedFrame {
+            id: frame.id,
+            format: frame.format,
+            width: frame.width,
+            height: frame.height,
+            offset,
+            size: frame.raw_data.len(),
+            timestamp: frame.capture_timestamp,
+            receive_timestamp: frame.receive_timestamp,
+            placement_metadata: frame
+                .placement_metadata
+                .map(|metadata| BASE64.encode(metadata)),
+        });
+
+        offset += frame.raw_data.len() as u64;
+        summary.frame_written(frame.receive_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    writer
+        .flush()
+        .map_err(VrawError::io("failed to write frame"))?;
+
+    let descriptor_file = File::create(Path::new(output).with_extension("json"))
+        .map_err(VrawError::io("file creation failed"))?;
+    let mut writer = BufWriter::new(descriptor_file);
+
+    serde_json::to_writer_pretty(&mut writer, &descriptor)
+        .map_err(VrawError::mux("failed to write dump descriptor"))?;
+    writer
+        .flush()
+        .map_err(VrawError::io("failed to write dump descriptor"))?;
+
+    Ok(summary)
+}
+
+/// Writes every Stats frame as a JSON array of [`StatsRecord`]s.
+fn extract_stats_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    options: &ConvertOptions,
+) -> Result<usize, VrawError> {
+    let mut records = Vec::new();
+
+    let mut frames = Frames::new(f, entries, options);
+
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format != VideoCaptureFormat::Stats {
+            continue;
+        }
+
+        let (encoding, payload) = match String::from_utf8(frame.raw_data.to_vec()) {
+            Ok(text) => ("utf8", text),
+            Err(e) => ("base64", BASE64.encode(e.into_bytes())),
+        };
+
+        records.push(StatsRecord {
+            timestamp: frame.capture_timestamp,
+            receive_timestamp: frame.receive_timestamp,
+            encoding,
+            payload,
+        });
+    }
+
+    let dst_file = File::create(output).map_err(VrawError::io("file creation failed"))?;
+    let mut writer = BufWriter::new(dst_file);
+
+    serde_json::to_writer_pretty(&mut writer, &records)
+        .map_err(VrawError::mux("failed to write stats"))?;
+    writer
+        .flush()
+        .map_err(VrawError::io("failed to write stats"))?;
+
+    Ok(records.len())
+}
+
+/// Writes an SRT subtitle cue for every frame of a format, lasting until the
+/// next frame and showing the wall clock time the frame was received at.
+/// Returns the number of cues written.
+fn extract_subtitles_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Result<usize, VrawError> {
+    let recording_start = f.recording_start().map_err(VrawError::corrupt_header(
+        "failed to read the recording start time",
+    ))?;
+
+    let timestamps = frame_timestamps(f, entries, format, options);
+
+    let dst_file = File::create(output).map_err(VrawError::io("file creation failed"))?;
+    let mut writer = BufWriter::new(dst_file);
+
+    write_subtitles(&mut writer, recording_start, &timestamps)
+        .and_then(|_| writer.flush())
+        .map_err(VrawError::io("failed to write subtitles"))?;
+
+    Ok(timestamps.len())
+}
+
+/// Writes a [`GenericMetadataRecord`] line for every frame of a format, frames
+/// without generic metadata included with an empty payload. Returns the number
+/// of lines written.
+fn extract_generic_metadata_from_vraw(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    output: &Path,
+    format: VideoCaptureFormat,
+    options: &ConvertOptions,
+) -> Result<usize, VrawError> {
+    let dst_file = File::create(output).map_err(VrawError::io("file creation failed"))?;
+    let mut writer = BufWriter::new(dst_file);
+
+    let mut frames_written = 0;
+    let mut frames = Frames::new(f, entries, options);
+    while let Some((_, frame)) = frames.next_ref() {
+        if frame.format != format {
+            continue;
+        }
+
+        let record = GenericMetadataRecord {
+            frame: frames_written,
+            timestamp: frame.capture_timestamp,
+            receive_timestamp: frame.receive_timestamp,
+            payload: BASE64.encode(&frame.generic_metadata),
+        };
+        serde_json::to_writer(&mut writer, &record)
+            .map_err(VrawError::mux("failed to write generic metadata"))?;
+        writeln!(writer).map_err(VrawError::io("failed to write generic metadata"))?;
+
+        frames_written += 1;
+    }
+
+    writer
+        .flush()
+        .map_err(VrawError::io("failed to write generic metadata"))?;
+
+    Ok(frames_written)
+}
+
+/// Writes SRT cues for frames with the given timestamps, relative to the start
+/// of the recording. The cues start at the first frame, the last cue lasts as
+/// long as the one before it.
+fn write_subtitles<W: Write>(
+    writer: &mut W,
+    recording_start: DateTime<Utc>,
+    timestamps: &[i64],
+) -> io::Result<()> {
+    let first_timestamp = timestamps.first().copied().unwrap_or_default();
+
+    for (index, &timestamp) in timestamps.iter().enumerate() {
+        let end_timestamp = match timestamps.get(index + 1) {
+            Some(&next) => next,
+            None if index > 0 => timestamp + (timestamp - timestamps[index - 1]),
+            None => timestamp,
+        };
+        let wall_clock = recording_start + chrono::Duration::nanoseconds(timestamp);
+
+        writeln!(writer, "{}", index + 1)?;
+        writeln!(
+            writer,
+            "{} --> {}",
+            srt_time(timestamp - first_timestamp),
+            srt_time(end_timestamp - first_timestamp)
+        )?;
+        writeln!(writer, "{}", wall_clock.format("%Y-%m-%d %H:%M:%S%.3f UTC"))?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Formats nanoseconds as an SRT time, HH:MM:SS,mmm.
+fn srt_time(nanos: i64) -> String {
+    let millis = nanos.max(0) / 1_000_000;
+
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn has_annex_b_start_code(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1])
+}
+
+/// Warns about frames with a timestamp before the first frame, as recorded by
+/// devices whose clock was set while recording. The tracks start at the first
+/// frame, so they are timed right after the frame before them instead.
+fn warn_frames_before_start(frames_before_start: usize) {
+    if frames_before_start > 0 {
+        log::warn!(
+            "{} frames have a timestamp before the first frame, they are timed right after the \
+             frame before them",
+            frames_before_start
+        );
+    }
+}
+
+/// Converts nanoseconds to ticks of a timescale, rounded to the nearest tick.
+fn nanos_to_timescale(nanos: i64, timescale: u32) -> i64 {
+    let ticks = (nanos as i128 * timescale as i128 * 2 + 1_000_000_000).div_euclid(2_000_000_000);
+    ticks.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Duration in ticks of a timescale of a frame starting at the given position
+/// in ticks and lasting until the next timestamp. The end is rounded relative
+/// to the first frame, so the rounding errors of consecutive frames cancel out
+/// instead of adding up. Every frame lasts at least one tick, frames received
+/// at the same time as the one before them borrow it from the following ones.
+pub fn sample_duration(
+    position: i64,
+    first_timestamp: i64,
+    next_timestamp: i64,
+    timescale: u32,
+) -> u32 {
+    let end = nanos_to_timescale(next_timestamp.saturating_sub(first_timestamp), timescale);
+
+    (end - position).clamp(1, u32::MAX as i64) as u32
+}
+
+/// Repairs the timestamps of frames with a timestamp before that of a frame
+/// before them. Returns the timestamp every frame is timed by, None for frames
+/// left out. Clamped frames keep their timestamp, [`sample_duration`] lets them
+/// last one tick. Interpolated frames are spaced evenly between the latest
+/// timestamp before them and the next frame after it, or clamped when no such
+/// frame follows.
+pub fn repair_timestamps(timestamps: &[i64], policy: TimestampRegressions) -> Vec<Option<i64>> {
+    let mut repaired = Vec::with_capacity(timestamps.len());
+    let mut latest = i64::MIN;
+    let mut i = 0;
+
+    while let Some(&timestamp) = timestamps.get(i) {
+        if timestamp >= latest {
+            repaired.push(Some(timestamp));
+            latest = timestamp;
+            i += 1;
+            continue;
+        }
+
+        let end = (i..timestamps.len())
+            .find(|&j| timestamps[j] >= latest)
+            .unwrap_or(timestamps.len());
+        let regressed = &timestamps[i..end];
+
+        match (policy, timestamps.get(end)) {
+            (TimestampRegressions::Drop, _) => repaired.extend(regressed.iter().map(|_| None)),
+            (TimestampRegressions::Interpolate, Some(&next)) => {
+                let steps = regressed.len() as i128 + 1;
+                repaired.extend((1..steps).map(|step| {
+                    Some(latest + ((next as i128 - latest as i128) * step / steps) as i64)
+                }));
+            }
+            _ => repaired.extend(regressed.iter().map(|&timestamp| Some(timestamp))),
+        }
+        i = end;
+    }
+
+    repaired
+}
+
+/// Composition time offsets, in nanoseconds, of frames in decode order with the
+/// given presentation timestamps. Every frame is decoded at the time the frame
+/// in its position of the presentation order is presented, shifted so that no
+/// offset is negative.
+pub fn composition_offsets(presentation_timestamps: &[i64]) -> Vec<i64> {
+    let mut decode_timestamps = presentation_timestamps.to_vec();
+    decode_timestamps.sort_unstable();
+
+    let offsets: Vec<i64> = presentation_timestamps
+        .iter()
+        .zip(&decode_timestamps)
+        .map(|(presentation, decode)| presentation - decode)
+        .collect();
+    let shift = offsets.iter().min().map_or(0, |min| -min.min(&0));
+
+    offsets.into_iter().map(|offset| offset + shift).collect()
+}
+
+/// Gaps between frames with the given receive timestamps, where the delta to
+/// the previous frame exceeds the threshold times the median delta.
+pub fn find_gaps(timestamps: &[i64], threshold: f64) -> Vec<Gap> {
+    let mut deltas: Vec<i64> = timestamps
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect();
+    if deltas.is_empty() {
+        return Vec::new();
+    }
+    deltas.sort_unstable();
+    let limit = deltas[deltas.len() / 2] as f64 * threshold;
+
+    timestamps
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| (pair[1] - pair[0]) as f64 > limit)
+        .map(|(index, pair)| Gap {
+            frame: index + 1,
+            position: pair[1] - timestamps[0],
+            duration: pair[1] - pair[0],
+        })
+        .collect()
+}
+
+/// The video track of an .mp4 file written by [`write_mp4`].
+struct Mp4Track {
+    format: VideoCaptureFormat,
+    compatible_brand: &'static str,
+    media_config: MediaConfig,
+    /// Replaces the sample entry written by the mp4 crate, for codecs and
+    /// configurations the crate has no support for
+    sample_entry: Option<isobmff::Mp4Box>,
+    /// The colour description of the stream, written to the sample entry
+    colour: Option<bitstream::ColourDescription>,
+    /// Converts the payload of a frame to the bytes of its sample
+    sample_bytes: fn(&[u8]) -> Vec<u8>,
+    /// Whether decoding can start at a frame
+    is_sync: fn(&[u8]) -> bool,
+}
+
+fn write_mp4(
+    f: &mut VrawReader<impl RecordingReader>,
+    entries: &[RecordingIndexEntry],
+    writer: impl Write + Seek,
+    track: Mp4Track,
+    options: &ConvertOptions,
+) -> Result<ConversionSummary, VrawError> {
+    let format = track.format;
+    let timescale = options.timescale.unwrap_or(DEFAULT_MP4_TIMESCALE);
+    if timescale == 0 {
+        return Err(VrawError::InvalidOptions(
+            "the mp4 timescale must not be zero".into(),
+        ));
+    }
+
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse(track.compatible_brand).unwrap()],
+        timescale,
+    };
+
+    let gap_threshold = options.gap_threshold.unwrap_or(DEFAULT_GAP_THRESHOLD);
+
+    // Composition offsets, chapters and repairing timestamp regressions need
+    // the timestamps of all frames up front, the moov of fragmented files is
+    // written first
+    let mut timestamps = Vec::new();
+    let mut capture_timestamps = Vec::new();
+    if options.composition_offsets
+        || options.chapters
+        || options.timestamp_regressions != TimestampRegressions::Clamp
+    {
+        let mut frames = Frames::new(f, entries, options);
+        while let Some((_, frame)) = frames.next_ref() {
+            if frame.format != format {
+                continue;
+            }
+
+            timestamps.push(options.timestamp_source.timestamp(&frame));
+            capture_timestamps.push(frame.capture_timestamp);
+        }
+    }
+
+    let repaired_timestamps = repair_timestamps(&timestamps, options.timestamp_regressions);
+
+    // The frames are decoded in the order they were received, and presented
+    // in the order they were captured
+    let rendering_offsets = if options.composition_offsets {
+        composition_offsets(&capture_timestamps)
+    } else {
+        Vec::new()
+    };
+
+    // Presentation starts at the earliest composition time, which is after
+    // zero when the first decoded frame is not the first presented one
+    let presentation_start = timestamps
+        .iter()
+        .zip(&rendering_offsets)
+        .map(|(timestamp, offset)| {
+            nanos_to_timescale(timestamp.saturating_sub(timestamps[0]), timescale)
+                + nanos_to_timescale(*offset, timescale)
+        })
+        .min()
+        .unwrap_or(0);
+
+    let chapters = if options.chapters {
+        let mut chapters = vec![(0, "Start".to_string())];
+        chapters.extend(find_gaps(&timestamps, gap_threshold).iter().map(|gap| {
+            (
+                gap.position,
+                format!("Gap of {:.3} s", gap.duration as f64 / 1e9),
+            )
+        }));
+        chapters
+    } else {
+        Vec::new()
+    };
+
+    // The mp4 crate writes neither creation times, custom sample entries,
+    // rotations, edit lists nor chapters, so they are patched into the moov
+    if options.timestamp_sei && format != VideoCaptureFormat::H265 {
+        return Err(VrawError::InvalidOptions(
+            "timestamp SEI NAL units are only supported for HEVC".into(),
+        ));
+    }
+    let sei_recording_start = timestamp_sei_recording_start(f, options)?;
+
+    let recording_start = match f.recording_start() {
+        Ok(recording_start) => Some(recording_start.timestamp().max(0) as u64),
+        Err(e) => {
+            log::warn!("failed to read the recording start time: {e}");
+            None
+        }
+    };
+    let mut sample_entry = track.sample_entry;
+    if let (Some(sample_entry), Some(colour)) = (
+        &mut sample_entry,
+        track
+            .colour
+            .or(options.colour_space.map(ColourSpace::description)),
+    ) {
+        sample_entry
+            .children
+            .push(isobmff::colour_information(&colour));
+    }
+    let rotation = options.rotation;
+    let mut patch = Some(move |moov: &mut isobmff::Mp4Box| {
+        if let Some(sample_entry) = sample_entry {
+            isobmff::replace_sample_entry(moov, sample_entry)?;
+        }
+        if let Some(recording_start) = recording_start {
+            isobmff::set_creation_time(moov, recording_start)?;
+        }
+        if rotation != Rotation::None {
+            isobmff::set_rotation(moov, rotation.degrees())?;
+        }
+        if presentation_start > 0 {
+            isobmff::set_edit_list(moov, presentation_start as u64)?;
+        }
+        if !chapters.is_empty() {
+            isobmff::set_chapters(moov, &chapters)?;
+        }
+        Ok(())
+    });
+
+    let track_config = TrackConfig {
+        timescale,
+        ..TrackConfig::from(track.media_config)
+    };
+
+    let mut mp4_writer = match options.fragment_duration {
+        None => {
+            let writer = isobmff::MoovWriter::new(writer)
+                .map_err(VrawError::io("failed to start writing mp4"))?;
+            let mut mp4_writer = Mp4Writer::write_start(writer, &config)
+                .map_err(VrawError::mux("failed to start writing mp4"))?;
+            mp4_writer
+                .add_track(&track_config)
+                .map_err(VrawError::mux("failed to add mp4 track"))?;
+
+            Mp4Output::Regular(mp4_writer, 0)
+        }
+        Some(fragment_duration) => {
+            let init_segment = fmp4::init_segment(&config, &track_config, patch.take().unwrap())
+                .map_err(VrawError::mux("failed to write the init segment"))?;
+            let fragment_duration = nanos_to_timescale(
+                i64::try_from(fragment_duration.as_nanos()).unwrap_or(i64::MAX),
+                timescale,
+            ) as u64;
+            let mp4_writer =
+                fmp4::FragmentedMp4Writer::new(writer, &init_segment, fragment_duration)
+                    .map_err(VrawError::mux("failed to start writing mp4"))?;
+
+            Mp4Output::Fragmented(mp4_writer)
+        }
+    };
+
+    let mut summary = ConversionSummary::default();
+
+    // Every frame lasts until the next frame is received, so its sample is
+    // written once the next frame is known
+    let mut pending_sample: Option<(Mp4Sample, i64)> = None;
+    let mut last_duration = 0;
+    let mut position = 0;
+    let mut first_timestamp = None;
+    let mut written_timestamps = Vec::new();
+    let mut last_capture_timestamp = i64::MIN;
+    let mut frames_out_of_order = 0;
+    let mut frames_before_start = 0;
+    let mut track_position = 0;
+    let mut latest_timestamp = i64::MIN;
+
+    let mut frames = Frames::new(f, entries, options).reporting();
+    while let Some((index, frame)) = frames.next_ref() {
+        if frame.format != format {
+            summary.skip_frame(index, frame.format);
+            continue;
+        }
+
+        let timestamp = options.timestamp_source.timestamp(&frame);
+        if timestamp < latest_timestamp {
+            summary.timestamp_regressions += 1;
+        }
+        latest_timestamp = latest_timestamp.max(timestamp);
+
+        let repaired_timestamp = repaired_timestamps.get(track_position);
+        let rendering_offset = rendering_offsets
+            .get(track_position)
+            .map_or(0, |offset| nanos_to_timescale(*offset, timescale) as i32);
+        track_position += 1;
+        let repaired_timestamp = match repaired_timestamp {
+            Some(None) => continue,
+            Some(Some(repaired_timestamp)) => *repaired_timestamp,
+            None => timestamp,
+        };
+
+        if frame.capture_timestamp < last_capture_timestamp {
+            frames_out_of_order += 1;
+        }
+        last_capture_timestamp = last_capture_timestamp.max(frame.capture_timestamp);
+
+        // The track starts at the first frame
+        let first_timestamp = *first_timestamp.get_or_insert(repaired_timestamp);
+        if repaired_timestamp < first_timestamp {
+            frames_before_start += 1;
+        }
+
+        if let Some((mut sample, pending_timestamp)) = pending_sample.take() {
+            if timestamp == pending_timestamp {
+                summary.duplicate_timestamps += 1;
+            }
+
+            last_duration =
+                sample_duration(position, first_timestamp, repaired_timestamp, timescale);
+            sample.duration = last_duration;
+            mp4_writer.write_sample(&sample)?;
+            position += last_duration as i64;
+        }
+
+        let is_sync = (track.is_sync)(frame.raw_data);
+        let data = match sei_recording_start {
+            Some(recording_start) => Cow::Owned(with_timestamp_sei(&frame, recording_start)),
+            None => Cow::Borrowed(frame.raw_data),
+        };
+        let video_sample = Mp4Sample {
+            start_time: position as u64,
+            duration: 0,
+            rendering_offset,
+            is_sync,
+            bytes: mp4::Bytes::from((track.sample_bytes)(&data)),
+        };
+        pending_sample = Some((video_sample, timestamp));
+
+        summary.frame_written(frame.receive_timestamp);
+        written_timestamps.push(repaired_timestamp);
+    }
+    frames.summarize(&mut summary);
+
+    // The last frame lasts as long as the one before it, a single frame one
+    // tick like every other frame
+    if let Some((mut sample, _)) = pending_sample {
+        sample.duration = last_duration.max(1);
+        mp4_writer.write_sample(&sample)?;
+    }
+
+    if frames_out_of_order > 0 && !options.composition_offsets {
+        log::warn!(
+            "{} frames were captured before a frame received earlier, the recording probably has \
+             B-frames and is presented in the wrong order without composition offsets",
+            frames_out_of_order
+        );
+    }
+
+    warn_frames_before_start(frames_before_start);
+
+    // The init segment of fragmented files is patched already
+    mp4_writer.finish(patch)?;
+    summary.gaps = find_gaps(&written_timestamps, gap_threshold);
+
+    Ok(summary)
+}
+
+/// Largest mdat box of regular mp4 files, the mp4 crate writes its size in 32
+/// bits. Fragments have an mdat box each and are not limited.
+const MAX_MDAT_SIZE: u64 = u32::MAX as u64;
+
+/// The writer of a regular or a fragmented mp4 file.
+enum Mp4Output<W: Write + Seek> {
+    /// A regular mp4 writer and the size of its mdat box so far
+    Regular(Mp4Writer<isobmff::MoovWriter<W>>, u64),
+    Fragmented(fmp4::FragmentedMp4Writer<W>),
+}
+
+impl<W: Write + Seek> Mp4Output<W> {
+    fn write_sample(&mut self, sample: &Mp4Sample) -> Result<(), VrawError> {
+        match self {
+            Mp4Output::Regular(mp4_writer, mdat_size) => {
+                // Fail before writing gigabytes that end up in a file without
+                // a moov box
+                *mdat_size += sample.bytes.len() as u64;
+                if *mdat_size + 8 > MAX_MDAT_SIZE {
+                    return Err(VrawError::InvalidOptions(
+                        "the recording exceeds the 4 GB of media data regular mp4 files are \
+                         written with, use --fragment-duration to write a fragmented mp4 instead"
+                            .into(),
+                    ));
+                }
+
+                mp4_writer
+                    .write_sample(1, sample)
+                    .map_err(VrawError::mux("failed to write sample"))
+            }
+            Mp4Output::Fragmented(mp4_writer) => mp4_writer
+                .write_sample(sample)
+                .map_err(VrawError::mux("failed to write sample")),
+        }
+    }
+
+    /// Ends the file, patching the moov box of regular files.
+    fn finish<F>(self, patch: Option<F>) -> Result<(), VrawError>
+    where
+        F: FnOnce(&mut isobmff::Mp4Box) -> Result<(), String>,
+    {
+        let mut writer = match self {
+            Mp4Output::Regular(mut mp4_writer, _) => {
+                mp4_writer
+                    .write_end()
+                    .map_err(VrawError::mux("failed to end mp4 writing"))?;
+                mp4_writer
+                    .into_writer()
+                    .finish(|moov| patch.map_or(Ok(()), |patch| patch(moov)))
+                    .map_err(VrawError::mux("failed to patch the moov box"))?
+            }
+            Mp4Output::Fragmented(mp4_writer) => mp4_writer
+                .finish()
+                .map_err(VrawError::mux("failed to end mp4 writing"))?,
+        };
+
+        writer
+            .flush()
+            .map_err(VrawError::io("failed to end mp4 writing"))
+    }
 }
@@ -0,0 +1,297 @@
+//! Python bindings, built with the python feature by maturin, see
+//! pyproject.toml. The module is named vraw_convert like the crate.
+//!
+//! ```python
+//! import vraw_convert
+//!
+//! reader = vraw_convert.VrawReader.open("recording.vraw")
+//! for frame in reader:
+//!     if frame.format == "Stats":
+//!         print(frame.receive_timestamp, frame.data)
+//! ```
+
+use crate::error::VrawError;
+use crate::parser::{FrameRef, Strictness};
+use crate::probe::{probe_with_options, ProbeOptions};
+use crate::processing::{convert_vraw_with_options, Container, ConvertOptions};
+use crate::reader;
+use clap::ValueEnum;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use std::path::PathBuf;
+use std::time::Duration;
+
+create_exception!(
+    vraw_convert,
+    Error,
+    PyException,
+    "A recording that cannot be read or converted, or options that do not apply to it."
+);
+
+fn py_err(e: VrawError) -> PyErr {
+    Error::new_err(e.to_string())
+}
+
+/// A frame of a recording, see [`FrameInfo`](crate::FrameInfo). Timestamps
+/// are in nanoseconds relative to the start of the recording.
+#[pyclass(name = "Frame", module = "vraw_convert", frozen, get_all)]
+struct Frame {
+    /// Position of the frame in the index
+    index: usize,
+    /// Id of the source stream, the camera, the frame belongs to
+    id: i32,
+    width: i32,
+    height: i32,
+    /// Name of the format, e.g. "H264" or "Stats"
+    format: String,
+    /// The payload, without the video placement metadata after it
+    data: Py<PyBytes>,
+    /// Size of the payload as stored, including the video placement metadata
+    size: usize,
+    receive_timestamp: i64,
+    capture_timestamp: i64,
+    placement_metadata: Option<Py<PyBytes>>,
+    warnings: Vec<String>,
+    generic_metadata: Py<PyBytes>,
+}
+
+impl Frame {
+    /// Copies the frame out of the buffer it was read into, once, into the
+    /// bytes objects of Python.
+    fn new(py: Python<'_>, index: usize, frame: FrameRef<'_>) -> Self {
+        Self {
+            index,
+            id: frame.id,
+            width: frame.width,
+            height: frame.height,
+            format: format!("{:?}", frame.format),
+            data: PyBytes::new(py, frame.raw_data).unbind(),
+            size: frame.size,
+            receive_timestamp: frame.receive_timestamp,
+            capture_timestamp: frame.capture_timestamp,
+            placement_metadata: frame
+                .placement_metadata
+                .map(|metadata| PyBytes::new(py, metadata).unbind()),
+            warnings: frame.warnings,
+            generic_metadata: PyBytes::new(py, &frame.generic_metadata).unbind(),
+        }
+    }
+}
+
+#[pymethods]
+impl Frame {
+    fn __repr__(&self) -> String {
+        format!(
+            "Frame(index={}, format={}, receive_timestamp={})",
+            self.index, self.format, self.receive_timestamp
+        )
+    }
+}
+
+/// A recording opened to read its frames, see [`reader::VrawReader`].
+#[pyclass(name = "VrawReader", module = "vraw_convert")]
+struct VrawReader {
+    reader: reader::VrawReader,
+    /// The payload of the last frame read, reused for every frame
+    buf: Vec<u8>,
+}
+
+#[pymethods]
+impl VrawReader {
+    #[staticmethod]
+    fn open(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let reader = py
+            .allow_threads(|| reader::VrawReader::open(path))
+            .map_err(py_err)?;
+
+        Ok(Self {
+            reader,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Number of frames listed by the index, Stats frames included.
+    fn frame_count(&self) -> usize {
+        self.reader.frame_count()
+    }
+
+    fn __len__(&self) -> usize {
+        self.reader.frame_count()
+    }
+
+    /// Reads the frame at a position in the index.
+    fn frame(&mut self, py: Python<'_>, index: usize) -> PyResult<Frame> {
+        let frame = self
+            .reader
+            .read_frame_into(&mut self.buf, index)
+            .map_err(py_err)?;
+
+        Ok(Frame::new(py, index, frame))
+    }
+
+    /// Iterates over the frames from the first on, with a reader of its own
+    /// so iterations do not disturb each other.
+    fn __iter__(&self) -> PyResult<FrameIterator> {
+        Ok(FrameIterator {
+            reader: self.reader.try_clone().map_err(py_err)?,
+            next: 0,
+            buf: Vec::new(),
+        })
+    }
+}
+
+/// Iterator over the frames of a [`VrawReader`]. A frame that cannot be read
+/// raises [`Error`], the iteration goes on with the next frame.
+#[pyclass(module = "vraw_convert")]
+struct FrameIterator {
+    reader: reader::VrawReader,
+    next: usize,
+    buf: Vec<u8>,
+}
+
+#[pymethods]
+impl FrameIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Frame>> {
+        let index = self.next;
+        if index >= self.reader.frame_count() {
+            return Ok(None);
+        }
+        self.next += 1;
+
+        let frame = self
+            .reader
+            .read_frame_into(&mut self.buf, index)
+            .map_err(py_err)?;
+
+        Ok(Some(Frame::new(py, index, frame)))
+    }
+}
+
+/// Converts a value serialized by serde to the Python value of its JSON.
+fn to_python<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        serde_json::Value::Null => py.None().into_bound(py),
+        serde_json::Value::Bool(value) => value.into_pyobject(py)?.to_owned().into_any(),
+        serde_json::Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(number), _) => number.into_pyobject(py)?.into_any(),
+            (None, Some(number)) => number.into_pyobject(py)?.into_any(),
+            (None, None) => number.as_f64().into_pyobject(py)?,
+        },
+        serde_json::Value::String(value) => value.into_pyobject(py)?.into_any(),
+        serde_json::Value::Array(values) => {
+            let list = PyList::empty(py);
+            for value in values {
+                list.append(to_python(py, value)?)?;
+            }
+            list.into_any()
+        }
+        serde_json::Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (name, value) in fields {
+                dict.set_item(name, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+fn serialize<'py>(py: Python<'py>, value: &impl serde::Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let value = serde_json::to_value(value).map_err(|e| Error::new_err(e.to_string()))?;
+    to_python(py, &value)
+}
+
+/// Converts a recording, see [`convert_vraw_with_options`]. Returns the
+/// summary of the conversion as a dict.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    out = None,
+    *,
+    container = None,
+    export_stats = false,
+    faststart = false,
+    fragment_duration = None,
+    strict = false,
+    stop_at_corrupt_frame = false,
+    start = None,
+    end = None,
+    no_overwrite = false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn convert<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    out: Option<PathBuf>,
+    container: Option<&str>,
+    export_stats: bool,
+    faststart: bool,
+    fragment_duration: Option<f64>,
+    strict: bool,
+    stop_at_corrupt_frame: bool,
+    start: Option<f64>,
+    end: Option<f64>,
+    no_overwrite: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let container = match container {
+        Some(name) => Some(
+            Container::from_str(name, true)
+                .map_err(|_| Error::new_err(format!("unknown container {name}")))?,
+        ),
+        None => None,
+    };
+    let seconds = |seconds: Option<f64>| {
+        seconds
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| Error::new_err(e.to_string()))
+    };
+
+    let options = ConvertOptions {
+        container,
+        export_stats,
+        faststart,
+        fragment_duration: seconds(fragment_duration)?,
+        strictness: match strict {
+            true => Strictness::Strict,
+            false => Strictness::Lenient,
+        },
+        stop_at_corrupt_frame,
+        start: seconds(start)?,
+        end: seconds(end)?,
+        no_overwrite,
+        ..Default::default()
+    };
+
+    let summary = py
+        .allow_threads(|| convert_vraw_with_options(path, out, &options))
+        .map_err(py_err)?;
+    serialize(py, &summary)
+}
+
+/// Tells what a recording holds without converting it, see
+/// [`probe_with_options`]. Returns the information as a dict.
+#[pyfunction]
+#[pyo3(signature = (path, *, exact = false))]
+fn probe(py: Python<'_>, path: PathBuf, exact: bool) -> PyResult<Bound<'_, PyAny>> {
+    let info = py
+        .allow_threads(|| probe_with_options(path, &ProbeOptions { exact }))
+        .map_err(py_err)?;
+    serialize(py, &info)
+}
+
+#[pymodule]
+fn vraw_convert(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("Error", m.py().get_type::<Error>())?;
+    m.add_class::<VrawReader>()?;
+    m.add_class::<Frame>()?;
+    m.add_class::<FrameIterator>()?;
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add_function(wrap_pyfunction!(probe, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Conversion of uncompressed frames to planar layouts and writing of
+//! YUV4MPEG2 (.y4m) streams and PNG images.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Size in bytes of a 4:2:0 frame with 8 bit samples.
+pub fn yuv420_frame_size(width: usize, height: usize) -> usize {
+    width * height * 3 / 2
+}
+
+/// Converts an NV12 frame (Y plane followed by interleaved UV samples) to I420
+/// (Y, U and V planes).
+pub fn nv12_to_i420(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let luma_size = width * height;
+    let (luma, chroma) = data[..yuv420_frame_size(width, height)].split_at(luma_size);
+
+    let mut res = Vec::with_capacity(data.len());
+    res.extend_from_slice(luma);
+    res.extend(chroma.iter().step_by(2));
+    res.extend(chroma.iter().skip(1).step_by(2));
+
+    res
+}
+
+/// Sample order of an interleaved 4:2:2 frame, where every pair of pixels
+/// shares one U and one V sample.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Packed422 {
+    /// Y0 U Y1 V
+    Yuyv,
+    /// U Y0 V Y1
+    Uyvy,
+}
+
+/// Converts an interleaved 4:2:2 frame to I420. The chroma of two consecutive
+/// rows is averaged, so width and height are expected to be even.
+pub fn packed422_to_i420(data: &[u8], width: usize, height: usize, packing: Packed422) -> Vec<u8> {
+    // offsets of Y0, U, Y1 and V within a pair of pixels
+    let (y0, u, y1, v) = match packing {
+        Packed422::Yuyv => (0, 1, 2, 3),
+        Packed422::Uyvy => (1, 0, 3, 2),
+    };
+
+    let row_size = width * 2;
+    let rows: Vec<&[u8]> = data[..row_size * height].chunks_exact(row_size).collect();
+
+    let mut res = Vec::with_capacity(yuv420_frame_size(width, height));
+
+    for row in &rows {
+        res.extend(row.chunks_exact(4).flat_map(|pair| [pair[y0], pair[y1]]));
+    }
+
+    for offset in [u, v] {
+        for row_pair in rows.chunks_exact(2) {
+            let (top, bottom) = (row_pair[0], row_pair[1]);
+            res.extend(
+                top.chunks_exact(4)
+                    .zip(bottom.chunks_exact(4))
+                    .map(|(top, bottom)| {
+                        (top[offset] as u16 + bottom[offset] as u16).div_ceil(2) as u8
+                    }),
+            );
+        }
+    }
+
+    res
+}
+
+/// Swaps the first and third channel of packed 8 bit BGR pixels.
+pub fn bgr_to_rgb(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(3)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+        .collect()
+}
+
+/// Converts little endian 16 bit grayscale samples to the big endian samples
+/// PNG expects. With auto scaling the samples are stretched so that the
+/// darkest and brightest pixel of the frame span the full 16 bit range.
+pub fn mono16_to_png_samples(data: &[u8], auto_scale: bool) -> Vec<u8> {
+    let samples = data
+        .chunks_exact(2)
+        .map(|sample| u16::from_le_bytes([sample[0], sample[1]]));
+
+    let (min, max) = samples
+        .clone()
+        .fold((u16::MAX, u16::MIN), |(min, max), sample| {
+            (min.min(sample), max.max(sample))
+        });
+
+    let scale = |sample: u16| {
+        if auto_scale && max > min {
+            ((sample - min) as u32 * u16::MAX as u32 / (max - min) as u32) as u16
+        } else {
+            sample
+        }
+    };
+
+    samples
+        .flat_map(|sample| scale(sample).to_be_bytes())
+        .collect()
+}
+
+/// Layout of the color filter array of a Bayer sensor, named after the colors of
+/// the top left 2x2 pixels in reading order.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum BayerPattern {
+    #[default]
+    Rggb,
+    Bggr,
+    Grbg,
+    Gbrg,
+}
+
+impl BayerPattern {
+    /// The channel (0 = red, 1 = green, 2 = blue) sampled at a pixel.
+    fn channel(&self, x: usize, y: usize) -> usize {
+        let layout = match self {
+            BayerPattern::Rggb => [[0, 1], [1, 2]],
+            BayerPattern::Bggr => [[2, 1], [1, 0]],
+            BayerPattern::Grbg => [[1, 0], [2, 1]],
+            BayerPattern::Gbrg => [[1, 2], [0, 1]],
+        };
+
+        layout[y % 2][x % 2]
+    }
+}
+
+/// Bilinear demosaic of a Bayer frame with little endian 16 bit samples. Every
+/// missing channel is the average of the neighboring pixels sampling it.
+/// Returns packed RGB pixels.
+pub fn demosaic_bilinear(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pattern: BayerPattern,
+) -> Vec<u16> {
+    let samples: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+        .collect();
+
+    let mut res = Vec::with_capacity(width * height * 3);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sums = [0u32; 3];
+            let mut counts = [0u32; 3];
+
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let channel = pattern.channel(nx, ny);
+                    sums[channel] += samples[ny * width + nx] as u32;
+                    counts[channel] += 1;
+                }
+            }
+
+            let own_channel = pattern.channel(x, y);
+            for channel in 0..3 {
+                if channel == own_channel {
+                    res.push(samples[y * width + x]);
+                } else {
+                    res.push((sums[channel] / counts[channel].max(1)) as u16);
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Writes a PNG image, 16 bit samples are expected in big endian order.
+pub fn write_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    color_type: png::ColorType,
+    bit_depth: png::BitDepth,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        u32::try_from(width)?,
+        u32::try_from(height)?,
+    );
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Writes frames as a YUV4MPEG2 stream.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header. The frame rate is given as a fraction and the
+    /// colorspace as a y4m `C` tag, e.g. "420jpeg".
+    pub fn new(
+        mut writer: W,
+        width: usize,
+        height: usize,
+        frame_rate: (u32, u32),
+        colorspace: &str,
+    ) -> io::Result<Self> {
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C{}",
+            width, height, frame_rate.0, frame_rate.1, colorspace
+        )?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes one frame with its planes stored back to back.
+    pub fn write_frame(&mut self, planes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(planes)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Estimates the frame rate of frames with the given timestamps in nanoseconds,
+/// as a fraction with millisecond precision. Falls back to 30 fps if there is
+/// not enough timing information.
+pub fn estimate_frame_rate(timestamps: &[i64]) -> (u32, u32) {
+    match (timestamps.first(), timestamps.last()) {
+        (Some(first), Some(last)) if last > first => {
+            let frame_rate = (timestamps.len() - 1) as f64 * 1e9 / (last - first) as f64;
+            ((frame_rate * 1000.0).round().max(1.0) as u32, 1000)
+        }
+        _ => (30, 1),
+    }
+}
@@ -0,0 +1,382 @@
+use crate::error::VrawError;
+use crate::parser::{
+    parse_frame_metadata, parse_raw_frame, read_frame_into, read_recording_metadata,
+    read_recording_start, FrameInfo, FrameMetadata, FrameRef, IndexedFrames, IndexedReadFrame,
+    ParseOptions, RecordingIndexEntry, RecordingMetadata, RecordingReader,
+};
+use crate::processing::{read_or_rebuild_index, ConvertOptions, IndexOrder, IndexRepairs};
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Which frame [`VrawReader::frame_at_timestamp`] finds for a timestamp no
+/// frame was received at.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum SeekPolicy {
+    /// The frame received closest to the timestamp, the earlier one of two
+    /// frames as close
+    #[default]
+    Nearest,
+    /// The last frame received at or before the timestamp
+    AtOrBefore,
+    /// The first frame received at or after the timestamp
+    AtOrAfter,
+}
+
+/// A .vraw recording opened to read its frames. The index is read on opening,
+/// or rebuilt from the frames when it is missing. The conversion reads the
+/// recordings it converts with a reader as well. Threads reading the same
+/// recording each read it with their own reader, see
+/// [`try_clone`](VrawReader::try_clone).
+///
+/// ```no_run
+/// let mut reader = vraw_convert::VrawReader::open("recording.vraw")?;
+/// for frame in &mut reader {
+///     let frame = frame?;
+///     println!("{:?} frame received at {} ns", frame.format, frame.receive_timestamp);
+/// }
+/// # Ok::<(), vraw_convert::VrawError>(())
+/// ```
+pub struct VrawReader<R = BufReader<File>> {
+    f: R,
+    /// The file the recording was opened from, reopened by clones
+    path: Option<PathBuf>,
+    metadata: Option<RecordingMetadata>,
+    /// The index, shared by the clones of the reader
+    index: Arc<ReaderIndex>,
+    options: ParseOptions,
+}
+
+pub(crate) struct ReaderIndex {
+    pub(crate) entries: Vec<RecordingIndexEntry>,
+    /// Positions of the entries, ordered by their receive timestamp
+    by_timestamp: Vec<usize>,
+}
+
+impl VrawReader {
+    /// Opens a recording file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, VrawError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(VrawError::input("failed to open file"))?;
+        let mut reader = Self::new(BufReader::new(file))?;
+        reader.path = Some(path.to_path_buf());
+        Ok(reader)
+    }
+
+    /// Opens the file of the recording again, for another thread to read its
+    /// frames independently of this reader. The index is shared, not read
+    /// again. Only readers opened with [`open`](Self::open) know their file.
+    pub fn try_clone(&self) -> Result<Self, VrawError> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            VrawError::InvalidOptions(
+                "the recording was not opened from a path and cannot be opened again".into(),
+            )
+        })?;
+        let file = File::open(path).map_err(VrawError::input("failed to open file"))?;
+
+        Ok(self.with_reader(BufReader::new(file)))
+    }
+}
+
+impl<'a> VrawReader<Cursor<&'a [u8]>> {
+    /// Reads a recording held in memory.
+    pub fn from_bytes(recording: &'a [u8]) -> Result<Self, VrawError> {
+        Self::new(Cursor::new(recording))
+    }
+
+    /// Another reader of the recording in memory, for another thread to read
+    /// its frames independently of this reader. The index is shared.
+    pub fn try_clone(&self) -> Result<Self, VrawError> {
+        Ok(self.with_reader(Cursor::new(*self.f.get_ref())))
+    }
+}
+
+impl<R: RecordingReader> VrawReader<R> {
+    /// Reads a recording, e.g. from memory with an [`std::io::Cursor`].
+    pub fn new(f: R) -> Result<Self, VrawError> {
+        Self::with_options(f, ParseOptions::default())
+    }
+
+    /// Reads a recording, with [`Strictness::Strict`](crate::Strictness)
+    /// failing on any inconsistency of the index and of the frames read.
+    pub fn with_options(mut f: R, options: ParseOptions) -> Result<Self, VrawError> {
+        let metadata = read_recording_metadata(&mut f).map_err(VrawError::corrupt_header(
+            "failed to read the recording header",
+        ))?;
+        let (entries, _) = read_or_rebuild_index(&mut f, &options, IndexOrder::Offset, false)?;
+
+        Ok(Self::with_entries(f, metadata, entries, options))
+    }
+
+    /// Reads a recording to convert it, its index read and repaired as the
+    /// options of the conversion ask for. A header that cannot be read is
+    /// left to the passes of the conversion that need it.
+    pub(crate) fn for_conversion(
+        mut f: R,
+        options: &ConvertOptions,
+    ) -> Result<(Self, IndexRepairs), VrawError> {
+        let metadata = read_recording_metadata(&mut f).ok().flatten();
+        let parse_options = options.parse_options();
+        let (entries, repairs) = read_or_rebuild_index(
+            &mut f,
+            &parse_options,
+            options.index_order,
+            options.lenient_index,
+        )?;
+
+        Ok((
+            Self::with_entries(f, metadata, entries, parse_options),
+            repairs,
+        ))
+    }
+
+    /// A reader of a recording whose index is already known, e.g. from
+    /// reading it front to back.
+    pub(crate) fn with_entries(
+        f: R,
+        metadata: Option<RecordingMetadata>,
+        entries: Vec<RecordingIndexEntry>,
+        options: ParseOptions,
+    ) -> Self {
+        // Sorted once, as the frames of a recording whose clock was stepped
+        // back are not in the order of their timestamps
+        let mut by_timestamp: Vec<usize> = (0..entries.len()).collect();
+        by_timestamp.sort_by_key(|&index| entries[index].receive_timestamp());
+
+        Self {
+            f,
+            path: None,
+            metadata,
+            index: Arc::new(ReaderIndex {
+                entries,
+                by_timestamp,
+            }),
+            options,
+        }
+    }
+
+    /// A reader of the same recording with another reader, sharing the index.
+    fn with_reader<S>(&self, f: S) -> VrawReader<S> {
+        VrawReader {
+            f,
+            path: self.path.clone(),
+            metadata: self.metadata.clone(),
+            index: self.index.clone(),
+            options: self.options,
+        }
+    }
+
+    /// Number of frames listed by the index, Stats frames and frames that
+    /// cannot be read included.
+    pub fn frame_count(&self) -> usize {
+        self.index.entries.len()
+    }
+
+    /// The header of the recording, None for recordings of old recorders,
+    /// which have none.
+    pub fn recording_metadata(&self) -> Option<&RecordingMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Reads the frame at a position in the index.
+    pub fn frame(&mut self, index: usize) -> Result<FrameInfo, VrawError> {
+        let entry = index_entry(&self.index.entries, index)?;
+
+        parse_raw_frame(&mut self.f, entry, &self.options).map_err(|reason| VrawError::BadFrame {
+            index,
+            offset: entry.offset(),
+            reason,
+        })
+    }
+
+    /// Reads the frame at a position in the index into a buffer reused for
+    /// every frame, see [`read_frame_into`](crate::read_frame_into).
+    pub fn read_frame_into<'b>(
+        &mut self,
+        buf: &'b mut Vec<u8>,
+        index: usize,
+    ) -> Result<FrameRef<'b>, VrawError> {
+        let entry = index_entry(&self.index.entries, index)?;
+
+        read_frame_into(&mut self.f, entry, &self.options, buf).map_err(|reason| {
+            VrawError::BadFrame {
+                index,
+                offset: entry.offset(),
+                reason,
+            }
+        })
+    }
+
+    /// Position in the index of the frame received at a timestamp in
+    /// nanoseconds, or the frame the policy finds for a timestamp no frame
+    /// was received at. The timestamps are those of the index, found by binary
+    /// search. None if there is no such frame.
+    pub fn frame_index_at_timestamp(&self, timestamp: i64, policy: SeekPolicy) -> Option<usize> {
+        let by_timestamp = &self.index.by_timestamp;
+        let timestamp_of = |index: usize| self.index.entries[index].receive_timestamp();
+        let after = by_timestamp.partition_point(|&index| timestamp_of(index) < timestamp);
+        let at_or_after = by_timestamp.get(after).copied();
+        let at_or_before = by_timestamp
+            .partition_point(|&index| timestamp_of(index) <= timestamp)
+            .checked_sub(1)
+            .map(|position| by_timestamp[position]);
+
+        match (policy, at_or_before, at_or_after) {
+            (SeekPolicy::AtOrBefore, at_or_before, _) => at_or_before,
+            (SeekPolicy::AtOrAfter, _, at_or_after) => at_or_after,
+            (SeekPolicy::Nearest, Some(before), Some(after)) => {
+                if timestamp.abs_diff(timestamp_of(before))
+                    <= timestamp_of(after).abs_diff(timestamp)
+                {
+                    Some(before)
+                } else {
+                    Some(after)
+                }
+            }
+            (SeekPolicy::Nearest, before, after) => before.or(after),
+        }
+    }
+
+    /// Reads the frame received at a timestamp in nanoseconds, see
+    /// [`frame_index_at_timestamp`](Self::frame_index_at_timestamp).
+    pub fn frame_at_timestamp(
+        &mut self,
+        timestamp: i64,
+        policy: SeekPolicy,
+    ) -> Result<FrameInfo, VrawError> {
+        let index = self
+            .frame_index_at_timestamp(timestamp, policy)
+            .ok_or_else(|| {
+                VrawError::InvalidOptions(match policy {
+                    SeekPolicy::Nearest => "the recording has no frames".into(),
+                    SeekPolicy::AtOrBefore => {
+                        format!("no frame was received at or before {timestamp} ns")
+                    }
+                    SeekPolicy::AtOrAfter => {
+                        format!("no frame was received at or after {timestamp} ns")
+                    }
+                })
+            })?;
+
+        self.frame(index)
+    }
+
+    /// Iterates over the frames of the recording in the order of the index,
+    /// Stats frames included.
+    pub fn frames(&mut self) -> VrawFrames<'_, R> {
+        VrawFrames {
+            frames: IndexedFrames::new(&mut self.f, &self.index.entries, self.options),
+        }
+    }
+
+    /// The index, shared with the reader, for the passes of the conversion
+    /// over some of its entries.
+    pub(crate) fn index(&self) -> Arc<ReaderIndex> {
+        self.index.clone()
+    }
+
+    /// Iterates over the frames of some index entries, e.g. of a time range.
+    pub(crate) fn frames_of<'a>(
+        &'a mut self,
+        entries: &'a [RecordingIndexEntry],
+    ) -> VrawFrames<'a, R> {
+        VrawFrames {
+            frames: IndexedFrames::new(&mut self.f, entries, self.options),
+        }
+    }
+
+    /// Reads the header of the frame of an index entry, see
+    /// [`parse_frame_metadata`].
+    pub(crate) fn frame_metadata(
+        &mut self,
+        entry: &RecordingIndexEntry,
+    ) -> Result<FrameMetadata, Box<dyn Error + Send + Sync>> {
+        parse_frame_metadata(&mut self.f, entry)
+    }
+
+    /// Reads the frame of an index entry, see [`parse_raw_frame`].
+    pub(crate) fn entry_frame(
+        &mut self,
+        entry: &RecordingIndexEntry,
+        options: &ParseOptions,
+    ) -> Result<FrameInfo, Box<dyn Error + Send + Sync>> {
+        parse_raw_frame(&mut self.f, entry, options)
+    }
+
+    /// Reads the wall clock time at which the recording started from its
+    /// header, see [`read_recording_start`].
+    pub(crate) fn recording_start(
+        &mut self,
+    ) -> Result<DateTime<Utc>, Box<dyn Error + Send + Sync>> {
+        read_recording_start(&mut self.f)
+    }
+}
+
+/// The entry at a position in the index.
+fn index_entry(
+    entries: &[RecordingIndexEntry],
+    index: usize,
+) -> Result<&RecordingIndexEntry, VrawError> {
+    entries.get(index).ok_or_else(|| {
+        VrawError::InvalidOptions(format!(
+            "frame {index} out of range, the recording has {} frames",
+            entries.len()
+        ))
+    })
+}
+
+impl<'a, R: RecordingReader> IntoIterator for &'a mut VrawReader<R> {
+    type Item = Result<FrameInfo, VrawError>;
+    type IntoIter = VrawFrames<'a, R>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames()
+    }
+}
+
+/// Iterator over the frames of a [`VrawReader`]. A frame that cannot be read
+/// is a [`VrawError::BadFrame`] naming it, after which the iteration goes on
+/// with the next frame.
+pub struct VrawFrames<'a, R> {
+    frames: IndexedFrames<'a, R>,
+}
+
+impl<'a, R: RecordingReader> VrawFrames<'a, R> {
+    /// Reads the frame of the next entry with its payload into a buffer, for
+    /// the conversion, which tells the frames that cannot be read apart by
+    /// their errors.
+    pub(crate) fn next_into(&mut self, buf: &mut Vec<u8>) -> Option<IndexedReadFrame<'a>> {
+        self.frames.next_into(buf)
+    }
+
+    /// Number of entries whose frames are not read yet
+    pub(crate) fn remaining(&self) -> usize {
+        self.frames.remaining()
+    }
+
+    /// Ends the iteration without reading the frames of the remaining entries
+    pub(crate) fn stop(&mut self) {
+        self.frames.stop()
+    }
+
+    /// Byte offset in the recording the reading has reached
+    pub(crate) fn position(&mut self) -> io::Result<u64> {
+        self.frames.position()
+    }
+}
+
+impl<R: RecordingReader> Iterator for VrawFrames<'_, R> {
+    type Item = Result<FrameInfo, VrawError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, entry, frame) = self.frames.next()?;
+        Some(frame.map_err(|reason| VrawError::BadFrame {
+            index,
+            offset: entry.offset(),
+            reason,
+        }))
+    }
+}
@@ -0,0 +1,150 @@
+use crate::error::VrawError;
+use crate::parser::{
+    find_invalid_index_entries, first_frame_offset, read_index, read_recording_metadata,
+    scan_index, verify_frame, ParseOptions, RecordingReader, VideoCaptureFormat,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// A problem of a frame found by [`verify_vraw`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameProblem {
+    /// Position of the frame in the index
+    pub index: usize,
+    /// Byte offset of the frame in the recording
+    pub offset: i64,
+    pub problem: String,
+}
+
+/// Result of verifying a recording with [`verify_vraw`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct VerifyReport {
+    /// Number of frames that could be read
+    pub frames: usize,
+    /// Number of bytes of the frames that could be read, headers and generic
+    /// metadata included
+    pub bytes: u64,
+    /// Time between the first and the last frame received, in nanoseconds
+    pub duration: i64,
+    /// Number of frames that could be read, per format
+    pub formats: HashMap<VideoCaptureFormat, usize>,
+    /// Problems of the header and the index of the recording
+    pub problems: Vec<String>,
+    /// Problems of the frames, in the order of the index
+    pub frame_problems: Vec<FrameProblem>,
+}
+
+impl VerifyReport {
+    /// Whether the recording has no problems
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty() && self.frame_problems.is_empty()
+    }
+}
+
+/// Checks the structure of a .vraw recording without converting it: its
+/// header, its index, and the magics, sizes, generic metadata, video placement
+/// footer and receive timestamp of every frame. The payloads of the frames are
+/// seeked over, not read.
+///
+/// Only failing to open the file is an error, everything found in it is listed
+/// in the report.
+///
+/// input: path to .vraw file
+pub fn verify_vraw(input: impl AsRef<Path>) -> Result<VerifyReport, VrawError> {
+    let input_file = File::open(input).map_err(VrawError::input("failed to open file"))?;
+    let mut f = BufReader::new(input_file);
+    let options = ParseOptions::default();
+    let mut report = VerifyReport::default();
+
+    match read_recording_metadata(&mut f) {
+        Ok(Some(_)) => {}
+        Ok(None) => report
+            .problems
+            .push("The recording has no header, as written by old recorders.".into()),
+        Err(e) => report.problems.push(e.to_string()),
+    }
+
+    let entries = match read_index(&mut f, &options) {
+        Ok(index) => {
+            report.problems.extend(index.warnings);
+            index.entries
+        }
+        Err(e) => {
+            report.problems.push(format!("Failed to read index: {e}"));
+            match scan_index(&mut f) {
+                Ok(index) => {
+                    report.problems.extend(index.warnings);
+                    index.entries
+                }
+                Err(e) => {
+                    report
+                        .problems
+                        .push(format!("Failed to rebuild index: {e}"));
+                    Vec::new()
+                }
+            }
+        }
+    };
+
+    let file_len = match f.recording_len() {
+        Ok(file_len) => file_len,
+        Err(e) => {
+            report
+                .problems
+                .push(format!("Failed to read the file size: {e}"));
+            return Ok(report);
+        }
+    };
+    let mut invalid_entries =
+        find_invalid_index_entries(&entries, first_frame_offset(&mut f), file_len)
+            .into_iter()
+            .peekable();
+
+    let mut first_timestamp = None;
+    let mut last_timestamp = None;
+    for (index, entry) in entries.iter().enumerate() {
+        let mut problem = |problem| {
+            report.frame_problems.push(FrameProblem {
+                index,
+                offset: entry.offset(),
+                problem,
+            })
+        };
+
+        if let Some((_, e)) = invalid_entries.next_if(|(i, _)| *i == index) {
+            problem(format!("Invalid index entry: {e}"));
+            continue;
+        }
+
+        let frame = match verify_frame(&mut f, entry, &options) {
+            Ok(frame) => frame,
+            Err(e) => {
+                problem(e.to_string());
+                continue;
+            }
+        };
+        frame.warnings.into_iter().for_each(&mut problem);
+
+        let timestamp = frame.metadata.receive_timestamp;
+        if let Some(last_timestamp) = last_timestamp.filter(|last| timestamp < *last) {
+            problem(format!(
+                "Frame received at {timestamp} ns, before the frame before it at \
+                 {last_timestamp} ns"
+            ));
+        }
+        first_timestamp.get_or_insert(timestamp);
+        last_timestamp = Some(last_timestamp.map_or(timestamp, |last: i64| last.max(timestamp)));
+
+        report.frames += 1;
+        report.bytes += frame.len;
+        *report.formats.entry(frame.metadata.format).or_default() += 1;
+    }
+
+    report.duration = last_timestamp
+        .zip(first_timestamp)
+        .map_or(0, |(last, first)| last.saturating_sub(first));
+
+    Ok(report)
+}
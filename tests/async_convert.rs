@@ -0,0 +1,91 @@
+//! Conversions of async services, built with the tokio feature.
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use vraw_convert::{ConvertOptions, ProgressCallback, VideoCaptureFormat, VrawError};
+
+#[tokio::test]
+async fn convert_recordings_async() {
+    let output = std::env::temp_dir().join("vraw_convert_async.mp4");
+    let summary = vraw_convert::convert_vraw_async(
+        "assets/h264.vraw",
+        Some(output.clone()),
+        &ConvertOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(summary.frames_written, 30);
+    assert_eq!(summary.format, Some(VideoCaptureFormat::H264));
+    assert_eq!(
+        summary.output_size,
+        std::fs::metadata(&output).unwrap().len()
+    );
+
+    let output = std::env::temp_dir().join("vraw_convert_async_reader.mjpeg");
+    let file = tokio::fs::File::open("assets/mjpeg.vraw").await.unwrap();
+    let summary =
+        vraw_convert::convert_vraw_from_async_reader(file, &output, &ConvertOptions::default())
+            .await
+            .unwrap();
+    assert_eq!(summary.frames_written, 12);
+    assert_eq!(summary.output, output);
+
+    let recording = std::fs::read("assets/h264_corrupt.vraw").unwrap();
+    let sync_summary =
+        vraw_convert::convert_vraw_bytes(&recording, std::io::sink(), &ConvertOptions::default())
+            .unwrap();
+    let output = std::env::temp_dir().join("vraw_convert_async_corrupt.mp4");
+    let summary = vraw_convert::convert_vraw_from_async_reader(
+        std::io::Cursor::new(recording),
+        &output,
+        &ConvertOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(summary.frames_written, sync_summary.frames_written);
+    assert_eq!(summary.frames_corrupt, sync_summary.frames_corrupt);
+
+    let result =
+        vraw_convert::convert_vraw_async("assets/missing.vraw", None, &ConvertOptions::default())
+            .await;
+    assert!(matches!(result, Err(VrawError::Input { .. })));
+}
+
+#[tokio::test]
+async fn cancel_async_conversion() {
+    // Cancelled from the progress callback halfway through the recording
+    let output = std::env::temp_dir().join("vraw_convert_async_cancelled.mp4");
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = ConvertOptions {
+        progress: Some(ProgressCallback::new({
+            let cancel = cancel.clone();
+            move |progress| {
+                if progress.frames_processed >= 600 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        })),
+        cancel: Some(cancel),
+        ..Default::default()
+    };
+    let result =
+        vraw_convert::convert_vraw_async("assets/h265.vraw", Some(output.clone()), &options).await;
+    assert!(matches!(result, Err(VrawError::Cancelled)));
+    assert!(!output.exists());
+
+    // Cancelled by dropping the conversion, which has not finished when the
+    // timeout gives up on it as the file is opened on another thread
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = ConvertOptions {
+        cancel: Some(cancel.clone()),
+        ..Default::default()
+    };
+    let output = std::env::temp_dir().join("vraw_convert_async_dropped.mp4");
+    let conversion = vraw_convert::convert_vraw_async("assets/h265.vraw", Some(output), &options);
+    assert!(tokio::time::timeout(Duration::ZERO, conversion)
+        .await
+        .is_err());
+    assert!(cancel.load(Ordering::Relaxed));
+}
@@ -0,0 +1,60 @@
+/*
+ * Converts a recording through the C interface, proving its ABI. Built and run
+ * from the root of the repository with
+ *
+ *   cargo rustc --lib --release --features ffi --crate-type cdylib
+ *   cc tests/c/convert.c -Iinclude -Ltarget/release -lvraw_convert -o target/convert
+ *   LD_LIBRARY_PATH=target/release target/convert assets/h264.vraw target/convert.mp4
+ */
+
+#include <stdio.h>
+
+#include "vraw_convert.h"
+
+static bool print_progress(const VrawProgress *progress, void *user_data) {
+    unsigned *calls = user_data;
+    *calls += 1;
+    printf("Read %llu of %llu frames\n", (unsigned long long)progress->frames_processed,
+           (unsigned long long)progress->total_frames);
+    return true;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s <input.vraw> <output>\n", argv[0]);
+        return 1;
+    }
+
+    VrawProbeInfo info;
+    VrawStatus status = vraw_probe(argv[1], &info);
+    if (status != VRAW_STATUS_OK) {
+        fprintf(stderr, "probe failed with %d: %s\n", status, vraw_last_error_message());
+        return 1;
+    }
+    printf("%llu frames over %lld ns\n", (unsigned long long)info.frame_count,
+           (long long)info.duration);
+
+    unsigned calls = 0;
+    VrawConvertOptions options = vraw_default_options();
+    options.progress = print_progress;
+    options.progress_stride = 10;
+    options.progress_user_data = &calls;
+    status = vraw_convert_file(argv[1], argv[2], &options);
+    if (status != VRAW_STATUS_OK) {
+        fprintf(stderr, "conversion failed with %d: %s\n", status, vraw_last_error_message());
+        return 1;
+    }
+    if (calls == 0) {
+        fprintf(stderr, "the progress callback was never called\n");
+        return 1;
+    }
+
+    /* A missing recording fails with the message of its error */
+    status = vraw_convert_file("missing.vraw", argv[2], NULL);
+    if (status != VRAW_STATUS_BAD_INPUT || vraw_last_error_message() == NULL) {
+        fprintf(stderr, "converting a missing recording returned %d\n", status);
+        return 1;
+    }
+
+    return 0;
+}
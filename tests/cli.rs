@@ -0,0 +1,90 @@
+//! Exit codes of the command line converter, for scripts converting
+//! recordings in batch.
+
+use assert_cmd::Command;
+
+fn vraw_convert() -> Command {
+    let mut command = Command::cargo_bin("vraw_convert").unwrap();
+    // Without a display failures are only reported on stderr, a message box
+    // would wait for an answer
+    command.env("DISPLAY", "").env("WAYLAND_DISPLAY", "");
+    command
+}
+
+#[test]
+fn succeed_on_converted_recording() {
+    let output = std::env::temp_dir().join("vraw_convert_cli.mp4");
+    let assert = vraw_convert()
+        .arg("assets/h264.vraw")
+        .arg(&output)
+        .assert()
+        .success();
+
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+    assert!(stdout.starts_with("Wrote 30 frames to "));
+    assert!(stdout.contains("Converted H264 video, "));
+}
+
+#[test]
+fn fail_on_missing_input() {
+    vraw_convert()
+        .arg("assets/missing.vraw")
+        .arg(std::env::temp_dir().join("vraw_convert_cli_missing.mp4"))
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn fail_on_corrupt_input() {
+    let input = std::env::temp_dir().join("vraw_convert_cli_corrupt.vraw");
+    std::fs::write(&input, [0; 64]).unwrap();
+    vraw_convert()
+        .arg(&input)
+        .arg(std::env::temp_dir().join("vraw_convert_cli_corrupt.mp4"))
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn fail_on_unwritable_output() {
+    vraw_convert()
+        .arg("assets/h264.vraw")
+        .arg(std::env::temp_dir().join("vraw_convert_cli_missing_folder/output.mp4"))
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn fail_on_options_not_applying() {
+    vraw_convert()
+        .arg("assets/h264.vraw")
+        .arg(std::env::temp_dir().join("vraw_convert_cli_options.avi"))
+        .args(["--container", "avi"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn fail_on_existing_output_kept() {
+    let output = std::env::temp_dir().join("vraw_convert_cli_existing.mp4");
+    std::fs::write(&output, b"existing").unwrap();
+    vraw_convert()
+        .arg("assets/h264.vraw")
+        .arg(&output)
+        .arg("--no-overwrite")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn report_failure_on_stderr_without_gui() {
+    let assert = vraw_convert()
+        .arg("assets/missing.vraw")
+        .arg(std::env::temp_dir().join("vraw_convert_cli_no_gui.mp4"))
+        .arg("--no-gui")
+        .assert()
+        .code(3);
+
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.starts_with("Application error: "));
+}
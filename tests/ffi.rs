@@ -0,0 +1,85 @@
+//! The C interface, built with the ffi feature. tests/c/convert.c exercises
+//! the same functions from C.
+#![cfg(feature = "ffi")]
+
+use std::ffi::{c_void, CStr, CString};
+use vraw_convert::ffi::{
+    vraw_convert_file, vraw_default_options, vraw_last_error_message, vraw_probe, VrawProbeInfo,
+    VrawProgress, VrawStatus, VRAW_CONTAINER_ELEMENTARY_STREAM,
+};
+
+unsafe extern "C" fn stop_halfway(progress: *const VrawProgress, user_data: *mut c_void) -> bool {
+    let progress = &*progress;
+    *(user_data as *mut u64) = progress.frames_processed;
+    progress.frames_processed * 2 < progress.total_frames
+}
+
+#[test]
+fn convert_and_probe_through_c_interface() {
+    let input = CString::new("assets/h264.vraw").unwrap();
+    let output = std::env::temp_dir().join("vraw_convert_ffi.h264");
+    let output = CString::new(output.to_str().unwrap()).unwrap();
+
+    let mut options = vraw_default_options();
+    options.container = VRAW_CONTAINER_ELEMENTARY_STREAM;
+    let status = unsafe { vraw_convert_file(input.as_ptr(), output.as_ptr(), &options) };
+    assert_eq!(status, VrawStatus::Ok);
+    assert!(vraw_last_error_message().is_null());
+
+    let mut info = VrawProbeInfo::default();
+    assert_eq!(
+        unsafe { vraw_probe(input.as_ptr(), &mut info) },
+        VrawStatus::Ok
+    );
+    assert_eq!(info.frame_count, 40);
+    assert!(info.has_recording_start);
+}
+
+#[test]
+fn cancel_from_progress_callback() {
+    let input = CString::new("assets/h264.vraw").unwrap();
+    let output = std::env::temp_dir().join("vraw_convert_ffi_cancelled.mp4");
+    let output = CString::new(output.to_str().unwrap()).unwrap();
+
+    let mut frames_processed = 0u64;
+    let mut options = vraw_default_options();
+    options.progress = Some(stop_halfway);
+    options.progress_stride = 1;
+    options.progress_user_data = &mut frames_processed as *mut u64 as *mut c_void;
+
+    let status = unsafe { vraw_convert_file(input.as_ptr(), output.as_ptr(), &options) };
+    assert_eq!(status, VrawStatus::Cancelled);
+    assert!(frames_processed < 30);
+}
+
+#[test]
+fn report_errors_through_last_error_message() {
+    let input = CString::new("assets/missing.vraw").unwrap();
+    let status = unsafe { vraw_convert_file(input.as_ptr(), std::ptr::null(), std::ptr::null()) };
+    assert_eq!(status, VrawStatus::BadInput);
+    let message = unsafe { CStr::from_ptr(vraw_last_error_message()) };
+    assert!(message
+        .to_str()
+        .unwrap()
+        .starts_with("vraw_convert: failed to open file: "));
+
+    let status = unsafe { vraw_probe(std::ptr::null(), std::ptr::null_mut()) };
+    assert_eq!(status, VrawStatus::InvalidArgument);
+
+    let mut options = vraw_default_options();
+    options.container = 100;
+    let status = unsafe { vraw_convert_file(input.as_ptr(), std::ptr::null(), &options) };
+    assert_eq!(status, VrawStatus::InvalidOptions);
+}
+
+#[test]
+fn committed_header_is_current() {
+    // The build script writes the header of the C interface to OUT_DIR
+    let generated = std::fs::read_to_string(concat!(env!("OUT_DIR"), "/vraw_convert.h")).unwrap();
+    let committed = std::fs::read_to_string("include/vraw_convert.h").unwrap();
+    assert!(
+        generated == committed,
+        "include/vraw_convert.h is out of date, copy it from {}",
+        env!("OUT_DIR")
+    );
+}
@@ -0,0 +1,46 @@
+"""The Python module, built with `maturin develop` from the root of the
+repository."""
+
+from pathlib import Path
+
+import pytest
+
+import vraw_convert
+
+ASSETS = Path(__file__).resolve().parents[2] / "assets"
+
+
+def test_iterate_h265_frames():
+    reader = vraw_convert.VrawReader.open(ASSETS / "h265.vraw")
+    frames = list(reader)
+
+    assert len(frames) == reader.frame_count() == len(reader) == 2981
+    video = [frame for frame in frames if frame.format == "H265"]
+    assert len(video) == 1265
+    assert all(isinstance(frame.data, bytes) and frame.data for frame in video)
+    assert [frame.index for frame in frames] == list(range(len(frames)))
+
+    # Iterations read the recording independently of each other
+    assert next(iter(reader)).data == frames[0].data
+    assert reader.frame(10).data == frames[10].data
+
+
+def test_convert_and_probe(tmp_path):
+    output = tmp_path / "h264.mp4"
+    summary = vraw_convert.convert(ASSETS / "h264.vraw", output)
+    assert summary["frames_written"] == 30
+    assert summary["format"] == "H264"
+    assert output.exists()
+
+    # The frame count of the index includes the Stats frames
+    info = vraw_convert.probe(ASSETS / "h264.vraw")
+    assert info["frame_count"] == 40
+    assert info["first_timestamp"] == 7_723_900
+
+
+def test_raise_on_missing_recording():
+    with pytest.raises(vraw_convert.Error, match="failed to open file"):
+        vraw_convert.VrawReader.open(ASSETS / "missing.vraw")
+
+    with pytest.raises(vraw_convert.Error, match="unknown container"):
+        vraw_convert.convert(ASSETS / "h264.vraw", container="gif")